@@ -0,0 +1,42 @@
+// An arcade cabinet: a CHIP-8 screen on a sprite in a 2D scene.
+//
+//     cargo run --example cabinet -- ROM
+
+use std::env;
+use std::fs;
+
+use bevy::prelude::*;
+use chip8_bevy::{Chip8Plugin, Chip8Screen};
+
+fn main() {
+    let path = env::args().nth(1).expect("usage: cabinet ROM");
+    let rom = fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugins(Chip8Plugin::new(&rom))
+        .add_systems(Startup, setup)
+        .run();
+}
+
+fn setup(mut commands: Commands, screen: Res<Chip8Screen>) {
+    commands.spawn(Camera2dBundle::default());
+    // The cabinet, and the screen set into it.
+    commands.spawn(SpriteBundle {
+        sprite: Sprite {
+            color: Color::rgb(0.2, 0.1, 0.3),
+            custom_size: Some(Vec2::new(720.0, 440.0)),
+            ..default()
+        },
+        ..default()
+    });
+    commands.spawn(SpriteBundle {
+        texture: screen.0.clone(),
+        sprite: Sprite {
+            custom_size: Some(Vec2::new(640.0, 320.0)),
+            ..default()
+        },
+        transform: Transform::from_xyz(0.0, 20.0, 1.0),
+        ..default()
+    });
+}
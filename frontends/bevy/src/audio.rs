@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bevy::audio::{Decodable, Source};
+use bevy::prelude::*;
+use chip8::Audio;
+
+// What the machine renders at; Bevy's mixer resamples it for the device.
+const SAMPLE_RATE: u32 = 44_100;
+
+// Samples the queue may hold before new ones are dropped, in 60Hz frames.
+// Enough to ride over a late frame without the sound lagging behind the
+// game.
+const MAX_QUEUED_FRAMES: usize = 6;
+
+type Queue = Arc<Mutex<VecDeque<f32>>>;
+
+// The machine's sound as an audio asset. Playing it starts a never-ending
+// source that takes samples off the queue, and silence while it is empty.
+#[derive(Asset, TypePath)]
+pub struct Chip8Sound {
+    queue: Queue,
+}
+
+pub struct QueueDecoder {
+    queue: Queue,
+}
+
+impl Iterator for QueueDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut queue = self.queue.lock().expect("chip8-bevy: audio queue poisoned");
+        Some(queue.pop_front().unwrap_or(0.0))
+    }
+}
+
+impl Source for QueueDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Decodable for Chip8Sound {
+    type DecoderItem = f32;
+    type Decoder = QueueDecoder;
+
+    fn decoder(&self) -> QueueDecoder {
+        QueueDecoder {
+            queue: Arc::clone(&self.queue),
+        }
+    }
+}
+
+// The machine's audio backend, feeding a `Chip8Sound`. The machine's
+// `PatternSynth` renders the buzzer, fading it in and out, so the beep
+// edges need no handling here.
+pub struct BevyAudio {
+    queue: Queue,
+}
+
+impl BevyAudio {
+    // A backend and the sound asset that plays what it is given.
+    pub fn new() -> (Self, Chip8Sound) {
+        let queue = Queue::default();
+        let sound = Chip8Sound {
+            queue: Arc::clone(&queue),
+        };
+        (BevyAudio { queue }, sound)
+    }
+}
+
+impl Audio for BevyAudio {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+
+    fn sample_rate(&self) -> Option<u32> {
+        Some(SAMPLE_RATE)
+    }
+
+    fn queue_samples(&mut self, samples: &[f32]) {
+        let mut queue = self.queue.lock().expect("chip8-bevy: audio queue poisoned");
+        let max = SAMPLE_RATE as usize / 60 * MAX_QUEUED_FRAMES;
+        let room = max.saturating_sub(queue.len());
+        queue.extend(samples.iter().take(room));
+    }
+}
@@ -0,0 +1,233 @@
+// A Bevy plugin running a CHIP-8 machine inside a game, e.g. on the screen
+// of an arcade cabinet. The picture is an `Image` asset, `Chip8Screen`,
+// that can go on a sprite or a material like any other texture; the
+// keyboard and gamepads drive the keypad; the sound plays through Bevy's audio; and
+// the machine runs at 60 frames a second of game time.
+//
+//     App::new()
+//         .add_plugins(DefaultPlugins)
+//         .add_plugins(Chip8Plugin::new(&rom))
+//         .run();
+//
+// `Machine` holds its backends as trait objects that aren't Send, so the
+// `Chip8` resource is a non-send one and systems reach it through
+// `NonSend`/`NonSendMut`.
+
+mod audio;
+
+use chip8::{
+    Chip8Error, GamepadMap, KeyMap, Machine, Palette, RgbaTexture, CHIP8_HEIGHT, CHIP8_WIDTH,
+};
+
+use bevy::audio::AddAudioSource;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+
+use crate::audio::{BevyAudio, Chip8Sound};
+
+pub struct Chip8Plugin {
+    rom: Vec<u8>,
+    cycles_per_frame: Option<usize>,
+    keys: KeyMap,
+    gamepad: GamepadMap,
+    palette: Palette,
+}
+
+impl Chip8Plugin {
+    // Run `rom` with the standard 1234/QWER/ASDF/ZXCV keys and gamepad
+    // buttons.
+    pub fn new(rom: &[u8]) -> Self {
+        Chip8Plugin {
+            rom: rom.to_vec(),
+            cycles_per_frame: None,
+            keys: KeyMap::standard(),
+            gamepad: GamepadMap::standard(),
+            palette: Palette::MONOCHROME,
+        }
+    }
+
+    pub fn cycles_per_frame(mut self, cycles: usize) -> Self {
+        self.cycles_per_frame = Some(cycles);
+        self
+    }
+
+    pub fn keys(mut self, keys: KeyMap) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    pub fn gamepad(mut self, gamepad: GamepadMap) -> Self {
+        self.gamepad = gamepad;
+        self
+    }
+
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+}
+
+// The running machine, for systems that want to look at or change it.
+pub struct Chip8 {
+    pub machine: Machine,
+    pub keys: KeyMap,
+    pub gamepad: GamepadMap,
+    // Set once the program has failed; the machine stops there.
+    pub error: Option<Chip8Error>,
+    screen: RgbaTexture,
+}
+
+// The machine's screen as an RGBA image, NEAREST sampled.
+#[derive(Resource, Clone, Debug)]
+pub struct Chip8Screen(pub Handle<Image>);
+
+impl Plugin for Chip8Plugin {
+    fn build(&self, app: &mut App) {
+        let mut machine = Machine::new();
+        machine
+            .load_rom(&self.rom)
+            .expect("chip8-bevy: the ROM doesn't load");
+        if let Some(cycles) = self.cycles_per_frame {
+            machine.set_cycles_per_frame(cycles);
+        }
+        let (audio, sound) = BevyAudio::new();
+        machine.set_audio(Box::new(audio));
+
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: CHIP8_WIDTH as u32,
+                height: CHIP8_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &self.palette.rgba()[0],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        image.sampler = ImageSampler::nearest();
+        let handle = app
+            .world
+            .get_resource_mut::<Assets<Image>>()
+            .expect("chip8-bevy: add DefaultPlugins before Chip8Plugin")
+            .add(image);
+
+        app.add_audio_source::<Chip8Sound>();
+        let sound = app.world.resource_mut::<Assets<Chip8Sound>>().add(sound);
+        // The source never ends, so playing it once is for good.
+        app.world.spawn(AudioSourceBundle {
+            source: sound,
+            settings: PlaybackSettings::ONCE,
+        });
+
+        app.insert_resource(Chip8Screen(handle))
+            .insert_non_send_resource(Chip8 {
+                machine,
+                keys: self.keys.clone(),
+                gamepad: self.gamepad.clone(),
+                error: None,
+                screen: RgbaTexture::new(self.palette.rgba()),
+            })
+            .add_systems(
+                Update,
+                (chip8_input, chip8_gamepad, chip8_run, chip8_draw).chain(),
+            );
+    }
+}
+
+fn chip8_input(mut chip8: NonSendMut<Chip8>, keyboard: Res<ButtonInput<KeyCode>>) {
+    let chip8 = &mut *chip8;
+    for &code in keyboard.get_just_pressed() {
+        if let Some(key) = key_name(code).and_then(|name| chip8.keys.key(name)) {
+            chip8.machine.press_key(key);
+        }
+    }
+    for &code in keyboard.get_just_released() {
+        if let Some(key) = key_name(code).and_then(|name| chip8.keys.key(name)) {
+            chip8.machine.release_key(key);
+        }
+    }
+}
+
+// Every connected pad drives the same keypad.
+fn chip8_gamepad(mut chip8: NonSendMut<Chip8>, buttons: Res<ButtonInput<GamepadButton>>) {
+    let chip8 = &mut *chip8;
+    for button in buttons.get_just_pressed() {
+        if let Some(key) = pad_button(button.button_type).and_then(|b| chip8.gamepad.key(b)) {
+            chip8.machine.press_key(key);
+        }
+    }
+    for button in buttons.get_just_released() {
+        if let Some(key) = pad_button(button.button_type).and_then(|b| chip8.gamepad.key(b)) {
+            chip8.machine.release_key(key);
+        }
+    }
+}
+
+fn chip8_run(mut chip8: NonSendMut<Chip8>, time: Res<Time>) {
+    if chip8.error.is_some() {
+        return;
+    }
+    if let Err(e) = chip8.machine.update(time.delta()) {
+        error!("chip8-bevy: {}", e);
+        chip8.error = Some(e);
+    }
+}
+
+fn chip8_draw(
+    mut chip8: NonSendMut<Chip8>,
+    screen: Res<Chip8Screen>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let chip8 = &mut *chip8;
+    let resized = chip8.screen.update(chip8.machine.framebuffer());
+    let image = match images.get_mut(&screen.0) {
+        Some(image) => image,
+        None => return,
+    };
+    if resized {
+        image.resize(Extent3d {
+            width: chip8.screen.width() as u32,
+            height: chip8.screen.height() as u32,
+            depth_or_array_layers: 1,
+        });
+    }
+    image.data.copy_from_slice(chip8.screen.pixels());
+}
+
+// The character on a letter or digit key, which is how the key map names
+// keys. Bevy calls them KeyA to KeyZ and Digit0 to Digit9.
+fn key_name(code: KeyCode) -> Option<char> {
+    let name = format!("{:?}", code);
+    let name = name
+        .strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))?;
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+// Bevy names buttons by position too; its shoulder buttons are the first
+// pair of triggers. Its `GamepadButton` is a button on a given pad, hence
+// the full path for the chip8 one.
+fn pad_button(button: GamepadButtonType) -> Option<chip8::GamepadButton> {
+    use chip8::GamepadButton as Pad;
+    Some(match button {
+        GamepadButtonType::DPadUp => Pad::Up,
+        GamepadButtonType::DPadDown => Pad::Down,
+        GamepadButtonType::DPadLeft => Pad::Left,
+        GamepadButtonType::DPadRight => Pad::Right,
+        GamepadButtonType::South => Pad::South,
+        GamepadButtonType::East => Pad::East,
+        GamepadButtonType::West => Pad::West,
+        GamepadButtonType::North => Pad::North,
+        GamepadButtonType::LeftTrigger => Pad::LeftShoulder,
+        GamepadButtonType::RightTrigger => Pad::RightShoulder,
+        GamepadButtonType::Select => Pad::Select,
+        GamepadButtonType::Start => Pad::Start,
+        _ => return None,
+    })
+}
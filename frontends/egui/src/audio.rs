@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chip8::Audio;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+// Samples the queue may hold before new ones are dropped, in 60Hz frames.
+// Enough to ride over a late frame without the sound lagging behind the
+// game.
+const MAX_QUEUED_FRAMES: usize = 6;
+
+// The machine's samples on the default output device. The machine's
+// `PatternSynth` renders the buzzer, fading it in and out, so the beep
+// edges need no handling here; the stream plays silence while the queue is
+// empty.
+pub struct CpalAudio {
+    // Kept so the stream plays until the backend is dropped.
+    _stream: Stream,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: u32,
+}
+
+impl CpalAudio {
+    pub fn new() -> Result<Self, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device")?;
+        let supported = device.default_output_config().map_err(|e| e.to_string())?;
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+        let sample_rate = config.sample_rate.0;
+
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let stream = match format {
+            SampleFormat::F32 => build::<f32>(&device, &config, &queue),
+            SampleFormat::I16 => build::<i16>(&device, &config, &queue),
+            SampleFormat::U16 => build::<u16>(&device, &config, &queue),
+            format => return Err(format!("unsupported sample format {}", format)),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(CpalAudio {
+            _stream: stream,
+            queue,
+            sample_rate,
+        })
+    }
+}
+
+// An output stream taking mono samples off `queue` and copying each to
+// every channel.
+fn build<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    queue: &Arc<Mutex<VecDeque<f32>>>,
+) -> Result<Stream, String>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let queue = Arc::clone(queue);
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut queue = queue.lock().expect("chip8-egui: audio queue poisoned");
+                for frame in data.chunks_mut(channels) {
+                    let sample = T::from_sample(queue.pop_front().unwrap_or(0.0));
+                    for out in frame {
+                        *out = sample;
+                    }
+                }
+            },
+            |e| eprintln!("chip8-egui: audio: {}", e),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
+impl Audio for CpalAudio {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+
+    fn sample_rate(&self) -> Option<u32> {
+        Some(self.sample_rate)
+    }
+
+    fn queue_samples(&mut self, samples: &[f32]) {
+        let mut queue = self.queue.lock().expect("chip8-egui: audio queue poisoned");
+        let max = self.sample_rate as usize / 60 * MAX_QUEUED_FRAMES;
+        let room = max.saturating_sub(queue.len());
+        queue.extend(samples.iter().take(room));
+    }
+}
@@ -0,0 +1,410 @@
+mod audio;
+mod gamepad;
+
+use std::env;
+use std::process;
+use std::time::Instant;
+
+use chip8::{DebugCommand, Debugger, GamepadMap, KeyMap, Machine, Palette, Quirks, RgbaTexture};
+use eframe::egui;
+use egui_dock::{DockArea, DockState, NodeIndex, Style, TabViewer};
+
+use crate::audio::CpalAudio;
+use crate::gamepad::Gamepads;
+
+const USAGE: &str = "usage: chip8-egui [--speed CYCLES] [--gamepad BUTTON=KEY,...] ROM";
+
+// The tabs of the dock. Any of them can be closed, dragged to another
+// side or into a window of its own, and reopened from the View menu.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tab {
+    Game,
+    Registers,
+    Disassembly,
+    Memory,
+    Quirks,
+    Keypad,
+}
+
+impl Tab {
+    const ALL: [Tab; 6] = [
+        Tab::Game,
+        Tab::Keypad,
+        Tab::Registers,
+        Tab::Disassembly,
+        Tab::Memory,
+        Tab::Quirks,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            Tab::Game => "Game",
+            Tab::Registers => "Registers",
+            Tab::Disassembly => "Disassembly",
+            Tab::Memory => "Memory",
+            Tab::Quirks => "Quirks",
+            Tab::Keypad => "Keypad",
+        }
+    }
+}
+
+// The hex keys as the COSMAC VIP laid them out.
+const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+
+// Everything the tabs show and change, apart from the dock itself.
+struct Emulator {
+    machine: Machine,
+    debugger: Debugger,
+    keys: KeyMap,
+    screen: RgbaTexture,
+    texture: Option<egui::TextureHandle>,
+    // What has been typed into the breakpoint and memory address fields.
+    breakpoint: String,
+    memory: String,
+    // The keypad key the mouse is holding down.
+    clicked: Option<u8>,
+    error: Option<String>,
+}
+
+struct App {
+    emulator: Emulator,
+    dock: DockState<Tab>,
+    gamepads: Option<Gamepads>,
+    last: Instant,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(message) = run(&args) {
+        eprintln!("chip8-egui: {}", message);
+        process::exit(2);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut speed = None;
+    let mut pad_map = GamepadMap::standard();
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => {
+                let value = args.next().ok_or(USAGE)?;
+                speed = Some(value.parse().map_err(|_| format!("bad speed: {}", value))?);
+            }
+            "--gamepad" => {
+                let value = args.next().ok_or(USAGE)?;
+                pad_map = GamepadMap::parse(value)
+                    .ok_or_else(|| format!("bad gamepad map: {}", value))?;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let mut machine = Machine::new();
+    machine
+        .load_rom_file(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    if let Some(speed) = speed {
+        machine.set_cycles_per_frame(speed);
+    }
+    // Without a sound device the game still runs, silently.
+    match CpalAudio::new() {
+        Ok(audio) => machine.set_audio(Box::new(audio)),
+        Err(e) => eprintln!("chip8-egui: no sound: {}", e),
+    }
+    let gamepads = match Gamepads::new(pad_map) {
+        Ok(gamepads) => Some(gamepads),
+        Err(e) => {
+            eprintln!("chip8-egui: no gamepads: {}", e);
+            None
+        }
+    };
+
+    let emulator = Emulator {
+        machine,
+        debugger: Debugger::new(),
+        keys: KeyMap::standard(),
+        screen: RgbaTexture::new(Palette::MONOCHROME.rgba()),
+        texture: None,
+        breakpoint: String::new(),
+        memory: String::new(),
+        clicked: None,
+        error: None,
+    };
+
+    // The game on the left, registers and disassembly to its right, memory
+    // and the keypad, memory and quirks below.
+    let mut dock = DockState::new(vec![Tab::Game]);
+    let surface = dock.main_surface_mut();
+    let [game, side] = surface.split_right(NodeIndex::root(), 0.6, vec![Tab::Registers]);
+    surface.split_below(side, 0.4, vec![Tab::Disassembly]);
+    surface.split_below(game, 0.6, vec![Tab::Keypad, Tab::Memory, Tab::Quirks]);
+
+    let app = App {
+        emulator,
+        dock,
+        gamepads,
+        last: Instant::now(),
+    };
+    eframe::run_native(
+        "CHIP-8",
+        eframe::NativeOptions::default(),
+        Box::new(|_| Box::new(app)),
+    )
+    .map_err(|e| e.to_string())
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let emulator = &mut self.emulator;
+        let dock = &mut self.dock;
+
+        // Keys go to the game unless a text field has the focus.
+        if !ctx.wants_keyboard_input() {
+            ctx.input(|input| {
+                for event in &input.events {
+                    if let egui::Event::Key {
+                        key,
+                        pressed,
+                        repeat: false,
+                        ..
+                    } = event
+                    {
+                        if let Some(key) = key_name(*key).and_then(|name| emulator.keys.key(name)) {
+                            match pressed {
+                                true => emulator.machine.press_key(key),
+                                false => emulator.machine.release_key(key),
+                            }
+                        }
+                    }
+                }
+            });
+        }
+        if let Some(gamepads) = &mut self.gamepads {
+            gamepads.poll(&mut emulator.machine);
+        }
+
+        // Frames run at 60Hz whatever the repaint rate; Debugger::run_frame
+        // does nothing while paused.
+        let now = Instant::now();
+        let frames = ((now - self.last).as_secs_f64() * 60.0) as u32;
+        if frames > 0 {
+            self.last = now;
+        }
+        for _ in 0..frames.min(4) {
+            if let Err(e) = emulator.debugger.run_frame(&mut emulator.machine) {
+                emulator.error = Some(e.to_string());
+                emulator.run(DebugCommand::Pause);
+                break;
+            }
+        }
+
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.menu_button("View", |ui| {
+                    for &tab in Tab::ALL.iter() {
+                        let found = dock.find_tab(&tab);
+                        let mut open = found.is_some();
+                        if ui.checkbox(&mut open, tab.title()).changed() {
+                            match found {
+                                Some(found) => {
+                                    dock.remove_tab(found);
+                                }
+                                None => dock.push_to_focused_leaf(tab),
+                            }
+                        }
+                    }
+                });
+                ui.separator();
+                emulator.controls(ui);
+            });
+        });
+
+        DockArea::new(dock)
+            .style(Style::from_egui(ctx.style().as_ref()))
+            .show(ctx, emulator);
+
+        ctx.request_repaint();
+    }
+}
+
+impl Emulator {
+    fn run(&mut self, command: DebugCommand) {
+        if let Err(e) = self.debugger.execute(command, &mut self.machine) {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    // Pause, continue and step, breakpoints, and the last error.
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        match self.debugger.is_paused() {
+            true => {
+                if ui.button("Continue").clicked() {
+                    self.error = None;
+                    self.run(DebugCommand::Continue);
+                }
+            }
+            false => {
+                if ui.button("Pause").clicked() {
+                    self.run(DebugCommand::Pause);
+                }
+            }
+        }
+        if ui.button("Step").clicked() {
+            self.run(DebugCommand::Step(1));
+        }
+        if ui.button("Reset").clicked() {
+            self.error = self.machine.reset().err().map(|e| e.to_string());
+        }
+
+        ui.separator();
+        ui.label("Break at");
+        ui.add(egui::TextEdit::singleline(&mut self.breakpoint).desired_width(48.0));
+        if ui.button("Set").clicked() {
+            if let Some(addr) = parse_address(&self.breakpoint) {
+                self.run(DebugCommand::Break(addr));
+            }
+        }
+        if ui.button("Clear").clicked() {
+            if let Some(addr) = parse_address(&self.breakpoint) {
+                self.run(DebugCommand::Delete(addr));
+            }
+        }
+
+        if let Some(error) = &self.error {
+            ui.separator();
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+
+    fn game(&mut self, ui: &mut egui::Ui) {
+        let resized = self.screen.update(self.machine.framebuffer());
+        let image = egui::ColorImage::from_rgba_unmultiplied(
+            [self.screen.width(), self.screen.height()],
+            self.screen.pixels(),
+        );
+        match &mut self.texture {
+            Some(texture) if !resized => texture.set(image, egui::TextureOptions::NEAREST),
+            _ => {
+                self.texture = Some(ui.ctx().load_texture(
+                    "screen",
+                    image,
+                    egui::TextureOptions::NEAREST,
+                ))
+            }
+        }
+
+        // As big as the tab allows, keeping the screen's shape.
+        let texture = self.texture.as_ref().expect("chip8-egui: no texture");
+        let size = texture.size_vec2();
+        let available = ui.available_size();
+        let scale = (available.x / size.x).min(available.y / size.y).max(1.0);
+        ui.centered_and_justified(|ui| {
+            ui.add(egui::Image::new((texture.id(), size * scale)));
+        });
+    }
+
+    // Keys are held for as long as the mouse button is down on them, and
+    // show as selected while held by any means.
+    fn keypad(&mut self, ui: &mut egui::Ui) {
+        let mut clicked = None;
+        egui::Grid::new("keypad").show(ui, |ui| {
+            for row in KEYPAD.iter() {
+                for &key in row {
+                    let held = self.machine.cpu().is_key_pressed(key);
+                    let button =
+                        egui::Button::new(egui::RichText::new(format!("{:X}", key)).monospace())
+                            .min_size(egui::vec2(32.0, 32.0))
+                            .selected(held);
+                    if ui.add(button).is_pointer_button_down_on() {
+                        clicked = Some(key);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+        if clicked != self.clicked {
+            if let Some(key) = self.clicked {
+                self.machine.release_key(key);
+            }
+            if let Some(key) = clicked {
+                self.machine.press_key(key);
+            }
+            self.clicked = clicked;
+        }
+    }
+
+    fn memory(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Address");
+            let field = ui.add(egui::TextEdit::singleline(&mut self.memory).desired_width(48.0));
+            if field.lost_focus() {
+                if let Some(addr) = parse_address(&self.memory) {
+                    self.run(DebugCommand::Memory(addr));
+                }
+            }
+        });
+        lines(ui, self.debugger.memory_panel(self.machine.cpu()));
+    }
+
+    fn quirks(&mut self, ui: &mut egui::Ui) {
+        let quirks = self.machine.cpu().quirks();
+        for &name in Quirks::NAMES.iter() {
+            let mut value = quirks.get(name).unwrap_or(false);
+            if ui.checkbox(&mut value, name).changed() {
+                self.run(DebugCommand::Quirk(name, value));
+            }
+        }
+    }
+}
+
+impl TabViewer for Emulator {
+    type Tab = Tab;
+
+    fn title(&mut self, tab: &mut Tab) -> egui::WidgetText {
+        tab.title().into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Tab) {
+        match tab {
+            Tab::Game => self.game(ui),
+            Tab::Registers => lines(ui, self.debugger.registers_panel(self.machine.cpu())),
+            Tab::Disassembly => lines(ui, self.debugger.disassembly_panel(self.machine.cpu())),
+            Tab::Memory => self.memory(ui),
+            Tab::Quirks => self.quirks(ui),
+            Tab::Keypad => self.keypad(ui),
+        }
+    }
+}
+
+// One of the debugger's text panels, in a monospace font.
+fn lines(ui: &mut egui::Ui, lines: Vec<String>) {
+    for line in lines {
+        ui.monospace(line);
+    }
+}
+
+// egui names the letter and digit keys by the character on them; the key
+// map wants that character.
+fn key_name(key: egui::Key) -> Option<char> {
+    let mut chars = key.name().chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+// Addresses are typed in hex, with or without a 0x prefix.
+fn parse_address(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.trim().trim_start_matches("0x"), 16).ok()
+}
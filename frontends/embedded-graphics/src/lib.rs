@@ -0,0 +1,148 @@
+// Draws a CHIP-8 screen on any embedded-graphics `DrawTarget`, e.g. an
+// SSD1306 or ST7789 driver:
+//
+//     let mut cpu = Cpu::with_seed(seed);
+//     cpu.load_program(ROM)?;
+//     loop {
+//         if cpu.run_frame(11)? {
+//             Screen::binary(cpu.framebuffer())
+//                 .scale(2)
+//                 .draw_dirty(&mut display)?;
+//         }
+//     }
+//
+// Every pixel goes through `fill_contiguous`, which drivers turn into one
+// window write rather than a command per pixel. `draw_dirty` sends only the
+// rows that changed in the last frame, for displays on a slow bus.
+//
+// Like the core without its std feature, this needs neither std nor a heap,
+// so it builds for microcontrollers.
+#![no_std]
+
+use chip8::Framebuffer;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+
+// A framebuffer placed on a display: its colours, how many display pixels
+// each CHIP-8 pixel covers, and where its top left corner goes.
+#[derive(Clone, Copy, Debug)]
+pub struct Screen<'a, C> {
+    framebuffer: &'a Framebuffer,
+    colors: [C; 4],
+    scale: u32,
+    top_left: Point,
+}
+
+impl<'a> Screen<'a, BinaryColor> {
+    // Lit pixels on, the rest off, for monochrome panels. Any lit plane
+    // counts.
+    pub fn binary(framebuffer: &'a Framebuffer) -> Self {
+        Screen::new(
+            framebuffer,
+            [
+                BinaryColor::Off,
+                BinaryColor::On,
+                BinaryColor::On,
+                BinaryColor::On,
+            ],
+        )
+    }
+}
+
+impl<'a, C: PixelColor> Screen<'a, C> {
+    // `colors` are indexed by pixel value: background, plane 1, plane 2,
+    // both planes.
+    pub fn new(framebuffer: &'a Framebuffer, colors: [C; 4]) -> Self {
+        Screen {
+            framebuffer,
+            colors,
+            scale: 1,
+            top_left: Point::zero(),
+        }
+    }
+
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn at(mut self, top_left: Point) -> Self {
+        self.top_left = top_left;
+        self
+    }
+
+    // Draw only the rows `Framebuffer::dirty_rows` reports.
+    pub fn draw_dirty<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        for y in self.framebuffer.dirty_rows() {
+            self.draw_rows(target, y, y + 1)?;
+        }
+        Ok(())
+    }
+
+    // Rows `start` to `end` (exclusive), scaled, in one contiguous fill.
+    fn draw_rows<D>(&self, target: &mut D, start: usize, end: usize) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let scale = self.scale as usize;
+        let width = self.framebuffer.width();
+        let area = Rectangle::new(
+            self.top_left + Point::new(0, (start * scale) as i32),
+            Size::new((width * scale) as u32, ((end - start) * scale) as u32),
+        );
+        let colors = (start * scale..end * scale).flat_map(move |y| {
+            (0..width * scale).map(move |x| {
+                self.colors[self.framebuffer.pixel(x / scale, y / scale) as usize & 3]
+            })
+        });
+        target.fill_contiguous(&area, colors)
+    }
+}
+
+impl<C> Dimensions for Screen<'_, C> {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(
+            self.top_left,
+            Size::new(
+                self.framebuffer.width() as u32 * self.scale,
+                self.framebuffer.height() as u32 * self.scale,
+            ),
+        )
+    }
+}
+
+impl<C: PixelColor> Drawable for Screen<'_, C> {
+    type Color = C;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        self.draw_rows(target, 0, self.framebuffer.height())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use embedded_graphics::mock_display::MockDisplay;
+
+    #[test]
+    fn test_draw_scaled() {
+        let mut fb = Framebuffer::with_size(2, 1);
+        fb.toggle(1, 0);
+
+        let mut display = MockDisplay::new();
+        Screen::binary(&fb).scale(2).draw(&mut display).unwrap();
+        display.assert_pattern(&[
+            "..##", //
+            "..##",
+        ]);
+    }
+}
@@ -0,0 +1,53 @@
+use chip8::{GamepadButton, GamepadMap, Machine};
+use gilrs::{Button, EventType, Gilrs};
+
+// Controllers through gilrs, pressing the hex keys `map` binds their
+// buttons to.
+pub struct Gamepads {
+    gilrs: Gilrs,
+    map: GamepadMap,
+}
+
+impl Gamepads {
+    pub fn new(map: GamepadMap) -> Result<Self, String> {
+        let gilrs = Gilrs::new().map_err(|e| e.to_string())?;
+        Ok(Gamepads { gilrs, map })
+    }
+
+    // Press and release keys for the button events since the last call.
+    pub fn poll(&mut self, machine: &mut Machine) {
+        while let Some(event) = self.gilrs.next_event() {
+            let (button, pressed) = match event.event {
+                EventType::ButtonPressed(button, _) => (button, true),
+                EventType::ButtonReleased(button, _) => (button, false),
+                _ => continue,
+            };
+            if let Some(key) = pad_button(button).and_then(|button| self.map.key(button)) {
+                match pressed {
+                    true => machine.press_key(key),
+                    false => machine.release_key(key),
+                }
+            }
+        }
+    }
+}
+
+// gilrs names buttons by position too; its shoulder buttons are the first
+// pair of triggers.
+fn pad_button(button: Button) -> Option<GamepadButton> {
+    Some(match button {
+        Button::DPadUp => GamepadButton::Up,
+        Button::DPadDown => GamepadButton::Down,
+        Button::DPadLeft => GamepadButton::Left,
+        Button::DPadRight => GamepadButton::Right,
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::West => GamepadButton::West,
+        Button::North => GamepadButton::North,
+        Button::LeftTrigger => GamepadButton::LeftShoulder,
+        Button::RightTrigger => GamepadButton::RightShoulder,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        _ => return None,
+    })
+}
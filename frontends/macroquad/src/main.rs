@@ -0,0 +1,133 @@
+// cpal and gilrs in a browser would need wasm-bindgen, which macroquad's
+// wasm build doesn't use, so there the game is silent and keyboard-only.
+#[cfg(not(target_arch = "wasm32"))]
+mod audio;
+#[cfg(not(target_arch = "wasm32"))]
+mod gamepad;
+
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+use chip8::test_roms::DISPLAY_TEST;
+use chip8::{KeyMap, Machine, Palette, RgbaTexture};
+use macroquad::prelude::*;
+
+fn window_conf() -> Conf {
+    Conf {
+        window_title: "CHIP-8".to_string(),
+        window_width: 640,
+        window_height: 320,
+        ..Default::default()
+    }
+}
+
+// The typical game loop: take the frame time macroquad measured, hand it to
+// Machine::update, draw what came out. Run with a ROM path, or with none
+// (and in a browser) to get the bundled display test.
+#[macroquad::main(window_conf)]
+async fn main() {
+    let mut machine = Machine::new();
+    let loaded = match env::args().nth(1) {
+        Some(path) => fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|rom| machine.load_rom(&rom).map_err(|e| e.to_string()))
+            .map_err(|e| format!("{}: {}", path, e)),
+        None => machine.load_rom(DISPLAY_TEST).map_err(|e| e.to_string()),
+    };
+    let mut error = loaded.err();
+    // Without a sound device the game still runs, silently.
+    #[cfg(not(target_arch = "wasm32"))]
+    match audio::CpalAudio::new() {
+        Ok(audio) => machine.set_audio(Box::new(audio)),
+        Err(e) => eprintln!("chip8-macroquad: no sound: {}", e),
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut gamepads = match gamepad::Gamepads::new(chip8::GamepadMap::standard()) {
+        Ok(gamepads) => Some(gamepads),
+        Err(e) => {
+            eprintln!("chip8-macroquad: no gamepads: {}", e);
+            None
+        }
+    };
+
+    let keys = KeyMap::standard();
+    let mut screen = RgbaTexture::new(Palette::MONOCHROME.rgba());
+    let mut texture: Option<Texture2D> = None;
+
+    loop {
+        if is_key_pressed(KeyCode::Escape) {
+            break;
+        }
+        for code in get_keys_pressed() {
+            if let Some(key) = key_name(code).and_then(|name| keys.key(name)) {
+                machine.press_key(key);
+            }
+        }
+        for code in get_keys_released() {
+            if let Some(key) = key_name(code).and_then(|name| keys.key(name)) {
+                machine.release_key(key);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(gamepads) = &mut gamepads {
+            gamepads.poll(&mut machine);
+        }
+
+        if error.is_none() {
+            let elapsed = Duration::from_secs_f32(get_frame_time());
+            if let Err(e) = machine.update(elapsed) {
+                error = Some(e.to_string());
+            }
+        }
+
+        // A new texture when the program switches resolution, otherwise
+        // just new pixels.
+        let resized = screen.update(machine.framebuffer());
+        let (width, height) = (screen.width() as u16, screen.height() as u16);
+        match &texture {
+            Some(texture) if !resized => {
+                texture.update_from_bytes(width as u32, height as u32, screen.pixels())
+            }
+            _ => {
+                let new = Texture2D::from_rgba8(width, height, screen.pixels());
+                new.set_filter(FilterMode::Nearest);
+                texture = Some(new);
+            }
+        }
+
+        clear_background(BLACK);
+        if let Some(texture) = &texture {
+            draw_texture_ex(
+                texture,
+                0.0,
+                0.0,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(screen_width(), screen_height())),
+                    ..Default::default()
+                },
+            );
+        }
+        if let Some(error) = &error {
+            draw_text(error, 8.0, screen_height() - 12.0, 20.0, RED);
+        }
+
+        if machine.has_exited() {
+            break;
+        }
+        next_frame().await;
+    }
+}
+
+// The character on a letter or digit key, which is how the key map names
+// keys. macroquad's names for them are the letter itself and Key0 to Key9.
+fn key_name(code: KeyCode) -> Option<char> {
+    let name = format!("{:?}", code);
+    let name = name.strip_prefix("Key").unwrap_or(&name);
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_lowercase()),
+        _ => None,
+    }
+}
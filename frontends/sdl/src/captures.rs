@@ -0,0 +1,70 @@
+use std::fs;
+use std::time::SystemTime;
+
+use chip8::{
+    recording_name, screenshot_name, screenshot_png, Machine, Palette, Recorder, RecordingFormat,
+};
+
+// Screenshots and recordings, saved to the current directory.
+pub struct Captures {
+    palette: Palette,
+    format: RecordingFormat,
+    // The recording going on, the file it goes to, and the machine's frame
+    // count when it last captured.
+    recording: Option<(String, Recorder, u64)>,
+}
+
+impl Captures {
+    pub fn new(palette: Palette, format: RecordingFormat) -> Self {
+        Captures {
+            palette,
+            format,
+            recording: None,
+        }
+    }
+
+    // Save the screen with every pixel `scale` pixels square, as big as it
+    // is on screen. Returns what happened, to show the user.
+    pub fn screenshot(&self, machine: &Machine, scale: usize) -> String {
+        let name = screenshot_name(SystemTime::now());
+        let png = screenshot_png(machine.framebuffer(), &self.palette, scale);
+        match fs::write(&name, png) {
+            Ok(()) => format!("saved {}", name),
+            Err(e) => format!("{}: {}", name, e),
+        }
+    }
+
+    // Start recording at `scale`, or save the recording going on. Returns
+    // what happened, to show the user.
+    pub fn toggle_recording(&mut self, machine: &Machine, scale: usize) -> String {
+        if let Some(message) = self.stop_recording() {
+            return message;
+        }
+        let name = recording_name(SystemTime::now(), self.format);
+        let message = format!("recording {}", name);
+        let recorder = Recorder::new(self.format, self.palette, scale);
+        self.recording = Some((name, recorder, machine.frame_count()));
+        message
+    }
+
+    // Call after running the machine: records the screen once for every
+    // frame run since the last call, so the recording keeps 60Hz time
+    // whatever the window's refresh rate.
+    pub fn capture(&mut self, machine: &Machine) {
+        if let Some((_, recorder, frames)) = &mut self.recording {
+            for _ in *frames..machine.frame_count() {
+                recorder.capture(machine.framebuffer());
+            }
+            *frames = machine.frame_count();
+        }
+    }
+
+    // Save the recording going on, if any.
+    pub fn stop_recording(&mut self) -> Option<String> {
+        let (name, recorder, _) = self.recording.take()?;
+        Some(match fs::write(&name, recorder.finish()) {
+            Ok(()) => format!("saved {}", name),
+            Err(e) => format!("{}: {}", name, e),
+        })
+    }
+}
@@ -0,0 +1,387 @@
+mod captures;
+
+use std::collections::VecDeque;
+use std::env;
+use std::process;
+use std::time::Instant;
+
+use chip8::{
+    Audio, GamepadButton, GamepadMap, KeyMap, Machine, Palette, PhosphorFilter, RecordingFormat,
+    RgbaTexture, CHIP8_HEIGHT, CHIP8_WIDTH,
+};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::{Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::video::FullscreenType;
+
+use crate::captures::Captures;
+
+const USAGE: &str = "usage: chip8-sdl [--speed CYCLES] [--scale PIXELS]
+                 [--palette mono|green|amber|octo|lcd|COLORS]
+                 [--gamepad BUTTON=KEY,...] [--phosphor FRAMES]
+                 [--record gif|apng] ROM";
+
+// Window pixels per CHIP-8 pixel unless --scale says otherwise.
+const DEFAULT_SCALE: u32 = 10;
+
+// Besides the keypad keys: F11 switches fullscreen on and off, - and =
+// make the window a whole multiple of the screen smaller or larger, F12
+// saves a screenshot to the current directory, and F10 starts recording
+// the screen and, pressed again, saves the recording there.
+const FULLSCREEN_KEY: Keycode = Keycode::F11;
+const SCREENSHOT_KEY: Keycode = Keycode::F12;
+const RECORD_KEY: Keycode = Keycode::F10;
+const SMALLER_KEY: Keycode = Keycode::Minus;
+const LARGER_KEY: Keycode = Keycode::Equals;
+
+// Samples the queue may hold before new ones are dropped, in 60Hz frames.
+// Enough to ride over a late frame without the sound lagging behind the
+// game.
+const MAX_QUEUED_FRAMES: usize = 6;
+
+// The audio callback: it plays the queued samples, and silence while the
+// queue is empty.
+struct Samples(VecDeque<f32>);
+
+impl AudioCallback for Samples {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.0.pop_front().unwrap_or(0.0);
+        }
+    }
+}
+
+// The machine's samples on an SDL audio device. The machine's
+// `PatternSynth` renders the buzzer, XO-CHIP patterns included, and fades
+// it in and out, so the beep edges need no handling here.
+struct SdlAudio {
+    device: AudioDevice<Samples>,
+}
+
+impl Audio for SdlAudio {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+
+    fn sample_rate(&self) -> Option<u32> {
+        Some(self.device.spec().freq as u32)
+    }
+
+    fn queue_samples(&mut self, samples: &[f32]) {
+        let max = self.device.spec().freq as usize / 60 * MAX_QUEUED_FRAMES;
+        let mut queue = self.device.lock();
+        let room = max.saturating_sub(queue.0.len());
+        queue.0.extend(samples.iter().take(room));
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(message) = run(&args) {
+        eprintln!("chip8-sdl: {}", message);
+        process::exit(2);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut speed = None;
+    let mut scale = DEFAULT_SCALE;
+    let mut palette = Palette::MONOCHROME;
+    let mut pad_map = GamepadMap::standard();
+    let mut phosphor = None;
+    let mut format = RecordingFormat::Gif;
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => {
+                let value = args.next().ok_or(USAGE)?;
+                speed = Some(value.parse().map_err(|_| format!("bad speed: {}", value))?);
+            }
+            "--scale" => {
+                let value = args.next().ok_or(USAGE)?;
+                scale = value
+                    .parse()
+                    .ok()
+                    .filter(|&scale| scale > 0)
+                    .ok_or_else(|| format!("bad scale: {}", value))?;
+            }
+            "--palette" => {
+                let value = args.next().ok_or(USAGE)?;
+                palette = Palette::from_name(value)
+                    .or_else(|| Palette::parse(value))
+                    .ok_or_else(|| format!("bad palette: {}", value))?;
+            }
+            "--gamepad" => {
+                let value = args.next().ok_or(USAGE)?;
+                pad_map = GamepadMap::parse(value)
+                    .ok_or_else(|| format!("bad gamepad map: {}", value))?;
+            }
+            "--phosphor" => {
+                let value = args.next().ok_or(USAGE)?;
+                let frames = value
+                    .parse()
+                    .ok()
+                    .filter(|&frames| frames > 0)
+                    .ok_or_else(|| format!("bad phosphor: {}", value))?;
+                phosphor = Some(PhosphorFilter::new(frames));
+            }
+            "--record" => {
+                let value = args.next().ok_or(USAGE)?;
+                format = RecordingFormat::from_name(value)
+                    .ok_or_else(|| format!("unknown recording format: {}", value))?;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let mut machine = Machine::new();
+    machine
+        .load_rom_file(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    if let Some(speed) = speed {
+        machine.set_cycles_per_frame(speed);
+    }
+    let keys = KeyMap::standard();
+
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+    let window = video
+        .window(
+            "CHIP-8",
+            CHIP8_WIDTH as u32 * scale,
+            CHIP8_HEIGHT as u32 * scale,
+        )
+        .position_centered()
+        .resizable()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut canvas = window
+        .into_canvas()
+        .present_vsync()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let creator = canvas.texture_creator();
+
+    // Without a sound device the game still runs, silently.
+    match open_audio(&sdl) {
+        Ok(audio) => machine.set_audio(Box::new(audio)),
+        Err(e) => eprintln!("chip8-sdl: no sound: {}", e),
+    }
+
+    // SDL reports the controllers already plugged in as added too. They
+    // send events only while open, so they are kept here.
+    let controllers = sdl.game_controller()?;
+    let mut pads: Vec<GameController> = Vec::new();
+
+    // The texture is made again whenever the program switches resolution.
+    let mut screen = RgbaTexture::new(palette.rgba());
+    screen.set_phosphor(phosphor);
+    let mut captures = Captures::new(palette, format);
+    let mut texture = None;
+    let mut events = sdl.event_pump()?;
+    let mut last = Instant::now();
+
+    'running: loop {
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(FULLSCREEN_KEY),
+                    repeat: false,
+                    ..
+                } => {
+                    let window = canvas.window_mut();
+                    let fullscreen = match window.fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off,
+                    };
+                    window.set_fullscreen(fullscreen)?;
+                }
+                Event::KeyDown {
+                    keycode: Some(code @ (SCREENSHOT_KEY | RECORD_KEY)),
+                    repeat: false,
+                    ..
+                } => {
+                    let shown = whole_scale(
+                        canvas.output_size()?,
+                        screen.width() as u32,
+                        screen.height() as u32,
+                    ) as usize;
+                    let message = match code {
+                        SCREENSHOT_KEY => captures.screenshot(&machine, shown),
+                        _ => captures.toggle_recording(&machine, shown),
+                    };
+                    eprintln!("chip8-sdl: {}", message);
+                }
+                Event::KeyDown {
+                    keycode: Some(code @ (SMALLER_KEY | LARGER_KEY)),
+                    ..
+                } => {
+                    scale = match code {
+                        SMALLER_KEY => (scale - 1).max(1),
+                        _ => scale + 1,
+                    };
+                    let window = canvas.window_mut();
+                    if window.fullscreen_state() == FullscreenType::Off {
+                        window
+                            .set_size(
+                                screen.width() as u32 * scale,
+                                screen.height() as u32 * scale,
+                            )
+                            .map_err(|e| e.to_string())?;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(key) = key_name(keycode).and_then(|name| keys.key(name)) {
+                        machine.press_key(key);
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(key) = key_name(keycode).and_then(|name| keys.key(name)) {
+                        machine.release_key(key);
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => match controllers.open(which) {
+                    Ok(pad) => pads.push(pad),
+                    Err(e) => eprintln!("chip8-sdl: gamepad {}: {}", which, e),
+                },
+                Event::ControllerButtonDown { button, .. } => {
+                    if let Some(key) = pad_button(button).and_then(|button| pad_map.key(button)) {
+                        machine.press_key(key);
+                    }
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    if let Some(key) = pad_button(button).and_then(|button| pad_map.key(button)) {
+                        machine.release_key(key);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let now = Instant::now();
+        machine
+            .update(now - last)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        last = now;
+        captures.capture(&machine);
+
+        if screen.update(machine.framebuffer()) || texture.is_none() {
+            texture = Some(
+                creator
+                    .create_texture_streaming(
+                        PixelFormatEnum::RGBA32,
+                        screen.width() as u32,
+                        screen.height() as u32,
+                    )
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        if let Some(texture) = texture.as_mut() {
+            texture
+                .update(None, screen.pixels(), screen.width() * 4)
+                .map_err(|e| e.to_string())?;
+            let area = letterbox(
+                canvas.output_size()?,
+                screen.width() as u32,
+                screen.height() as u32,
+            );
+            canvas.copy(texture, None, area)?;
+        }
+        canvas.present();
+
+        if machine.has_exited() {
+            break;
+        }
+    }
+
+    if let Some(message) = captures.stop_recording() {
+        eprintln!("chip8-sdl: {}", message);
+    }
+    Ok(())
+}
+
+// The largest whole multiple of the `width` x `height` screen that fits
+// the window, centred, so that every CHIP-8 pixel comes out the same size.
+fn letterbox(window: (u32, u32), width: u32, height: u32) -> Rect {
+    let scale = whole_scale(window, width, height);
+    let (w, h) = (width * scale, height * scale);
+    Rect::new(
+        (window.0 as i32 - w as i32) / 2,
+        (window.1 as i32 - h as i32) / 2,
+        w,
+        h,
+    )
+}
+
+// Window pixels per CHIP-8 pixel when the `width` x `height` screen is
+// letterboxed.
+fn whole_scale(window: (u32, u32), width: u32, height: u32) -> u32 {
+    (window.0 / width).min(window.1 / height).max(1)
+}
+
+fn open_audio(sdl: &sdl2::Sdl) -> Result<SdlAudio, String> {
+    let spec = AudioSpecDesired {
+        freq: Some(44_100),
+        channels: Some(1),
+        samples: None,
+    };
+    let device = sdl
+        .audio()?
+        .open_playback(None, &spec, |_| Samples(VecDeque::new()))?;
+    device.resume();
+    Ok(SdlAudio { device })
+}
+
+// SDL names the letter and digit keys by the character on them; the key
+// map wants that character.
+fn key_name(keycode: Keycode) -> Option<char> {
+    let name = keycode.name();
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+// SDL names the face buttons after an Xbox pad's labels, A at the bottom
+// and B on the right.
+fn pad_button(button: Button) -> Option<GamepadButton> {
+    Some(match button {
+        Button::DPadUp => GamepadButton::Up,
+        Button::DPadDown => GamepadButton::Down,
+        Button::DPadLeft => GamepadButton::Left,
+        Button::DPadRight => GamepadButton::Right,
+        Button::A => GamepadButton::South,
+        Button::B => GamepadButton::East,
+        Button::X => GamepadButton::West,
+        Button::Y => GamepadButton::North,
+        Button::LeftShoulder => GamepadButton::LeftShoulder,
+        Button::RightShoulder => GamepadButton::RightShoulder,
+        Button::Back => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        _ => return None,
+    })
+}
@@ -0,0 +1,146 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use chip8::{half_blocks, DebugCommand, Debugger, Machine, FRAME_DURATION};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{Frame, Terminal};
+
+use crate::{is_quit, Keys};
+
+const HELP: &str = "step [N], continue, pause, break/delete ADDR, memory ADDR, quirk NAME on/off";
+
+// Run with the debugger: the screen and memory on the left, registers with
+// the stack and the disassembly around PC on the right. Typing ':' opens
+// the command line at the bottom and Enter runs the command, Esc drops it;
+// other keys go to the keypad, as in `chip8 play --debug`.
+pub fn run(machine: &mut Machine, keys: &mut Keys) -> Result<(), String> {
+    let mut terminal =
+        Terminal::new(CrosstermBackend::new(io::stdout())).map_err(|e| e.to_string())?;
+    let mut debugger = Debugger::new();
+    let mut command: Option<String> = None;
+    let mut message = String::from("type : for a command");
+
+    while !machine.has_exited() {
+        while event::poll(Duration::from_secs(0)).map_err(|e| e.to_string())? {
+            let key = match event::read().map_err(|e| e.to_string())? {
+                Event::Key(key) => key,
+                _ => continue,
+            };
+            let line = match command.as_mut() {
+                Some(line) => line,
+                None if is_quit(&key) => return Ok(()),
+                None if key.code == KeyCode::Char(':') => {
+                    if key.kind != KeyEventKind::Release {
+                        command = Some(String::new());
+                    }
+                    continue;
+                }
+                None => {
+                    keys.key(key, machine);
+                    continue;
+                }
+            };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            match key.code {
+                KeyCode::Enter => {
+                    message = match DebugCommand::parse(line) {
+                        Some(parsed) => match debugger.execute(parsed, machine) {
+                            Ok(()) => format!("ok: {}", line),
+                            Err(e) => e.to_string(),
+                        },
+                        None => format!("unknown command: {} ({})", line, HELP),
+                    };
+                    command = None;
+                }
+                KeyCode::Esc => command = None,
+                KeyCode::Backspace => {
+                    line.pop();
+                }
+                KeyCode::Char(c) => line.push(c),
+                _ => {}
+            }
+        }
+        keys.frame(machine);
+
+        // A failing program pauses where it failed, to be looked at.
+        if let Err(e) = debugger.run_frame(machine) {
+            message = e.to_string();
+            let _ = debugger.execute(DebugCommand::Pause, machine);
+        }
+
+        let prompt = match &command {
+            Some(line) => format!(":{}", line),
+            None => message.clone(),
+        };
+        terminal
+            .draw(|frame| draw(frame, machine, &debugger, &prompt))
+            .map_err(|e| e.to_string())?;
+
+        thread::sleep(FRAME_DURATION);
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, machine: &Machine, debugger: &Debugger, prompt: &str) {
+    let cpu = machine.cpu();
+    // Stepping stops mid-frame, so this is the screen being drawn rather
+    // than the last finished one.
+    let fb = cpu.framebuffer();
+    let screen: Vec<Line> = half_blocks(fb)
+        .split("\r\n")
+        .map(|row| Line::from(row.to_string()))
+        .collect();
+    let registers = debugger.registers_panel(cpu);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(frame.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Length(fb.width() as u16 + 2),
+            Constraint::Min(0),
+        ])
+        .split(rows[0]);
+    let left = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(screen.len() as u16 + 2),
+            Constraint::Min(0),
+        ])
+        .split(columns[0]);
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(registers.len() as u16 + 2),
+            Constraint::Min(0),
+        ])
+        .split(columns[1]);
+
+    frame.render_widget(Paragraph::new(screen).block(titled("Screen")), left[0]);
+    frame.render_widget(panel("Memory", debugger.memory_panel(cpu)), left[1]);
+    frame.render_widget(panel("Registers", registers), right[0]);
+    frame.render_widget(
+        panel("Disassembly", debugger.disassembly_panel(cpu)),
+        right[1],
+    );
+    frame.render_widget(Paragraph::new(prompt), rows[1]);
+}
+
+// One of the debugger's text panels in a box.
+fn panel(title: &str, lines: Vec<String>) -> Paragraph {
+    let lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
+    Paragraph::new(lines).block(titled(title))
+}
+
+fn titled(title: &str) -> Block {
+    Block::default().borders(Borders::ALL).title(title)
+}
@@ -0,0 +1,199 @@
+// CHIP-8 in a terminal, on crossterm: the screen in half blocks, the
+// keypad on the keyboard and the buzzer on the terminal bell. Unlike
+// `chip8 play`, which sets the terminal up with stty, this also runs in
+// Windows consoles, and in terminals with the kitty keyboard protocol it
+// hears keys coming up, so they are held for exactly as long as they are.
+//
+// With --debug the screen shares the terminal with ratatui panes for the
+// debugger, see the debug module.
+
+mod debug;
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use std::time::Duration;
+
+use chip8::{half_blocks, KeyMap, Machine, TerminalBell, KEY_HOLD_FRAMES};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+const USAGE: &str = "usage: chip8-tui [--speed CYCLES] [--debug] ROM";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(message) = run(&args) {
+        eprintln!("chip8-tui: {}", message);
+        process::exit(2);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut speed = None;
+    let mut debug = false;
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => {
+                let value = args.next().ok_or(USAGE)?;
+                speed = Some(value.parse().map_err(|_| format!("bad speed: {}", value))?);
+            }
+            "--debug" => debug = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let mut machine = Machine::new();
+    machine
+        .load_rom_file(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    if let Some(speed) = speed {
+        machine.set_cycles_per_frame(speed);
+    }
+    machine.set_audio(Box::new(TerminalBell::new(io::stdout())));
+
+    let terminal = Terminal::enter().map_err(|e| e.to_string())?;
+    let mut keys = Keys::new(terminal.releases);
+    let result = match debug {
+        true => debug::run(&mut machine, &mut keys),
+        false => play(&mut machine, &mut keys),
+    };
+    result.map_err(|e| format!("{}: {}", path, e))
+}
+
+// The terminal in raw mode on the alternate screen. Dropping it puts the
+// terminal back as it was, on errors too.
+struct Terminal {
+    // Whether the terminal reports keys coming up.
+    releases: bool,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Terminal { releases })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// The keypad on the standard keys. Unless the terminal reports releases,
+// a key counts as released once it hasn't come in for KEY_HOLD_FRAMES, as
+// with `TerminalInput`.
+struct Keys {
+    map: KeyMap,
+    releases: bool,
+    // Frames each key has left before it counts as released.
+    held: [u8; 16],
+}
+
+impl Keys {
+    fn new(releases: bool) -> Self {
+        Keys {
+            map: KeyMap::standard(),
+            releases,
+            held: [0; 16],
+        }
+    }
+
+    // Press or release the keypad key `key` is on, if any.
+    fn key(&mut self, key: KeyEvent, machine: &mut Machine) {
+        let hex = match key.code {
+            KeyCode::Char(c) => self.map.key(c.to_ascii_lowercase()),
+            _ => None,
+        };
+        match (hex, key.kind) {
+            (Some(hex), KeyEventKind::Release) => machine.release_key(hex),
+            (Some(hex), _) => {
+                machine.press_key(hex);
+                self.held[hex as usize] = KEY_HOLD_FRAMES;
+            }
+            (None, _) => {}
+        }
+    }
+
+    // Call once a frame: releases the keys that stopped coming in.
+    fn frame(&mut self, machine: &mut Machine) {
+        if self.releases {
+            return;
+        }
+        for (key, frames) in self.held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    machine.release_key(key as u8);
+                }
+            }
+        }
+    }
+}
+
+// Esc and Ctrl-C quit.
+fn is_quit(key: &KeyEvent) -> bool {
+    let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+    key.code == KeyCode::Esc || ctrl_c
+}
+
+// Run until the program exits or a quit key is pressed.
+fn play(machine: &mut Machine, keys: &mut Keys) -> Result<(), String> {
+    // The resolution last drawn; a new one clears the screen first.
+    let mut drawn = None;
+    let mut out = io::stdout();
+
+    while !machine.has_exited() {
+        while event::poll(Duration::from_secs(0)).map_err(|e| e.to_string())? {
+            let key = match event::read().map_err(|e| e.to_string())? {
+                Event::Key(key) => key,
+                Event::Resize(..) => {
+                    drawn = None;
+                    continue;
+                }
+                _ => continue,
+            };
+            if is_quit(&key) {
+                return Ok(());
+            }
+            keys.key(key, machine);
+        }
+        keys.frame(machine);
+
+        let changed = machine.run_paced().map_err(|e| e.to_string())?;
+        let fb = machine.framebuffer();
+        let resolution = Some((fb.width(), fb.height()));
+        if changed || drawn != resolution {
+            if drawn != resolution {
+                queue!(out, Clear(ClearType::All)).map_err(|e| e.to_string())?;
+                drawn = resolution;
+            }
+            queue!(out, MoveTo(0, 0), Print(half_blocks(fb))).map_err(|e| e.to_string())?;
+            out.flush().map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
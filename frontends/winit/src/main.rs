@@ -0,0 +1,270 @@
+mod audio;
+mod captures;
+mod gamepad;
+
+use std::env;
+use std::process;
+use std::time::Instant;
+
+use chip8::{
+    GamepadMap, KeyMap, Machine, Palette, PhosphorFilter, RecordingFormat, RgbaTexture,
+    CHIP8_HEIGHT, CHIP8_WIDTH,
+};
+use pixels::wgpu::Color;
+use pixels::{PixelsBuilder, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::{Fullscreen, WindowBuilder};
+
+use audio::CpalAudio;
+use captures::Captures;
+use gamepad::Gamepads;
+
+const USAGE: &str = "usage: chip8-winit [--speed CYCLES] [--scale PIXELS]
+                   [--palette mono|green|amber|octo|lcd|COLORS]
+                   [--gamepad BUTTON=KEY,...] [--phosphor FRAMES]
+                   [--record gif|apng] ROM";
+
+// Window pixels per CHIP-8 pixel unless --scale says otherwise.
+const DEFAULT_SCALE: u32 = 10;
+
+// Besides the keypad keys: F11 switches fullscreen on and off, - and =
+// make the window a whole multiple of the screen smaller or larger, F12
+// saves a screenshot to the current directory, and F10 starts recording
+// the screen and, pressed again, saves the recording there.
+const FULLSCREEN_KEY: VirtualKeyCode = VirtualKeyCode::F11;
+const SCREENSHOT_KEY: VirtualKeyCode = VirtualKeyCode::F12;
+const RECORD_KEY: VirtualKeyCode = VirtualKeyCode::F10;
+const SMALLER_KEY: VirtualKeyCode = VirtualKeyCode::Minus;
+const LARGER_KEY: VirtualKeyCode = VirtualKeyCode::Equals;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(message) = run(&args) {
+        eprintln!("chip8-winit: {}", message);
+        process::exit(2);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let mut speed = None;
+    let mut scale = DEFAULT_SCALE;
+    let mut palette = Palette::MONOCHROME;
+    let mut pad_map = GamepadMap::standard();
+    let mut phosphor = None;
+    let mut format = RecordingFormat::Gif;
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => {
+                let value = args.next().ok_or(USAGE)?;
+                speed = Some(value.parse().map_err(|_| format!("bad speed: {}", value))?);
+            }
+            "--scale" => {
+                let value = args.next().ok_or(USAGE)?;
+                scale = value
+                    .parse()
+                    .ok()
+                    .filter(|&scale| scale > 0)
+                    .ok_or_else(|| format!("bad scale: {}", value))?;
+            }
+            "--palette" => {
+                let value = args.next().ok_or(USAGE)?;
+                palette = Palette::from_name(value)
+                    .or_else(|| Palette::parse(value))
+                    .ok_or_else(|| format!("bad palette: {}", value))?;
+            }
+            "--gamepad" => {
+                let value = args.next().ok_or(USAGE)?;
+                pad_map = GamepadMap::parse(value)
+                    .ok_or_else(|| format!("bad gamepad map: {}", value))?;
+            }
+            "--phosphor" => {
+                let value = args.next().ok_or(USAGE)?;
+                let frames = value
+                    .parse()
+                    .ok()
+                    .filter(|&frames| frames > 0)
+                    .ok_or_else(|| format!("bad phosphor: {}", value))?;
+                phosphor = Some(PhosphorFilter::new(frames));
+            }
+            "--record" => {
+                let value = args.next().ok_or(USAGE)?;
+                format = RecordingFormat::from_name(value)
+                    .ok_or_else(|| format!("unknown recording format: {}", value))?;
+            }
+            _ if path.is_none() => path = Some(arg.clone()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let mut machine = Machine::new();
+    machine
+        .load_rom_file(&path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    if let Some(speed) = speed {
+        machine.set_cycles_per_frame(speed);
+    }
+    // Without a sound device the game still runs, silently.
+    match CpalAudio::new() {
+        Ok(audio) => machine.set_audio(Box::new(audio)),
+        Err(e) => eprintln!("chip8-winit: no sound: {}", e),
+    }
+    let keys = KeyMap::standard();
+    let mut gamepads = match Gamepads::new(pad_map) {
+        Ok(gamepads) => Some(gamepads),
+        Err(e) => {
+            eprintln!("chip8-winit: no gamepads: {}", e);
+            None
+        }
+    };
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("CHIP-8")
+        .with_inner_size(LogicalSize::new(
+            CHIP8_WIDTH as u32 * scale,
+            CHIP8_HEIGHT as u32 * scale,
+        ))
+        .build(&event_loop)
+        .map_err(|e| e.to_string())?;
+    let size = window.inner_size();
+    let surface = SurfaceTexture::new(size.width, size.height, &window);
+    // pixels draws the screen at the largest whole multiple that fits the
+    // surface, centred, and clears the rest.
+    let mut pixels = PixelsBuilder::new(CHIP8_WIDTH as u32, CHIP8_HEIGHT as u32, surface)
+        .clear_color(Color::BLACK)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut screen = RgbaTexture::new(palette.rgba());
+    screen.set_phosphor(phosphor);
+    let mut captures = Captures::new(palette, format);
+    let mut last = Instant::now();
+
+    // The event loop never returns, so errors from here on are reported
+    // before it exits.
+    event_loop.run(move |event, _, control_flow| {
+        let result = match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => {
+                    *control_flow = ControlFlow::Exit;
+                    Ok(())
+                }
+                // Minimizing reports a zero size, which the surface can't
+                // take; it keeps the old one until the window comes back.
+                // Any other size letterboxes the screen at a whole multiple.
+                WindowEvent::Resized(size) if size.width == 0 || size.height == 0 => Ok(()),
+                WindowEvent::Resized(size) => pixels
+                    .resize_surface(size.width, size.height)
+                    .map_err(|e| e.to_string()),
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(code),
+                            state,
+                            ..
+                        },
+                    ..
+                } => {
+                    if state == ElementState::Pressed {
+                        match code {
+                            VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
+                            FULLSCREEN_KEY => window.set_fullscreen(match window.fullscreen() {
+                                Some(_) => None,
+                                None => Some(Fullscreen::Borderless(None)),
+                            }),
+                            SCREENSHOT_KEY | RECORD_KEY => {
+                                // The scale pixels letterboxes the screen at.
+                                let size = window.inner_size();
+                                let shown = (size.width / screen.width() as u32)
+                                    .min(size.height / screen.height() as u32)
+                                    .max(1) as usize;
+                                let message = match code {
+                                    SCREENSHOT_KEY => captures.screenshot(&machine, shown),
+                                    _ => captures.toggle_recording(&machine, shown),
+                                };
+                                eprintln!("chip8-winit: {}", message);
+                            }
+                            SMALLER_KEY | LARGER_KEY if window.fullscreen().is_none() => {
+                                scale = match code {
+                                    SMALLER_KEY => (scale - 1).max(1),
+                                    _ => scale + 1,
+                                };
+                                window.set_inner_size(LogicalSize::new(
+                                    screen.width() as u32 * scale,
+                                    screen.height() as u32 * scale,
+                                ));
+                            }
+                            _ => {}
+                        }
+                    }
+                    match (key_name(code).and_then(|name| keys.key(name)), state) {
+                        (Some(key), ElementState::Pressed) => machine.press_key(key),
+                        (Some(key), ElementState::Released) => machine.release_key(key),
+                        (None, _) => {}
+                    }
+                    Ok(())
+                }
+                _ => Ok(()),
+            },
+            Event::MainEventsCleared => {
+                if let Some(gamepads) = &mut gamepads {
+                    gamepads.poll(&mut machine);
+                }
+                let now = Instant::now();
+                let result = machine.update(now - last);
+                last = now;
+                captures.capture(&machine);
+                if machine.has_exited() {
+                    *control_flow = ControlFlow::Exit;
+                }
+                window.request_redraw();
+                result.map(|_| ()).map_err(|e| format!("{}: {}", path, e))
+            }
+            Event::RedrawRequested(_) => {
+                let resized = screen.update(machine.framebuffer());
+                let result = if resized {
+                    pixels
+                        .resize_buffer(screen.width() as u32, screen.height() as u32)
+                        .map_err(|e| e.to_string())
+                } else {
+                    Ok(())
+                };
+                result.and_then(|_| {
+                    pixels.frame_mut().copy_from_slice(screen.pixels());
+                    pixels.render().map_err(|e| e.to_string())
+                })
+            }
+            // Exiting saves a recording still going on.
+            Event::LoopDestroyed => {
+                if let Some(message) = captures.stop_recording() {
+                    eprintln!("chip8-winit: {}", message);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(message) = result {
+            eprintln!("chip8-winit: {}", message);
+            *control_flow = ControlFlow::ExitWithCode(1);
+        }
+    })
+}
+
+// The character on a letter or digit key, which is how the key map names
+// keys. winit's names for them are the letter itself and Key0 to Key9.
+fn key_name(code: VirtualKeyCode) -> Option<char> {
+    let name = format!("{:?}", code);
+    let name = name.strip_prefix("Key").unwrap_or(&name);
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_lowercase()),
+        _ => None,
+    }
+}
@@ -0,0 +1,461 @@
+#include "chip8_screen.h"
+
+#include <cstring>
+
+#include <godot_cpp/classes/config_file.hpp>
+#include <godot_cpp/classes/dir_access.hpp>
+#include <godot_cpp/classes/display_server.hpp>
+#include <godot_cpp/classes/engine.hpp>
+#include <godot_cpp/classes/file_access.hpp>
+#include <godot_cpp/classes/input_event_key.hpp>
+#include <godot_cpp/classes/material.hpp>
+#include <godot_cpp/classes/shader.hpp>
+#include <godot_cpp/classes/shader_material.hpp>
+#include <godot_cpp/classes/time.hpp>
+#include <godot_cpp/classes/viewport.hpp>
+#include <godot_cpp/core/class_db.hpp>
+#include <godot_cpp/variant/utility_functions.hpp>
+
+using namespace godot;
+
+// Frames run at most per _process, so a stall doesn't turn into a burst.
+static const int MAX_FRAMES = 4;
+static const double FRAME_TIME = 1.0 / 60.0;
+// Where remember_window keeps the window size.
+static const char *WINDOW_CONFIG = "user://chip8_window.cfg";
+static const char *SCREENSHOT_DIR = "user://screenshots";
+static const char *RECORDING_DIR = "user://recordings";
+
+// The CRT look, the same as CrtScreen's in web/chip8.js: scanlines
+// between the rows, a slight barrel curvature and some bloom.
+static const char *CRT_SHADER = R"(
+shader_type canvas_item;
+
+void fragment() {
+    vec2 size = 1.0 / TEXTURE_PIXEL_SIZE;
+    vec2 uv = UV * 2.0 - 1.0;
+    uv *= 1.0 + 0.03 * dot(uv, uv);
+    uv = uv * 0.5 + 0.5;
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        COLOR = vec4(0.0, 0.0, 0.0, 1.0);
+    } else {
+        vec2 texel = TEXTURE_PIXEL_SIZE;
+        vec3 color = texture(TEXTURE, uv).rgb;
+        vec3 glow = texture(TEXTURE, uv + vec2(texel.x, 0.0)).rgb
+            + texture(TEXTURE, uv - vec2(texel.x, 0.0)).rgb
+            + texture(TEXTURE, uv + vec2(0.0, texel.y)).rgb
+            + texture(TEXTURE, uv - vec2(0.0, texel.y)).rgb;
+        color += glow * 0.08;
+        float row = fract(uv.y * size.y);
+        color *= 0.7 + 0.3 * sin(row * 3.14159265);
+        vec2 edge = uv * (1.0 - uv);
+        color *= pow(edge.x * edge.y * 16.0, 0.15);
+        COLOR = vec4(color, 1.0);
+    }
+}
+)";
+
+void Chip8Screen::_bind_methods() {
+    ClassDB::bind_method(D_METHOD("load_rom", "rom"), &Chip8Screen::load_rom);
+    ClassDB::bind_method(D_METHOD("reset"), &Chip8Screen::reset);
+    ClassDB::bind_method(D_METHOD("key_down", "key"), &Chip8Screen::key_down);
+    ClassDB::bind_method(D_METHOD("key_up", "key"), &Chip8Screen::key_up);
+    ClassDB::bind_method(D_METHOD("is_beeping"), &Chip8Screen::is_beeping);
+    ClassDB::bind_method(D_METHOD("toggle_fullscreen"), &Chip8Screen::toggle_fullscreen);
+    ClassDB::bind_method(D_METHOD("save_screenshot"), &Chip8Screen::save_screenshot);
+    ClassDB::bind_method(D_METHOD("start_recording", "apng"), &Chip8Screen::start_recording,
+                         DEFVAL(false));
+    ClassDB::bind_method(D_METHOD("stop_recording"), &Chip8Screen::stop_recording);
+
+    ClassDB::bind_method(D_METHOD("set_rom_path", "path"), &Chip8Screen::set_rom_path);
+    ClassDB::bind_method(D_METHOD("get_rom_path"), &Chip8Screen::get_rom_path);
+    ClassDB::bind_method(D_METHOD("set_platform", "preset"), &Chip8Screen::set_platform);
+    ClassDB::bind_method(D_METHOD("get_platform"), &Chip8Screen::get_platform);
+    ClassDB::bind_method(D_METHOD("set_speed", "cycles"), &Chip8Screen::set_speed);
+    ClassDB::bind_method(D_METHOD("get_speed"), &Chip8Screen::get_speed);
+    ClassDB::bind_method(D_METHOD("set_keyboard", "enabled"), &Chip8Screen::set_keyboard);
+    ClassDB::bind_method(D_METHOD("get_keyboard"), &Chip8Screen::get_keyboard);
+    ClassDB::bind_method(D_METHOD("set_crt", "enabled"), &Chip8Screen::set_crt);
+    ClassDB::bind_method(D_METHOD("get_crt"), &Chip8Screen::get_crt);
+    ClassDB::bind_method(D_METHOD("set_phosphor", "frames"), &Chip8Screen::set_phosphor);
+    ClassDB::bind_method(D_METHOD("get_phosphor"), &Chip8Screen::get_phosphor);
+    ClassDB::bind_method(D_METHOD("set_integer_scale", "enabled"), &Chip8Screen::set_integer_scale);
+    ClassDB::bind_method(D_METHOD("get_integer_scale"), &Chip8Screen::get_integer_scale);
+    ClassDB::bind_method(D_METHOD("set_remember_window", "enabled"),
+                         &Chip8Screen::set_remember_window);
+    ClassDB::bind_method(D_METHOD("get_remember_window"), &Chip8Screen::get_remember_window);
+
+    ADD_PROPERTY(PropertyInfo(Variant::STRING, "rom_path", PROPERTY_HINT_FILE, "*.ch8,*.sc8,*.xo8"),
+                 "set_rom_path", "get_rom_path");
+    // The interpreter to emulate, a CHIP8_PRESET_*; Default runs every
+    // instruction set. Takes effect at the next load_rom.
+    ADD_PROPERTY(PropertyInfo(Variant::INT, "platform", PROPERTY_HINT_ENUM,
+                              "Default:-1,CHIP-8:0,CHIP-8 hires:1,CHIP-48:2,"
+                              "SUPER-CHIP legacy:3,SUPER-CHIP:4,XO-CHIP:5"),
+                 "set_platform", "get_platform");
+    ADD_PROPERTY(PropertyInfo(Variant::INT, "speed", PROPERTY_HINT_RANGE, "1,1000"), "set_speed",
+                 "get_speed");
+    ADD_PROPERTY(PropertyInfo(Variant::BOOL, "keyboard"), "set_keyboard", "get_keyboard");
+    ADD_PROPERTY(PropertyInfo(Variant::BOOL, "crt"), "set_crt", "get_crt");
+    // Frames a pixel takes to fade out; 0 turns it off at once.
+    ADD_PROPERTY(PropertyInfo(Variant::INT, "phosphor", PROPERTY_HINT_RANGE, "0,16"),
+                 "set_phosphor", "get_phosphor");
+    ADD_PROPERTY(PropertyInfo(Variant::BOOL, "integer_scale"), "set_integer_scale",
+                 "get_integer_scale");
+    ADD_PROPERTY(PropertyInfo(Variant::BOOL, "remember_window"), "set_remember_window",
+                 "get_remember_window");
+
+    // Emitted when the buzzer starts (true) and stops (false).
+    ADD_SIGNAL(MethodInfo("beep", PropertyInfo(Variant::BOOL, "on")));
+    // Emitted when the program crashes; the machine stops until the next
+    // load_rom or reset.
+    ADD_SIGNAL(MethodInfo("crashed"));
+}
+
+Chip8Screen::Chip8Screen() {
+    machine = chip8_new((uint32_t)UtilityFunctions::randi());
+    set_texture_filter(TEXTURE_FILTER_NEAREST);
+    set_expand_mode(EXPAND_IGNORE_SIZE);
+    set_stretch_mode(STRETCH_KEEP_ASPECT_CENTERED);
+    update_screen();
+}
+
+Chip8Screen::~Chip8Screen() {
+    chip8_free(machine);
+}
+
+void Chip8Screen::_ready() {
+    if (Engine::get_singleton()->is_editor_hint()) {
+        return;
+    }
+    windowed_size = DisplayServer::get_singleton()->window_get_size();
+    if (remember_window) {
+        Ref<ConfigFile> config;
+        config.instantiate();
+        if (config->load(WINDOW_CONFIG) == OK) {
+            DisplayServer *display = DisplayServer::get_singleton();
+            windowed_size = config->get_value("window", "size", windowed_size);
+            display->window_set_size(windowed_size);
+        }
+    }
+    if (!rom_path.is_empty()) {
+        PackedByteArray rom = FileAccess::get_file_as_bytes(rom_path);
+        if (!load_rom(rom)) {
+            UtilityFunctions::push_error("chip8: can't load ", rom_path);
+        }
+    }
+}
+
+void Chip8Screen::_notification(int what) {
+    if (what == NOTIFICATION_RESIZED) {
+        apply_scale();
+    } else if (what == NOTIFICATION_WM_CLOSE_REQUEST && remember_window) {
+        save_window();
+    }
+}
+
+void Chip8Screen::_process(double delta) {
+    if (!running) {
+        return;
+    }
+
+    elapsed += delta;
+    bool changed = false;
+    for (int n = 0; n < MAX_FRAMES && elapsed >= FRAME_TIME; n++) {
+        elapsed -= FRAME_TIME;
+        int result = chip8_run_frame(machine);
+        if (result < 0) {
+            running = false;
+            emit_signal("crashed");
+            break;
+        }
+        changed |= result > 0;
+    }
+    if (elapsed > FRAME_TIME) {
+        elapsed = 0.0;
+    }
+    if (changed) {
+        update_screen();
+    }
+
+    bool now = chip8_is_beeping(machine);
+    if (now != beeping) {
+        beeping = now;
+        emit_signal("beep", now);
+    }
+}
+
+void Chip8Screen::_unhandled_input(const Ref<InputEvent> &event) {
+    Ref<InputEventKey> key = event;
+    if (!keyboard || key.is_null() || key->is_echo()) {
+        return;
+    }
+    Key code = key->get_keycode();
+    if (code == KEY_F10 || code == KEY_F11 || code == KEY_F12) {
+        if (key->is_pressed() && code == KEY_F10) {
+            if (chip8_is_recording(machine)) {
+                stop_recording();
+            } else {
+                start_recording(false);
+            }
+        } else if (key->is_pressed() && code == KEY_F11) {
+            toggle_fullscreen();
+        } else if (key->is_pressed()) {
+            save_screenshot();
+        }
+        get_viewport()->set_input_as_handled();
+        return;
+    }
+    if (!running) {
+        return;
+    }
+    // Letters and digits have their upper case character as key code.
+    int hex = chip8_key_for_char(machine, (uint32_t)key->get_keycode());
+    if (hex < 0) {
+        return;
+    }
+    if (key->is_pressed()) {
+        chip8_key_down(machine, (uint8_t)hex);
+    } else {
+        chip8_key_up(machine, (uint8_t)hex);
+    }
+    get_viewport()->set_input_as_handled();
+}
+
+// Copy the machine's screen into the texture, replacing the texture when
+// the resolution changed.
+void Chip8Screen::update_screen() {
+    int width = (int)chip8_width(machine);
+    int height = (int)chip8_height(machine);
+    PackedByteArray pixels;
+    pixels.resize(width * height * 4);
+    memcpy(pixels.ptrw(), chip8_frame(machine), pixels.size());
+
+    if (image.is_valid() && image->get_width() == width && image->get_height() == height) {
+        image->set_data(width, height, false, Image::FORMAT_RGBA8, pixels);
+        // update() wants the texture at the image's own size.
+        screen->set_size_override(Vector2i(width, height));
+        screen->update(image);
+    } else {
+        image = Image::create_from_data(width, height, false, Image::FORMAT_RGBA8, pixels);
+        screen = ImageTexture::create_from_image(image);
+        set_texture(screen);
+    }
+    apply_scale();
+}
+
+// With integer_scale, show the texture at the largest whole multiple of
+// its size that fits, centred; otherwise stretch it keeping the aspect.
+void Chip8Screen::apply_scale() {
+    if (screen.is_null()) {
+        return;
+    }
+    Vector2i native(image->get_width(), image->get_height());
+    if (!integer_scale) {
+        screen->set_size_override(native);
+        set_stretch_mode(STRETCH_KEEP_ASPECT_CENTERED);
+        return;
+    }
+    screen->set_size_override(native * fit_scale());
+    set_stretch_mode(STRETCH_KEEP_CENTERED);
+}
+
+// The largest whole multiple of the screen's size that fits, at least 1.
+int Chip8Screen::fit_scale() const {
+    Vector2 area = get_size();
+    int scale = (int)MIN(area.x / image->get_width(), area.y / image->get_height());
+    return MAX(scale, 1);
+}
+
+// Keep the windowed size for the next run, see remember_window.
+void Chip8Screen::save_window() const {
+    DisplayServer *display = DisplayServer::get_singleton();
+    bool fullscreen = display->window_get_mode() == DisplayServer::WINDOW_MODE_FULLSCREEN;
+    Ref<ConfigFile> config;
+    config.instantiate();
+    config->set_value("window", "size", fullscreen ? windowed_size : display->window_get_size());
+    config->save(WINDOW_CONFIG);
+}
+
+bool Chip8Screen::load_rom(const PackedByteArray &rom) {
+    loaded = chip8_load_rom(machine, rom.ptr(), rom.size());
+    running = loaded;
+    elapsed = 0.0;
+    update_screen();
+    return running;
+}
+
+void Chip8Screen::reset() {
+    running = loaded && chip8_reset(machine);
+    elapsed = 0.0;
+    update_screen();
+}
+
+void Chip8Screen::key_down(int key) {
+    chip8_key_down(machine, (uint8_t)key);
+}
+
+void Chip8Screen::key_up(int key) {
+    chip8_key_up(machine, (uint8_t)key);
+}
+
+bool Chip8Screen::is_beeping() const {
+    return beeping;
+}
+
+// Save the screen as shown, at the whole scale that fits, as
+// user://screenshots/chip8-YYYYMMDD-HHMMSS.png. Returns the path, or an
+// empty string if it couldn't be written.
+String Chip8Screen::save_screenshot() {
+    int scale = image.is_valid() ? fit_scale() : 1;
+    const uint8_t *png = chip8_screenshot(machine, (size_t)scale);
+    return save_capture(SCREENSHOT_DIR, "png", png, chip8_screenshot_len(machine));
+}
+
+// Record every frame from now on, at the whole scale that fits, into a GIF
+// or, with `apng`, an APNG. A recording going on is dropped.
+bool Chip8Screen::start_recording(bool apng) {
+    int scale = image.is_valid() ? fit_scale() : 1;
+    recording_extension = apng ? "png" : "gif";
+    return chip8_start_recording(machine, apng ? CHIP8_RECORD_APNG : CHIP8_RECORD_GIF,
+                                 (size_t)scale);
+}
+
+// Save the recording as user://recordings/chip8-YYYYMMDD-HHMMSS.gif (or
+// .png). Returns the path, or an empty string if nothing was recorded or
+// it couldn't be written.
+String Chip8Screen::stop_recording() {
+    if (!chip8_is_recording(machine)) {
+        return String();
+    }
+    const uint8_t *data = chip8_stop_recording(machine);
+    if (chip8_recording_len(machine) == 0) {
+        return String();
+    }
+    return save_capture(RECORDING_DIR, recording_extension, data, chip8_recording_len(machine));
+}
+
+// Write `len` bytes at `data` to a timestamped file in `dir`.
+String Chip8Screen::save_capture(const char *dir, const String &extension, const uint8_t *data,
+                                 size_t len) {
+    PackedByteArray bytes;
+    bytes.resize((int64_t)len);
+    memcpy(bytes.ptrw(), data, len);
+
+    String stamp = Time::get_singleton()->get_datetime_string_from_system(false, true);
+    stamp = stamp.replace("-", "").replace(":", "").replace(" ", "-");
+    String path = String(dir).path_join("chip8-" + stamp + "." + extension);
+    DirAccess::make_dir_recursive_absolute(dir);
+    Ref<FileAccess> file = FileAccess::open(path, FileAccess::WRITE);
+    if (file.is_null()) {
+        UtilityFunctions::push_error("chip8: can't write ", path);
+        return String();
+    }
+    file->store_buffer(bytes);
+    return path;
+}
+
+// Fullscreen keeps the aspect ratio, with bars on the sides or the top
+// and bottom.
+void Chip8Screen::toggle_fullscreen() {
+    DisplayServer *display = DisplayServer::get_singleton();
+    if (display->window_get_mode() == DisplayServer::WINDOW_MODE_FULLSCREEN) {
+        display->window_set_mode(DisplayServer::WINDOW_MODE_WINDOWED);
+    } else {
+        windowed_size = display->window_get_size();
+        display->window_set_mode(DisplayServer::WINDOW_MODE_FULLSCREEN);
+    }
+}
+
+void Chip8Screen::set_rom_path(const String &path) {
+    rom_path = path;
+}
+
+String Chip8Screen::get_rom_path() const {
+    return rom_path;
+}
+
+void Chip8Screen::set_platform(int preset) {
+    if (preset >= 0) {
+        if (chip8_set_preset(machine, preset)) {
+            platform = preset;
+        }
+        return;
+    }
+    // There is no preset for the default, so start over from a new
+    // machine with the same settings.
+    size_t speed = chip8_speed(machine);
+    chip8_free(machine);
+    machine = chip8_new((uint32_t)UtilityFunctions::randi());
+    chip8_set_speed(machine, speed);
+    chip8_set_phosphor(machine, (uint8_t)phosphor);
+    platform = -1;
+    loaded = false;
+    running = false;
+    update_screen();
+}
+
+int Chip8Screen::get_platform() const {
+    return platform;
+}
+
+void Chip8Screen::set_speed(int cycles) {
+    chip8_set_speed(machine, (size_t)cycles);
+}
+
+int Chip8Screen::get_speed() const {
+    return (int)chip8_speed(machine);
+}
+
+void Chip8Screen::set_keyboard(bool enabled) {
+    keyboard = enabled;
+}
+
+bool Chip8Screen::get_keyboard() const {
+    return keyboard;
+}
+
+void Chip8Screen::set_crt(bool enabled) {
+    crt = enabled;
+    if (!enabled) {
+        set_material(Ref<Material>());
+        return;
+    }
+    Ref<Shader> shader;
+    shader.instantiate();
+    shader->set_code(CRT_SHADER);
+    Ref<ShaderMaterial> material;
+    material.instantiate();
+    material->set_shader(shader);
+    set_material(material);
+}
+
+bool Chip8Screen::get_crt() const {
+    return crt;
+}
+
+void Chip8Screen::set_phosphor(int frames) {
+    phosphor = frames < 0 ? 0 : frames > 255 ? 255 : frames;
+    chip8_set_phosphor(machine, (uint8_t)phosphor);
+}
+
+int Chip8Screen::get_phosphor() const {
+    return phosphor;
+}
+
+void Chip8Screen::set_integer_scale(bool enabled) {
+    integer_scale = enabled;
+    apply_scale();
+}
+
+bool Chip8Screen::get_integer_scale() const {
+    return integer_scale;
+}
+
+void Chip8Screen::set_remember_window(bool enabled) {
+    remember_window = enabled;
+}
+
+bool Chip8Screen::get_remember_window() const {
+    return remember_window;
+}
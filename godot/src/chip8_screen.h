@@ -0,0 +1,99 @@
+// A TextureRect that runs a CHIP-8 machine and shows its screen. Set
+// rom_path (or call load_rom) and it plays at 60 frames a second, taking
+// keys from the 1234/QWER/ASDF/ZXCV block or key_down/key_up. Set platform
+// to emulate one interpreter's quirks and memory. Turn on crt for
+// scanlines, curvature and bloom, and set phosphor to fade pixels out over
+// a few frames instead of flickering.
+//
+// With integer_scale every emulated pixel takes the same whole number of
+// screen pixels. F11 switches the window to fullscreen and back, and with
+// remember_window the window comes back at the size it was closed at.
+// F12 or save_screenshot() saves the screen as a PNG under
+// user://screenshots; F10 or start_recording() and stop_recording() record
+// it into a GIF or APNG under user://recordings.
+#ifndef CHIP8_SCREEN_H
+#define CHIP8_SCREEN_H
+
+#include <godot_cpp/classes/image.hpp>
+#include <godot_cpp/classes/image_texture.hpp>
+#include <godot_cpp/classes/input_event.hpp>
+#include <godot_cpp/classes/texture_rect.hpp>
+
+#include "chip8.h"
+
+namespace godot {
+
+class Chip8Screen : public TextureRect {
+    GDCLASS(Chip8Screen, TextureRect)
+
+    Chip8Machine *machine;
+    Ref<Image> image;
+    Ref<ImageTexture> screen;
+    String rom_path;
+    // The CHIP8_PRESET_* in use, or -1 for the default.
+    int platform = -1;
+    bool keyboard = true;
+    bool crt = false;
+    int phosphor = 0;
+    bool integer_scale = false;
+    bool remember_window = false;
+    // The window's size before going fullscreen.
+    Vector2i windowed_size;
+    // The file extension of the recording going on.
+    String recording_extension;
+    bool loaded = false;
+    bool running = false;
+    bool beeping = false;
+    // Time not yet run, in seconds.
+    double elapsed = 0.0;
+
+    void update_screen();
+    void apply_scale();
+    int fit_scale() const;
+    String save_capture(const char *dir, const String &extension, const uint8_t *data,
+                        size_t len);
+    void save_window() const;
+
+protected:
+    static void _bind_methods();
+    void _notification(int what);
+
+public:
+    Chip8Screen();
+    ~Chip8Screen();
+
+    void _ready() override;
+    void _process(double delta) override;
+    void _unhandled_input(const Ref<InputEvent> &event) override;
+
+    bool load_rom(const PackedByteArray &rom);
+    void reset();
+    void key_down(int key);
+    void key_up(int key);
+    bool is_beeping() const;
+    void toggle_fullscreen();
+    String save_screenshot();
+    bool start_recording(bool apng);
+    String stop_recording();
+
+    void set_rom_path(const String &path);
+    String get_rom_path() const;
+    void set_platform(int preset);
+    int get_platform() const;
+    void set_speed(int cycles);
+    int get_speed() const;
+    void set_keyboard(bool enabled);
+    bool get_keyboard() const;
+    void set_crt(bool enabled);
+    bool get_crt() const;
+    void set_phosphor(int frames);
+    int get_phosphor() const;
+    void set_integer_scale(bool enabled);
+    bool get_integer_scale() const;
+    void set_remember_window(bool enabled);
+    bool get_remember_window() const;
+};
+
+} // namespace godot
+
+#endif // CHIP8_SCREEN_H
@@ -0,0 +1,27 @@
+// Entry point Godot calls when it loads chip8.gdextension.
+#include <gdextension_interface.h>
+#include <godot_cpp/core/class_db.hpp>
+#include <godot_cpp/godot.hpp>
+
+#include "chip8_screen.h"
+
+using namespace godot;
+
+static void initialize(ModuleInitializationLevel level) {
+    if (level == MODULE_INITIALIZATION_LEVEL_SCENE) {
+        GDREGISTER_CLASS(Chip8Screen);
+    }
+}
+
+static void uninitialize(ModuleInitializationLevel level) {}
+
+extern "C" GDExtensionBool GDE_EXPORT chip8_godot_init(
+    GDExtensionInterfaceGetProcAddress get_proc_address,
+    GDExtensionClassLibraryPtr library,
+    GDExtensionInitialization *initialization) {
+    GDExtensionBinding::InitObject init(get_proc_address, library, initialization);
+    init.register_initializer(initialize);
+    init.register_terminator(uninitialize);
+    init.set_minimum_library_initialization_level(MODULE_INITIALIZATION_LEVEL_SCENE);
+    return init.init();
+}
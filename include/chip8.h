@@ -0,0 +1,130 @@
+/* The C API of the chip8 emulator, see src/ffi.rs. */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define CHIP8_STATE_RUNNING 0
+
+#define CHIP8_STATE_WAITING_FOR_KEY 1
+
+#define CHIP8_STATE_WAITING_FOR_VBLANK 2
+
+#define CHIP8_STATE_EXITED 3
+
+#define CHIP8_STATE_HALTED 4
+
+#define CHIP8_STATE_CRASHED -1
+
+#define CHIP8_RECORD_GIF 0
+
+#define CHIP8_RECORD_APNG 1
+
+#define CHIP8_PRESET_CHIP8 0
+
+#define CHIP8_PRESET_CHIP8_HIRES 1
+
+#define CHIP8_PRESET_CHIP48 2
+
+#define CHIP8_PRESET_SCHIP_LEGACY 3
+
+#define CHIP8_PRESET_SCHIP 4
+
+#define CHIP8_PRESET_XOCHIP 5
+
+#define CHIP8_QUIRK_SHIFT (1 << 0)
+
+#define CHIP8_QUIRK_MEMORY_INCREMENT (1 << 1)
+
+#define CHIP8_QUIRK_MEMORY_INCREMENT_BY_X (1 << 2)
+
+#define CHIP8_QUIRK_JUMP (1 << 3)
+
+#define CHIP8_QUIRK_VF_RESET (1 << 4)
+
+#define CHIP8_QUIRK_CLIPPING (1 << 5)
+
+#define CHIP8_QUIRK_DISPLAY_WAIT (1 << 6)
+
+#define CHIP8_QUIRK_INDEX_OVERFLOW (1 << 7)
+
+#define CHIP8_QUIRK_HALF_SCROLL (1 << 8)
+
+#define CHIP8_QUIRK_VF_ORDER (1 << 9)
+
+typedef struct Chip8Machine Chip8Machine;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+Chip8Machine *chip8_new(uint32_t seed);
+
+void chip8_free(Chip8Machine *machine);
+
+bool chip8_load_rom(Chip8Machine *machine, const uint8_t *rom, size_t len);
+
+int32_t chip8_run_frame(Chip8Machine *machine);
+
+int32_t chip8_step(Chip8Machine *machine);
+
+bool chip8_reset(Chip8Machine *machine);
+
+bool chip8_set_preset(Chip8Machine *machine, int32_t preset);
+
+uint32_t chip8_quirks(Chip8Machine *machine);
+
+void chip8_set_quirks(Chip8Machine *machine, uint32_t bits);
+
+size_t chip8_speed(Chip8Machine *machine);
+
+void chip8_set_speed(Chip8Machine *machine, size_t cycles);
+
+void chip8_key_down(Chip8Machine *machine, uint8_t key);
+
+void chip8_key_up(Chip8Machine *machine, uint8_t key);
+
+int32_t chip8_key_for_char(Chip8Machine *machine, uint32_t code);
+
+size_t chip8_width(Chip8Machine *machine);
+
+size_t chip8_height(Chip8Machine *machine);
+
+const uint8_t *chip8_frame(Chip8Machine *machine);
+
+void chip8_set_phosphor(Chip8Machine *machine, uint8_t frames);
+
+void chip8_set_palette(Chip8Machine *machine, const uint8_t *colors);
+
+bool chip8_is_beeping(Chip8Machine *machine);
+
+const uint8_t *chip8_save_state(Chip8Machine *machine);
+
+size_t chip8_state_len(Chip8Machine *machine);
+
+bool chip8_load_state(Chip8Machine *machine, const uint8_t *state, size_t len);
+
+const uint8_t *chip8_screenshot(Chip8Machine *machine, size_t scale);
+
+size_t chip8_screenshot_len(Chip8Machine *machine);
+
+bool chip8_start_recording(Chip8Machine *machine, int32_t format, size_t scale);
+
+bool chip8_is_recording(Chip8Machine *machine);
+
+const uint8_t *chip8_stop_recording(Chip8Machine *machine);
+
+size_t chip8_recording_len(Chip8Machine *machine);
+
+const uint8_t *chip8_rpl_flags(Chip8Machine *machine);
+
+void chip8_set_rpl_flags(Chip8Machine *machine, const uint8_t *flags);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHIP8_H */
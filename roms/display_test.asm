@@ -0,0 +1,39 @@
+; Display test in the spirit of the IBM logo ROM: clears the screen, draws
+; the word CHIP8 from its own sprites and halts. Uses only 00E0, 6xkk,
+; 7xkk, Annn, Dxyn and 1nnn, so it is the first thing to get running on a
+; new interpreter. Assembled to display_test.ch8 (Cowgod mnemonics, one
+; instruction per line).
+;
+; Result: CHIP8 in 4x5 letters with its top left corner at (8, 12), the
+; letters 5 pixels apart. There is nothing to check in the registers.
+
+    CLS
+    LD V0, 0x08
+    LD V1, 0x0c
+    LD I, letter_c
+    DRW V0, V1, 5
+    ADD V0, 0x05
+    LD I, letter_h
+    DRW V0, V1, 5
+    ADD V0, 0x05
+    LD I, letter_i
+    DRW V0, V1, 5
+    ADD V0, 0x05
+    LD I, letter_p
+    DRW V0, V1, 5
+    ADD V0, 0x05
+    LD I, digit_8
+    DRW V0, V1, 5
+halt:
+    JP halt
+
+letter_c:
+    db 0xf0, 0x80, 0x80, 0x80, 0xf0
+letter_h:
+    db 0x90, 0x90, 0xf0, 0x90, 0x90
+letter_i:
+    db 0xe0, 0x40, 0x40, 0x40, 0xe0
+letter_p:
+    db 0xf0, 0x90, 0xf0, 0x80, 0x80
+digit_8:
+    db 0xf0, 0x90, 0xf0, 0x90, 0xf0
@@ -0,0 +1,150 @@
+; VF flag self-test for the arithmetic and shift instructions. Assembled to
+; flags_test.ch8 (Cowgod mnemonics, one instruction per line).
+;
+; Same conventions as opcode_test: VE holds the number of the current test,
+; the screen reads 600D and VE = 0 on success, or BAD and the number of the
+; failed test otherwise.
+
+    LD VE, 0x01         ; 8xy4 without carry
+    LD V0, 0x10
+    LD V1, 0x20
+    ADD V0, V1
+    SE VF, 0x00
+    JP fail
+    LD VE, 0x02         ; 8xy4 with carry
+    LD V0, 0xff
+    LD V1, 0x02
+    ADD V0, V1
+    SE V0, 0x01
+    JP fail
+    SE VF, 0x01
+    JP fail
+    LD VE, 0x03         ; 8xy5 without borrow: VF = 1
+    LD V0, 0x05
+    LD V1, 0x03
+    SUB V0, V1
+    SE V0, 0x02
+    JP fail
+    SE VF, 0x01
+    JP fail
+    LD VE, 0x04         ; 8xy5 with borrow: VF = 0
+    LD V0, 0x03
+    LD V1, 0x05
+    SUB V0, V1
+    SE V0, 0xfe
+    JP fail
+    SE VF, 0x00
+    JP fail
+    LD VE, 0x05         ; 8xy5 of equal values is not a borrow
+    LD V0, 0x04
+    LD V1, 0x04
+    SUB V0, V1
+    SE V0, 0x00
+    JP fail
+    SE VF, 0x01
+    JP fail
+    LD VE, 0x06         ; 8xy7 without borrow
+    LD V0, 0x03
+    LD V1, 0x05
+    SUBN V0, V1
+    SE V0, 0x02
+    JP fail
+    SE VF, 0x01
+    JP fail
+    LD VE, 0x07         ; 8xy7 with borrow
+    LD V0, 0x05
+    LD V1, 0x03
+    SUBN V0, V1
+    SE V0, 0xfe
+    JP fail
+    SE VF, 0x00
+    JP fail
+    LD VE, 0x08         ; 8xy6 shifts the low bit into VF
+    LD V0, 0x05
+    SHR V0
+    SE V0, 0x02
+    JP fail
+    SE VF, 0x01
+    JP fail
+    SHR V0
+    SE VF, 0x00
+    JP fail
+    LD VE, 0x09         ; 8xyE shifts the high bit into VF
+    LD V0, 0x81
+    SHL V0
+    SE V0, 0x02
+    JP fail
+    SE VF, 0x01
+    JP fail
+    SHL V0
+    SE VF, 0x00
+    JP fail
+    LD VE, 0x0a         ; the flag wins when VF is the destination
+    LD VF, 0xff
+    LD V1, 0x01
+    ADD VF, V1
+    SE VF, 0x01
+    JP fail
+    LD VE, 0x0b
+    LD VF, 0x05
+    LD V1, 0x03
+    SUB VF, V1
+    SE VF, 0x01
+    JP fail
+    LD VE, 0x0c
+    LD VF, 0x04
+    SHR VF
+    SE VF, 0x00
+    JP fail
+    LD VE, 0x0d         ; 7xkk never touches VF
+    LD VF, 0x55
+    LD V0, 0xff
+    ADD V0, 0x02
+    SE VF, 0x55
+    JP fail
+
+pass:
+    CLS
+    LD VE, 0x00
+    LD V1, 0x00
+    LD V2, 0x00
+    LD V0, 0x06
+    CALL glyph
+    LD V0, 0x00
+    CALL glyph
+    LD V0, 0x00
+    CALL glyph
+    LD V0, 0x0d
+    CALL glyph
+halt:
+    JP halt
+
+fail:
+    CLS
+    LD V1, 0x00
+    LD V2, 0x00
+    LD V0, 0x0b
+    CALL glyph
+    LD V0, 0x0a
+    CALL glyph
+    LD V0, 0x0d
+    CALL glyph
+    ADD V1, 0x05
+    LD V0, VE
+    SHR V0
+    SHR V0
+    SHR V0
+    SHR V0
+    CALL glyph
+    LD V0, VE
+    LD V3, 0x0f
+    AND V0, V3
+    CALL glyph
+    JP halt
+
+; Draw the font glyph for V0 at (V1, V2) and move V1 along.
+glyph:
+    LD F, V0
+    DRW V1, V2, 5
+    ADD V1, 0x05
+    RET
@@ -0,0 +1,195 @@
+; Opcode self-test for the default CHIP-8 profile. Assembled to
+; opcode_test.ch8 (Cowgod mnemonics, one instruction per line).
+;
+; Runs the tests in order with VE holding the number of the current one.
+; Avoids every behaviour that differs between interpreters (shifts use
+; Vx = Vy, Bnnn sets both V0 and Vx, I is reloaded after Fx55/Fx65).
+;
+; Result: the screen reads 600D ("good") and VE = 0, or BAD followed by
+; the number of the failed test, which is left in VE. Either way the ROM
+; ends on a jump to itself.
+
+    LD VE, 0x01         ; 3xkk
+    LD V0, 0x2a
+    SE V0, 0x2a
+    JP fail
+    LD VE, 0x02         ; 4xkk
+    SNE V0, 0x2b
+    JP fail
+    LD VE, 0x03         ; 5xy0
+    LD V1, 0x2a
+    SE V0, V1
+    JP fail
+    LD VE, 0x04         ; 9xy0
+    LD V1, 0x2b
+    SNE V0, V1
+    JP fail
+    LD VE, 0x05         ; 7xkk, wrapping and leaving VF alone
+    ADD V0, 0x01
+    SE V0, 0x2b
+    JP fail
+    LD VF, 0x55
+    LD V0, 0xff
+    ADD V0, 0x02
+    SE V0, 0x01
+    JP fail
+    SE VF, 0x55
+    JP fail
+    LD VE, 0x06         ; 8xy0
+    LD V1, 0x33
+    LD V0, V1
+    SE V0, 0x33
+    JP fail
+    LD VE, 0x07         ; 8xy1
+    LD V0, 0x0c
+    LD V1, 0x0a
+    OR V0, V1
+    SE V0, 0x0e
+    JP fail
+    LD VE, 0x08         ; 8xy2
+    LD V0, 0x0c
+    AND V0, V1
+    SE V0, 0x08
+    JP fail
+    LD VE, 0x09         ; 8xy3
+    LD V0, 0x0c
+    XOR V0, V1
+    SE V0, 0x06
+    JP fail
+    LD VE, 0x0a         ; 8xy4
+    LD V0, 0x10
+    LD V1, 0x20
+    ADD V0, V1
+    SE V0, 0x30
+    JP fail
+    LD VE, 0x0b         ; 8xy5
+    SUB V0, V1
+    SE V0, 0x10
+    JP fail
+    LD VE, 0x0c         ; 8xy7
+    SUBN V0, V1
+    SE V0, 0x10
+    JP fail
+    LD VE, 0x0d         ; 8xy6
+    LD V0, 0x06
+    SHR V0
+    SE V0, 0x03
+    JP fail
+    LD VE, 0x0e         ; 8xyE
+    SHL V0
+    SE V0, 0x06
+    JP fail
+    LD VE, 0x0f         ; Annn, Fx55, Fx65
+    LD I, scratch
+    LD V0, 0x11
+    LD V1, 0x22
+    LD [I], V1
+    LD V0, 0x00
+    LD V1, 0x00
+    LD I, scratch
+    LD V1, [I]
+    SE V0, 0x11
+    JP fail
+    SE V1, 0x22
+    JP fail
+    LD VE, 0x10         ; Fx1E
+    LD I, scratch
+    LD V2, 0x01
+    ADD I, V2
+    LD V0, [I]
+    SE V0, 0x22
+    JP fail
+    LD VE, 0x11         ; Fx33
+    LD V0, 0x89
+    LD I, scratch
+    LD B, V0
+    LD I, scratch
+    LD V2, [I]
+    SE V0, 0x01
+    JP fail
+    SE V1, 0x03
+    JP fail
+    SE V2, 0x07
+    JP fail
+    LD VE, 0x12         ; 2nnn, 00EE
+    LD V0, 0x00
+    CALL subroutine
+    SE V0, 0x5a
+    JP fail
+    LD VE, 0x13         ; Bnnn
+    LD V0, 0x02
+    LD V2, 0x02
+    JP V0, jump_table
+jump_table:
+    JP fail
+    LD VE, 0x14         ; Fx29
+    LD V0, 0x0a
+    LD F, V0
+    LD V0, [I]
+    SE V0, 0xf0
+    JP fail
+    LD VE, 0x15         ; Dxyn collision
+    CLS
+    LD I, dot
+    LD V0, 0x00
+    DRW V0, V0, 1
+    SE VF, 0x00
+    JP fail
+    DRW V0, V0, 1
+    SE VF, 0x01
+    JP fail
+
+pass:
+    LD VE, 0x00
+    LD V1, 0x00
+    LD V2, 0x00
+    LD V0, 0x06
+    CALL glyph
+    LD V0, 0x00
+    CALL glyph
+    LD V0, 0x00
+    CALL glyph
+    LD V0, 0x0d
+    CALL glyph
+halt:
+    JP halt
+
+fail:
+    CLS
+    LD V1, 0x00
+    LD V2, 0x00
+    LD V0, 0x0b
+    CALL glyph
+    LD V0, 0x0a
+    CALL glyph
+    LD V0, 0x0d
+    CALL glyph
+    ADD V1, 0x05
+    LD V0, VE
+    SHR V0
+    SHR V0
+    SHR V0
+    SHR V0
+    CALL glyph
+    LD V0, VE
+    LD V3, 0x0f
+    AND V0, V3
+    CALL glyph
+    JP halt
+
+; Draw the font glyph for V0 at (V1, V2) and move V1 along.
+glyph:
+    LD F, V0
+    DRW V1, V2, 5
+    ADD V1, 0x05
+    RET
+
+subroutine:
+    LD V0, 0x5a
+    RET
+
+dot:
+    db 0x80
+    db 0x00
+scratch:
+    db 0x00, 0x00, 0x00, 0x00
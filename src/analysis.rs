@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use crate::platform::Platform;
+
+// Behaviours that differ between interpreters and that a ROM appears to
+// depend on, found by looking at how it uses the affected instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum QuirkHint {
+    // 8xy6/8xyE with x != y: the result depends on whether Vx or Vy is
+    // shifted.
+    Shift,
+    // I is used again after Fx55/Fx65 without being reloaded, so the ROM
+    // relies on whether those instructions advance I.
+    LoadStore,
+    // Bnnn is used: V0 or Vx is added depending on the interpreter.
+    Jump,
+    // 8xy1/8xy2/8xy3 are used and some interpreters reset VF after them.
+    VfReset,
+}
+
+// What a ROM contains, worked out without running it. Code is found by
+// following control flow from the start address, so data never gets
+// mistaken for instructions; code only reachable through Bnnn jump tables
+// is missed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomAnalysis {
+    // Instruction patterns ("8xy4", "Dxyn", ...) and how often each appears
+    // in reachable code.
+    pub opcodes: BTreeMap<&'static str, usize>,
+    // Lowest instruction set that covers every instruction found.
+    pub platform: Option<Platform>,
+    pub quirk_hints: BTreeSet<QuirkHint>,
+    // Memory the program reads or writes through I (sprites, BCD,
+    // register stores), merged into ranges.
+    pub memory_ranges: Vec<Range<usize>>,
+    // Reachable opcodes that no supported platform defines, by address.
+    pub unknown: Vec<(u16, u16)>,
+    // Addresses of all reachable instructions.
+    pub code: BTreeSet<u16>,
+}
+
+impl RomAnalysis {
+    // Analyse `rom` as loaded at `start` (0x200 for most ROMs).
+    pub fn analyze(rom: &[u8], start: u16) -> RomAnalysis {
+        let mut analysis = RomAnalysis::default();
+        let mut ranges = Vec::new();
+        let fetch = |addr: u16| {
+            let at = (addr as usize).checked_sub(start as usize)?;
+            let bytes = rom.get(at..at + 2)?;
+            Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+
+        // Paths still to follow: (address, I if known, whether I was last
+        // left behind by Fx55/Fx65).
+        let mut pending = vec![(start, None, false)];
+        while let Some((mut pc, mut i, mut after_load_store)) = pending.pop() {
+            while let Some(opcode) = fetch(pc) {
+                if !analysis.code.insert(pc) {
+                    break;
+                }
+
+                let (pattern, platform) = match classify(opcode) {
+                    Some(class) => class,
+                    None => {
+                        analysis.unknown.push((pc, opcode));
+                        break;
+                    }
+                };
+                *analysis.opcodes.entry(pattern).or_insert(0) += 1;
+                analysis.platform = analysis.platform.max(Some(platform));
+
+                // XO-CHIP's F000 nnnn is four bytes long. A ROM can run up
+                // to 0xfffe, so a path that would step past it just ends.
+                let length = |opcode| if opcode == Some(0xf000) { 4 } else { 2 };
+                let next = pc.checked_add(length(Some(opcode)));
+                let long_next = |addr: u16| addr.checked_add(length(fetch(addr)));
+
+                let x = ((opcode >> 8) & 0xf) as usize;
+                let y = ((opcode >> 4) & 0xf) as usize;
+                let n = (opcode & 0xf) as usize;
+                let nnn = opcode & 0xfff;
+
+                // Bytes accessed through I by this instruction, if any.
+                let access = match pattern {
+                    "Dxyn" => Some(n),
+                    "Dxy0" => Some(32),
+                    "Fx33" => Some(3),
+                    "Fx55" | "Fx65" => Some(x + 1),
+                    _ => None,
+                };
+                if let (Some(len), Some(addr)) = (access, i) {
+                    ranges.push(addr as usize..addr as usize + len);
+                }
+
+                let uses_i = access.is_some() || pattern == "Fx1E";
+                if uses_i && after_load_store {
+                    analysis.quirk_hints.insert(QuirkHint::LoadStore);
+                }
+
+                match pattern {
+                    "8xy6" | "8xyE" if x != y => {
+                        analysis.quirk_hints.insert(QuirkHint::Shift);
+                    }
+                    "8xy1" | "8xy2" | "8xy3" => {
+                        analysis.quirk_hints.insert(QuirkHint::VfReset);
+                    }
+                    _ => {}
+                }
+
+                // Track I along the path.
+                match pattern {
+                    "Annn" => i = Some(nnn),
+                    "F000" => i = pc.checked_add(2).and_then(fetch),
+                    "Fx1E" | "Fx29" | "Fx30" => i = None,
+                    _ => {}
+                }
+                after_load_store = match pattern {
+                    "Fx55" | "Fx65" => true,
+                    "Annn" | "F000" | "Fx29" | "Fx30" => false,
+                    _ => after_load_store,
+                };
+
+                match pattern {
+                    // End of the path.
+                    "00EE" | "00FD" => break,
+                    "Bnnn" => {
+                        analysis.quirk_hints.insert(QuirkHint::Jump);
+                        break;
+                    }
+                    "1nnn" => {
+                        pending.push((nnn, i, after_load_store));
+                        break;
+                    }
+                    "2nnn" => pending.push((nnn, i, after_load_store)),
+                    "3xkk" | "4xkk" | "5xy0" | "9xy0" | "Ex9E" | "ExA1" => {
+                        if let Some(skip) = next.and_then(long_next) {
+                            pending.push((skip, i, after_load_store));
+                        }
+                    }
+                    _ => {}
+                }
+
+                match next {
+                    Some(next) => pc = next,
+                    None => break,
+                }
+            }
+        }
+
+        analysis.memory_ranges = merge(ranges);
+        analysis
+    }
+}
+
+// Sort and merge overlapping or touching ranges.
+fn merge(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+// Instruction pattern of `opcode` and the platform that introduced it.
+fn classify(opcode: u16) -> Option<(&'static str, Platform)> {
+    use Platform::*;
+
+    let x = (opcode >> 8) & 0xf;
+    let n = opcode & 0xf;
+    let kk = opcode & 0xff;
+
+    let class = match opcode >> 12 {
+        0x0 => match opcode {
+            0x00e0 => ("00E0", Chip8),
+            0x00ee => ("00EE", Chip8),
+            0x00fb => ("00FB", SuperChip),
+            0x00fc => ("00FC", SuperChip),
+            0x00fd => ("00FD", SuperChip),
+            0x00fe => ("00FE", SuperChip),
+            0x00ff => ("00FF", SuperChip),
+            _ if opcode & 0xfff0 == 0x00c0 => ("00Cn", SuperChip),
+            _ if opcode & 0xfff0 == 0x00d0 => ("00Dn", XoChip),
+            _ => ("0nnn", Chip8),
+        },
+        0x1 => ("1nnn", Chip8),
+        0x2 => ("2nnn", Chip8),
+        0x3 => ("3xkk", Chip8),
+        0x4 => ("4xkk", Chip8),
+        0x5 => match n {
+            0x0 => ("5xy0", Chip8),
+            0x2 => ("5xy2", XoChip),
+            0x3 => ("5xy3", XoChip),
+            _ => return None,
+        },
+        0x6 => ("6xkk", Chip8),
+        0x7 => ("7xkk", Chip8),
+        0x8 => match n {
+            0x0 => ("8xy0", Chip8),
+            0x1 => ("8xy1", Chip8),
+            0x2 => ("8xy2", Chip8),
+            0x3 => ("8xy3", Chip8),
+            0x4 => ("8xy4", Chip8),
+            0x5 => ("8xy5", Chip8),
+            0x6 => ("8xy6", Chip8),
+            0x7 => ("8xy7", Chip8),
+            0xe => ("8xyE", Chip8),
+            _ => return None,
+        },
+        0x9 if n == 0 => ("9xy0", Chip8),
+        0xa => ("Annn", Chip8),
+        0xb => ("Bnnn", Chip8),
+        0xc => ("Cxkk", Chip8),
+        0xd if n == 0 => ("Dxy0", SuperChip),
+        0xd => ("Dxyn", Chip8),
+        0xe => match kk {
+            0x9e => ("Ex9E", Chip8),
+            0xa1 => ("ExA1", Chip8),
+            _ => return None,
+        },
+        0xf => match kk {
+            0x00 if x == 0 => ("F000", XoChip),
+            0x01 => ("Fx01", XoChip),
+            0x02 if x == 0 => ("F002", XoChip),
+            0x07 => ("Fx07", Chip8),
+            0x0a => ("Fx0A", Chip8),
+            0x15 => ("Fx15", Chip8),
+            0x18 => ("Fx18", Chip8),
+            0x1e => ("Fx1E", Chip8),
+            0x29 => ("Fx29", Chip8),
+            0x30 => ("Fx30", SuperChip),
+            0x33 => ("Fx33", Chip8),
+            0x3a => ("Fx3A", XoChip),
+            0x55 => ("Fx55", Chip8),
+            0x65 => ("Fx65", Chip8),
+            0x75 => ("Fx75", SuperChip),
+            0x85 => ("Fx85", SuperChip),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(class)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_follows_control_flow() {
+        let rom = [
+            0xa2, 0x0a, // 0x200: LD I, 0x20a
+            0xd0, 0x15, // 0x202: DRW V0, V1, 5
+            0x30, 0x00, // 0x204: SE V0, 0
+            0x12, 0x08, // 0x206: JP 0x208
+            0x12, 0x08, // 0x208: JP 0x208
+            0xff, 0xff, // 0x20a: sprite data, never executed
+            0xff, 0xff, 0xff,
+        ];
+        let analysis = RomAnalysis::analyze(&rom, 0x200);
+
+        assert_eq!(analysis.platform, Some(Platform::Chip8));
+        assert_eq!(analysis.opcodes.get("1nnn"), Some(&2));
+        assert!(analysis.unknown.is_empty(), "data is not decoded");
+        assert_eq!(
+            analysis.code.iter().copied().collect::<Vec<_>>(),
+            vec![0x200, 0x202, 0x204, 0x206, 0x208]
+        );
+        assert_eq!(analysis.memory_ranges, vec![0x20a..0x20f]);
+    }
+
+    #[test]
+    fn test_rom_up_to_end_of_memory() {
+        // A full XO-CHIP ROM of LD V0, 0 ending in SE V0, 0 at 0xfffc and
+        // F000 at 0xfffe, where neither the skip nor the long load has
+        // anywhere to go.
+        let mut rom = [0x60, 0x00].repeat(0x7f00);
+        rom[0xfdfc..].copy_from_slice(&[0x30, 0x00, 0xf0, 0x00]);
+        let analysis = RomAnalysis::analyze(&rom, 0x200);
+
+        assert_eq!(analysis.code.len(), 0x7f00);
+        assert!(analysis.code.contains(&0xfffe));
+        assert_eq!(analysis.platform, Some(Platform::XoChip));
+    }
+
+    #[test]
+    fn test_detects_platform() {
+        // SCROLL DOWN 4; F000 0x0300 (XO-CHIP long LD I); DRW V0, V1, 0
+        let rom = [0x00, 0xc4, 0xf0, 0x00, 0x03, 0x00, 0xd0, 0x10, 0x00, 0xfd];
+        let analysis = RomAnalysis::analyze(&rom, 0x200);
+
+        assert_eq!(analysis.platform, Some(Platform::XoChip));
+        assert_eq!(analysis.opcodes.get("Dxy0"), Some(&1));
+        assert_eq!(analysis.memory_ranges, vec![0x300..0x320]);
+
+        let schip = RomAnalysis::analyze(&[0x00, 0xff, 0x00, 0xfd], 0x200);
+        assert_eq!(schip.platform, Some(Platform::SuperChip));
+    }
+
+    #[test]
+    fn test_quirk_hints() {
+        let rom = [
+            0x81, 0x26, // SHR V1, V2
+            0x81, 0x21, // OR V1, V2
+            0xa3, 0x00, // LD I, 0x300
+            0xf1, 0x55, // LD [I], V1
+            0xf1, 0x65, // LD V1, [I]  (depends on I having moved)
+            0xb3, 0x00, // JP V0, 0x300
+        ];
+        let analysis = RomAnalysis::analyze(&rom, 0x200);
+
+        assert_eq!(
+            analysis.quirk_hints.iter().copied().collect::<Vec<_>>(),
+            vec![
+                QuirkHint::Shift,
+                QuirkHint::LoadStore,
+                QuirkHint::Jump,
+                QuirkHint::VfReset
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let analysis = RomAnalysis::analyze(&[0x60, 0x00, 0x80, 0x0f], 0x200);
+        assert_eq!(analysis.unknown, vec![(0x202, 0x800f)]);
+    }
+}
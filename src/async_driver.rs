@@ -0,0 +1,113 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::machine::Machine;
+
+// Future that returns `Pending` once, giving the executor a chance to run
+// other tasks.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+fn yield_now() -> YieldNow {
+    YieldNow(false)
+}
+
+impl Machine {
+    // Async version of looping over `run_paced`, for embedding in async
+    // servers without tying up a runtime thread.
+    //
+    // The crate doesn't depend on any runtime: `sleep` is the runtime's
+    // timer (e.g. `tokio::time::sleep`) and is awaited for the time left
+    // until each frame is due, on the machine's clock. Between frames the
+    // task always yields. `keep_running` is called before every frame to
+    // inject input or stop the loop by returning false.
+    pub async fn run_async<S, F, K>(&mut self, mut sleep: S, mut keep_running: K) -> Result<()>
+    where
+        S: FnMut(Duration) -> F,
+        F: Future<Output = ()>,
+        K: FnMut(&mut Machine) -> bool,
+    {
+        while keep_running(self) {
+            let delay = self.schedule_frame();
+            if delay > Duration::from_secs(0) {
+                sleep(delay).await;
+            }
+
+            self.run_frame()?;
+            yield_now().await;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::{VirtualClock, FRAME_DURATION};
+
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    // Minimal executor: poll until ready, counting the yields.
+    fn block_on<F: Future>(future: F) -> (F::Output, usize) {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        let mut yields = 0;
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return (output, yields);
+            }
+            yields += 1;
+        }
+    }
+
+    #[test]
+    fn test_run_async() {
+        let mut machine = Machine::new();
+        machine.set_clock(Box::new(VirtualClock::new()));
+        machine.load_rom(&[0x12, 0x00]).unwrap();
+
+        let mut slept = Vec::new();
+        let mut frames = 0;
+        let (result, yields) = block_on(machine.run_async(
+            |delay| {
+                slept.push(delay);
+                async {}
+            },
+            |_| {
+                frames += 1;
+                frames <= 3
+            },
+        ));
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(yields, 3, "the task yields after every frame");
+        // The sleep callback doesn't advance the virtual clock, so each
+        // frame is due one frame later than the last.
+        assert_eq!(slept, vec![FRAME_DURATION, FRAME_DURATION * 2]);
+    }
+}
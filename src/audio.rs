@@ -0,0 +1,150 @@
+// Sound output of a frontend. The machine drives it from the sound timer,
+// calling `start_beep` when the timer becomes non-zero and `stop_beep` when
+// it runs out, so backends only see the edges.
+//
+// Backends that can play raw samples return their rate from `sample_rate`
+// and are handed one frame of audio at a time through `queue_samples`. That
+// is what makes XO-CHIP pattern audio (F002/Fx3A) audible; the edges are
+// still reported as well.
+pub trait Audio {
+    fn start_beep(&mut self);
+
+    fn stop_beep(&mut self);
+
+    // Samples per second wanted by `queue_samples`, or None for a backend
+    // that only cares about the beep edges.
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    // Mono samples in -1.0..=1.0 covering the frame that just ran.
+    fn queue_samples(&mut self, _samples: &[f32]) {}
+}
+
+// Silent audio backend for headless use.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullAudio;
+
+impl Audio for NullAudio {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+}
+
+// Bytes in the XO-CHIP audio pattern buffer.
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+
+// Pattern the buffer holds until a program loads its own: a 500Hz square
+// wave at the default pitch, close to the classic buzzer.
+pub const DEFAULT_AUDIO_PATTERN: [u8; AUDIO_PATTERN_SIZE] = [0xf0; AUDIO_PATTERN_SIZE];
+
+// Pitch register value at power-on.
+pub const DEFAULT_PITCH: u8 = 64;
+
+// Rate in bits per second at which the pattern is played at `pitch`: 4000Hz
+// at the default pitch of 64, an octave up or down every 48 steps.
+#[cfg(feature = "std")]
+pub fn pattern_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+// How long the synth takes to fade the buzzer in or out. A square wave
+// switched on or off at full level clicks.
+#[cfg(feature = "std")]
+const FADE_TIME: f64 = 0.002;
+
+// Turns the 1-bit pattern buffer into samples, a frame at a time. It keeps
+// the position within the pattern between frames so the waveform stays
+// continuous, and fades in and out over FADE_TIME at the edges.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct PatternSynth {
+    // Position in the pattern, in bits.
+    position: f64,
+    // Fraction of a sample left over from the previous frame.
+    carry: f64,
+    // Level of the fade, 0.0 silent to 1.0 full.
+    level: f64,
+}
+
+#[cfg(feature = "std")]
+impl PatternSynth {
+    pub fn new() -> Self {
+        PatternSynth::default()
+    }
+
+    // Append 1/60s of samples at `sample_rate` to `out`. Once `playing`
+    // is false and the fade out is over the samples are silent and the
+    // pattern restarts next time.
+    pub fn render(
+        &mut self,
+        pattern: &[u8; AUDIO_PATTERN_SIZE],
+        pitch: u8,
+        playing: bool,
+        sample_rate: u32,
+        out: &mut Vec<f32>,
+    ) {
+        let wanted = sample_rate as f64 / 60.0 + self.carry;
+        let count = wanted as usize;
+        self.carry = wanted - count as f64;
+
+        if !playing && self.level == 0.0 {
+            self.position = 0.0;
+            out.resize(out.len() + count, 0.0);
+            return;
+        }
+
+        let bits = (AUDIO_PATTERN_SIZE * 8) as f64;
+        let step = pattern_rate(pitch) / sample_rate as f64;
+        let fade = 1.0 / (FADE_TIME * sample_rate as f64);
+        for _ in 0..count {
+            self.level = match playing {
+                true => (self.level + fade).min(1.0),
+                false => (self.level - fade).max(0.0),
+            };
+            let bit = self.position as usize;
+            let set = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            let sample = if set { 0.25 } else { -0.25 };
+            out.push((sample * self.level) as f32);
+            self.position = (self.position + step) % bits;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pattern_rate() {
+        assert_eq!(pattern_rate(DEFAULT_PITCH), 4000.0);
+        assert!((pattern_rate(112) - 8000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut pattern = [0; AUDIO_PATTERN_SIZE];
+        pattern[0] = 0x80;
+        let mut synth = PatternSynth::new();
+        let mut out = Vec::new();
+
+        // At 8000 samples/s each pattern bit lasts two samples, and the
+        // fade in takes 16.
+        synth.render(&pattern, DEFAULT_PITCH, true, 8000, &mut out);
+        assert_eq!(out.len(), 133);
+        assert_eq!(
+            out[..4],
+            [0.25 / 16.0, 0.25 / 8.0, -0.25 * 3.0 / 16.0, -0.25 / 4.0]
+        );
+        assert_eq!(out[16..18], [-0.25, -0.25]);
+
+        // The fade out, then silence. The 1/3 sample left over adds up
+        // over three frames.
+        out.clear();
+        synth.render(&pattern, DEFAULT_PITCH, false, 8000, &mut out);
+        assert!(out[0] < 0.0 && out[0] > -0.25);
+        assert!(out[15..].iter().all(|&sample| sample == 0.0));
+        synth.render(&pattern, DEFAULT_PITCH, false, 8000, &mut out);
+        assert_eq!(out.len(), 267);
+    }
+}
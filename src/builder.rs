@@ -0,0 +1,224 @@
+use crate::error::Result;
+use crate::platform::Platform;
+use crate::processor::{Cpu, SysPolicy};
+use crate::quirks::{QuirkPreset, Quirks};
+use crate::rng::{Rng, XorShiftRng};
+
+// Step-by-step construction of a preconfigured `Cpu`, so tests and embedders
+// don't have to poke at a fresh CPU to set up a scenario:
+//
+//     let cpu = Cpu::builder().rom(&rom).seed(42).pc(0x200).build()?;
+#[derive(Default)]
+pub struct CpuBuilder {
+    rom: Vec<u8>,
+    rng: Option<Box<dyn Rng>>,
+    pc: Option<u16>,
+    program_start: Option<u16>,
+    i: u16,
+    registers: Vec<(usize, u8)>,
+    quirks: Option<Quirks>,
+    memory_size: Option<usize>,
+    platform: Option<Platform>,
+    two_page_hires: bool,
+    sys_policy: Option<SysPolicy>,
+}
+
+impl Cpu {
+    pub fn builder() -> CpuBuilder {
+        CpuBuilder::default()
+    }
+}
+
+impl CpuBuilder {
+    // Program to load at the program start address.
+    pub fn rom(mut self, rom: &[u8]) -> Self {
+        self.rom = rom.to_vec();
+        self
+    }
+
+    // Use the default PRNG with a fixed seed, for reproducible runs.
+    pub fn seed(self, seed: u32) -> Self {
+        self.rng(Box::new(XorShiftRng::new(seed)))
+    }
+
+    // Use a custom random source.
+    pub fn rng(mut self, rng: Box<dyn Rng>) -> Self {
+        self.rng = Some(rng);
+        self
+    }
+
+    // Load address of the ROM and start of execution, 0x200 by default.
+    pub fn program_start(mut self, addr: u16) -> Self {
+        self.program_start = Some(addr);
+        self
+    }
+
+    // Initial program counter, the program start by default.
+    pub fn pc(mut self, pc: u16) -> Self {
+        self.pc = Some(pc);
+        self
+    }
+
+    // Initial index register.
+    pub fn i(mut self, i: u16) -> Self {
+        self.i = i;
+        self
+    }
+
+    // Initial value of register Vx.
+    pub fn register(mut self, x: usize, value: u8) -> Self {
+        self.registers.push((x, value));
+        self
+    }
+
+    // Interpreter behaviors to emulate, `Quirks::default()` otherwise.
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    // Use the quirks, instruction set and memory size of a well-known
+    // interpreter.
+    pub fn preset(self, preset: QuirkPreset) -> Self {
+        let mut builder = self
+            .quirks(preset.quirks())
+            .platform(preset.platform())
+            .memory_size(preset.memory_size());
+        builder.two_page_hires = preset.two_page_hires();
+        builder
+    }
+
+    // Instruction set to accept, all of them by default.
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    // Bytes of RAM, `CHIP8_RAM` by default.
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = Some(size);
+        self
+    }
+
+    pub fn sys_policy(mut self, policy: SysPolicy) -> Self {
+        self.sys_policy = Some(policy);
+        self
+    }
+
+    // Create the CPU. Fails if the ROM does not fit in memory.
+    pub fn build(self) -> Result<Cpu> {
+        let mut cpu = match self.rng {
+            Some(rng) => Cpu::with_rng(rng),
+            None => Cpu::new(),
+        };
+
+        if let Some(size) = self.memory_size {
+            cpu.set_memory_size(size);
+        }
+        if let Some(addr) = self.program_start {
+            cpu.set_program_start(addr);
+        }
+        if !self.rom.is_empty() {
+            cpu.load_program(&self.rom)?;
+        }
+        if let Some(pc) = self.pc {
+            cpu.set_pc(pc);
+        }
+        cpu.set_i(self.i);
+        for (x, value) in self.registers {
+            cpu.set_register(x, value);
+        }
+
+        if let Some(quirks) = self.quirks {
+            cpu.set_quirks(quirks);
+        }
+        if let Some(platform) = self.platform {
+            cpu.set_platform(platform);
+        }
+        cpu.set_two_page_hires(self.two_page_hires);
+        if let Some(policy) = self.sys_policy {
+            cpu.set_sys_policy(policy);
+        }
+
+        Ok(cpu)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::processor::{Cpu, State};
+    use crate::quirks::{QuirkPreset, Quirks};
+
+    #[test]
+    fn test_build() {
+        let mut cpu = Cpu::builder()
+            .rom(&[0x80, 0x14, 0x12, 0x02])
+            .register(0, 0x10)
+            .register(1, 0x20)
+            .i(0x300)
+            .build()
+            .unwrap();
+
+        assert_eq!(cpu.pc(), 0x200);
+        assert_eq!(cpu.i(), 0x300);
+
+        cpu.step().unwrap();
+        assert_eq!(
+            cpu.registers()[0],
+            0x30,
+            "the ROM ran with the given registers"
+        );
+        assert_eq!(cpu.step(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_build_program_start() {
+        let cpu = Cpu::builder()
+            .program_start(0x600)
+            .rom(&[0x16, 0x00])
+            .build()
+            .unwrap();
+
+        assert_eq!(cpu.pc(), 0x600);
+        assert_eq!(cpu.memory().bytes()[0x600], 0x16);
+    }
+
+    #[test]
+    fn test_build_pc() {
+        let cpu = Cpu::builder().pc(0x2f0).build().unwrap();
+        assert_eq!(cpu.pc(), 0x2f0);
+    }
+
+    #[test]
+    fn test_build_quirks() {
+        let quirks = Quirks {
+            memory_increment: true,
+            ..Quirks::default()
+        };
+        let cpu = Cpu::builder().quirks(quirks).build().unwrap();
+        assert_eq!(cpu.quirks(), quirks);
+        assert_eq!(Cpu::builder().build().unwrap().quirks(), Quirks::default());
+
+        let cpu = Cpu::builder().preset(QuirkPreset::Chip48).build().unwrap();
+        assert_eq!(cpu.quirks(), QuirkPreset::Chip48.quirks());
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        // RND V0, 0xff; RND V1, 0xff
+        let rom = [0xc0, 0xff, 0xc1, 0xff];
+        let run = || {
+            let mut cpu = Cpu::builder().rom(&rom).seed(42).build().unwrap();
+            cpu.step().unwrap();
+            cpu.step().unwrap();
+            [cpu.registers()[0], cpu.registers()[1]]
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_build_rom_too_large() {
+        assert!(Cpu::builder().rom(&[0; 4096]).build().is_err());
+    }
+}
@@ -0,0 +1,348 @@
+// The RCA CDP1802, the COSMAC VIP's processor, for the machine code
+// subroutines hybrid programs call through SYS addr (0nnn). It runs
+// directly on the CHIP-8 memory.
+//
+// The VIP interpreter kept its state in the top of a 4K machine, and
+// hybrid programs poke at it there: V0-VF at 0xEF0, the display at 0xF00
+// (256 bytes, one bit per pixel) and the call stack below 0xED0. `Cpu`
+// copies the registers and screen into those places before the call and
+// back afterwards; see `SysPolicy::Cdp1802`.
+
+// Where the VIP interpreter keeps V0-VF...
+pub const VIP_REGISTERS: usize = 0xef0;
+// ...the 64x32 display page...
+pub const VIP_DISPLAY: usize = 0xf00;
+// ...and the top of the machine code stack (R2).
+pub const VIP_STACK: u16 = 0xecf;
+
+// A subroutine returns to the interpreter with SEP R4 (D4).
+const RETURN_REGISTER: u8 = 4;
+
+// Outcome of `Cdp1802::run`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cdp1802Exit {
+    // SEP R4 handed control back to the interpreter.
+    Returned,
+    // The instruction limit ran out first.
+    Stuck,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cdp1802 {
+    // The 16 scratchpad registers.
+    pub r: [u16; 16],
+    pub d: u8,
+    pub df: bool,
+    // Program counter and data pointer register numbers.
+    pub p: u8,
+    pub x: u8,
+    pub t: u8,
+    pub ie: bool,
+    pub q: bool,
+    // Key selected on the VIP keypad latch with OUT 2, read through EF3.
+    key_latch: u8,
+}
+
+impl Cdp1802 {
+    pub fn new() -> Self {
+        Cdp1802::default()
+    }
+
+    // Execute from R(P) until the program returns with SEP R4 or
+    // `max_instructions` have run. `keys` tells whether a keypad key is
+    // down, for EF3.
+    pub fn run<F>(&mut self, memory: &mut [u8], max_instructions: usize, keys: F) -> Cdp1802Exit
+    where
+        F: Fn(u8) -> bool,
+    {
+        for _ in 0..max_instructions {
+            self.step(memory, &keys);
+            if self.p == RETURN_REGISTER {
+                return Cdp1802Exit::Returned;
+            }
+        }
+        Cdp1802Exit::Stuck
+    }
+
+    // Execute one instruction.
+    pub fn step<F>(&mut self, memory: &mut [u8], keys: F)
+    where
+        F: Fn(u8) -> bool,
+    {
+        let opcode = self.fetch(memory);
+        let (i, n) = (opcode >> 4, (opcode & 0xf) as usize);
+        let x = self.x as usize;
+
+        match (i, n) {
+            // IDL waits for an interrupt or DMA, which don't happen here.
+            (0x0, 0) => {}
+            (0x0, _) => self.d = read(memory, self.r[n]),
+            (0x1, _) => self.r[n] = self.r[n].wrapping_add(1),
+            (0x2, _) => self.r[n] = self.r[n].wrapping_sub(1),
+            (0x3, _) => {
+                let taken = self.condition(n & 0x7, &keys) != (n & 0x8 != 0);
+                self.short_branch(memory, taken);
+            }
+            (0x4, _) => {
+                self.d = read(memory, self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            (0x5, _) => write(memory, self.r[n], self.d),
+            (0x6, 0) => self.r[x] = self.r[x].wrapping_add(1),
+            // OUT 1-7: OUT 2 latches the keypad key to test.
+            (0x6, 1..=7) => {
+                let value = read(memory, self.r[x]);
+                if n == 2 {
+                    self.key_latch = value & 0xf;
+                }
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            // INP 1-7: nothing is attached, the bus reads 0.
+            (0x6, _) => {
+                self.d = 0;
+                write(memory, self.r[x], 0);
+            }
+            (0x7, _) => self.op_7n(memory, n),
+            (0x8, _) => self.d = self.r[n] as u8,
+            (0x9, _) => self.d = (self.r[n] >> 8) as u8,
+            (0xa, _) => self.r[n] = self.r[n] & 0xff00 | self.d as u16,
+            (0xb, _) => self.r[n] = self.r[n] & 0x00ff | (self.d as u16) << 8,
+            (0xc, _) => self.op_cn(memory, n, &keys),
+            (0xd, _) => self.p = n as u8,
+            (0xe, _) => self.x = n as u8,
+            (0xf, _) => self.op_fn(memory, n),
+            _ => unreachable!(),
+        }
+    }
+
+    fn fetch(&mut self, memory: &[u8]) -> u8 {
+        let p = self.p as usize;
+        let byte = read(memory, self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        byte
+    }
+
+    // Branch conditions 0-7 of the short and long branches: always, Q, D
+    // zero, DF, then the EF1-EF4 flags (only EF3, the keypad, is wired).
+    fn condition<F: Fn(u8) -> bool>(&self, n: usize, keys: &F) -> bool {
+        match n {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            6 => keys(self.key_latch),
+            _ => false,
+        }
+    }
+
+    fn short_branch(&mut self, memory: &[u8], taken: bool) {
+        let p = self.p as usize;
+        if taken {
+            let target = read(memory, self.r[p]);
+            self.r[p] = self.r[p] & 0xff00 | target as u16;
+        } else {
+            self.r[p] = self.r[p].wrapping_add(1);
+        }
+    }
+
+    fn op_7n(&mut self, memory: &mut [u8], n: usize) {
+        let x = self.x as usize;
+        match n {
+            // RET and DIS
+            0x0 | 0x1 => {
+                let xp = read(memory, self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = xp >> 4;
+                self.p = xp & 0xf;
+                self.ie = n == 0;
+            }
+            // LDXA
+            0x2 => {
+                self.d = read(memory, self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            // STXD
+            0x3 => {
+                write(memory, self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x4 => self.add(read(memory, self.r[x]), self.df),
+            0x5 => self.subtract(read(memory, self.r[x]), self.d, self.df),
+            // SHRC
+            0x6 => {
+                let carry = self.d & 1 != 0;
+                self.d = self.d >> 1 | (self.df as u8) << 7;
+                self.df = carry;
+            }
+            0x7 => self.subtract(self.d, read(memory, self.r[x]), self.df),
+            // SAV
+            0x8 => write(memory, self.r[x], self.t),
+            // MARK
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                write(memory, self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xa => self.q = false,
+            0xb => self.q = true,
+            0xc => {
+                let value = self.fetch(memory);
+                self.add(value, self.df);
+            }
+            0xd => {
+                let value = self.fetch(memory);
+                self.subtract(value, self.d, self.df);
+            }
+            // SHLC
+            0xe => {
+                let carry = self.d & 0x80 != 0;
+                self.d = self.d << 1 | self.df as u8;
+                self.df = carry;
+            }
+            _ => {
+                let value = self.fetch(memory);
+                self.subtract(self.d, value, self.df);
+            }
+        }
+    }
+
+    fn op_cn<F: Fn(u8) -> bool>(&mut self, memory: &[u8], n: usize, keys: &F) {
+        let p = self.p as usize;
+        match n {
+            // NOP
+            0x4 => {}
+            // Long skips: LSNQ, LSNZ, LSNF, LSIE, LSQ, LSZ, LSDF, and LSKP.
+            0x5..=0x8 | 0xc..=0xf => {
+                let skip = match n {
+                    0x5 => !self.q,
+                    0x6 => self.d != 0,
+                    0x7 => !self.df,
+                    0x8 => true,
+                    0xc => self.ie,
+                    0xd => self.q,
+                    0xe => self.d == 0,
+                    _ => self.df,
+                };
+                if skip {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            }
+            // Long branches.
+            _ => {
+                let taken = self.condition(n & 0x3, keys) != (n & 0x8 != 0);
+                if taken {
+                    let high = read(memory, self.r[p]);
+                    let low = read(memory, self.r[p].wrapping_add(1));
+                    self.r[p] = (high as u16) << 8 | low as u16;
+                } else {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            }
+        }
+    }
+
+    fn op_fn(&mut self, memory: &mut [u8], n: usize) {
+        // F0-F7 work on M(R(X)), F8-FF on the immediate byte.
+        let operand = match n {
+            0x6 | 0xe => 0,
+            0x0..=0x7 => read(memory, self.r[self.x as usize]),
+            _ => self.fetch(memory),
+        };
+
+        match n & 0x7 {
+            0x0 => self.d = operand,
+            0x1 => self.d |= operand,
+            0x2 => self.d &= operand,
+            0x3 => self.d ^= operand,
+            0x4 => self.add(operand, false),
+            0x5 => self.subtract(operand, self.d, true),
+            0x6 if n == 0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            0x6 => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => self.subtract(self.d, operand, true),
+        }
+    }
+
+    // D = D + value + carry, DF = carry out.
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xff;
+    }
+
+    // D = a - b, with DF = 0 borrowing (and `no_borrow` false borrowing
+    // one more).
+    fn subtract(&mut self, a: u8, b: u8, no_borrow: bool) {
+        let difference = a as i16 - b as i16 - !no_borrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+// The VIP only decodes as many address lines as it has memory, so
+// addresses past the end wrap around.
+fn read(memory: &[u8], addr: u16) -> u8 {
+    memory[addr as usize % memory.len()]
+}
+
+fn write(memory: &mut [u8], addr: u16, value: u8) {
+    let len = memory.len();
+    memory[addr as usize % len] = value;
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    fn run(code: &[u8]) -> (Cdp1802, Vec<u8>) {
+        let mut memory = vec![0; 0x1000];
+        memory[0x300..0x300 + code.len()].copy_from_slice(code);
+        let mut cpu = Cdp1802::new();
+        cpu.p = 3;
+        cpu.r[3] = 0x300;
+        assert_eq!(cpu.run(&mut memory, 1000, |_| false), Cdp1802Exit::Returned);
+        (cpu, memory)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        // LDI 0xf0; ADI 0x20; SEP R4
+        let (cpu, _) = run(&[0xf8, 0xf0, 0xfc, 0x20, 0xd4]);
+        assert_eq!((cpu.d, cpu.df), (0x10, true));
+
+        // LDI 0x10; SMI 0x20; SEP R4
+        let (cpu, _) = run(&[0xf8, 0x10, 0xff, 0x20, 0xd4]);
+        assert_eq!((cpu.d, cpu.df), (0xf0, false), "DF = 0 on borrow");
+
+        // LDI 0x81; SHL; SEP R4
+        let (cpu, _) = run(&[0xf8, 0x81, 0xfe, 0xd4]);
+        assert_eq!((cpu.d, cpu.df), (0x02, true));
+    }
+
+    #[test]
+    fn test_branches_and_memory() {
+        let (cpu, memory) = run(&[
+            0xf8, 0x03, // LDI 3
+            0xa5, // PLO R5
+            0x25, // loop: DEC R5
+            0x85, // GLO R5
+            0x3a, 0x03, // BNZ loop
+            0xf8, 0x0e, 0xbf, // LDI 0x0e; PHI RF
+            0xf8, 0xf3, 0xaf, // LDI 0xf3; PLO RF
+            0xf8, 0x2a, 0x5f, // LDI 0x2a; STR RF
+            0xc0, 0x03, 0x14, // LBR 0x314
+            0x7b, // SEQ, jumped over
+            0xd4, // SEP R4
+        ]);
+        assert_eq!(memory[0xef3], 0x2a);
+        assert_eq!(cpu.r[5], 0);
+        assert!(!cpu.q);
+        assert_eq!(cpu.r[3], 0x315);
+    }
+}
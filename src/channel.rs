@@ -0,0 +1,289 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use crate::audio::Audio;
+use crate::display::Display;
+use crate::error::{Chip8Error, Result};
+use crate::framebuffer::Framebuffer;
+use crate::input::KeyEvent;
+use crate::machine::Machine;
+
+// Messages sent by the emulator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    // A frame that changed the display has completed.
+    FrameReady(Box<Framebuffer>),
+    BeepStart,
+    BeepStop,
+    // The program ran EXIT. Sent once; frames keep being paced, so a
+    // `Reset` starts it again.
+    Exited,
+    // Emulation stopped because of an error.
+    Error(Chip8Error),
+}
+
+// Messages consumed by the emulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Key(KeyEvent),
+    Pause,
+    Resume,
+    Reset,
+    Quit,
+}
+
+// Display backend forwarding frames as events.
+struct ChannelDisplay(Sender<Event>);
+
+impl Display for ChannelDisplay {
+    fn draw(&mut self, framebuffer: &Framebuffer) {
+        // A receiver that went away just means nobody is watching.
+        let _ = self
+            .0
+            .send(Event::FrameReady(Box::new(framebuffer.clone())));
+    }
+}
+
+// Audio backend forwarding buzzer edges as events.
+struct ChannelAudio(Sender<Event>);
+
+impl Audio for ChannelAudio {
+    fn start_beep(&mut self) {
+        let _ = self.0.send(Event::BeepStart);
+    }
+
+    fn stop_beep(&mut self) {
+        let _ = self.0.send(Event::BeepStop);
+    }
+}
+
+// Drives a `Machine` from `Command` messages and reports back with `Event`
+// messages, as an alternative to implementing the backend traits. Either
+// call `pump` from an existing loop or hand the runner a thread with `run`.
+pub struct ChannelRunner {
+    machine: Machine,
+    commands: Receiver<Command>,
+    events: Sender<Event>,
+    paused: bool,
+    // Whether Event::Exited went out for the current exit.
+    exit_reported: bool,
+}
+
+impl ChannelRunner {
+    // Wrap `machine`, replacing its display and audio backends. Returns the
+    // runner along with the ends of the channels for the frontend.
+    pub fn new(mut machine: Machine) -> (ChannelRunner, Sender<Command>, Receiver<Event>) {
+        let (command_tx, command_rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
+
+        machine.set_display(Box::new(ChannelDisplay(event_tx.clone())));
+        machine.set_audio(Box::new(ChannelAudio(event_tx.clone())));
+
+        let runner = ChannelRunner {
+            machine,
+            commands: command_rx,
+            events: event_tx,
+            paused: false,
+            exit_reported: false,
+        };
+        (runner, command_tx, event_rx)
+    }
+
+    // Apply pending commands, then run one paced frame unless paused.
+    // Returns false once the runner should stop, either because `Quit` was
+    // received or the command sender was dropped. Errors are also sent as
+    // `Event::Error`.
+    pub fn pump(&mut self) -> Result<bool> {
+        loop {
+            match self.commands.try_recv() {
+                Ok(command) => {
+                    if !self.apply(command) {
+                        return Ok(false);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(false),
+            }
+        }
+
+        if self.paused {
+            return Ok(true);
+        }
+
+        match self.machine.run_paced() {
+            Ok(_) => {
+                let exited = self.machine.has_exited();
+                if exited && !self.exit_reported {
+                    let _ = self.events.send(Event::Exited);
+                }
+                self.exit_reported = exited;
+                Ok(true)
+            }
+            Err(error) => {
+                let _ = self.events.send(Event::Error(error.clone()));
+                Err(error)
+            }
+        }
+    }
+
+    // Run until told to quit. While paused the thread blocks on the command
+    // channel instead of spinning.
+    pub fn run(mut self) -> Result<()> {
+        loop {
+            if self.paused {
+                match self.commands.recv() {
+                    Ok(command) => {
+                        if !self.apply(command) {
+                            return Ok(());
+                        }
+                    }
+                    Err(_) => return Ok(()),
+                }
+                continue;
+            }
+
+            if !self.pump()? {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    // Returns false for `Quit`.
+    fn apply(&mut self, command: Command) -> bool {
+        match command {
+            Command::Key(KeyEvent::Pressed(key)) => self.machine.press_key(key),
+            Command::Key(KeyEvent::Released(key)) => self.machine.release_key(key),
+            Command::Pause => self.paused = true,
+            Command::Resume => self.paused = false,
+            Command::Reset => {
+                if let Err(error) = self.machine.reset() {
+                    let _ = self.events.send(Event::Error(error));
+                }
+                self.exit_reported = false;
+            }
+            Command::Quit => return false,
+        }
+        true
+    }
+}
+
+// Create a machine with `factory` on a new thread and run it there,
+// returning the frontend's ends of the channels.
+pub fn spawn<F>(factory: F) -> (Sender<Command>, Receiver<Event>, JoinHandle<Result<()>>)
+where
+    F: FnOnce() -> Machine + Send + 'static,
+{
+    let (setup_tx, setup_rx) = mpsc::channel();
+
+    let thread = thread::spawn(move || {
+        let (runner, commands, events) = ChannelRunner::new(factory());
+        setup_tx
+            .send((commands, events))
+            .expect("chip8.channel: spawner went away");
+        runner.run()
+    });
+
+    let (commands, events) = setup_rx
+        .recv()
+        .expect("chip8.channel: emulation thread failed to start");
+    (commands, events, thread)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::VirtualClock;
+
+    use std::time::{Duration, Instant};
+
+    fn machine(rom: &[u8]) -> Machine {
+        let mut machine = Machine::new();
+        machine.set_clock(Box::new(VirtualClock::new()));
+        machine.load_rom(rom).unwrap();
+        machine
+    }
+
+    #[test]
+    fn test_pump() {
+        // LD V0, 2; LD ST, V0; JP 0x204
+        let (mut runner, commands, events) =
+            ChannelRunner::new(machine(&[0x60, 0x02, 0xf0, 0x18, 0x12, 0x04]));
+
+        assert!(runner.pump().unwrap());
+        assert!(runner.pump().unwrap());
+        assert!(runner.pump().unwrap());
+
+        let received: Vec<Event> = events.try_iter().collect();
+        let blank = Box::new(Framebuffer::new());
+        assert_eq!(
+            received,
+            vec![
+                // Sent when the display is attached, then for the first
+                // frame after loading the ROM.
+                Event::FrameReady(blank.clone()),
+                Event::FrameReady(blank),
+                Event::BeepStart,
+                Event::BeepStop
+            ]
+        );
+
+        commands.send(Command::Quit).unwrap();
+        assert!(!runner.pump().unwrap());
+    }
+
+    #[test]
+    fn test_exited_event() {
+        // EXIT
+        let (mut runner, commands, events) = ChannelRunner::new(machine(&[0x00, 0xfd]));
+        runner.pump().unwrap();
+        runner.pump().unwrap();
+
+        let exits = events.try_iter().filter(|e| *e == Event::Exited).count();
+        assert_eq!(exits, 1, "the exit is reported once");
+
+        commands.send(Command::Reset).unwrap();
+        runner.pump().unwrap();
+        assert!(
+            events.try_iter().any(|e| e == Event::Exited),
+            "and again after a reset"
+        );
+    }
+
+    #[test]
+    fn test_spawn() {
+        // LD V0, K; LD F, V0; DRW V0, V1, 5; JP 0x206
+        let rom = [0xf0, 0x0a, 0xf0, 0x29, 0xd0, 0x15, 0x12, 0x06];
+        let (commands, events, thread) = spawn(move || machine(&rom));
+
+        // Fx0A only latches a release seen while it is waiting, so keep
+        // tapping the key until the drawn glyph comes back.
+        let deadline = Instant::now() + Duration::from_secs(2);
+        loop {
+            assert!(Instant::now() < deadline, "no frame with the glyph");
+            commands.send(Command::Key(KeyEvent::Pressed(0x8))).unwrap();
+            commands
+                .send(Command::Key(KeyEvent::Released(0x8)))
+                .unwrap();
+            match events.recv_timeout(Duration::from_millis(10)) {
+                Ok(Event::FrameReady(fb)) if fb.get(8, 0) => break,
+                _ => {}
+            }
+        }
+
+        commands.send(Command::Quit).unwrap();
+        assert_eq!(thread.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn test_error_event() {
+        let (mut runner, _commands, events) = ChannelRunner::new(machine(&[0x00, 0xee]));
+
+        let error = Chip8Error::StackUnderflow { pc: 0x200 };
+        assert_eq!(runner.pump(), Err(error.clone()));
+        assert_eq!(events.try_iter().last(), Some(Event::Error(error)));
+    }
+}
@@ -0,0 +1,88 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::timers::TIMER_HZ;
+
+// Length of one 60Hz frame.
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
+
+// Time source used for frame pacing. Wall-clock time in normal use, a
+// virtual clock in tests and replays so runs are deterministic and fast.
+pub trait Clock {
+    // Time elapsed since an arbitrary, fixed origin.
+    fn now(&self) -> Duration;
+
+    // Block until `duration` has passed.
+    fn sleep(&mut self, duration: Duration);
+}
+
+// Clock backed by `Instant` and `thread::sleep`.
+#[derive(Clone, Copy, Debug)]
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// Clock that only moves when told to. Sleeping advances it instantly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VirtualClock {
+    now: Duration,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        VirtualClock::default()
+    }
+
+    pub fn advance(&mut self, duration: Duration) {
+        self.now += duration;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.now
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_virtual_clock() {
+        let mut clock = VirtualClock::new();
+        assert_eq!(clock.now(), Duration::from_secs(0));
+
+        clock.sleep(FRAME_DURATION);
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(clock.now(), FRAME_DURATION + Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,370 @@
+use crate::error::Result;
+use crate::instruction::Instruction;
+use crate::machine::Machine;
+use crate::processor::Cpu;
+use crate::quirks::Quirks;
+
+use std::collections::BTreeSet;
+
+// Instructions shown before and after PC in the disassembly.
+const DISASSEMBLY_BEFORE: u16 = 3;
+const DISASSEMBLY_AFTER: u16 = 6;
+// Rows of 8 bytes in the memory panel.
+const MEMORY_ROWS: usize = 8;
+const MEMORY_ROW_BYTES: usize = 8;
+
+// A command typed into the debugger's command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugCommand {
+    // Execute this many instructions, then stay paused.
+    Step(usize),
+    Continue,
+    Pause,
+    // Set or clear a breakpoint at an address.
+    Break(u16),
+    Delete(u16),
+    // Show memory from an address in the memory panel.
+    Memory(u16),
+    // Switch one of the CPU's `Quirks`, by name.
+    Quirk(&'static str, bool),
+}
+
+impl DebugCommand {
+    // Parse "step [N]", "continue", "pause", "break ADDR", "delete ADDR",
+    // "memory ADDR" or "quirk NAME on|off", each also by its first letter.
+    // Addresses are hex.
+    pub fn parse(line: &str) -> Option<DebugCommand> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, argument) = match words[..] {
+            [command] => (command, None),
+            [command, argument] => (command, Some(argument)),
+            ["q", name, value] | ["quirk", name, value] => {
+                let name = Quirks::NAMES.iter().find(|&&quirk| quirk == name)?;
+                return match value {
+                    "on" => Some(DebugCommand::Quirk(name, true)),
+                    "off" => Some(DebugCommand::Quirk(name, false)),
+                    _ => None,
+                };
+            }
+            _ => return None,
+        };
+
+        let address = || u16::from_str_radix(argument?.trim_start_matches("0x"), 16).ok();
+        match command {
+            "s" | "step" => match argument {
+                Some(count) => count.parse().ok().map(DebugCommand::Step),
+                None => Some(DebugCommand::Step(1)),
+            },
+            "c" | "continue" if argument.is_none() => Some(DebugCommand::Continue),
+            "p" | "pause" if argument.is_none() => Some(DebugCommand::Pause),
+            "b" | "break" => address().map(DebugCommand::Break),
+            "d" | "delete" => address().map(DebugCommand::Delete),
+            "m" | "memory" => address().map(DebugCommand::Memory),
+            _ => None,
+        }
+    }
+}
+
+// Breakpoints and single stepping on top of a `Machine`, plus the text
+// panels a frontend shows next to the screen: registers, stack and the
+// disassembly around PC, a memory view and the quirk settings.
+// `chip8 play --debug` drives one, laying the panels out with the same
+// escape sequences as the terminal backends; frontends/tui puts them in
+// ratatui panes, and the egui frontend in tabs.
+#[derive(Clone, Debug)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    paused: bool,
+    // Set when paused at PC, so resuming doesn't stop on the breakpoint
+    // it is sitting on.
+    resuming: bool,
+    // Start of the memory panel.
+    memory: u16,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            paused: false,
+            resuming: false,
+            memory: 0x200,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    pub fn execute(&mut self, command: DebugCommand, machine: &mut Machine) -> Result<()> {
+        match command {
+            DebugCommand::Step(count) => {
+                let mut left = count;
+                // Every false from the predicate lets one instruction run;
+                // a program stuck waiting uses one up per frame.
+                for _ in 0..count {
+                    let stopped = machine.run_frame_until(|_| match left {
+                        0 => true,
+                        _ => {
+                            left -= 1;
+                            false
+                        }
+                    })?;
+                    if stopped.is_none() {
+                        break;
+                    }
+                }
+                self.paused = true;
+                self.resuming = true;
+            }
+            DebugCommand::Continue => self.paused = false,
+            DebugCommand::Pause => {
+                self.paused = true;
+                self.resuming = true;
+            }
+            DebugCommand::Break(addr) => {
+                self.breakpoints.insert(addr);
+            }
+            DebugCommand::Delete(addr) => {
+                self.breakpoints.remove(&addr);
+            }
+            DebugCommand::Memory(addr) => self.memory = addr,
+            DebugCommand::Quirk(name, value) => {
+                let mut quirks = machine.cpu().quirks();
+                quirks.set(name, value);
+                machine.cpu_mut().set_quirks(quirks);
+            }
+        }
+        Ok(())
+    }
+
+    // Run a frame unless paused, pausing at the first breakpoint reached.
+    // Returns whether the display changed.
+    pub fn run_frame(&mut self, machine: &mut Machine) -> Result<bool> {
+        if self.paused {
+            return Ok(false);
+        }
+
+        let mut skip = self.resuming;
+        self.resuming = false;
+        let breakpoints = &self.breakpoints;
+        let stopped = machine.run_frame_until(|cpu| {
+            let hit = !skip && breakpoints.contains(&cpu.pc());
+            skip = false;
+            hit
+        })?;
+
+        match stopped {
+            Some(changed) => Ok(changed),
+            None => {
+                self.paused = true;
+                self.resuming = true;
+                Ok(false)
+            }
+        }
+    }
+
+    // The register, stack and disassembly panels, one string per line.
+    pub fn panel(&self, cpu: &Cpu) -> Vec<String> {
+        let mut lines = self.registers_panel(cpu);
+        lines.push(String::new());
+        lines.extend(self.disassembly_panel(cpu));
+        lines
+    }
+
+    // PC, I, the timers and whether the program is paused, then the
+    // registers and the stack.
+    pub fn registers_panel(&self, cpu: &Cpu) -> Vec<String> {
+        let timers = cpu.timers();
+        let mut lines = vec![format!(
+            "PC {:#05x}  I {:#05x}  DT {:02x}  ST {:02x}  {}",
+            cpu.pc(),
+            cpu.i(),
+            timers.delay(),
+            timers.sound(),
+            if self.paused { "paused" } else { "running" }
+        )];
+
+        for (row, values) in cpu.registers().chunks(4).enumerate() {
+            let cells: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(n, value)| format!("V{:X} {:02x}", row * 4 + n, value))
+                .collect();
+            lines.push(cells.join("  "));
+        }
+
+        let stack: Vec<String> = cpu.stack().iter().map(|a| format!("{:#05x}", a)).collect();
+        lines.push(match stack.is_empty() {
+            true => "stack: empty".to_string(),
+            false => format!("stack: {}", stack.join(" ")),
+        });
+        lines
+    }
+
+    // The instructions around PC, marked with > and breakpoints with *.
+    pub fn disassembly_panel(&self, cpu: &Cpu) -> Vec<String> {
+        let mut lines = Vec::new();
+        let start = cpu.pc().saturating_sub(2 * DISASSEMBLY_BEFORE);
+        let memory = cpu.memory().bytes();
+        for n in 0..=DISASSEMBLY_BEFORE + DISASSEMBLY_AFTER {
+            let addr = match start.checked_add(2 * n) {
+                Some(addr) => addr,
+                None => break,
+            };
+            let (high, low) = match (memory.get(addr as usize), memory.get(addr as usize + 1)) {
+                (Some(&high), Some(&low)) => (high, low),
+                _ => break,
+            };
+            let opcode = (high as u16) << 8 | low as u16;
+            let marker = match (addr == cpu.pc(), self.breakpoints.contains(&addr)) {
+                (true, _) => '>',
+                (false, true) => '*',
+                _ => ' ',
+            };
+            let text = match Instruction::decode(opcode) {
+                Some(instruction) => instruction.to_string(),
+                None => "???".to_string(),
+            };
+            lines.push(format!(
+                "{} {:#05x}  {:04x}  {}",
+                marker, addr, opcode, text
+            ));
+        }
+        lines
+    }
+
+    // A hex dump of the memory from the address set with
+    // `DebugCommand::Memory`, I marked with brackets.
+    pub fn memory_panel(&self, cpu: &Cpu) -> Vec<String> {
+        let memory = cpu.memory().bytes();
+        let start = self.memory as usize;
+        let mut lines = Vec::new();
+        for row in 0..MEMORY_ROWS {
+            let addr = start + row * MEMORY_ROW_BYTES;
+            if addr >= memory.len() {
+                break;
+            }
+            let end = (addr + MEMORY_ROW_BYTES).min(memory.len());
+            let mut line = format!("{:#05x} ", addr);
+            for (n, byte) in memory[addr..end].iter().enumerate() {
+                match addr + n == cpu.i() as usize {
+                    true => line.push_str(&format!("[{:02x}]", byte)),
+                    false => line.push_str(&format!(" {:02x} ", byte)),
+                }
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        lines
+    }
+
+    // The quirk settings, one "name on|off" per line.
+    pub fn quirks_panel(&self, cpu: &Cpu) -> Vec<String> {
+        let quirks = cpu.quirks();
+        Quirks::NAMES
+            .iter()
+            .map(|name| match quirks.get(name) {
+                Some(true) => format!("{:<22} on", name),
+                _ => format!("{:<22} off", name),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(DebugCommand::parse("s"), Some(DebugCommand::Step(1)));
+        assert_eq!(DebugCommand::parse("step 5"), Some(DebugCommand::Step(5)));
+        assert_eq!(
+            DebugCommand::parse("b 0x2a0"),
+            Some(DebugCommand::Break(0x2a0))
+        );
+        assert_eq!(DebugCommand::parse("b"), None);
+        assert_eq!(DebugCommand::parse("c now"), None);
+        assert_eq!(DebugCommand::parse(""), None);
+        assert_eq!(
+            DebugCommand::parse("m 300"),
+            Some(DebugCommand::Memory(0x300))
+        );
+        assert_eq!(
+            DebugCommand::parse("quirk shift off"),
+            Some(DebugCommand::Quirk("shift", false))
+        );
+        assert_eq!(DebugCommand::parse("quirk wrap on"), None);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // ADD V0, 1; JP 0x200
+        let mut machine = Machine::new();
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut debugger = Debugger::new();
+        debugger
+            .execute(DebugCommand::Break(0x202), &mut machine)
+            .unwrap();
+
+        debugger.run_frame(&mut machine).unwrap();
+        assert!(debugger.is_paused());
+        assert_eq!(machine.cpu().pc(), 0x202);
+        assert!(debugger.panel(machine.cpu())[10].starts_with("> 0x202  1200  JP"));
+
+        debugger
+            .execute(DebugCommand::Step(3), &mut machine)
+            .unwrap();
+        assert_eq!(machine.cpu().registers()[0], 2);
+        assert_eq!(machine.cpu().pc(), 0x200);
+
+        // Continuing runs into the breakpoint again.
+        debugger
+            .execute(DebugCommand::Continue, &mut machine)
+            .unwrap();
+        debugger.run_frame(&mut machine).unwrap();
+        assert_eq!(machine.cpu().pc(), 0x202);
+        assert_eq!(machine.cpu().registers()[0], 3);
+    }
+
+    #[test]
+    fn test_panels() {
+        // LD I, 0x202
+        let mut machine = Machine::new();
+        machine.load_rom(&[0xa2, 0x02, 0x12, 0x02]).unwrap();
+        let mut debugger = Debugger::new();
+        debugger
+            .execute(DebugCommand::Step(1), &mut machine)
+            .unwrap();
+        assert_eq!(
+            debugger.memory_panel(machine.cpu())[0],
+            "0x200  a2  02 [12] 02  00  00  00  00"
+        );
+
+        debugger
+            .execute(DebugCommand::Quirk("clipping", true), &mut machine)
+            .unwrap();
+        assert!(machine.cpu().quirks().clipping);
+        assert!(debugger.quirks_panel(machine.cpu())[5].ends_with(" on"));
+    }
+
+    #[test]
+    fn test_disassembly_at_end_of_memory() {
+        let mut machine = Machine::new();
+        machine.cpu_mut().set_memory_size(0x10000);
+        machine.cpu_mut().set_pc(0xfffe);
+        let lines = Debugger::new().disassembly_panel(machine.cpu());
+        assert_eq!(lines.len(), DISASSEMBLY_BEFORE as usize + 1);
+        assert!(lines[DISASSEMBLY_BEFORE as usize].starts_with("> 0xfffe"));
+    }
+}
@@ -0,0 +1,345 @@
+use crate::analysis::QuirkHint;
+use crate::instruction::Instruction;
+use crate::observer::{Observer, RegisterSnapshot};
+use crate::quirks::Quirks;
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+// What `Machine` does with quirk detection, see `Machine::set_quirk_detection`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuirkDetection {
+    // Don't watch the program.
+    Off,
+    // Record findings without changing the quirks.
+    Suggest,
+    // Switch the quirks as soon as something is found.
+    Auto,
+}
+
+// A conclusion about the quirks a program expects, drawn from how it used
+// an ambiguous instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuirkFinding {
+    pub hint: QuirkHint,
+    // What the quirk flag should be set to.
+    pub enabled: bool,
+    // Address of the instruction that gave it away.
+    pub pc: u16,
+    pub reason: &'static str,
+}
+
+impl QuirkFinding {
+    // Set the flag this finding is about in `quirks`.
+    pub fn apply(&self, quirks: &mut Quirks) {
+        match self.hint {
+            QuirkHint::Shift => quirks.shift = self.enabled,
+            QuirkHint::LoadStore => quirks.memory_increment = self.enabled,
+            QuirkHint::Jump => quirks.jump = self.enabled,
+            QuirkHint::VfReset => quirks.vf_reset = self.enabled,
+        }
+    }
+}
+
+impl fmt::Display for QuirkFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let quirk = match self.hint {
+            QuirkHint::Shift => "shift",
+            QuirkHint::LoadStore => "memory increment",
+            QuirkHint::Jump => "jump",
+            QuirkHint::VfReset => "VF reset",
+        };
+        let state = if self.enabled { "on" } else { "off" };
+        write!(
+            f,
+            "{:#05x}: {} quirk {}: {}",
+            self.pc, quirk, state, self.reason
+        )
+    }
+}
+
+// Watches instructions as they run for the telltale signs of a program
+// written for one interpreter or another. Each quirk is decided at most
+// once, by the first unambiguous sign.
+//
+// The heuristics:
+//  - 8xy6/8xyE where only one of Vx and Vy has ever been set: the unset one
+//    can't be the operand.
+//  - Fx65 right after an Fx55 of the same registers without touching I is
+//    reading back what was just stored, so I must not move. Runs of Fx55 or
+//    Fx65 without reloading I step through memory, so I must.
+//  - BXnn where only one of nnn + V0 and nnn + Vx lands inside the program.
+#[derive(Clone, Debug)]
+pub struct QuirkDetector {
+    // Where the program was loaded.
+    program: Range<u16>,
+    // Registers written since the start, one bit each.
+    written: u16,
+    // The last Fx55 (true) or Fx65 (false) and its x, while I hasn't been
+    // changed since.
+    last_load_store: Option<(bool, u8)>,
+    decided: BTreeSet<QuirkHint>,
+    findings: Vec<QuirkFinding>,
+    // How many findings take_new() has handed out.
+    taken: usize,
+}
+
+impl QuirkDetector {
+    // Detector for a program occupying `program` in memory.
+    pub fn new(program: Range<u16>) -> Self {
+        QuirkDetector {
+            program,
+            written: 0,
+            last_load_store: None,
+            decided: BTreeSet::new(),
+            findings: Vec::new(),
+            taken: 0,
+        }
+    }
+
+    // Everything found so far, oldest first.
+    pub fn findings(&self) -> &[QuirkFinding] {
+        &self.findings
+    }
+
+    // Findings made since the last call.
+    pub fn take_new(&mut self) -> &[QuirkFinding] {
+        let new = &self.findings[self.taken..];
+        self.taken = self.findings.len();
+        new
+    }
+
+    fn find(&mut self, hint: QuirkHint, enabled: bool, pc: u16, reason: &'static str) {
+        if self.decided.insert(hint) {
+            self.findings.push(QuirkFinding {
+                hint,
+                enabled,
+                pc,
+                reason,
+            });
+        }
+    }
+
+    fn is_written(&self, x: u8) -> bool {
+        self.written & (1 << x) != 0
+    }
+
+    fn check_shift(&mut self, pc: u16, x: u8, y: u8) {
+        if x == y {
+            return;
+        }
+        match (self.is_written(x), self.is_written(y)) {
+            (true, false) => self.find(QuirkHint::Shift, true, pc, "Vy is never set"),
+            (false, true) => self.find(QuirkHint::Shift, false, pc, "Vx is never set"),
+            _ => {}
+        }
+    }
+
+    fn check_load_store(&mut self, pc: u16, store: bool, x: u8) {
+        match self.last_load_store {
+            Some((true, last_x)) if !store && last_x == x => self.find(
+                QuirkHint::LoadStore,
+                false,
+                pc,
+                "registers are read back right after being stored",
+            ),
+            Some((last_store, _)) if last_store == store => self.find(
+                QuirkHint::LoadStore,
+                true,
+                pc,
+                "memory is walked without reloading I",
+            ),
+            _ => {}
+        }
+        self.last_load_store = Some((store, x));
+    }
+
+    fn check_jump(&mut self, pc: u16, nnn: u16, registers: &RegisterSnapshot) {
+        let x = (nnn >> 8) as usize;
+        let v0_target = nnn + registers.v[0] as u16;
+        let vx_target = nnn + registers.v[x] as u16;
+        if x == 0 || v0_target == vx_target {
+            return;
+        }
+        let reason = match self.program.contains(&vx_target) {
+            true => "nnn + V0 leaves the program",
+            false => "nnn + Vx leaves the program",
+        };
+        if self.program.contains(&v0_target) != self.program.contains(&vx_target) {
+            self.find(
+                QuirkHint::Jump,
+                self.program.contains(&vx_target),
+                pc,
+                reason,
+            );
+        }
+    }
+}
+
+impl Observer for QuirkDetector {
+    fn before_instruction(
+        &mut self,
+        pc: u16,
+        instruction: Instruction,
+        registers: &RegisterSnapshot,
+    ) {
+        use Instruction::*;
+
+        match instruction {
+            ShiftRight(x, y) | ShiftLeft(x, y) => self.check_shift(pc, x, y),
+            StoreRegs(x) => self.check_load_store(pc, true, x),
+            LoadRegs(x) => self.check_load_store(pc, false, x),
+            LoadI(_) | LoadLongI | LoadFont(_) | LoadBigFont(_) | AddI(_) => {
+                self.last_load_store = None
+            }
+            JumpV0(nnn) => self.check_jump(pc, nnn, registers),
+            _ => {}
+        }
+
+        let written = match instruction {
+            LoadByte(x, _)
+            | AddByte(x, _)
+            | LoadReg(x, _)
+            | Or(x, _)
+            | And(x, _)
+            | Xor(x, _)
+            | Random(x, _)
+            | LoadDelay(x)
+            | WaitKey(x) => 1 << x,
+            AddReg(x, _) | Sub(x, _) | SubN(x, _) | ShiftRight(x, _) | ShiftLeft(x, _) => {
+                1 << x | 1 << 0xf
+            }
+            Draw(..) => 1 << 0xf,
+            LoadRegs(x) | LoadFlags(x) => ((2u32 << x) - 1) as u16,
+            LoadRange(x, y) => (((2u32 << x.max(y)) - 1) & !((1u32 << x.min(y)) - 1)) as u16,
+            _ => 0,
+        };
+        self.written |= written;
+    }
+}
+
+// Lets `Machine` keep reading a detector the CPU owns as an observer.
+pub(crate) struct SharedDetector(pub(crate) Rc<RefCell<QuirkDetector>>);
+
+impl Observer for SharedDetector {
+    fn before_instruction(
+        &mut self,
+        pc: u16,
+        instruction: Instruction,
+        registers: &RegisterSnapshot,
+    ) {
+        self.0
+            .borrow_mut()
+            .before_instruction(pc, instruction, registers);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(detector: &mut QuirkDetector, program: &[(u16, Instruction, RegisterSnapshot)]) {
+        for &(pc, instruction, registers) in program {
+            detector.before_instruction(pc, instruction, &registers);
+        }
+    }
+
+    #[test]
+    fn test_shift() {
+        let regs = RegisterSnapshot::default();
+        let mut detector = QuirkDetector::new(0x200..0x300);
+        run(
+            &mut detector,
+            &[
+                (0x200, Instruction::LoadByte(1, 0x80), regs),
+                (0x202, Instruction::ShiftRight(1, 4), regs),
+                (0x204, Instruction::LoadByte(4, 0x80), regs),
+                (0x206, Instruction::ShiftRight(2, 4), regs),
+            ],
+        );
+
+        let findings = detector.findings();
+        assert_eq!(findings.len(), 1, "the quirk is decided once");
+        assert_eq!(findings[0].hint, QuirkHint::Shift);
+        assert!(findings[0].enabled, "V4 was never set, so V1 is shifted");
+        assert_eq!(findings[0].pc, 0x202);
+    }
+
+    #[test]
+    fn test_load_store() {
+        let regs = RegisterSnapshot::default();
+
+        let mut readback = QuirkDetector::new(0x200..0x300);
+        run(
+            &mut readback,
+            &[
+                (0x200, Instruction::StoreRegs(2), regs),
+                (0x202, Instruction::LoadRegs(2), regs),
+            ],
+        );
+        assert!(!readback.findings()[0].enabled);
+
+        let mut walk = QuirkDetector::new(0x200..0x300);
+        run(
+            &mut walk,
+            &[
+                (0x200, Instruction::LoadI(0x300), regs),
+                (0x202, Instruction::LoadRegs(1), regs),
+                (0x204, Instruction::LoadRegs(1), regs),
+            ],
+        );
+        assert!(walk.findings()[0].enabled);
+
+        let mut reload = QuirkDetector::new(0x200..0x300);
+        run(
+            &mut reload,
+            &[
+                (0x200, Instruction::StoreRegs(1), regs),
+                (0x202, Instruction::LoadI(0x300), regs),
+                (0x204, Instruction::StoreRegs(1), regs),
+            ],
+        );
+        assert!(reload.findings().is_empty(), "I was reloaded in between");
+    }
+
+    #[test]
+    fn test_jump() {
+        let mut regs = RegisterSnapshot::default();
+        regs.v[0] = 0x40;
+        regs.v[2] = 0x02;
+
+        let mut detector = QuirkDetector::new(0x200..0x280);
+        run(&mut detector, &[(0x200, Instruction::JumpV0(0x240), regs)]);
+        assert_eq!(
+            detector.take_new(),
+            &[QuirkFinding {
+                hint: QuirkHint::Jump,
+                enabled: true,
+                pc: 0x200,
+                reason: "nnn + V0 leaves the program",
+            }]
+        );
+        assert!(detector.take_new().is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut quirks = Quirks::default();
+        let finding = QuirkFinding {
+            hint: QuirkHint::Shift,
+            enabled: false,
+            pc: 0x200,
+            reason: "Vx is never set",
+        };
+        finding.apply(&mut quirks);
+
+        assert!(!quirks.shift);
+        assert_eq!(
+            finding.to_string(),
+            "0x200: shift quirk off: Vx is never set"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use crate::framebuffer::Framebuffer;
+
+// Output side of a frontend. The machine calls into it whenever the
+// emulated screen changes, so SDL, terminal or WASM renderers don't need to
+// poll the CPU.
+pub trait Display {
+    // Present a new frame.
+    fn draw(&mut self, framebuffer: &Framebuffer);
+
+    // Blank the screen, e.g. on reset.
+    fn clear(&mut self) {}
+
+    // The emulated resolution changed (or is being announced for the first
+    // time).
+    fn set_resolution(&mut self, _width: usize, _height: usize) {}
+
+    // A new MegaChip picture was presented: MEGA_WIDTH x MEGA_HEIGHT ARGB
+    // pixels, to be shown at opacity `alpha`. Called after `draw` while the
+    // mega mode is on; displays without colour can ignore it.
+    #[cfg(feature = "megachip")]
+    fn draw_megachip(&mut self, _frame: &[u32], _alpha: u8) {}
+}
+
+// Display that discards everything, for headless runs and tests.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullDisplay;
+
+impl Display for NullDisplay {
+    fn draw(&mut self, _framebuffer: &Framebuffer) {}
+}
@@ -0,0 +1,158 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::memory::MemoryError;
+
+pub type Result<T> = core::result::Result<T, Chip8Error>;
+
+// Errors raised while loading or executing a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    // The opcode does not decode to any known instruction.
+    UnknownInstruction {
+        opcode: u16,
+        pc: u16,
+    },
+    // SYS addr was executed while `SysPolicy::Error` is in effect.
+    UnsupportedSys {
+        addr: u16,
+        pc: u16,
+    },
+    // Machine code called by SYS addr under `SysPolicy::Cdp1802` didn't
+    // return to the interpreter.
+    MachineCodeStuck {
+        addr: u16,
+        pc: u16,
+    },
+    // CALL with all 16 stack levels in use.
+    StackOverflow {
+        pc: u16,
+    },
+    // RET with an empty stack.
+    StackUnderflow {
+        pc: u16,
+    },
+    // An instruction at `pc` touched memory past the end of RAM.
+    MemoryOutOfBounds {
+        addr: usize,
+        pc: u16,
+    },
+    // An instruction at `pc` wrote to write-protected memory.
+    WriteProtected {
+        addr: usize,
+        pc: u16,
+    },
+    // A ROM with no bytes in it.
+    EmptyRom,
+    // A ROM bigger than the memory available to programs.
+    RomTooLarge {
+        size: usize,
+        max: usize,
+    },
+    // Reading a ROM failed. Holds the message rather than the `io::Error`
+    // so the error stays cloneable and comparable.
+    #[cfg(feature = "std")]
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
+    // Game metadata that couldn't be parsed.
+    InvalidMetadata {
+        reason: &'static str,
+    },
+    // An IPS patch that couldn't be applied.
+    InvalidPatch {
+        reason: &'static str,
+    },
+    // A save state from `Cpu::save_state` that couldn't be restored.
+    InvalidSaveState {
+        reason: &'static str,
+    },
+    // A ZIP archive that couldn't be read.
+    #[cfg(feature = "zip")]
+    InvalidArchive {
+        reason: &'static str,
+    },
+    // The archive has no entry by that name (or no .ch8 entry at all).
+    #[cfg(feature = "zip")]
+    RomNotInArchive {
+        name: Option<String>,
+    },
+}
+
+impl Chip8Error {
+    // Attach the PC of the faulting instruction to a memory bus error.
+    pub(crate) fn from_memory(error: MemoryError, pc: u16) -> Chip8Error {
+        match error {
+            MemoryError::OutOfBounds(addr) => Chip8Error::MemoryOutOfBounds { addr, pc },
+            MemoryError::WriteProtected(addr) => Chip8Error::WriteProtected { addr, pc },
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownInstruction { opcode, pc } => {
+                write!(f, "unknown instruction {:#06x} at {:#05x}", opcode, pc)
+            }
+            Chip8Error::UnsupportedSys { addr, pc } => {
+                write!(f, "unsupported SYS {:#05x} at {:#05x}", addr, pc)
+            }
+            Chip8Error::MachineCodeStuck { addr, pc } => write!(
+                f,
+                "machine code at {:#05x} called from {:#05x} never returned",
+                addr, pc
+            ),
+            Chip8Error::StackOverflow { pc } => write!(f, "stack overflow at {:#05x}", pc),
+            Chip8Error::StackUnderflow { pc } => write!(f, "stack underflow at {:#05x}", pc),
+            Chip8Error::MemoryOutOfBounds { addr, pc } => write!(
+                f,
+                "memory access out of bounds at {:#06x} (pc {:#05x})",
+                addr, pc
+            ),
+            Chip8Error::WriteProtected { addr, pc } => write!(
+                f,
+                "write to protected memory at {:#06x} (pc {:#05x})",
+                addr, pc
+            ),
+            Chip8Error::EmptyRom => write!(f, "ROM is empty"),
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes, only {} fit in memory", size, max)
+            }
+            #[cfg(feature = "std")]
+            Chip8Error::Io { message, .. } => write!(f, "I/O error: {}", message),
+            Chip8Error::InvalidMetadata { reason } => {
+                write!(f, "invalid game metadata: {}", reason)
+            }
+            Chip8Error::InvalidPatch { reason } => write!(f, "invalid IPS patch: {}", reason),
+            Chip8Error::InvalidSaveState { reason } => {
+                write!(f, "invalid save state: {}", reason)
+            }
+            #[cfg(feature = "zip")]
+            Chip8Error::InvalidArchive { reason } => write!(f, "invalid ZIP archive: {}", reason),
+            #[cfg(feature = "zip")]
+            Chip8Error::RomNotInArchive { name: Some(name) } => {
+                write!(f, "no entry named {} in the archive", name)
+            }
+            #[cfg(feature = "zip")]
+            Chip8Error::RomNotInArchive { name: None } => write!(f, "no .ch8 entry in the archive"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for Chip8Error {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Chip8Error {
+    fn from(error: io::Error) -> Chip8Error {
+        Chip8Error::Io {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
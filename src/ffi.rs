@@ -0,0 +1,476 @@
+// A C API, for reusing the emulator from C, C++, Zig or anything else with
+// a C FFI. Everything is numbers and pointers into the library's memory,
+// which also makes a wasm32-unknown-unknown build a module JavaScript can
+// drive: web/chip8.js wraps it in a class. That module is built without
+// wasm-bindgen, which isn't among the dependencies this crate can use; the
+// glue it would generate is chip8.js, and chip8_alloc()/chip8_dealloc()
+// stand in for its buffer passing. Those two are for JavaScript only and
+// are left out of the C header. For C the
+// declarations are in include/chip8.h, which cbindgen regenerates from
+// this file with the settings in cbindgen.toml.
+//
+//     cargo rustc --release --lib --crate-type cdylib --features ffi
+//     cargo rustc --release --lib --crate-type cdylib \
+//         --target wasm32-unknown-unknown --features wasm
+//
+// The functions taking a `*mut Chip8Machine` must be given a pointer from
+// chip8_new() that hasn't been freed yet, and the ones taking a buffer a
+// pointer to at least `len` bytes.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::keymap::KeyMap;
+use crate::machine::Machine;
+use crate::palette::Palette;
+use crate::phosphor::PhosphorFilter;
+use crate::png::{encode_png, scale_rgba};
+use crate::processor::{Cpu, State};
+use crate::quirks::{QuirkPreset, Quirks};
+use crate::recording::{Recorder, RecordingFormat};
+use crate::rng::XorShiftRng;
+use crate::texture::RgbaTexture;
+
+// chip8_step() results.
+pub const CHIP8_STATE_RUNNING: i32 = 0;
+pub const CHIP8_STATE_WAITING_FOR_KEY: i32 = 1;
+pub const CHIP8_STATE_WAITING_FOR_VBLANK: i32 = 2;
+pub const CHIP8_STATE_EXITED: i32 = 3;
+pub const CHIP8_STATE_HALTED: i32 = 4;
+pub const CHIP8_STATE_CRASHED: i32 = -1;
+
+// chip8_start_recording() formats.
+pub const CHIP8_RECORD_GIF: i32 = 0;
+pub const CHIP8_RECORD_APNG: i32 = 1;
+
+// chip8_set_preset() interpreters, in `QuirkPreset::ALL` order.
+pub const CHIP8_PRESET_CHIP8: i32 = 0;
+pub const CHIP8_PRESET_CHIP8_HIRES: i32 = 1;
+pub const CHIP8_PRESET_CHIP48: i32 = 2;
+pub const CHIP8_PRESET_SCHIP_LEGACY: i32 = 3;
+pub const CHIP8_PRESET_SCHIP: i32 = 4;
+pub const CHIP8_PRESET_XOCHIP: i32 = 5;
+
+// chip8_quirks() bits, one per `Quirks::NAMES` entry in that order.
+pub const CHIP8_QUIRK_SHIFT: u32 = 1 << 0;
+pub const CHIP8_QUIRK_MEMORY_INCREMENT: u32 = 1 << 1;
+pub const CHIP8_QUIRK_MEMORY_INCREMENT_BY_X: u32 = 1 << 2;
+pub const CHIP8_QUIRK_JUMP: u32 = 1 << 3;
+pub const CHIP8_QUIRK_VF_RESET: u32 = 1 << 4;
+pub const CHIP8_QUIRK_CLIPPING: u32 = 1 << 5;
+pub const CHIP8_QUIRK_DISPLAY_WAIT: u32 = 1 << 6;
+pub const CHIP8_QUIRK_INDEX_OVERFLOW: u32 = 1 << 7;
+pub const CHIP8_QUIRK_HALF_SCROLL: u32 = 1 << 8;
+pub const CHIP8_QUIRK_VF_ORDER: u32 = 1 << 9;
+
+// A machine together with the RGBA copy of its screen callers read.
+pub struct Chip8Machine {
+    machine: Machine,
+    keymap: KeyMap,
+    rgba: RgbaTexture,
+    // The last chip8_save_state().
+    state: Vec<u8>,
+    // The last chip8_screenshot().
+    screenshot: Vec<u8>,
+    recorder: Option<Recorder>,
+    // The last chip8_stop_recording().
+    recording: Vec<u8>,
+}
+
+// A machine whose random numbers are seeded with `seed`, e.g. time(NULL)
+// in C or Math.random() * 2 ** 32 in a browser, which has no clock for the
+// library to read.
+#[no_mangle]
+pub extern "C" fn chip8_new(seed: u32) -> *mut Chip8Machine {
+    let cpu = Cpu::with_rng(Box::new(XorShiftRng::new(seed)));
+    Box::into_raw(Box::new(Chip8Machine {
+        machine: Machine::with_cpu(cpu),
+        keymap: KeyMap::standard(),
+        rgba: RgbaTexture::new(Palette::default().rgba()),
+        state: Vec::new(),
+        screenshot: Vec::new(),
+        recorder: None,
+        recording: Vec::new(),
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(machine: *mut Chip8Machine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+// A buffer of `len` bytes in the wasm module's memory, for JavaScript to
+// copy ROMs and save states into. Not in chip8.h: C callers pass their own
+// buffers.
+#[no_mangle]
+pub extern "C" fn chip8_alloc(len: usize) -> *mut u8 {
+    let mut buffer = vec![0u8; len].into_boxed_slice();
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_dealloc(ptr: *mut u8, len: usize) {
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)));
+}
+
+// Load the `len` byte ROM at `rom` and start it. Returns false if it was
+// refused.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(
+    machine: *mut Chip8Machine,
+    rom: *const u8,
+    len: usize,
+) -> bool {
+    let rom = std::slice::from_raw_parts(rom, len);
+    (*machine).machine.load_rom(rom).is_ok()
+}
+
+// Run one frame. 1 if the screen changed, 0 if not, -1 if the program
+// crashed. With phosphor decay on, pixels still fading count as changes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(machine: *mut Chip8Machine) -> i32 {
+    let chip8 = &mut *machine;
+    let result = chip8.machine.run_frame();
+    if let (Ok(_), Some(recorder)) = (&result, &mut chip8.recorder) {
+        recorder.capture(chip8.machine.framebuffer());
+    }
+    match result {
+        Ok(changed) if chip8.rgba.has_phosphor() => {
+            let fading = chip8.rgba.is_fading();
+            chip8.rgba.update(chip8.machine.framebuffer());
+            (changed || fading) as i32
+        }
+        Ok(changed) => changed as i32,
+        Err(_) => -1,
+    }
+}
+
+// Execute one instruction, returning one of the CHIP8_STATE_* values.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(machine: *mut Chip8Machine) -> i32 {
+    match (*machine).machine.step() {
+        Ok(State::Running) => CHIP8_STATE_RUNNING,
+        Ok(State::WaitingForKey) => CHIP8_STATE_WAITING_FOR_KEY,
+        Ok(State::WaitingForVblank) => CHIP8_STATE_WAITING_FOR_VBLANK,
+        Ok(State::Exited) => CHIP8_STATE_EXITED,
+        Ok(State::Halted) => CHIP8_STATE_HALTED,
+        Err(_) => CHIP8_STATE_CRASHED,
+    }
+}
+
+// Restart the loaded ROM. False if it no longer fits in memory.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(machine: *mut Chip8Machine) -> bool {
+    (*machine).machine.reset().is_ok()
+}
+
+// Emulate one of the CHIP8_PRESET_* interpreters: its quirks, instruction
+// set and memory size, e.g. 64K for XO-CHIP. Call before chip8_load_rom().
+// False for an unknown preset.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_preset(machine: *mut Chip8Machine, preset: i32) -> bool {
+    if preset < 0 {
+        return false;
+    }
+    let preset = match QuirkPreset::ALL.get(preset as usize) {
+        Some(&preset) => preset,
+        None => return false,
+    };
+    (*machine).machine.cpu_mut().set_preset(preset);
+    true
+}
+
+// The quirks in effect as CHIP8_QUIRK_* bits.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_quirks(machine: *mut Chip8Machine) -> u32 {
+    let quirks = (*machine).machine.cpu().quirks();
+    Quirks::NAMES
+        .iter()
+        .enumerate()
+        .filter(|&(_, name)| quirks.get(name) == Some(true))
+        .fold(0, |bits, (n, _)| bits | 1 << n)
+}
+
+// Turn on the quirks whose CHIP8_QUIRK_* bits are set and the others off,
+// from the next instruction.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_quirks(machine: *mut Chip8Machine, bits: u32) {
+    let mut quirks = Quirks::new();
+    for (n, name) in Quirks::NAMES.iter().enumerate() {
+        quirks.set(name, bits & 1 << n != 0);
+    }
+    (*machine).machine.cpu_mut().set_quirks(quirks);
+}
+
+// Instructions run per frame, see `Machine::set_cycles_per_frame`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_speed(machine: *mut Chip8Machine) -> usize {
+    (*machine).machine.cycles_per_frame()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_speed(machine: *mut Chip8Machine, cycles: usize) {
+    (*machine).machine.set_cycles_per_frame(cycles);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key_down(machine: *mut Chip8Machine, key: u8) {
+    (*machine).machine.press_key(key);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key_up(machine: *mut Chip8Machine, key: u8) {
+    (*machine).machine.release_key(key);
+}
+
+// The hex key the keyboard key producing the character `code` stands
+// for, or -1, so the page uses the same layout as the other frontends.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key_for_char(machine: *mut Chip8Machine, code: u32) -> i32 {
+    std::char::from_u32(code)
+        .and_then(|c| (*machine).keymap.key(c))
+        .map_or(-1, |key| key as i32)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_width(machine: *mut Chip8Machine) -> usize {
+    (*machine).machine.framebuffer().width()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_height(machine: *mut Chip8Machine) -> usize {
+    (*machine).machine.framebuffer().height()
+}
+
+// The screen as width * height RGBA pixels, valid until the next call into
+// the machine: the canvas' ImageData format, and a texture upload away on
+// most graphics APIs.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_frame(machine: *mut Chip8Machine) -> *const u8 {
+    let chip8 = &mut *machine;
+    // Phosphor decay advances with chip8_run_frame instead.
+    let fb = chip8.machine.framebuffer();
+    let resized = (fb.width(), fb.height()) != (chip8.rgba.width(), chip8.rgba.height());
+    if !chip8.rgba.has_phosphor() || resized {
+        chip8.rgba.update(fb);
+    }
+    chip8.rgba.pixels().as_ptr()
+}
+
+// Fade pixels out over `frames` frames instead of turning them off at once,
+// which hides the flicker of XOR drawing; 0 turns it off. See
+// `PhosphorFilter`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_phosphor(machine: *mut Chip8Machine, frames: u8) {
+    let phosphor = match frames {
+        0 => None,
+        _ => Some(PhosphorFilter::new(frames)),
+    };
+    (*machine).rgba.set_phosphor(phosphor);
+}
+
+// Colour chip8_frame() with 12 bytes of RGB: off, plane 1, plane 2 and
+// both, see `Palette`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_palette(machine: *mut Chip8Machine, colors: *const u8) {
+    let bytes = std::slice::from_raw_parts(colors, 12);
+    let mut palette = Palette::default();
+    for (value, color) in bytes.chunks_exact(3).enumerate() {
+        palette.set_color(value as u8, [color[0], color[1], color[2]]);
+    }
+    (*machine).rgba.set_colors(palette.rgba());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_is_beeping(machine: *mut Chip8Machine) -> bool {
+    (*machine).machine.is_beeping()
+}
+
+// Save the machine's state, see `Cpu::save_state`. Returns a pointer to
+// the bytes, chip8_state_len() long and valid until the next save.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(machine: *mut Chip8Machine) -> *const u8 {
+    let chip8 = &mut *machine;
+    chip8.state = chip8.machine.save_state();
+    chip8.state.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_state_len(machine: *mut Chip8Machine) -> usize {
+    (*machine).state.len()
+}
+
+// Restore the `len` bytes of a saved state at `state`. Returns false if
+// they were refused.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(
+    machine: *mut Chip8Machine,
+    state: *const u8,
+    len: usize,
+) -> bool {
+    let state = std::slice::from_raw_parts(state, len);
+    (*machine).machine.load_state(state).is_ok()
+}
+
+// The screen as chip8_frame() shows it, palette and phosphor decay
+// included, as a PNG with every pixel `scale` pixels square. Returns a
+// pointer to the bytes, chip8_screenshot_len() long and valid until the
+// next screenshot.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_screenshot(machine: *mut Chip8Machine, scale: usize) -> *const u8 {
+    chip8_frame(machine);
+    let chip8 = &mut *machine;
+    let (width, height) = (chip8.rgba.width(), chip8.rgba.height());
+    let scale = scale.max(1);
+    let rgba = scale_rgba(width, height, chip8.rgba.pixels(), scale);
+    chip8.screenshot = encode_png(width * scale, height * scale, &rgba);
+    chip8.screenshot.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_screenshot_len(machine: *mut Chip8Machine) -> usize {
+    (*machine).screenshot.len()
+}
+
+// Start recording every chip8_run_frame() into an animation, in
+// CHIP8_RECORD_GIF or CHIP8_RECORD_APNG format, in the current palette and
+// every pixel `scale` pixels square. A recording already going on is
+// dropped. Returns false for an unknown format.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_start_recording(
+    machine: *mut Chip8Machine,
+    format: i32,
+    scale: usize,
+) -> bool {
+    let format = match format {
+        CHIP8_RECORD_GIF => RecordingFormat::Gif,
+        CHIP8_RECORD_APNG => RecordingFormat::Apng,
+        _ => return false,
+    };
+    let chip8 = &mut *machine;
+    let palette = Palette::new(chip8.rgba.colors().map(|[r, g, b, _]| [r, g, b]));
+    chip8.recorder = Some(Recorder::new(format, palette, scale));
+    true
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_is_recording(machine: *mut Chip8Machine) -> bool {
+    (*machine).recorder.is_some()
+}
+
+// Stop recording. Returns a pointer to the GIF or APNG file,
+// chip8_recording_len() long and valid until the next stop; empty if
+// nothing was recorded.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_stop_recording(machine: *mut Chip8Machine) -> *const u8 {
+    let chip8 = &mut *machine;
+    chip8.recording = match chip8.recorder.take() {
+        Some(recorder) => recorder.finish(),
+        None => Vec::new(),
+    };
+    chip8.recording.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_recording_len(machine: *mut Chip8Machine) -> usize {
+    (*machine).recording.len()
+}
+
+// The 16 RPL user flags, for keeping them in the page's storage.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_rpl_flags(machine: *mut Chip8Machine) -> *const u8 {
+    (*machine).machine.cpu().rpl_flags().as_ptr()
+}
+
+// Set the RPL flags from the 16 bytes at `flags`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_rpl_flags(machine: *mut Chip8Machine, flags: *const u8) {
+    let mut rpl = [0; 16];
+    rpl.copy_from_slice(std::slice::from_raw_parts(flags, 16));
+    (*machine).machine.cpu_mut().set_rpl_flags(rpl);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_exports() {
+        unsafe {
+            let machine = chip8_new(1);
+            // CLS; LD F, V0 (0); DRW V0, V0, 5; JP 0x206
+            let rom = [0x00, 0xe0, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x06];
+            let buffer = chip8_alloc(rom.len());
+            std::ptr::copy_nonoverlapping(rom.as_ptr(), buffer, rom.len());
+            assert!(chip8_load_rom(machine, buffer, rom.len()));
+            chip8_dealloc(buffer, rom.len());
+
+            assert_eq!(chip8_step(machine), CHIP8_STATE_RUNNING);
+            assert_eq!(chip8_run_frame(machine), 1);
+            let (width, height) = (chip8_width(machine), chip8_height(machine));
+            let frame = std::slice::from_raw_parts(chip8_frame(machine), width * height * 4);
+            assert_eq!(frame[..4], [0xff; 4], "the top left of the 0 glyph");
+            let octo: Vec<u8> = Palette::OCTO.colors().concat();
+            chip8_set_palette(machine, octo.as_ptr());
+            let frame = std::slice::from_raw_parts(chip8_frame(machine), width * height * 4);
+            assert_eq!(frame[..4], [0xff, 0xcc, 0x00, 0xff]);
+            chip8_set_phosphor(machine, 4);
+            assert_eq!(chip8_run_frame(machine), 0, "nothing is fading yet");
+            chip8_set_phosphor(machine, 0);
+            assert_eq!(chip8_key_for_char(machine, 'w' as u32), 0x5);
+            assert_eq!(chip8_key_for_char(machine, 'p' as u32), -1);
+
+            let state = chip8_save_state(machine);
+            let len = chip8_state_len(machine);
+            let saved = std::slice::from_raw_parts(state, len).to_vec();
+            chip8_run_frame(machine);
+            assert!(chip8_load_state(machine, saved.as_ptr(), len));
+            assert!(!chip8_load_state(machine, saved.as_ptr(), 3));
+
+            let png = chip8_screenshot(machine, 2);
+            let png = std::slice::from_raw_parts(png, chip8_screenshot_len(machine));
+            assert_eq!(png[1..4], *b"PNG");
+            assert_eq!(png[16..24], [0, 0, 0, 128, 0, 0, 0, 64], "64x32 at scale 2");
+
+            assert!(!chip8_start_recording(machine, 7, 1));
+            assert!(chip8_start_recording(machine, CHIP8_RECORD_GIF, 1));
+            chip8_run_frame(machine);
+            chip8_run_frame(machine);
+            let gif = chip8_stop_recording(machine);
+            let gif = std::slice::from_raw_parts(gif, chip8_recording_len(machine));
+            assert_eq!(gif[..6], *b"GIF89a");
+            assert!(!chip8_is_recording(machine));
+
+            chip8_set_rpl_flags(machine, [7; 16].as_ptr());
+            assert_eq!(*chip8_rpl_flags(machine).add(15), 7);
+            chip8_reset(machine);
+            assert_eq!(chip8_step(machine), CHIP8_STATE_RUNNING);
+            chip8_set_speed(machine, 30);
+            assert_eq!(chip8_speed(machine), 30);
+            chip8_free(machine);
+        }
+    }
+
+    #[test]
+    fn test_presets() {
+        unsafe {
+            let machine = chip8_new(1);
+            assert!(!chip8_load_rom(machine, [0; 8000].as_ptr(), 8000));
+            assert!(!chip8_set_preset(machine, 6));
+            assert!(chip8_set_preset(machine, CHIP8_PRESET_XOCHIP));
+            assert!(chip8_load_rom(machine, [0; 8000].as_ptr(), 8000));
+            assert_eq!(chip8_quirks(machine), CHIP8_QUIRK_MEMORY_INCREMENT);
+
+            chip8_set_quirks(machine, CHIP8_QUIRK_CLIPPING | CHIP8_QUIRK_VF_ORDER);
+            let quirks = (*machine).machine.cpu().quirks();
+            assert!(quirks.clipping && quirks.vf_order && !quirks.memory_increment);
+            assert_eq!(
+                chip8_quirks(machine),
+                CHIP8_QUIRK_CLIPPING | CHIP8_QUIRK_VF_ORDER
+            );
+            chip8_free(machine);
+        }
+    }
+}
@@ -0,0 +1,483 @@
+pub const CHIP8_WIDTH: usize = 64;
+pub const CHIP8_HEIGHT: usize = 32;
+
+// SUPER-CHIP high resolution mode.
+pub const SCHIP_WIDTH: usize = 128;
+pub const SCHIP_HEIGHT: usize = 64;
+
+// Two-page hires CHIP-8 on the COSMAC VIP: the usual width, twice the rows.
+pub const HIRES_CHIP8_HEIGHT: usize = 64;
+
+// Both XO-CHIP bitplanes.
+pub const ALL_PLANES: u8 = 0b11;
+
+// The largest screen a program can switch to, which the pixel store is
+// sized for so that it needs no heap.
+#[cfg(not(feature = "megachip"))]
+const MAX_WIDTH: usize = SCHIP_WIDTH;
+#[cfg(not(feature = "megachip"))]
+const MAX_HEIGHT: usize = SCHIP_HEIGHT;
+#[cfg(feature = "megachip")]
+const MAX_WIDTH: usize = crate::megachip::MEGA_WIDTH;
+#[cfg(feature = "megachip")]
+const MAX_HEIGHT: usize = crate::megachip::MEGA_HEIGHT;
+
+// Display memory, one byte per pixel in row-major order. Starts out at
+// 64x32; SUPER-CHIP programs can switch it to 128x64.
+//
+// Each pixel is a 2-bit value: bit 0 is the first plane, the only one
+// CHIP-8 and SUPER-CHIP draw to, and bit 1 the second XO-CHIP plane. Plain
+// CHIP-8 pixels are therefore 0 or 1; XO-CHIP frontends map all four values
+// to a palette. The MegaChip mode stores full palette indices instead.
+//
+// It also tracks which rows changed, so frontends can redraw only those:
+// the rows written since `end_frame` was last called, and the rows the
+// frame before that changed, which is what `dirty_rows` reports. The Cpu
+// ends a frame after its instructions ran, before the frame is drawn.
+//
+// The arrays have room for the largest screen; only the first width *
+// height pixels and height rows are in use.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: [u8; MAX_WIDTH * MAX_HEIGHT],
+    // Rows changed in the frame being run.
+    touched: [bool; MAX_HEIGHT],
+    // Rows changed in the last finished frame.
+    dirty: [bool; MAX_HEIGHT],
+}
+
+// Screens are equal by their pixels; which rows changed doesn't matter.
+impl PartialEq for Framebuffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width && self.height == other.height && self.pixels() == other.pixels()
+    }
+}
+
+impl Eq for Framebuffer {}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer::new()
+    }
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Framebuffer::with_size(CHIP8_WIDTH, CHIP8_HEIGHT)
+    }
+
+    // A blank framebuffer of the given size, at most the largest screen a
+    // program can switch to. Every row counts as dirty, since nothing has
+    // been drawn yet.
+    pub fn with_size(width: usize, height: usize) -> Self {
+        assert!(
+            width * height <= MAX_WIDTH * MAX_HEIGHT && height <= MAX_HEIGHT,
+            "chip8.framebuffer: unsupported size {}x{}",
+            width,
+            height
+        );
+        Framebuffer {
+            width,
+            height,
+            pixels: [0; MAX_WIDTH * MAX_HEIGHT],
+            touched: [true; MAX_HEIGHT],
+            dirty: [true; MAX_HEIGHT],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Whether the SUPER-CHIP 128x64 mode is active.
+    pub fn is_hires(&self) -> bool {
+        self.width == SCHIP_WIDTH
+    }
+
+    // Make this a copy of `other`, copying only the pixels in use. Double
+    // buffering frontends do this every frame.
+    pub fn copy_from(&mut self, other: &Framebuffer) {
+        let len = other.width * other.height;
+        self.width = other.width;
+        self.height = other.height;
+        self.pixels[..len].copy_from_slice(&other.pixels[..len]);
+        self.touched = other.touched;
+        self.dirty = other.dirty;
+    }
+
+    // Switch to a new resolution. The screen is blanked.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        *self = Framebuffer::with_size(width, height);
+    }
+
+    // Whether the pixel at (x, y) is lit in any plane.
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixel(x, y) != 0
+    }
+
+    // The 2-bit value of the pixel at (x, y).
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    // Overwrite the pixel at (x, y), e.g. with a MegaChip palette index.
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        let pixel = &mut self.pixels[y * self.width + x];
+        if *pixel != value {
+            *pixel = value;
+            self.touched[y] = true;
+        }
+    }
+
+    // XOR a lit pixel onto (x, y) in the first plane. Returns true if the
+    // pixel was erased.
+    pub fn toggle(&mut self, x: usize, y: usize) -> bool {
+        self.toggle_planes(x, y, 1)
+    }
+
+    // XOR the planes in `planes` at (x, y). Returns true if any of them
+    // was erased.
+    pub fn toggle_planes(&mut self, x: usize, y: usize, planes: u8) -> bool {
+        let pixel = &mut self.pixels[y * self.width + x];
+        let erased = *pixel & planes != 0;
+        *pixel ^= planes;
+        self.touched[y] |= planes != 0;
+        erased
+    }
+
+    pub fn clear(&mut self) {
+        self.clear_planes(ALL_PLANES);
+    }
+
+    // Blank the planes in `planes`, leaving the others alone.
+    pub fn clear_planes(&mut self, planes: u8) {
+        let (width, len) = (self.width, self.width * self.height);
+        for (y, row) in self.pixels[..len].chunks_exact_mut(width).enumerate() {
+            if row.iter().any(|&pixel| pixel & planes != 0) {
+                row.iter_mut().for_each(|pixel| *pixel &= !planes);
+                self.touched[y] = true;
+            }
+        }
+    }
+
+    // Move the picture down by `n` rows, blanking the rows uncovered at
+    // the top. Pixels scrolled off the edge are lost.
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize, ALL_PLANES);
+    }
+
+    // Move the picture up by `n` rows.
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n as isize), ALL_PLANES);
+    }
+
+    // Move the picture right by `n` columns.
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0, ALL_PLANES);
+    }
+
+    // Move the picture left by `n` columns.
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0, ALL_PLANES);
+    }
+
+    // Move the planes in `planes` by (dx, dy) pixels. Uncovered pixels are
+    // blanked and the other planes stay where they are.
+    pub fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        let old = self.pixels;
+        let (width, height) = (self.width as isize, self.height as isize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let moved = match (0..width).contains(&sx) && (0..height).contains(&sy) {
+                    true => old[(sy * width + sx) as usize] & planes,
+                    false => 0,
+                };
+                let pixel = &mut self.pixels[(y * width + x) as usize];
+                let value = *pixel & !planes | moved;
+                self.touched[y as usize] |= *pixel != value;
+                *pixel = value;
+            }
+        }
+    }
+
+    // A single row of pixels.
+    pub fn row(&self, y: usize) -> &[u8] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    // Finish a frame: the rows changed since the last call become the
+    // ones `dirty_rows` reports.
+    pub fn end_frame(&mut self) {
+        self.dirty.copy_from_slice(&self.touched);
+        self.touched.fill(false);
+    }
+
+    // Whether row `y` changed in the last finished frame.
+    pub fn is_row_dirty(&self, y: usize) -> bool {
+        self.dirty[y]
+    }
+
+    // The rows that changed in the last finished frame, top to bottom.
+    pub fn dirty_rows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.height).filter(move |&y| self.dirty[y])
+    }
+
+    // All pixels, width * height of them row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels[..self.width * self.height]
+    }
+
+    // Fill `out` with the screen as bitmasks, 64 pixels to a u64 with the
+    // leftmost in the top bit, so a 64x32 screen is one word per row and a
+    // 128x64 one two. A bit is set where the pixel is lit in any of
+    // `planes`. `out` must hold width / 64 (rounded up) times height words.
+    pub fn write_packed(&self, planes: u8, out: &mut [u64]) {
+        let words = self.width.div_ceil(64);
+        assert_eq!(
+            out.len(),
+            words * self.height,
+            "chip8.framebuffer: packed buffer of the wrong size"
+        );
+        out.fill(0);
+        for (index, &pixel) in self.pixels().iter().enumerate() {
+            if pixel & planes != 0 {
+                let (y, x) = (index / self.width, index % self.width);
+                out[y * words + x / 64] |= 1 << (63 - x % 64);
+            }
+        }
+    }
+
+    // Every pixel as (x, y, value), row by row. This is the shape of
+    // embedded-graphics' DrawTarget::draw_iter, so blitting to a display
+    // driver is a map to its Pixel type.
+    pub fn iter_pixels(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        let width = self.width;
+        self.pixels()
+            .iter()
+            .enumerate()
+            .map(move |(index, &pixel)| (index % width, index / width, pixel))
+    }
+
+    // Fill `out` with the screen scaled by `scale` in the page layout of
+    // monochrome OLED controllers like the SSD1306: one byte per column of
+    // 8 rows, bit 0 at the top, pages left to right and then downwards. Any
+    // lit plane sets a bit. `out` must hold width * scale times
+    // height * scale / 8 (rounded up) bytes; a 64x32 screen at scale 2
+    // fills a 128x64 panel's 1024.
+    pub fn write_pages(&self, scale: usize, out: &mut [u8]) {
+        let (width, height) = (self.width * scale, self.height * scale);
+        assert_eq!(
+            out.len(),
+            width * height.div_ceil(8),
+            "chip8.framebuffer: page buffer of the wrong size"
+        );
+        for (index, byte) in out.iter_mut().enumerate() {
+            let (page, x) = (index / width, index % width);
+            *byte = (0..8)
+                .map(|bit| page * 8 + bit)
+                .filter(|&y| y < height && self.get(x / scale, y / scale))
+                .fold(0, |bits, y| bits | 1 << (y % 8));
+        }
+    }
+
+    // Fill `out` with RGBA8 pixels, the format of pixels-style frame
+    // buffers and canvas image data, colouring each pixel value with
+    // `colors`. `out` must hold width * height * 4 bytes.
+    pub fn write_rgba(&self, colors: &[[u8; 4]; 4], out: &mut [u8]) {
+        assert_eq!(
+            out.len(),
+            self.pixels().len() * 4,
+            "chip8.framebuffer: RGBA buffer of the wrong size"
+        );
+        for (rgba, &pixel) in out.chunks_exact_mut(4).zip(self.pixels()) {
+            rgba.copy_from_slice(&colors[pixel as usize & ALL_PLANES as usize]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_toggle() {
+        let mut fb = Framebuffer::new();
+
+        assert!(!fb.toggle(3, 4), "lighting a pixel is not a collision");
+        assert!(fb.get(3, 4));
+        assert_eq!(fb.row(4)[3], 1);
+
+        assert!(fb.toggle(3, 4), "erasing a pixel is a collision");
+        assert!(!fb.get(3, 4));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut fb = Framebuffer::new();
+        fb.toggle(0, 0);
+        fb.toggle(63, 31);
+        fb.clear();
+
+        assert_eq!(fb, Framebuffer::new());
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut fb = Framebuffer::new();
+        fb.toggle(0, 0);
+        fb.resize(SCHIP_WIDTH, SCHIP_HEIGHT);
+
+        assert!(fb.is_hires());
+        assert_eq!((fb.width(), fb.height()), (128, 64));
+        assert!(!fb.get(0, 0), "the screen is blanked");
+        assert!(!fb.toggle(127, 63));
+        assert_eq!(fb.row(63).len(), 128);
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported size")]
+    fn test_too_big() {
+        Framebuffer::with_size(SCHIP_WIDTH * 4, SCHIP_HEIGHT * 4);
+    }
+
+    #[test]
+    fn test_write_rgba() {
+        let mut fb = Framebuffer::with_size(2, 1);
+        fb.toggle(1, 0);
+        let colors = [[0, 0, 0, 0xff], [0xff; 4], [1; 4], [2; 4]];
+        let mut out = [0; 8];
+        fb.write_rgba(&colors, &mut out);
+
+        assert_eq!(out, [0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut fb = Framebuffer::new();
+        fb.toggle(0, 0);
+        fb.toggle(63, 31);
+
+        fb.scroll_down(2);
+        assert!(fb.get(0, 2));
+        assert_eq!(fb.row(31), &[0; 64][..], "the bottom row fell off");
+
+        fb.scroll_up(1);
+        assert!(fb.get(0, 1));
+        fb.scroll_down(1);
+
+        fb.scroll_right(4);
+        assert!(fb.get(4, 2));
+        assert!(!fb.get(0, 2));
+
+        fb.scroll_left(3);
+        assert!(fb.get(1, 2));
+        fb.scroll_left(4);
+        assert_eq!(fb, Framebuffer::new(), "everything scrolled away");
+    }
+
+    #[test]
+    fn test_planes() {
+        let mut fb = Framebuffer::new();
+
+        assert!(!fb.toggle_planes(1, 1, 0b10));
+        assert_eq!(fb.pixel(1, 1), 2);
+        assert!(fb.get(1, 1), "a pixel in the second plane is lit");
+        assert!(!fb.toggle(1, 1));
+        assert_eq!(fb.pixel(1, 1), 3);
+
+        fb.scroll(1, 0, 0b10);
+        assert_eq!(
+            (fb.pixel(1, 1), fb.pixel(2, 1)),
+            (1, 2),
+            "only plane 2 moved"
+        );
+
+        fb.clear_planes(0b01);
+        assert_eq!((fb.pixel(1, 1), fb.pixel(2, 1)), (0, 2));
+        assert!(
+            fb.toggle_planes(2, 1, ALL_PLANES),
+            "erasing any plane collides"
+        );
+        assert_eq!(fb.pixel(2, 1), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_iter_pixels() {
+        let mut fb = Framebuffer::with_size(4, 2);
+        fb.toggle(3, 1);
+        let lit: Vec<_> = fb
+            .iter_pixels()
+            .filter(|&(_, _, pixel)| pixel != 0)
+            .collect();
+        assert_eq!(lit, [(3, 1, 1)]);
+        assert_eq!(fb.iter_pixels().count(), 8);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_dirty_rows() {
+        let mut fb = Framebuffer::new();
+        fb.end_frame();
+        assert_eq!(
+            fb.dirty_rows().count(),
+            CHIP8_HEIGHT,
+            "a new screen is dirty"
+        );
+        fb.end_frame();
+        assert_eq!(fb.dirty_rows().next(), None);
+
+        fb.toggle(1, 3);
+        fb.set_pixel(2, 5, 0);
+        fb.clear_planes(0b10);
+        assert_eq!(fb.dirty_rows().next(), None, "the frame is not over");
+        fb.end_frame();
+        assert_eq!(fb.dirty_rows().collect::<Vec<_>>(), [3]);
+
+        fb.scroll_down(1);
+        fb.end_frame();
+        assert_eq!(fb.dirty_rows().collect::<Vec<_>>(), [3, 4]);
+        assert!(fb.is_row_dirty(4) && !fb.is_row_dirty(5));
+    }
+
+    #[test]
+    fn test_write_packed() {
+        let mut fb = Framebuffer::new();
+        fb.toggle(0, 0);
+        fb.toggle(63, 1);
+        fb.toggle_planes(1, 1, 0b10);
+        let mut out = [0; CHIP8_HEIGHT];
+        fb.write_packed(ALL_PLANES, &mut out);
+        assert_eq!(out[..3], [1 << 63, 1 << 62 | 1, 0]);
+        fb.write_packed(1, &mut out);
+        assert_eq!(out[1], 1);
+        assert_eq!(fb.pixels().len(), CHIP8_WIDTH * CHIP8_HEIGHT);
+
+        fb.resize(SCHIP_WIDTH, SCHIP_HEIGHT);
+        fb.toggle(64, 0);
+        let mut out = [0; 2 * SCHIP_HEIGHT];
+        fb.write_packed(ALL_PLANES, &mut out);
+        assert_eq!(out[..2], [0, 1 << 63]);
+    }
+
+    #[test]
+    fn test_write_pages() {
+        let mut fb = Framebuffer::new();
+        fb.toggle(0, 0);
+        fb.toggle(1, 5);
+        let mut pages = [0; 1024];
+        fb.write_pages(2, &mut pages);
+        assert_eq!(pages[..4], [0b11, 0b11, 0, 0]);
+        // Row 5 is rows 10 and 11 scaled, bits 2 and 3 of the second page.
+        assert_eq!(pages[128 + 2..128 + 4], [0b1100, 0b1100]);
+        assert_eq!(pages.iter().filter(|&&byte| byte != 0).count(), 4);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::input::{Input, KeyEvent};
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Stick deflection, out of 32767, past which an axis counts as a d-pad
+// direction.
+const AXIS_THRESHOLD: i16 = 16384;
+
+// Size of a Linux `struct js_event`.
+pub const JS_EVENT_SIZE: usize = 8;
+const JS_EVENT_BUTTON: u8 = 0x01;
+const JS_EVENT_AXIS: u8 = 0x02;
+// Marks the events the driver sends on open to report the initial state.
+const JS_EVENT_INIT: u8 = 0x80;
+
+// A controller button, named after the position on the pad rather than the
+// label, since labels differ between vendors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GamepadButton {
+    Up,
+    Down,
+    Left,
+    Right,
+    South,
+    East,
+    West,
+    North,
+    LeftShoulder,
+    RightShoulder,
+    Select,
+    Start,
+}
+
+impl GamepadButton {
+    pub const ALL: [GamepadButton; 12] = [
+        GamepadButton::Up,
+        GamepadButton::Down,
+        GamepadButton::Left,
+        GamepadButton::Right,
+        GamepadButton::South,
+        GamepadButton::East,
+        GamepadButton::West,
+        GamepadButton::North,
+        GamepadButton::LeftShoulder,
+        GamepadButton::RightShoulder,
+        GamepadButton::Select,
+        GamepadButton::Start,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GamepadButton::Up => "up",
+            GamepadButton::Down => "down",
+            GamepadButton::Left => "left",
+            GamepadButton::Right => "right",
+            GamepadButton::South => "south",
+            GamepadButton::East => "east",
+            GamepadButton::West => "west",
+            GamepadButton::North => "north",
+            GamepadButton::LeftShoulder => "l",
+            GamepadButton::RightShoulder => "r",
+            GamepadButton::Select => "select",
+            GamepadButton::Start => "start",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GamepadButton> {
+        GamepadButton::ALL
+            .iter()
+            .copied()
+            .find(|button| button.name() == name)
+    }
+}
+
+// Which controller button presses which hex key. Like `KeyMap`, a button
+// presses one key, and several buttons may press the same one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GamepadMap {
+    buttons: Vec<(GamepadButton, u8)>,
+}
+
+impl Default for GamepadMap {
+    fn default() -> Self {
+        GamepadMap::standard()
+    }
+}
+
+impl GamepadMap {
+    // The d-pad on 2/4/6/8, which most games steer with, the bottom face
+    // button on 5 and the rest on keys games commonly use for actions.
+    pub fn standard() -> Self {
+        GamepadMap {
+            buttons: vec![
+                (GamepadButton::Up, 0x2),
+                (GamepadButton::Down, 0x8),
+                (GamepadButton::Left, 0x4),
+                (GamepadButton::Right, 0x6),
+                (GamepadButton::South, 0x5),
+                (GamepadButton::East, 0x0),
+                (GamepadButton::West, 0xa),
+                (GamepadButton::North, 0xb),
+                (GamepadButton::Select, 0xe),
+                (GamepadButton::Start, 0xf),
+            ],
+        }
+    }
+
+    // A map with no buttons, to be filled in with `bind`.
+    pub fn empty() -> Self {
+        GamepadMap {
+            buttons: Vec::new(),
+        }
+    }
+
+    // Parse a table like "up=2,down=8,south=5" on top of the standard
+    // map. Keys are hex.
+    pub fn parse(spec: &str) -> Option<GamepadMap> {
+        let mut map = GamepadMap::standard();
+        for entry in spec.split(',').filter(|entry| !entry.is_empty()) {
+            let (name, key) = entry.split_once('=')?;
+            let button = GamepadButton::from_name(name.trim())?;
+            let key = u8::from_str_radix(key.trim(), 16)
+                .ok()
+                .filter(|&key| key < 16)?;
+            map.bind(button, key);
+        }
+        Some(map)
+    }
+
+    // Make `button` press hex key `key`, replacing what it did before.
+    pub fn bind(&mut self, button: GamepadButton, key: u8) {
+        self.buttons.retain(|&(bound, _)| bound != button);
+        self.buttons.push((button, key & 0xf));
+    }
+
+    // The hex key `button` stands for.
+    pub fn key(&self, button: GamepadButton) -> Option<u8> {
+        self.buttons
+            .iter()
+            .find(|&&(bound, _)| bound == button)
+            .map(|&(_, key)| key)
+    }
+}
+
+// Turn one Linux joystick event into the buttons it changes, with their
+// new state. Button numbers and axes follow the common xpad layout: face
+// buttons 0-3, shoulders 4-5, select and start 6-7, the left stick on axes
+// 0-1 and the d-pad hat on axes 6-7.
+pub fn decode_js_event(event: &[u8; JS_EVENT_SIZE]) -> Vec<(GamepadButton, bool)> {
+    let value = i16::from_le_bytes([event[4], event[5]]);
+    let kind = event[6] & !JS_EVENT_INIT;
+    let number = event[7];
+    match kind {
+        JS_EVENT_BUTTON => {
+            let button = match number {
+                0 => GamepadButton::South,
+                1 => GamepadButton::East,
+                2 => GamepadButton::West,
+                3 => GamepadButton::North,
+                4 => GamepadButton::LeftShoulder,
+                5 => GamepadButton::RightShoulder,
+                6 => GamepadButton::Select,
+                7 => GamepadButton::Start,
+                _ => return Vec::new(),
+            };
+            vec![(button, value != 0)]
+        }
+        JS_EVENT_AXIS => {
+            let (negative, positive) = match number {
+                0 | 6 => (GamepadButton::Left, GamepadButton::Right),
+                1 | 7 => (GamepadButton::Up, GamepadButton::Down),
+                _ => return Vec::new(),
+            };
+            vec![
+                (negative, value < -AXIS_THRESHOLD),
+                (positive, value > AXIS_THRESHOLD),
+            ]
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Keys from a game controller, using the Linux joystick interface
+// (/dev/input/js*) so no gamepad library is needed. A thread reads the
+// device; `is_pressed` follows the buttons held, so holding a button holds
+// its key. The desktop frontends read pads through gilrs, SDL or Bevy
+// instead, with the same GamepadMap.
+pub struct GamepadInput {
+    events: Receiver<[u8; JS_EVENT_SIZE]>,
+    map: GamepadMap,
+    held: Vec<GamepadButton>,
+}
+
+impl GamepadInput {
+    // Input from js_event records arriving on `events`.
+    pub fn new(events: Receiver<[u8; JS_EVENT_SIZE]>, map: GamepadMap) -> Self {
+        GamepadInput {
+            events,
+            map,
+            held: Vec::new(),
+        }
+    }
+
+    // Open a joystick device and read it on a thread of its own.
+    pub fn open<P: AsRef<Path>>(path: P, map: GamepadMap) -> io::Result<Self> {
+        let mut device = File::open(path)?;
+        let (sender, events) = mpsc::channel();
+        thread::spawn(move || {
+            let mut event = [0; JS_EVENT_SIZE];
+            while device.read_exact(&mut event).is_ok() {
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(GamepadInput::new(events, map))
+    }
+}
+
+impl Input for GamepadInput {
+    // Takes in the events since the last frame. The keypad follows
+    // `is_pressed`, so no key events are needed.
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        for event in self.events.try_iter() {
+            for (button, pressed) in decode_js_event(&event) {
+                self.held.retain(|&held| held != button);
+                if pressed {
+                    self.held.push(button);
+                }
+            }
+        }
+        None
+    }
+
+    fn is_pressed(&self, key: u8) -> bool {
+        self.held
+            .iter()
+            .any(|&button| self.map.key(button) == Some(key & 0xf))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn js_event(value: i16, kind: u8, number: u8) -> [u8; JS_EVENT_SIZE] {
+        let value = value.to_le_bytes();
+        [0, 0, 0, 0, value[0], value[1], kind, number]
+    }
+
+    #[test]
+    fn test_map() {
+        let map = GamepadMap::parse("south=c, up=3").unwrap();
+        assert_eq!(map.key(GamepadButton::South), Some(0xc));
+        assert_eq!(map.key(GamepadButton::Up), Some(0x3));
+        assert_eq!(map.key(GamepadButton::Down), Some(0x8));
+        assert_eq!(map.key(GamepadButton::LeftShoulder), None);
+        assert_eq!(GamepadMap::parse("up=10"), None);
+        assert_eq!(GamepadMap::parse("jump=1"), None);
+        assert_eq!(GamepadMap::parse(""), Some(GamepadMap::standard()));
+    }
+
+    #[test]
+    fn test_gamepad_input() {
+        let (sender, events) = mpsc::channel();
+        let mut input = GamepadInput::new(events, GamepadMap::standard());
+
+        // The initial state on open, then the stick pushed left and the
+        // bottom face button pressed.
+        sender
+            .send(js_event(1, JS_EVENT_BUTTON | JS_EVENT_INIT, 0))
+            .unwrap();
+        sender.send(js_event(-32767, JS_EVENT_AXIS, 0)).unwrap();
+        assert_eq!(input.poll_event(), None);
+        assert!(input.is_pressed(0x5));
+        assert!(input.is_pressed(0x4));
+        assert!(!input.is_pressed(0x6));
+
+        sender.send(js_event(0, JS_EVENT_BUTTON, 0)).unwrap();
+        sender.send(js_event(32767, JS_EVENT_AXIS, 6)).unwrap();
+        input.poll_event();
+        assert!(!input.is_pressed(0x5));
+        assert!(!input.is_pressed(0x4));
+        assert!(input.is_pressed(0x6));
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use crate::error::{Chip8Error, Result};
+use crate::framebuffer::Framebuffer;
+use crate::input::KeyEvent;
+use crate::machine::Machine;
+
+// State shared between the handle and the emulation thread.
+struct Shared {
+    state: Mutex<SharedState>,
+    // Signalled when the thread should wake up from a pause.
+    wake: Condvar,
+}
+
+struct SharedState {
+    paused: bool,
+    quit: bool,
+    // Key events injected by the frontend, applied before the next frame.
+    keys: Vec<KeyEvent>,
+    // Copy of the display after the last frame that changed it.
+    framebuffer: Framebuffer,
+    frames: u64,
+    beeping: bool,
+    exited: bool,
+    // The error that stopped emulation, if any.
+    error: Option<Chip8Error>,
+}
+
+// Runs a `Machine` on a background thread, paced at 60 frames per second by
+// the machine's clock. GUI frontends talk to it from their event loop
+// through this handle instead of sharing the machine directly.
+//
+// The machine is created on the emulation thread by the factory passed to
+// `spawn`, so its backends don't need to be `Send`.
+pub struct EmulatorHandle {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EmulatorHandle {
+    pub fn spawn<F>(factory: F) -> Self
+    where
+        F: FnOnce() -> Machine + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(SharedState {
+                paused: false,
+                quit: false,
+                keys: Vec::new(),
+                framebuffer: Framebuffer::new(),
+                frames: 0,
+                beeping: false,
+                exited: false,
+                error: None,
+            }),
+            wake: Condvar::new(),
+        });
+
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || run(factory(), &thread_shared));
+
+        EmulatorHandle {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn pause(&self) {
+        self.lock().paused = true;
+    }
+
+    pub fn resume(&self) {
+        self.lock().paused = false;
+        self.shared.wake.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    pub fn press_key(&self, key: u8) {
+        self.lock().keys.push(KeyEvent::Pressed(key));
+    }
+
+    pub fn release_key(&self, key: u8) {
+        self.lock().keys.push(KeyEvent::Released(key));
+    }
+
+    // Copy of the most recently completed frame.
+    pub fn framebuffer(&self) -> Framebuffer {
+        self.lock().framebuffer.clone()
+    }
+
+    // Number of frames emulated so far.
+    pub fn frames(&self) -> u64 {
+        self.lock().frames
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.lock().beeping
+    }
+
+    // Whether the program ran EXIT, so the frontend can close its window.
+    pub fn has_exited(&self) -> bool {
+        self.lock().exited
+    }
+
+    // The error that stopped the emulation thread, if it failed.
+    pub fn error(&self) -> Option<Chip8Error> {
+        self.lock().error.clone()
+    }
+
+    // Stop the emulation thread and wait for it to finish. Returns the
+    // error that stopped it early, if any.
+    pub fn stop(mut self) -> Result<()> {
+        self.shutdown();
+        match self.error() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn shutdown(&mut self) {
+        self.lock().quit = true;
+        self.shared.wake.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            thread
+                .join()
+                .expect("chip8.handle: emulation thread panicked");
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SharedState> {
+        self.shared.state.lock().unwrap()
+    }
+}
+
+impl Drop for EmulatorHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// Body of the emulation thread.
+fn run(mut machine: Machine, shared: &Shared) {
+    loop {
+        let keys = {
+            let mut state = shared.state.lock().unwrap();
+            while state.paused && !state.quit {
+                state = shared.wake.wait(state).unwrap();
+            }
+            if state.quit {
+                return;
+            }
+            std::mem::take(&mut state.keys)
+        };
+
+        for event in keys {
+            match event {
+                KeyEvent::Pressed(key) => machine.press_key(key),
+                KeyEvent::Released(key) => machine.release_key(key),
+            }
+        }
+
+        let result = machine.run_paced();
+
+        let mut state = shared.state.lock().unwrap();
+        match result {
+            Ok(changed) => {
+                if changed {
+                    state.framebuffer = machine.framebuffer().clone();
+                }
+                state.frames += 1;
+                state.beeping = machine.is_beeping();
+                state.exited = machine.has_exited();
+            }
+            Err(error) => {
+                state.error = Some(error);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clock::VirtualClock;
+
+    use std::time::{Duration, Instant};
+
+    // Poll `condition` for up to two seconds.
+    fn wait_for<F: Fn() -> bool>(condition: F) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        false
+    }
+
+    fn machine(rom: &'static [u8]) -> impl FnOnce() -> Machine + Send {
+        move || {
+            let mut machine = Machine::new();
+            machine.set_clock(Box::new(VirtualClock::new()));
+            machine.load_rom(rom).unwrap();
+            machine
+        }
+    }
+
+    #[test]
+    fn test_key_injection_and_framebuffer() {
+        // LD V0, K; LD F, V0; DRW V0, V1, 5; JP 0x206
+        let handle =
+            EmulatorHandle::spawn(machine(&[0xf0, 0x0a, 0xf0, 0x29, 0xd0, 0x15, 0x12, 0x06]));
+
+        assert!(wait_for(|| handle.frames() > 0));
+        assert_eq!(handle.framebuffer(), Framebuffer::new());
+
+        handle.press_key(0x1);
+        handle.release_key(0x1);
+        assert!(wait_for(|| handle.framebuffer() != Framebuffer::new()));
+        assert!(handle.stop().is_ok());
+    }
+
+    #[test]
+    fn test_pause_resume() {
+        let handle = EmulatorHandle::spawn(machine(&[0x12, 0x00]));
+        assert!(wait_for(|| handle.frames() > 0));
+
+        handle.pause();
+        assert!(handle.is_paused());
+        // Let the thread finish any frame already in flight.
+        thread::sleep(Duration::from_millis(20));
+        let frames = handle.frames();
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(handle.frames(), frames, "no frames run while paused");
+
+        handle.resume();
+        assert!(wait_for(|| handle.frames() > frames));
+    }
+
+    #[test]
+    fn test_error_stops_thread() {
+        // RET with an empty stack.
+        let handle = EmulatorHandle::spawn(machine(&[0x00, 0xee]));
+
+        assert!(wait_for(|| handle.error().is_some()));
+        assert_eq!(handle.stop(), Err(Chip8Error::StackUnderflow { pc: 0x200 }));
+    }
+}
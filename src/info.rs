@@ -0,0 +1,58 @@
+use std::fmt;
+
+use crate::analysis::RomAnalysis;
+use crate::hash::{crc32, sha1_hex};
+
+// Summary of a ROM file for curating collections: size, checksums and the
+// platform it was written for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    pub size: usize,
+    // Lowercase hex, the key used by `RomDatabase`.
+    pub sha1: String,
+    pub crc32: u32,
+    pub analysis: RomAnalysis,
+}
+
+impl RomInfo {
+    // Describe `rom` as loaded at `start`.
+    pub fn new(rom: &[u8], start: u16) -> Self {
+        RomInfo {
+            size: rom.len(),
+            sha1: sha1_hex(rom),
+            crc32: crc32(rom),
+            analysis: RomAnalysis::analyze(rom, start),
+        }
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "size:     {} bytes", self.size)?;
+        writeln!(f, "sha1:     {}", self.sha1)?;
+        writeln!(f, "crc32:    {:08x}", self.crc32)?;
+        match self.analysis.platform {
+            Some(platform) => write!(f, "platform: {}", platform),
+            None => write!(f, "platform: unknown (no code found)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let info = RomInfo::new(&[0x00, 0xff, 0x12, 0x02], 0x200);
+
+        assert_eq!(
+            info.to_string(),
+            format!(
+                "size:     4 bytes\nsha1:     {}\ncrc32:    {:08x}\nplatform: SUPER-CHIP",
+                sha1_hex(&[0x00, 0xff, 0x12, 0x02]),
+                crc32(&[0x00, 0xff, 0x12, 0x02])
+            )
+        );
+    }
+}
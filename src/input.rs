@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+
+use crate::keypad::CHIP8_NUM_KEYS;
+
+// A change in the state of one hex key (0x0-0xF).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    Pressed(u8),
+    Released(u8),
+}
+
+// Input side of a frontend. The machine drains pending events at the start
+// of every frame, then checks `is_pressed` so level-based sources (e.g. a
+// polled gamepad) can't leave a key stuck if an edge was missed.
+pub trait Input {
+    // The next pending key event, if any.
+    fn poll_event(&mut self) -> Option<KeyEvent>;
+
+    // Whether `key` is currently held down.
+    fn is_pressed(&self, key: u8) -> bool;
+}
+
+// Input source with no keys, for headless runs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullInput;
+
+impl Input for NullInput {
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        None
+    }
+
+    fn is_pressed(&self, _key: u8) -> bool {
+        false
+    }
+}
+
+// A FIFO of key events. Frontends push events from their own event loop,
+// and scripted or replayed sessions can queue them up ahead of time.
+//
+// `is_pressed` reflects the events delivered so far, not the ones still
+// queued.
+#[derive(Clone, Debug, Default)]
+pub struct EventQueue {
+    pending: VecDeque<KeyEvent>,
+    keys: [bool; CHIP8_NUM_KEYS],
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        EventQueue::default()
+    }
+
+    pub fn push(&mut self, event: KeyEvent) {
+        self.pending.push_back(event);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl Input for EventQueue {
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        let event = self.pending.pop_front()?;
+        match event {
+            KeyEvent::Pressed(key) => self.keys[key as usize & 0xf] = true,
+            KeyEvent::Released(key) => self.keys[key as usize & 0xf] = false,
+        }
+        Some(event)
+    }
+
+    fn is_pressed(&self, key: u8) -> bool {
+        self.keys[key as usize & 0xf]
+    }
+}
+
+// Several input sources at once, such as a keyboard and a gamepad. Each
+// frame drains every source in turn; a key counts as pressed if any source
+// holds it.
+#[derive(Default)]
+pub struct CombinedInput {
+    inputs: Vec<Box<dyn Input>>,
+    // Source being drained this frame.
+    current: usize,
+}
+
+impl CombinedInput {
+    pub fn new(inputs: Vec<Box<dyn Input>>) -> Self {
+        CombinedInput { inputs, current: 0 }
+    }
+}
+
+impl Input for CombinedInput {
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        while let Some(input) = self.inputs.get_mut(self.current) {
+            if let Some(event) = input.poll_event() {
+                return Some(event);
+            }
+            self.current += 1;
+        }
+        self.current = 0;
+        None
+    }
+
+    fn is_pressed(&self, key: u8) -> bool {
+        self.inputs.iter().any(|input| input.is_pressed(key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_queue() {
+        let mut queue = EventQueue::new();
+        queue.push(KeyEvent::Pressed(0x5));
+        queue.push(KeyEvent::Released(0x5));
+        assert!(!queue.is_pressed(0x5), "events are not delivered yet");
+
+        assert_eq!(queue.poll_event(), Some(KeyEvent::Pressed(0x5)));
+        assert!(queue.is_pressed(0x5));
+
+        assert_eq!(queue.poll_event(), Some(KeyEvent::Released(0x5)));
+        assert!(!queue.is_pressed(0x5));
+
+        assert_eq!(queue.poll_event(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_combined_input() {
+        let mut first = EventQueue::new();
+        first.push(KeyEvent::Pressed(0x1));
+        let mut second = EventQueue::new();
+        second.push(KeyEvent::Pressed(0x2));
+        let mut input = CombinedInput::new(vec![Box::new(first), Box::new(second)]);
+
+        assert_eq!(input.poll_event(), Some(KeyEvent::Pressed(0x1)));
+        assert_eq!(input.poll_event(), Some(KeyEvent::Pressed(0x2)));
+        assert_eq!(input.poll_event(), None);
+        assert!(input.is_pressed(0x1) && input.is_pressed(0x2));
+        assert!(!input.is_pressed(0x3));
+    }
+}
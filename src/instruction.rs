@@ -0,0 +1,468 @@
+use core::fmt;
+
+use crate::platform::Platform;
+
+// A decoded CHIP-8 instruction. Register operands are register indices
+// (0x0-0xF), `kk` is an immediate byte and `nnn` a 12-bit address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // 0nnn: SYS addr.
+    Sys(u16),
+    // 0010: MEGAOFF, MegaChip leave the mega mode.
+    #[cfg(feature = "megachip")]
+    MegaOff,
+    // 0011: MEGAON, MegaChip enter the 256x192 mode.
+    #[cfg(feature = "megachip")]
+    MegaOn,
+    // 00BN: SCRU nibble, MegaChip scroll up.
+    #[cfg(feature = "megachip")]
+    MegaScrollUp(u8),
+    // 01nn nnnn: LDHI I, nnnnnn, MegaChip load a 24-bit address into I.
+    // The low 16 bits are the word after the opcode.
+    #[cfg(feature = "megachip")]
+    LoadHighI(u8),
+    // 02nn: LDPAL nn, MegaChip load nn palette colours from I.
+    #[cfg(feature = "megachip")]
+    LoadPalette(u8),
+    // 03nn: SPRW nn, MegaChip sprite width.
+    #[cfg(feature = "megachip")]
+    SpriteWidth(u8),
+    // 04nn: SPRH nn, MegaChip sprite height.
+    #[cfg(feature = "megachip")]
+    SpriteHeight(u8),
+    // 05nn: ALPHA nn, MegaChip screen opacity.
+    #[cfg(feature = "megachip")]
+    ScreenAlpha(u8),
+    // 060n: DIGISND n, MegaChip play the sample at I, looping if n is 0.
+    #[cfg(feature = "megachip")]
+    PlaySound(u8),
+    // 0700: STOPSND, MegaChip stop the sample.
+    #[cfg(feature = "megachip")]
+    StopSound,
+    // 080n: BMODE n, MegaChip sprite blend mode.
+    #[cfg(feature = "megachip")]
+    BlendMode(u8),
+    // 09nn: CCOL nn, MegaChip collision colour.
+    #[cfg(feature = "megachip")]
+    CollisionColor(u8),
+    // 00CN: SCD nibble, SUPER-CHIP scroll down.
+    ScrollDown(u8),
+    // 00DN: SCU nibble, XO-CHIP scroll up.
+    ScrollUp(u8),
+    // 00E0: CLS.
+    Cls,
+    // 00EE: RET.
+    Ret,
+    // 00FB: SCR, SUPER-CHIP scroll right by 4.
+    ScrollRight,
+    // 00FC: SCL, SUPER-CHIP scroll left by 4.
+    ScrollLeft,
+    // 00FD: EXIT, SUPER-CHIP stop the interpreter.
+    Exit,
+    // 00FE: LOW, SUPER-CHIP 64x32 mode.
+    LowRes,
+    // 00FF: HIGH, SUPER-CHIP 128x64 mode.
+    HighRes,
+    // 1nnn: JP addr.
+    Jump(u16),
+    // 2nnn: CALL addr.
+    Call(u16),
+    // 3xkk: SE Vx, byte.
+    SkipEqByte(u8, u8),
+    // 4xkk: SNE Vx, byte.
+    SkipNeByte(u8, u8),
+    // 5xy0: SE Vx, Vy.
+    SkipEqReg(u8, u8),
+    // 5xy2: LD [I], Vx-Vy, XO-CHIP store the registers from x to y.
+    StoreRange(u8, u8),
+    // 5xy3: LD Vx-Vy, [I], XO-CHIP load the registers from x to y.
+    LoadRange(u8, u8),
+    // 6xkk: LD Vx, byte.
+    LoadByte(u8, u8),
+    // 7xkk: ADD Vx, byte.
+    AddByte(u8, u8),
+    // 8xy0: LD Vx, Vy.
+    LoadReg(u8, u8),
+    // 8xy1: OR Vx, Vy.
+    Or(u8, u8),
+    // 8xy2: AND Vx, Vy.
+    And(u8, u8),
+    // 8xy3: XOR Vx, Vy.
+    Xor(u8, u8),
+    // 8xy4: ADD Vx, Vy.
+    AddReg(u8, u8),
+    // 8xy5: SUB Vx, Vy.
+    Sub(u8, u8),
+    // 8xy6: SHR Vx {, Vy}.
+    ShiftRight(u8, u8),
+    // 8xy7: SUBN Vx, Vy.
+    SubN(u8, u8),
+    // 8xyE: SHL Vx {, Vy}.
+    ShiftLeft(u8, u8),
+    // 9xy0: SNE Vx, Vy.
+    SkipNeReg(u8, u8),
+    // Annn: LD I, addr.
+    LoadI(u16),
+    // Bnnn: JP V0, addr.
+    JumpV0(u16),
+    // Cxkk: RND Vx, byte.
+    Random(u8, u8),
+    // Dxyn: DRW Vx, Vy, nibble.
+    Draw(u8, u8, u8),
+    // Ex9E: SKP Vx.
+    SkipKeyPressed(u8),
+    // ExA1: SKNP Vx.
+    SkipKeyReleased(u8),
+    // Fx07: LD Vx, DT.
+    LoadDelay(u8),
+    // F000 nnnn: LD I, LONG nnnn, XO-CHIP load a 16-bit address into I.
+    // The address is the word after the opcode, which the CPU fetches.
+    LoadLongI,
+    // F002: AUDIO, XO-CHIP load the audio pattern buffer from I.
+    LoadPattern,
+    // Fn01: PLANE n, XO-CHIP select the drawing planes.
+    SelectPlane(u8),
+    // Fx0A: LD Vx, K.
+    WaitKey(u8),
+    // Fx15: LD DT, Vx.
+    SetDelay(u8),
+    // Fx18: LD ST, Vx.
+    SetSound(u8),
+    // Fx1E: ADD I, Vx.
+    AddI(u8),
+    // Fx29: LD F, Vx.
+    LoadFont(u8),
+    // Fx30: LD HF, Vx, SUPER-CHIP big digit.
+    LoadBigFont(u8),
+    // Fx33: LD B, Vx.
+    Bcd(u8),
+    // Fx3A: PITCH Vx, XO-CHIP set the audio pitch.
+    SetPitch(u8),
+    // Fx55: LD [I], Vx.
+    StoreRegs(u8),
+    // Fx65: LD Vx, [I].
+    LoadRegs(u8),
+    // Fx75: LD R, Vx, SUPER-CHIP save to the RPL user flags.
+    StoreFlags(u8),
+    // Fx85: LD Vx, R, SUPER-CHIP restore from the RPL user flags.
+    LoadFlags(u8),
+}
+
+impl Instruction {
+    // Decode a raw 16-bit opcode, or None if it isn't an instruction.
+    // Decoding is pure and does not depend on any CPU state; `Cpu` turns
+    // None into `Chip8Error::UnknownInstruction` with the PC it was at.
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        let nibbles = (
+            (opcode & 0xF000) >> 12,
+            (opcode & 0x0F00) >> 8,
+            (opcode & 0x00F0) >> 4,
+            (opcode & 0x000F),
+        );
+
+        let nnn = opcode & 0x0FFF;
+        let kk = (opcode & 0x00FF) as u8;
+        let x = nibbles.1 as u8;
+        let y = nibbles.2 as u8;
+        let n = nibbles.3 as u8;
+
+        let instruction = match nibbles {
+            (0x0, 0x0, 0xc, _) => Instruction::ScrollDown(n),
+            (0x0, 0x0, 0xd, _) => Instruction::ScrollUp(n),
+            (0x0, 0x0, 0xe, 0x0) => Instruction::Cls,
+            (0x0, 0x0, 0xe, 0xe) => Instruction::Ret,
+            (0x0, 0x0, 0xf, 0xb) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xf, 0xc) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xf, 0xd) => Instruction::Exit,
+            (0x0, 0x0, 0xf, 0xe) => Instruction::LowRes,
+            (0x0, 0x0, 0xf, 0xf) => Instruction::HighRes,
+            (0x0, _, _, _) => Instruction::Sys(nnn),
+            (0x1, _, _, _) => Instruction::Jump(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, _, _, _) => Instruction::SkipEqByte(x, kk),
+            (0x4, _, _, _) => Instruction::SkipNeByte(x, kk),
+            (0x5, _, _, 0x0) => Instruction::SkipEqReg(x, y),
+            (0x5, _, _, 0x2) => Instruction::StoreRange(x, y),
+            (0x5, _, _, 0x3) => Instruction::LoadRange(x, y),
+            (0x6, _, _, _) => Instruction::LoadByte(x, kk),
+            (0x7, _, _, _) => Instruction::AddByte(x, kk),
+            (0x8, _, _, 0x0) => Instruction::LoadReg(x, y),
+            (0x8, _, _, 0x1) => Instruction::Or(x, y),
+            (0x8, _, _, 0x2) => Instruction::And(x, y),
+            (0x8, _, _, 0x3) => Instruction::Xor(x, y),
+            (0x8, _, _, 0x4) => Instruction::AddReg(x, y),
+            (0x8, _, _, 0x5) => Instruction::Sub(x, y),
+            (0x8, _, _, 0x6) => Instruction::ShiftRight(x, y),
+            (0x8, _, _, 0x7) => Instruction::SubN(x, y),
+            (0x8, _, _, 0xe) => Instruction::ShiftLeft(x, y),
+            (0x9, _, _, 0x0) => Instruction::SkipNeReg(x, y),
+            (0xa, _, _, _) => Instruction::LoadI(nnn),
+            (0xb, _, _, _) => Instruction::JumpV0(nnn),
+            (0xc, _, _, _) => Instruction::Random(x, kk),
+            (0xd, _, _, _) => Instruction::Draw(x, y, n),
+            (0xe, _, 0x9, 0xe) => Instruction::SkipKeyPressed(x),
+            (0xe, _, 0xa, 0x1) => Instruction::SkipKeyReleased(x),
+            (0xf, _, 0x0, 0x7) => Instruction::LoadDelay(x),
+            (0xf, 0x0, 0x0, 0x0) => Instruction::LoadLongI,
+            (0xf, 0x0, 0x0, 0x2) => Instruction::LoadPattern,
+            (0xf, _, 0x0, 0x1) => Instruction::SelectPlane(x),
+            (0xf, _, 0x0, 0xa) => Instruction::WaitKey(x),
+            (0xf, _, 0x1, 0x5) => Instruction::SetDelay(x),
+            (0xf, _, 0x1, 0x8) => Instruction::SetSound(x),
+            (0xf, _, 0x1, 0xe) => Instruction::AddI(x),
+            (0xf, _, 0x2, 0x9) => Instruction::LoadFont(x),
+            (0xf, _, 0x3, 0x0) => Instruction::LoadBigFont(x),
+            (0xf, _, 0x3, 0x3) => Instruction::Bcd(x),
+            (0xf, _, 0x3, 0xa) => Instruction::SetPitch(x),
+            (0xf, _, 0x5, 0x5) => Instruction::StoreRegs(x),
+            (0xf, _, 0x6, 0x5) => Instruction::LoadRegs(x),
+            (0xf, _, 0x7, 0x5) => Instruction::StoreFlags(x),
+            (0xf, _, 0x8, 0x5) => Instruction::LoadFlags(x),
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    // The first instruction set that has this instruction.
+    pub fn platform(self) -> Platform {
+        use Instruction::*;
+
+        match self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | LowRes | HighRes | LoadBigFont(_)
+            | StoreFlags(_) | LoadFlags(_) => Platform::SuperChip,
+            ScrollUp(_) | StoreRange(..) | LoadRange(..) | LoadLongI | LoadPattern
+            | SelectPlane(_) | SetPitch(_) => Platform::XoChip,
+            #[cfg(feature = "megachip")]
+            instruction if instruction.is_megachip() => Platform::SuperChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    // Decode `opcode` as one of the MegaChip additions, which take over
+    // part of the SYS range while the mega mode is on. None for anything
+    // else.
+    #[cfg(feature = "megachip")]
+    pub fn decode_megachip(opcode: u16) -> Option<Instruction> {
+        let nibbles = (
+            (opcode & 0xF000) >> 12,
+            (opcode & 0x0F00) >> 8,
+            (opcode & 0x00F0) >> 4,
+            (opcode & 0x000F),
+        );
+        let kk = (opcode & 0x00FF) as u8;
+        let n = nibbles.3 as u8;
+
+        let instruction = match nibbles {
+            (0x0, 0x0, 0x1, 0x0) => Instruction::MegaOff,
+            (0x0, 0x0, 0x1, 0x1) => Instruction::MegaOn,
+            (0x0, 0x0, 0xb, _) => Instruction::MegaScrollUp(n),
+            (0x0, 0x1, _, _) => Instruction::LoadHighI(kk),
+            (0x0, 0x2, _, _) => Instruction::LoadPalette(kk),
+            (0x0, 0x3, _, _) => Instruction::SpriteWidth(kk),
+            (0x0, 0x4, _, _) => Instruction::SpriteHeight(kk),
+            (0x0, 0x5, _, _) => Instruction::ScreenAlpha(kk),
+            (0x0, 0x6, 0x0, _) => Instruction::PlaySound(n),
+            (0x0, 0x7, 0x0, 0x0) => Instruction::StopSound,
+            (0x0, 0x8, 0x0, _) => Instruction::BlendMode(n),
+            (0x0, 0x9, _, _) => Instruction::CollisionColor(kk),
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    // Whether this is one of the MegaChip additions, which only exist in
+    // the mega mode.
+    #[cfg(feature = "megachip")]
+    pub fn is_megachip(self) -> bool {
+        use Instruction::*;
+
+        matches!(
+            self,
+            MegaOff
+                | MegaOn
+                | MegaScrollUp(_)
+                | LoadHighI(_)
+                | LoadPalette(_)
+                | SpriteWidth(_)
+                | SpriteHeight(_)
+                | ScreenAlpha(_)
+                | PlaySound(_)
+                | StopSound
+                | BlendMode(_)
+                | CollisionColor(_)
+        )
+    }
+}
+
+// Formats the instruction in Cowgod's assembly syntax, e.g. `LD V1, 0x2a`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "SYS {:#05x}", nnn),
+            #[cfg(feature = "megachip")]
+            Instruction::MegaOff => write!(f, "MEGAOFF"),
+            #[cfg(feature = "megachip")]
+            Instruction::MegaOn => write!(f, "MEGAON"),
+            #[cfg(feature = "megachip")]
+            Instruction::MegaScrollUp(n) => write!(f, "SCRU {}", n),
+            #[cfg(feature = "megachip")]
+            Instruction::LoadHighI(nn) => write!(f, "LDHI I, {:#04x}", nn),
+            #[cfg(feature = "megachip")]
+            Instruction::LoadPalette(nn) => write!(f, "LDPAL {}", nn),
+            #[cfg(feature = "megachip")]
+            Instruction::SpriteWidth(nn) => write!(f, "SPRW {}", nn),
+            #[cfg(feature = "megachip")]
+            Instruction::SpriteHeight(nn) => write!(f, "SPRH {}", nn),
+            #[cfg(feature = "megachip")]
+            Instruction::ScreenAlpha(nn) => write!(f, "ALPHA {:#04x}", nn),
+            #[cfg(feature = "megachip")]
+            Instruction::PlaySound(n) => write!(f, "DIGISND {}", n),
+            #[cfg(feature = "megachip")]
+            Instruction::StopSound => write!(f, "STOPSND"),
+            #[cfg(feature = "megachip")]
+            Instruction::BlendMode(n) => write!(f, "BMODE {}", n),
+            #[cfg(feature = "megachip")]
+            Instruction::CollisionColor(nn) => write!(f, "CCOL {}", nn),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Jump(nnn) => write!(f, "JP {:#05x}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL {:#05x}", nnn),
+            Instruction::SkipEqByte(x, kk) => write!(f, "SE V{:X}, {:#04x}", x, kk),
+            Instruction::SkipNeByte(x, kk) => write!(f, "SNE V{:X}, {:#04x}", x, kk),
+            Instruction::SkipEqReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange(x, y) => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            Instruction::LoadByte(x, kk) => write!(f, "LD V{:X}, {:#04x}", x, kk),
+            Instruction::AddByte(x, kk) => write!(f, "ADD V{:X}, {:#04x}", x, kk),
+            Instruction::LoadReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadI(nnn) => write!(f, "LD I, {:#05x}", nnn),
+            Instruction::JumpV0(nnn) => write!(f, "JP V0, {:#05x}", nnn),
+            Instruction::Random(x, kk) => write!(f, "RND V{:X}, {:#04x}", x, kk),
+            Instruction::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKeyPressed(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipKeyReleased(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadDelay(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::LoadLongI => write!(f, "LD I, LONG"),
+            Instruction::LoadPattern => write!(f, "AUDIO"),
+            Instruction::SelectPlane(n) => write!(f, "PLANE {}", n),
+            Instruction::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadBigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::SetPitch(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::StoreRegs(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegs(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Instruction::decode(0x00e0), Some(Instruction::Cls));
+        assert_eq!(Instruction::decode(0x00ee), Some(Instruction::Ret));
+        assert_eq!(Instruction::decode(0x0123), Some(Instruction::Sys(0x123)));
+        assert_eq!(
+            Instruction::decode(0x00c3),
+            Some(Instruction::ScrollDown(3))
+        );
+        assert_eq!(Instruction::decode(0x00d3), Some(Instruction::ScrollUp(3)));
+        assert_eq!(Instruction::decode(0x00fb), Some(Instruction::ScrollRight));
+        assert_eq!(Instruction::decode(0x00fe), Some(Instruction::LowRes));
+        assert_eq!(Instruction::decode(0x00ff), Some(Instruction::HighRes));
+        assert_eq!(Instruction::decode(0x1abc), Some(Instruction::Jump(0xabc)));
+        assert_eq!(
+            Instruction::decode(0x3a42),
+            Some(Instruction::SkipEqByte(0xa, 0x42))
+        );
+        assert_eq!(
+            Instruction::decode(0x8ab4),
+            Some(Instruction::AddReg(0xa, 0xb))
+        );
+        assert_eq!(
+            Instruction::decode(0xd125),
+            Some(Instruction::Draw(1, 2, 5))
+        );
+        assert_eq!(Instruction::decode(0xf265), Some(Instruction::LoadRegs(2)));
+    }
+
+    #[cfg(feature = "megachip")]
+    #[test]
+    fn test_decode_megachip() {
+        assert_eq!(
+            Instruction::decode_megachip(0x0011),
+            Some(Instruction::MegaOn)
+        );
+        assert_eq!(
+            Instruction::decode_megachip(0x0123),
+            Some(Instruction::LoadHighI(0x23))
+        );
+        assert_eq!(
+            Instruction::decode_megachip(0x0805),
+            Some(Instruction::BlendMode(5))
+        );
+        assert_eq!(Instruction::decode_megachip(0x00e0), None);
+        assert_eq!(Instruction::decode(0x0123), Some(Instruction::Sys(0x123)));
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        for &opcode in [0x5121, 0x8008, 0x9001, 0xe000, 0xf0ff].iter() {
+            assert_eq!(Instruction::decode(opcode), None);
+        }
+    }
+
+    #[test]
+    fn test_platform() {
+        assert_eq!(Instruction::Cls.platform(), Platform::Chip8);
+        assert_eq!(Instruction::Draw(0, 0, 0).platform(), Platform::Chip8);
+        assert_eq!(Instruction::HighRes.platform(), Platform::SuperChip);
+        assert_eq!(Instruction::LoadLongI.platform(), Platform::XoChip);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_decode_is_total() {
+        // Every opcode either decodes or is None; none of them panic.
+        for opcode in 0..=0xffffu16 {
+            if let Some(instruction) = Instruction::decode(opcode) {
+                assert!(!instruction.to_string().is_empty());
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_display() {
+        assert_eq!(Instruction::Cls.to_string(), "CLS");
+        assert_eq!(Instruction::LoadByte(1, 0x2a).to_string(), "LD V1, 0x2a");
+        assert_eq!(Instruction::Draw(0xa, 0xb, 5).to_string(), "DRW VA, VB, 5");
+        assert_eq!(Instruction::LoadI(0x2f0).to_string(), "LD I, 0x2f0");
+    }
+}
@@ -0,0 +1,113 @@
+use crate::error::{Chip8Error, Result};
+
+// IPS patches: a "PATCH" header, then records of a 3-byte offset and
+// 2-byte length followed by that many bytes (or, for length 0, a 2-byte
+// run length and the byte to repeat), then "EOF". An optional 3-byte size
+// after "EOF" truncates the result.
+
+const HEADER: &[u8] = b"PATCH";
+const FOOTER: &[u8] = b"EOF";
+
+// Apply the IPS `patch` to `rom`, returning the patched copy. Records past
+// the end of the ROM grow it, zero-filling any gap.
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if !patch.starts_with(HEADER) {
+        return Err(invalid("missing PATCH header"));
+    }
+
+    let mut out = rom.to_vec();
+    let mut at = HEADER.len();
+    loop {
+        let record = patch.get(at..at + 3).ok_or_else(|| invalid("truncated"))?;
+        // A record at offset 0x454f46 would read as "EOF"; the format has
+        // always resolved that in favour of the footer.
+        if record == FOOTER {
+            at += 3;
+            break;
+        }
+
+        let offset = be(record);
+        let len = be(patch
+            .get(at + 3..at + 5)
+            .ok_or_else(|| invalid("truncated"))?);
+        at += 5;
+
+        let (data, run) = if len == 0 {
+            let rle = patch.get(at..at + 3).ok_or_else(|| invalid("truncated"))?;
+            at += 3;
+            (vec![rle[2]; be(&rle[..2])], true)
+        } else {
+            let data = patch
+                .get(at..at + len)
+                .ok_or_else(|| invalid("truncated"))?;
+            at += len;
+            (data.to_vec(), false)
+        };
+
+        if run && data.is_empty() {
+            return Err(invalid("empty run"));
+        }
+        if out.len() < offset + data.len() {
+            out.resize(offset + data.len(), 0);
+        }
+        out[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    match patch.len() - at {
+        0 => {}
+        3 => out.truncate(be(&patch[at..])),
+        _ => return Err(invalid("trailing data after EOF")),
+    }
+
+    Ok(out)
+}
+
+// Big-endian integer of up to 3 bytes.
+fn be(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |acc, &b| acc << 8 | b as usize)
+}
+
+fn invalid(reason: &'static str) -> Chip8Error {
+    Chip8Error::InvalidPatch { reason }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_ips() {
+        let rom = [0x60, 0x00, 0x12, 0x02];
+        let patch = [
+            b'P', b'A', b'T', b'C', b'H', //
+            0x00, 0x00, 0x01, 0x00, 0x01, 0x2a, // byte 1 = 0x2a
+            0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x02, 0xff, // 2 x 0xff at 4
+            0x00, 0x00, 0x08, 0x00, 0x01, 0x11, // past the end, leaving a gap
+            b'E', b'O', b'F',
+        ];
+
+        assert_eq!(
+            apply_ips(&rom, &patch),
+            Ok(vec![0x60, 0x2a, 0x12, 0x02, 0xff, 0xff, 0x00, 0x00, 0x11])
+        );
+    }
+
+    #[test]
+    fn test_truncation() {
+        let patch = *b"PATCHEOF\x00\x00\x02";
+        assert_eq!(apply_ips(&[1, 2, 3, 4], &patch), Ok(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_invalid_patch() {
+        assert_eq!(
+            apply_ips(&[0], b"PATCH\x00\x00\x00\x00\x05\x01"),
+            Err(Chip8Error::InvalidPatch {
+                reason: "truncated"
+            })
+        );
+        assert!(apply_ips(&[0], b"PATCHED").is_err());
+        assert!(apply_ips(&[0], b"nope").is_err());
+        assert!(apply_ips(&[0], b"PATCHEOFxx").is_err());
+    }
+}
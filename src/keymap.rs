@@ -0,0 +1,92 @@
+use crate::input::KeyEvent;
+use crate::keypad::CHIP8_NUM_KEYS;
+
+// The COSMAC VIP keypad, row by row, and the block of a QWERTY keyboard
+// frontends conventionally put it on.
+const VIP_LAYOUT: [u8; CHIP8_NUM_KEYS] = [
+    0x1, 0x2, 0x3, 0xc, //
+    0x4, 0x5, 0x6, 0xd, //
+    0x7, 0x8, 0x9, 0xe, //
+    0xa, 0x0, 0xb, 0xf,
+];
+const QWERTY_KEYS: [char; CHIP8_NUM_KEYS] = [
+    '1', '2', '3', '4', //
+    'q', 'w', 'e', 'r', //
+    'a', 's', 'd', 'f', //
+    'z', 'x', 'c', 'v',
+];
+
+// Which keyboard key stands for which hex key, shared by the frontends.
+// Keys are named by the character they produce, lower case, so the map
+// works the same for a window system's key codes and terminal input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyMap {
+    keys: Vec<(char, u8)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::standard()
+    }
+}
+
+impl KeyMap {
+    // The usual 1234/QWER/ASDF/ZXCV layout.
+    pub fn standard() -> Self {
+        KeyMap {
+            keys: QWERTY_KEYS.iter().copied().zip(VIP_LAYOUT).collect(),
+        }
+    }
+
+    // A map with no keys, to be filled in with `bind`.
+    pub fn empty() -> Self {
+        KeyMap { keys: Vec::new() }
+    }
+
+    // Make `name` press hex key `key`, replacing what it did before.
+    pub fn bind(&mut self, name: char, key: u8) {
+        let name = name.to_ascii_lowercase();
+        self.keys.retain(|&(bound, _)| bound != name);
+        self.keys.push((name, key & 0xf));
+    }
+
+    // The hex key `name` stands for.
+    pub fn key(&self, name: char) -> Option<u8> {
+        let name = name.to_ascii_lowercase();
+        self.keys
+            .iter()
+            .find(|&&(bound, _)| bound == name)
+            .map(|&(_, key)| key)
+    }
+
+    // Every (name, hex key) pair, for frontends that poll keys by name.
+    pub fn bindings(&self) -> &[(char, u8)] {
+        &self.keys
+    }
+
+    // The event for `name` going down or up, if it is mapped.
+    pub fn event(&self, name: char, pressed: bool) -> Option<KeyEvent> {
+        self.key(name).map(|key| match pressed {
+            true => KeyEvent::Pressed(key),
+            false => KeyEvent::Released(key),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keymap() {
+        let mut map = KeyMap::standard();
+        assert_eq!(map.key('1'), Some(0x1));
+        assert_eq!(map.key('V'), Some(0xf));
+        assert_eq!(map.key('x'), Some(0x0));
+        assert_eq!(map.key('p'), None);
+
+        map.bind('x', 0x5);
+        assert_eq!(map.event('x', true), Some(KeyEvent::Pressed(0x5)));
+        assert_eq!(map.event('x', false), Some(KeyEvent::Released(0x5)));
+    }
+}
@@ -0,0 +1,71 @@
+pub const CHIP8_NUM_KEYS: usize = 16;
+
+// State of the 16-key hexadecimal keypad (keys 0x0-0xF).
+#[derive(Clone)]
+pub struct Keypad {
+    keys: [bool; CHIP8_NUM_KEYS],
+    // Last key that went from down to up, consumed by LD Vx, K.
+    released: Option<u8>,
+}
+
+impl Keypad {
+    pub fn new() -> Self {
+        Keypad {
+            keys: [false; CHIP8_NUM_KEYS],
+            released: None,
+        }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.keys[key as usize & 0xf] = true;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        let key = key & 0xf;
+        if self.keys[key as usize] {
+            self.released = Some(key);
+        }
+        self.keys[key as usize] = false;
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[key as usize & 0xf]
+    }
+
+    // Take the last key released since the previous call, if any.
+    pub fn take_released(&mut self) -> Option<u8> {
+        self.released.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_press_and_release() {
+        let mut keypad = Keypad::new();
+        assert!(!keypad.is_pressed(0xa));
+
+        keypad.press(0xa);
+        assert!(keypad.is_pressed(0xa));
+        assert!(!keypad.is_pressed(0xb));
+
+        keypad.release(0xa);
+        assert!(!keypad.is_pressed(0xa));
+    }
+
+    #[test]
+    fn test_release_requires_press() {
+        let mut keypad = Keypad::new();
+        keypad.release(0x3);
+        assert_eq!(keypad.take_released(), None, "key was never down");
+
+        keypad.press(0x3);
+        assert_eq!(keypad.take_released(), None, "key is still down");
+
+        keypad.release(0x3);
+        assert_eq!(keypad.take_released(), Some(0x3));
+        assert_eq!(keypad.take_released(), None, "release is consumed");
+    }
+}
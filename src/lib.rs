@@ -0,0 +1,170 @@
+// Without the default `std` feature the crate is no_std and only the
+// parts that need neither std nor a heap are built: the Cpu with its
+// memory and screen, which are fixed arrays sized for XO-CHIP's 64K and
+// the 128x64 screen, instruction decoding, quirk settings, timers and the
+// VIP timing tables. Machine with its boxed backends, file loading, save
+// states, observers and custom random sources need std. The cdylibs
+// (the C library, the wasm module and the libretro core) are built with
+// `cargo rustc --crate-type cdylib`, since a no_std cdylib would need its
+// own panic handler.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+mod analysis;
+#[cfg(feature = "async")]
+mod async_driver;
+mod audio;
+#[cfg(feature = "std")]
+mod builder;
+mod cdp1802;
+#[cfg(feature = "std")]
+pub mod channel;
+#[cfg(feature = "std")]
+mod clock;
+#[cfg(feature = "std")]
+mod debugger;
+#[cfg(feature = "std")]
+mod detect;
+#[cfg(feature = "std")]
+mod display;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod framebuffer;
+#[cfg(feature = "std")]
+mod gamepad;
+#[cfg(feature = "std")]
+mod handle;
+#[cfg(feature = "std")]
+mod hash;
+#[cfg(feature = "std")]
+mod info;
+#[cfg(feature = "std")]
+mod input;
+mod instruction;
+#[cfg(feature = "std")]
+mod ips;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "std")]
+mod keymap;
+mod keypad;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+#[cfg(feature = "std")]
+mod machine;
+#[cfg(feature = "megachip")]
+mod megachip;
+mod memory;
+#[cfg(feature = "std")]
+mod metadata;
+mod observer;
+#[cfg(feature = "std")]
+mod palette;
+#[cfg(feature = "std")]
+mod phosphor;
+#[cfg(feature = "std")]
+mod pipe_audio;
+mod platform;
+#[cfg(feature = "std")]
+mod png;
+mod processor;
+mod quirks;
+#[cfg(feature = "std")]
+mod recording;
+mod rng;
+#[cfg(feature = "rom-db")]
+mod romdb;
+#[cfg(feature = "std")]
+mod server;
+mod sprite;
+#[cfg(feature = "std")]
+mod terminal;
+#[cfg(feature = "test-roms")]
+pub mod test_roms;
+#[cfg(feature = "std")]
+mod texture;
+mod timers;
+mod timing;
+#[cfg(feature = "std")]
+mod watch;
+#[cfg(feature = "std")]
+mod websocket;
+#[cfg(feature = "zip")]
+mod zip;
+
+use sprite::{BIG_FONT_SET, FONT_SET};
+
+#[cfg(feature = "std")]
+pub use analysis::{QuirkHint, RomAnalysis};
+#[cfg(feature = "std")]
+pub use audio::{pattern_rate, PatternSynth};
+pub use audio::{Audio, NullAudio, AUDIO_PATTERN_SIZE, DEFAULT_AUDIO_PATTERN, DEFAULT_PITCH};
+#[cfg(feature = "std")]
+pub use builder::CpuBuilder;
+pub use cdp1802::{Cdp1802, Cdp1802Exit, VIP_DISPLAY, VIP_REGISTERS, VIP_STACK};
+#[cfg(feature = "std")]
+pub use clock::{Clock, SystemClock, VirtualClock, FRAME_DURATION};
+#[cfg(feature = "std")]
+pub use debugger::{DebugCommand, Debugger};
+#[cfg(feature = "std")]
+pub use detect::{QuirkDetection, QuirkDetector, QuirkFinding};
+#[cfg(feature = "std")]
+pub use display::{Display, NullDisplay};
+pub use error::{Chip8Error, Result};
+pub use framebuffer::{
+    Framebuffer, ALL_PLANES, CHIP8_HEIGHT, CHIP8_WIDTH, HIRES_CHIP8_HEIGHT, SCHIP_HEIGHT,
+    SCHIP_WIDTH,
+};
+#[cfg(feature = "std")]
+pub use gamepad::{decode_js_event, GamepadButton, GamepadInput, GamepadMap, JS_EVENT_SIZE};
+#[cfg(feature = "std")]
+pub use handle::EmulatorHandle;
+#[cfg(feature = "std")]
+pub use info::RomInfo;
+#[cfg(feature = "std")]
+pub use input::{CombinedInput, EventQueue, Input, KeyEvent, NullInput};
+pub use instruction::Instruction;
+#[cfg(feature = "std")]
+pub use ips::apply_ips;
+#[cfg(feature = "std")]
+pub use keymap::KeyMap;
+#[cfg(feature = "std")]
+pub use machine::{Machine, DEFAULT_CYCLES_PER_FRAME};
+#[cfg(feature = "megachip")]
+pub use megachip::{BlendMode, MegaChip, MEGACHIP_RAM, MEGA_HEIGHT, MEGA_WIDTH};
+pub use memory::{AccessKind, Memory, MemoryAccess, MemoryError, CHIP8_RAM, XOCHIP_RAM};
+#[cfg(feature = "std")]
+pub use metadata::{ArchiveOptions, GameMetadata};
+pub use observer::RegisterSnapshot;
+#[cfg(feature = "std")]
+pub use observer::{Observer, ObserverId};
+#[cfg(feature = "std")]
+pub use palette::Palette;
+#[cfg(feature = "std")]
+pub use phosphor::PhosphorFilter;
+#[cfg(feature = "std")]
+pub use pipe_audio::{PipeAudio, PIPE_SAMPLE_RATE};
+pub use platform::Platform;
+#[cfg(feature = "std")]
+pub use png::{encode_png, scale_rgba, screenshot_name, screenshot_png};
+pub use processor::{Cpu, State, StopReason, SysPolicy, CHIP8_PROGRAM_START, ETI660_PROGRAM_START};
+pub use quirks::{QuirkPreset, Quirks};
+#[cfg(feature = "std")]
+pub use recording::{recording_name, Recorder, RecordingFormat};
+pub use rng::{Rng, XorShiftRng};
+#[cfg(feature = "rom-db")]
+pub use romdb::RomDatabase;
+#[cfg(feature = "std")]
+pub use server::{StreamAudio, StreamDisplay, StreamInput, StreamServer};
+#[cfg(feature = "std")]
+pub use terminal::{
+    braille, half_blocks, kitty, sixel, TerminalBell, TerminalDisplay, TerminalInput,
+    TerminalRenderer, DEFAULT_PIXEL_SCALE, KEY_HOLD_FRAMES,
+};
+#[cfg(feature = "std")]
+pub use texture::RgbaTexture;
+pub use timers::{Timers, TIMER_HZ};
+pub use timing::{vip_cycles, Timing, VIP_CYCLES_PER_FRAME, VIP_DMA_CYCLES};
+#[cfg(feature = "std")]
+pub use watch::RomWatcher;
@@ -0,0 +1,720 @@
+// A libretro core, so RetroArch and other libretro frontends can run the
+// emulator with their shaders, save state menus and netplay. Build the
+// cdylib with the `libretro` feature and name it like a core:
+//
+//     cargo rustc --release --lib --crate-type cdylib --features libretro
+//     cp target/release/libhello_world.so chip8_libretro.so
+//
+// Frontends drive one game at a time from one thread, so the core and the
+// callbacks live in thread locals.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::{Cell, RefCell};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::rc::Rc;
+
+use crate::audio::Audio;
+use crate::framebuffer::{CHIP8_HEIGHT, CHIP8_WIDTH};
+use crate::input::{Input, KeyEvent};
+use crate::keymap::KeyMap;
+use crate::machine::Machine;
+#[cfg(feature = "megachip")]
+use crate::megachip::{MEGA_HEIGHT, MEGA_WIDTH};
+use crate::palette::Palette;
+use crate::processor::RESOLUTIONS;
+use crate::quirks::QuirkPreset;
+
+pub const RETRO_API_VERSION: c_uint = 1;
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_DEVICE_KEYBOARD: c_uint = 3;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+const RETRO_ENVIRONMENT_SET_GEOMETRY: c_uint = 37;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const RETRO_REGION_NTSC: c_uint = 0;
+const RETRO_MEMORY_SAVE_RAM: c_uint = 0;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+// Joypad buttons (RETRO_DEVICE_ID_JOYPAD_*) and the hex keys they press:
+// the d-pad on the 2/4/6/8 diamond most games steer with, A on the 5 in
+// its middle and the other buttons on the bottom row and right column.
+const JOYPAD_KEYS: [(c_uint, u8); 8] = [
+    (4, 0x2), // up
+    (5, 0x8), // down
+    (6, 0x4), // left
+    (7, 0x6), // right
+    (8, 0x5), // A
+    (0, 0x0), // B
+    (1, 0xa), // Y
+    (9, 0xb), // X
+];
+const JOYPAD_START: (c_uint, u8) = (3, 0xf);
+const JOYPAD_SELECT: (c_uint, u8) = (2, 0xe);
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+// A core option: its key, and the description and choices the frontend
+// shows ("Palette; mono|green") or the value it reports.
+#[repr(C)]
+pub struct RetroVariable {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+// The core option choosing the palette, one of `Palette::THEMES`.
+const PALETTE_OPTION: &[u8] = b"chip8_palette\0";
+const PALETTE_CHOICES: &[u8] = b"Palette; mono|green|amber|octo|lcd\0";
+// The core option choosing the interpreter to emulate, a `QuirkPreset`
+// name. It takes effect when the next game is loaded.
+const PLATFORM_OPTION: &[u8] = b"chip8_platform\0";
+const PLATFORM_CHOICES: &[u8] =
+    b"Platform (restart); default|chip8|chip8-hires|chip48|schip-legacy|schip|xochip\0";
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    pub geometry: RetroGameGeometry,
+    pub timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+pub type RetroEnvironment = extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type RetroVideoRefresh =
+    extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type RetroAudioSample = extern "C" fn(left: i16, right: i16);
+pub type RetroAudioSampleBatch = extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type RetroInputPoll = extern "C" fn();
+pub type RetroInputState =
+    extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[derive(Clone, Copy, Default)]
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample: Option<RetroAudioSample>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+}
+
+// Collects the samples the machine renders for the frontend to take.
+struct RetroAudio {
+    samples: Rc<RefCell<Vec<f32>>>,
+}
+
+impl Audio for RetroAudio {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+
+    fn sample_rate(&self) -> Option<u32> {
+        Some(SAMPLE_RATE)
+    }
+
+    fn queue_samples(&mut self, samples: &[f32]) {
+        self.samples.borrow_mut().extend_from_slice(samples);
+    }
+}
+
+// The keys the frontend last reported held, one bit each.
+struct RetroInput {
+    keys: Rc<Cell<u16>>,
+}
+
+impl Input for RetroInput {
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        None
+    }
+
+    fn is_pressed(&self, key: u8) -> bool {
+        self.keys.get() & 1 << key != 0
+    }
+}
+
+// The loaded game.
+struct Core {
+    machine: Machine,
+    keymap: KeyMap,
+    keys: Rc<Cell<u16>>,
+    samples: Rc<RefCell<Vec<f32>>>,
+    // Buffers handed to the video and audio callbacks.
+    video: Vec<u32>,
+    audio: Vec<i16>,
+    // The RPL flags as the frontend's save RAM, and what they were when
+    // last copied either way.
+    sram: [u8; 16],
+    sram_synced: [u8; 16],
+    resolution: (usize, usize),
+    // XRGB8888 colours of the four pixel values.
+    colors: [u32; 4],
+}
+
+// The value the frontend reports for the core option `key`.
+fn option(environment: Option<RetroEnvironment>, key: &[u8]) -> Option<String> {
+    let mut variable = RetroVariable {
+        key: key.as_ptr() as *const c_char,
+        value: std::ptr::null(),
+    };
+    let data = &mut variable as *mut RetroVariable as *mut c_void;
+    if !environment?(RETRO_ENVIRONMENT_GET_VARIABLE, data) || variable.value.is_null() {
+        return None;
+    }
+    let value = unsafe { CStr::from_ptr(variable.value) };
+    value.to_str().ok().map(String::from)
+}
+
+// The palette the frontend's core option picks, or the default.
+fn palette_option(environment: Option<RetroEnvironment>) -> Palette {
+    option(environment, PALETTE_OPTION)
+        .and_then(|name| Palette::from_name(&name))
+        .unwrap_or_default()
+}
+
+// The interpreter the frontend's core option picks, or None to keep the
+// default of running every instruction set.
+fn platform_option(environment: Option<RetroEnvironment>) -> Option<QuirkPreset> {
+    option(environment, PLATFORM_OPTION).and_then(|name| QuirkPreset::from_name(&name))
+}
+
+thread_local! {
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+    static CALLBACKS: Cell<Callbacks> = Cell::new(Callbacks::default());
+}
+
+fn callbacks() -> Callbacks {
+    CALLBACKS.with(|callbacks| callbacks.get())
+}
+
+fn set_callback(set: impl FnOnce(&mut Callbacks)) {
+    CALLBACKS.with(|callbacks| {
+        let mut changed = callbacks.get();
+        set(&mut changed);
+        callbacks.set(changed);
+    });
+}
+
+fn with_core<T>(f: impl FnOnce(&mut Core) -> T) -> Option<T> {
+    CORE.with(|core| core.borrow_mut().as_mut().map(f))
+}
+
+// The widest and the tallest screen a program can switch to: 128x64, or
+// MegaChip's 256x192 when that is built in.
+fn max_resolution() -> (usize, usize) {
+    let width = RESOLUTIONS.iter().map(|&(width, _)| width).max();
+    let height = RESOLUTIONS.iter().map(|&(_, height)| height).max();
+    (width.unwrap_or(CHIP8_WIDTH), height.unwrap_or(CHIP8_HEIGHT))
+}
+
+fn geometry(width: usize, height: usize) -> RetroGameGeometry {
+    let (max_width, max_height) = max_resolution();
+    RetroGameGeometry {
+        base_width: width as c_uint,
+        base_height: height as c_uint,
+        max_width: max_width as c_uint,
+        max_height: max_height as c_uint,
+        aspect_ratio: width as f32 / height as f32,
+    }
+}
+
+// Bytes retro_serialize() takes: a length, the state, and room for the
+// screen to grow to its largest so the size stays the same all game.
+fn serialized_size(machine: &Machine) -> usize {
+    let fb = machine.framebuffer();
+    let (max_width, max_height) = max_resolution();
+    4 + machine.save_state().len() - fb.width() * fb.height() + max_width * max_height
+}
+
+impl Core {
+    fn poll_keys(&mut self, input_state: RetroInputState) {
+        let mut keys = 0u16;
+        for &(id, key) in JOYPAD_KEYS.iter().chain(&[JOYPAD_START, JOYPAD_SELECT]) {
+            if input_state(0, RETRO_DEVICE_JOYPAD, 0, id) != 0 {
+                keys |= 1 << key;
+            }
+        }
+        // Keyboard ids are the RETROK codes, which are ASCII for the
+        // letters and digits.
+        for &(name, key) in self.keymap.bindings() {
+            if input_state(0, RETRO_DEVICE_KEYBOARD, 0, name as c_uint) != 0 {
+                keys |= 1 << key;
+            }
+        }
+        self.keys.set(keys);
+    }
+
+    fn run(&mut self, callbacks: Callbacks) {
+        if let Some(input_state) = callbacks.input_state {
+            self.poll_keys(input_state);
+        }
+        if self.sram != self.sram_synced {
+            self.machine.cpu_mut().set_rpl_flags(self.sram);
+        }
+        if let Some(environment) = callbacks.environment {
+            let mut updated = false;
+            let data = &mut updated as *mut bool as *mut c_void;
+            if environment(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE, data) && updated {
+                self.colors = palette_option(Some(environment)).xrgb();
+            }
+        }
+        // A crashed program keeps showing its last frame.
+        let _ = self.machine.run_frame();
+        self.sram = *self.machine.cpu().rpl_flags();
+        self.sram_synced = self.sram;
+
+        let fb = self.machine.framebuffer();
+        let (width, height) = (fb.width(), fb.height());
+        if (width, height) != self.resolution {
+            self.resolution = (width, height);
+            if let Some(environment) = callbacks.environment {
+                let mut geometry = geometry(width, height);
+                let data = &mut geometry as *mut RetroGameGeometry as *mut c_void;
+                environment(RETRO_ENVIRONMENT_SET_GEOMETRY, data);
+            }
+        }
+
+        let colors = self.colors;
+        self.video.clear();
+        // In MegaChip mode the pixels are indices into the program's own
+        // 256 colour palette, already looked up in the presented frame.
+        #[cfg(feature = "megachip")]
+        {
+            let mega = self.machine.cpu().megachip();
+            if mega.is_enabled() && (width, height) == (MEGA_WIDTH, MEGA_HEIGHT) {
+                self.video.extend_from_slice(mega.frame());
+            }
+        }
+        if self.video.is_empty() {
+            for y in 0..height {
+                self.video
+                    .extend(fb.row(y).iter().map(|&pixel| colors[pixel as usize & 3]));
+            }
+        }
+        if let Some(video_refresh) = callbacks.video_refresh {
+            let data = self.video.as_ptr() as *const c_void;
+            video_refresh(data, width as c_uint, height as c_uint, width * 4);
+        }
+
+        self.audio.clear();
+        for &sample in self.samples.borrow().iter() {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.audio.extend_from_slice(&[sample, sample]);
+        }
+        self.samples.borrow_mut().clear();
+        if let Some(batch) = callbacks.audio_sample_batch {
+            let mut frames = &self.audio[..];
+            while !frames.is_empty() {
+                let taken = batch(frames.as_ptr(), frames.len() / 2);
+                if taken == 0 {
+                    break;
+                }
+                frames = &frames[(taken * 2).min(frames.len())..];
+            }
+        } else if let Some(sample) = callbacks.audio_sample {
+            for frame in self.audio.chunks_exact(2) {
+                sample(frame[0], frame[1]);
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    CORE.with(|core| *core.borrow_mut() = None);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: b"chip8\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8|sc8|xo8\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    let (width, height) = with_core(|core| core.resolution).unwrap_or((CHIP8_WIDTH, CHIP8_HEIGHT));
+    *info = RetroSystemAvInfo {
+        geometry: geometry(width, height),
+        timing: RetroSystemTiming {
+            fps: 60.0,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: Option<RetroEnvironment>) {
+    set_callback(|callbacks| callbacks.environment = callback);
+    if let Some(environment) = callback {
+        let mut variables = [
+            RetroVariable {
+                key: PALETTE_OPTION.as_ptr() as *const c_char,
+                value: PALETTE_CHOICES.as_ptr() as *const c_char,
+            },
+            RetroVariable {
+                key: PLATFORM_OPTION.as_ptr() as *const c_char,
+                value: PLATFORM_CHOICES.as_ptr() as *const c_char,
+            },
+            RetroVariable {
+                key: std::ptr::null(),
+                value: std::ptr::null(),
+            },
+        ];
+        environment(
+            RETRO_ENVIRONMENT_SET_VARIABLES,
+            variables.as_mut_ptr() as *mut c_void,
+        );
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: Option<RetroVideoRefresh>) {
+    set_callback(|callbacks| callbacks.video_refresh = callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(callback: Option<RetroAudioSample>) {
+    set_callback(|callbacks| callbacks.audio_sample = callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: Option<RetroAudioSampleBatch>) {
+    set_callback(|callbacks| callbacks.audio_sample_batch = callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: Option<RetroInputPoll>) {
+    set_callback(|callbacks| callbacks.input_poll = callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: Option<RetroInputState>) {
+    set_callback(|callbacks| callbacks.input_state = callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    // States too small for the ROM are refused, so the ROM always fits.
+    with_core(|core| {
+        let _ = core.machine.reset();
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = callbacks();
+    if let Some(input_poll) = callbacks.input_poll {
+        input_poll();
+    }
+    with_core(|core| core.run(callbacks));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    with_core(|core| serialized_size(&core.machine)).unwrap_or(0)
+}
+
+// The state from `Machine::save_state`, after its length as a big endian
+// u32 and followed by zeros up to `size`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let state = match with_core(|core| core.machine.save_state()) {
+        Some(state) => state,
+        None => return false,
+    };
+    if state.len() + 4 > size {
+        return false;
+    }
+    let out = std::slice::from_raw_parts_mut(data as *mut u8, size);
+    out[..4].copy_from_slice(&(state.len() as u32).to_be_bytes());
+    out[4..4 + state.len()].copy_from_slice(&state);
+    out[4 + state.len()..].iter_mut().for_each(|byte| *byte = 0);
+    true
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let bytes = std::slice::from_raw_parts(data as *const u8, size);
+    if size < 4 {
+        return false;
+    }
+    let len = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    match bytes[4..].get(..len) {
+        Some(state) => with_core(|core| core.machine.load_state(state).is_ok()).unwrap_or(false),
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    let accepted = callbacks().environment.is_some_and(|environment| {
+        environment(
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+            &mut format as *mut c_uint as *mut c_void,
+        )
+    });
+    if !accepted {
+        return false;
+    }
+
+    let rom = std::slice::from_raw_parts((*game).data as *const u8, (*game).size);
+    let keys = Rc::new(Cell::new(0));
+    let samples = Rc::new(RefCell::new(Vec::new()));
+    let mut machine = Machine::new();
+    if let Some(preset) = platform_option(callbacks().environment) {
+        machine.cpu_mut().set_preset(preset);
+    }
+    machine.set_input(Box::new(RetroInput { keys: keys.clone() }));
+    machine.set_audio(Box::new(RetroAudio {
+        samples: samples.clone(),
+    }));
+    if machine.load_rom(rom).is_err() {
+        return false;
+    }
+
+    let core = Core {
+        machine,
+        keymap: KeyMap::standard(),
+        keys,
+        samples,
+        video: Vec::new(),
+        audio: Vec::new(),
+        sram: [0; 16],
+        sram_synced: [0; 16],
+        resolution: (CHIP8_WIDTH, CHIP8_HEIGHT),
+        colors: palette_option(callbacks().environment).xrgb(),
+    };
+    CORE.with(|slot| *slot.borrow_mut() = Some(core));
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    CORE.with(|core| *core.borrow_mut() = None);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+// The RPL flags are the save RAM, so frontends keep high scores.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match id {
+        RETRO_MEMORY_SAVE_RAM => {
+            with_core(|core| core.sram.as_mut_ptr() as *mut c_void).unwrap_or(std::ptr::null_mut())
+        }
+        _ => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match id {
+        RETRO_MEMORY_SAVE_RAM => with_core(|core| core.sram.len()).unwrap_or(0),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    thread_local! {
+        static FRAME: RefCell<(c_uint, c_uint, Vec<u32>)> =
+            const { RefCell::new((0, 0, Vec::new())) };
+        static AUDIO_FRAMES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    // Accepts the pixel format and geometry, and has the palette option
+    // set to amber and the platform to XO-CHIP.
+    extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+        match cmd {
+            RETRO_ENVIRONMENT_GET_VARIABLE => {
+                let variable = unsafe { &mut *(data as *mut RetroVariable) };
+                let key = unsafe { CStr::from_ptr(variable.key) };
+                let value: &[u8] = match key.to_bytes_with_nul() {
+                    PALETTE_OPTION => b"amber\0",
+                    PLATFORM_OPTION => b"xochip\0",
+                    _ => return false,
+                };
+                variable.value = value.as_ptr() as *const c_char;
+                true
+            }
+            _ => cmd == RETRO_ENVIRONMENT_SET_PIXEL_FORMAT || cmd == RETRO_ENVIRONMENT_SET_GEOMETRY,
+        }
+    }
+
+    extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+        let pixels =
+            unsafe { std::slice::from_raw_parts(data as *const u32, pitch / 4 * height as usize) };
+        FRAME.with(|frame| *frame.borrow_mut() = (width, height, pixels.to_vec()));
+    }
+
+    extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+        AUDIO_FRAMES.with(|count| count.set(count.get() + frames));
+        frames
+    }
+
+    // Only A is held.
+    extern "C" fn input_state(_port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+        (device == RETRO_DEVICE_JOYPAD && id == 8) as i16
+    }
+
+    #[test]
+    fn test_geometry() {
+        let geometry = geometry(CHIP8_WIDTH, CHIP8_HEIGHT);
+        assert_eq!(geometry.aspect_ratio, 2.0);
+        #[cfg(not(feature = "megachip"))]
+        assert_eq!((geometry.max_width, geometry.max_height), (128, 64));
+        #[cfg(feature = "megachip")]
+        assert_eq!((geometry.max_width, geometry.max_height), (256, 192));
+    }
+
+    #[test]
+    fn test_core() {
+        retro_set_environment(Some(environment));
+        retro_set_video_refresh(Some(video_refresh));
+        retro_set_audio_sample_batch(Some(audio_sample_batch));
+        retro_set_input_state(Some(input_state));
+        retro_init();
+
+        // LD V1, 5; SKP V1; JP 0x202; LD F, V1; DRW V0, V0, 5; JP 0x20a
+        let rom: [u8; 12] = [
+            0x61, 0x05, 0xe1, 0x9e, 0x12, 0x02, 0xf1, 0x29, 0xd0, 0x05, 0x12, 0x0a,
+        ];
+        let game = RetroGameInfo {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        unsafe {
+            assert!(retro_load_game(&game));
+        }
+        retro_run();
+
+        let (width, height, pixels) = FRAME.with(|frame| frame.borrow().clone());
+        assert_eq!((width, height), (64, 32));
+        assert_eq!(
+            pixels[0],
+            Palette::AMBER.xrgb()[1],
+            "A presses 5, so the 5 glyph is drawn"
+        );
+        assert_eq!(AUDIO_FRAMES.with(|count| count.get()), 735);
+        assert_eq!(
+            with_core(|core| core.machine.cpu().quirks()),
+            Some(QuirkPreset::XoChip.quirks())
+        );
+        assert_eq!(retro_get_memory_size(RETRO_MEMORY_SAVE_RAM), 16);
+
+        let size = retro_serialize_size();
+        let mut state = vec![0u8; size];
+        unsafe {
+            assert!(retro_serialize(state.as_mut_ptr() as *mut c_void, size));
+            assert!(retro_unserialize(state.as_ptr() as *const c_void, size));
+            assert!(!retro_unserialize(state.as_ptr() as *const c_void, 8));
+        }
+
+        retro_unload_game();
+        assert_eq!(retro_serialize_size(), 0);
+        retro_deinit();
+    }
+
+    #[cfg(feature = "megachip")]
+    #[test]
+    fn test_megachip_video() {
+        retro_set_environment(Some(environment));
+        retro_set_video_refresh(Some(video_refresh));
+        retro_init();
+
+        // MEGAON; JP 0x202
+        let rom: [u8; 4] = [0x00, 0x11, 0x12, 0x02];
+        let game = RetroGameInfo {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        unsafe {
+            assert!(retro_load_game(&game));
+        }
+        let small = retro_serialize_size();
+        retro_run();
+
+        let (width, height, pixels) = FRAME.with(|frame| frame.borrow().clone());
+        assert_eq!((width, height), (256, 192));
+        assert_eq!(pixels.len(), 256 * 192);
+        assert_eq!(pixels[0], 0, "palette index 0 is transparent");
+        assert_eq!(retro_serialize_size(), small, "room was left for 256x192");
+
+        retro_unload_game();
+        retro_deinit();
+    }
+}
@@ -0,0 +1,1341 @@
+use crate::audio::{Audio, NullAudio, PatternSynth};
+use crate::clock::{Clock, SystemClock, VirtualClock, FRAME_DURATION};
+use crate::detect::{QuirkDetection, QuirkDetector, QuirkFinding, SharedDetector};
+use crate::display::{Display, NullDisplay};
+use crate::error::Result;
+use crate::framebuffer::{Framebuffer, CHIP8_HEIGHT, CHIP8_WIDTH};
+use crate::input::{Input, KeyEvent, NullInput};
+use crate::ips::apply_ips;
+use crate::keypad::CHIP8_NUM_KEYS;
+use crate::memory::XOCHIP_RAM;
+use crate::metadata::GameMetadata;
+use crate::observer::ObserverId;
+use crate::platform::Platform;
+use crate::processor::{Cpu, State};
+#[cfg(feature = "rom-db")]
+use crate::romdb::RomDatabase;
+use crate::timing::Timing;
+use crate::watch::RomWatcher;
+#[cfg(feature = "zip")]
+use crate::zip;
+
+use std::cell::RefCell;
+use std::fs;
+#[cfg(not(feature = "zip"))]
+use std::fs::File;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+// Instructions per 60Hz frame unless configured otherwise (~600Hz).
+pub const DEFAULT_CYCLES_PER_FRAME: usize = 10;
+
+// Most frames one update() call runs.
+const MAX_UPDATE_FRAMES: usize = 4;
+
+// A complete CHIP-8 system: the CPU together with its memory, display,
+// keypad, timers and buzzer, plus the loaded ROM so it can be reset.
+//
+// This is the type frontends embed.
+pub struct Machine {
+    cpu: Cpu,
+    // The loaded program, kept around for reset().
+    rom: Vec<u8>,
+    cycles_per_frame: usize,
+    // Whether frames are cycles_per_frame instructions or VIP timed.
+    timing: Timing,
+    // Instructions already run of a frame run_frame_until() stopped in.
+    frame_progress: usize,
+    // The front buffer: the screen as of the last finished frame, which is
+    // what the display and framebuffer() show. The CPU draws into its own
+    // framebuffer, and that is copied here once a frame's instructions
+    // have all run, so nothing ever sees a half-drawn frame.
+    front: Framebuffer,
+    // Where finished frames are presented.
+    display: Box<dyn Display>,
+    // Where key events come from.
+    input: Box<dyn Input>,
+    // The buzzer.
+    audio: Box<dyn Audio>,
+    // Whether the buzzer was last told to sound.
+    beeping: bool,
+    // Generates the samples for backends that take them, and the buffer
+    // they are rendered into.
+    synth: PatternSynth,
+    samples: Vec<f32>,
+    // Time source for run_paced().
+    clock: Box<dyn Clock>,
+    // When the next paced frame is due, on `clock`'s timeline.
+    next_frame: Option<Duration>,
+    // Time handed to update() not yet spent on frames.
+    frame_time: Duration,
+    // Frames finished so far.
+    frames: u64,
+    // Archive metadata of the loaded game, if it came with any.
+    metadata: Option<GameMetadata>,
+    // Set while the ROM file is being watched for changes.
+    watcher: Option<RomWatcher>,
+    // File the RPL flags persist to, and the flags it was last known to
+    // hold.
+    rpl_file: Option<(PathBuf, [u8; 16])>,
+    // Resolution last announced to the display.
+    resolution: (usize, usize),
+    // Quirk detection mode and the detector observing the CPU, unless off.
+    detection: Option<(QuirkDetection, ObserverId, Rc<RefCell<QuirkDetector>>)>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Machine::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Machine::with_cpu(Cpu::new())
+    }
+
+    // Build a machine around an already configured CPU.
+    pub fn with_cpu(cpu: Cpu) -> Self {
+        // wasm32-unknown-unknown has no clock to read; pages pace frames
+        // themselves with requestAnimationFrame.
+        #[cfg(target_arch = "wasm32")]
+        let clock: Box<dyn Clock> = Box::new(VirtualClock::new());
+        #[cfg(not(target_arch = "wasm32"))]
+        let clock: Box<dyn Clock> = Box::new(SystemClock::new());
+
+        Machine {
+            rom: Vec::new(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            timing: Timing::Uniform,
+            frame_progress: 0,
+            front: cpu.framebuffer().clone(),
+            display: Box::new(NullDisplay),
+            input: Box::new(NullInput),
+            audio: Box::new(NullAudio),
+            beeping: false,
+            synth: PatternSynth::new(),
+            samples: Vec::new(),
+            clock,
+            next_frame: None,
+            frame_time: Duration::from_secs(0),
+            frames: 0,
+            metadata: None,
+            watcher: None,
+            rpl_file: None,
+            resolution: (CHIP8_WIDTH, CHIP8_HEIGHT),
+            detection: None,
+            cpu,
+        }
+    }
+
+    // Attach the display backend frames are presented to.
+    pub fn set_display(&mut self, mut display: Box<dyn Display>) {
+        let fb = &self.front;
+        self.resolution = (fb.width(), fb.height());
+        display.set_resolution(fb.width(), fb.height());
+        display.draw(fb);
+        self.display = display;
+    }
+
+    // Attach the input backend queried at the start of every frame.
+    pub fn set_input(&mut self, input: Box<dyn Input>) {
+        self.input = input;
+    }
+
+    // Attach the audio backend driven by the sound timer.
+    pub fn set_audio(&mut self, audio: Box<dyn Audio>) {
+        if self.beeping {
+            self.audio.stop_beep();
+        }
+        self.audio = audio;
+        if self.beeping {
+            self.audio.start_beep();
+        }
+    }
+
+    // Replace the clock used for frame pacing.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+        self.next_frame = None;
+    }
+
+    // Reset the machine and load `rom` at the program start address.
+    // Empty and oversized ROMs are refused before anything is reset.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<()> {
+        self.cpu.check_program(rom)?;
+        self.cpu.reset();
+        self.cpu.load_program(rom)?;
+        self.frame_progress = 0;
+        self.frame_time = Duration::from_secs(0);
+        self.rom = rom.to_vec();
+        self.metadata = None;
+        if let Some((_, _, detector)) = &self.detection {
+            *detector.borrow_mut() = QuirkDetector::new(self.program_range());
+        }
+        self.front.copy_from(self.cpu.framebuffer());
+        self.display.clear();
+        self.update_resolution();
+        self.update_audio();
+        Ok(())
+    }
+
+    // Keep the RPL user flags (Fx75/Fx85) in `path`, so games that save
+    // progress in them keep it across runs. Flags already in the file are
+    // loaded now; a missing file counts as all zero. The file is rewritten
+    // at the end of every frame that changed the flags. One file per ROM,
+    // e.g. `rom.with_extension("rpl")`, keeps games apart.
+    pub fn set_rpl_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        let mut flags = [0; 16];
+        match fs::read(&path) {
+            Ok(data) => {
+                let len = data.len().min(flags.len());
+                flags[..len].copy_from_slice(&data[..len]);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        self.cpu.set_rpl_flags(flags);
+        self.rpl_file = Some((path, flags));
+        Ok(())
+    }
+
+    fn save_rpl_flags(&mut self) -> Result<()> {
+        if let Some((path, saved)) = &mut self.rpl_file {
+            let flags = *self.cpu.rpl_flags();
+            if flags != *saved {
+                fs::write(path, flags)?;
+                *saved = flags;
+            }
+        }
+        Ok(())
+    }
+
+    // Watch the running program for signs of the quirks it expects. With
+    // `QuirkDetection::Auto` the CPU's quirks are switched at the end of
+    // the frame something was found in; either way the findings are
+    // reported by quirk_findings(). Loading a ROM starts over.
+    pub fn set_quirk_detection(&mut self, mode: QuirkDetection) {
+        if let Some((_, id, _)) = self.detection.take() {
+            self.cpu.remove_observer(id);
+        }
+        if mode == QuirkDetection::Off {
+            return;
+        }
+
+        let detector = Rc::new(RefCell::new(QuirkDetector::new(self.program_range())));
+        let id = self
+            .cpu
+            .add_observer(Box::new(SharedDetector(detector.clone())));
+        self.detection = Some((mode, id, detector));
+    }
+
+    // What quirk detection has found since the ROM was loaded.
+    pub fn quirk_findings(&self) -> Vec<QuirkFinding> {
+        match &self.detection {
+            Some((_, _, detector)) => detector.borrow().findings().to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    fn program_range(&self) -> Range<u16> {
+        let start = self.cpu.program_start();
+        start..start.saturating_add(self.rom.len() as u16)
+    }
+
+    // Switch to the quirks detection settled on during the last frame.
+    fn apply_quirk_findings(&mut self) {
+        if let Some((QuirkDetection::Auto, _, detector)) = &self.detection {
+            let mut quirks = self.cpu.quirks();
+            for finding in detector.borrow_mut().take_new() {
+                finding.apply(&mut quirks);
+            }
+            self.cpu.set_quirks(quirks);
+        }
+    }
+
+    // Read a ROM from disk and load it.
+    #[cfg(not(feature = "zip"))]
+    pub fn load_rom_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.load_rom_from_reader(File::open(path)?)
+    }
+
+    // Read a ROM from disk and load it. A ZIP archive is recognised by its
+    // signature and its first .ch8 entry is loaded.
+    #[cfg(feature = "zip")]
+    pub fn load_rom_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let data = std::fs::read(path)?;
+        self.load_file_contents(&data)
+    }
+
+    // Load what was read from a ROM file, unpacking it if it is a ZIP.
+    #[cfg(feature = "zip")]
+    fn load_file_contents(&mut self, data: &[u8]) -> Result<()> {
+        if zip::is_zip(data) {
+            self.load_rom(&zip::extract_rom_from_bytes(data, None)?)
+        } else {
+            self.load_rom(data)
+        }
+    }
+
+    #[cfg(not(feature = "zip"))]
+    fn load_file_contents(&mut self, data: &[u8]) -> Result<()> {
+        self.load_rom(data)
+    }
+
+    // Load a ROM out of the ZIP archive read from `reader`: the entry named
+    // `entry` (full path or bare file name), or the first .ch8 entry.
+    #[cfg(feature = "zip")]
+    pub fn load_rom_zip<R: Read>(&mut self, reader: R, entry: Option<&str>) -> Result<()> {
+        let rom = zip::extract_rom(reader, entry)?;
+        self.load_rom(&rom)
+    }
+
+    // Load a ROM streamed from `reader` (an archive entry, a socket,
+    // stdin...). At most one byte more than fits in memory is read, so an
+    // oversized stream is refused without buffering all of it; the size in
+    // the error is then a lower bound.
+    pub fn load_rom_from_reader<R: Read>(&mut self, reader: R) -> Result<()> {
+        let limit = self.cpu.max_program_size() as u64 + 1;
+        let mut rom = Vec::new();
+        reader.take(limit).read_to_end(&mut rom)?;
+        self.load_rom(&rom)
+    }
+
+    // Load a game from the CHIP-8 Archive and apply the platform and
+    // options its metadata recommends. The metadata stays available to the
+    // frontend through metadata(), e.g. for the title, key bindings and
+    // `ArchiveOptions::palette`. Metadata naming an unknown platform or
+    // colour is refused.
+    pub fn load_game(&mut self, rom: &[u8], metadata: GameMetadata) -> Result<()> {
+        let platform = metadata.target_platform()?;
+        metadata.options.palette()?;
+
+        // The platform goes first, since XO-CHIP games may need more than
+        // 4K. A ROM that still doesn't fit leaves the machine as it was.
+        let (old_platform, old_size) = (self.cpu.platform(), self.cpu.memory().size());
+        if let Some(platform) = platform {
+            self.cpu.set_platform(platform);
+            if platform == Platform::XoChip {
+                self.cpu.set_memory_size(old_size.max(XOCHIP_RAM));
+            }
+        }
+        if let Err(e) = self.load_rom(rom) {
+            self.cpu.set_platform(old_platform);
+            self.cpu.set_memory_size(old_size);
+            return Err(e);
+        }
+
+        let options = &metadata.options;
+        if let Some(tickrate) = options.tickrate {
+            self.cycles_per_frame = tickrate;
+        }
+        let mut quirks = self.cpu.quirks();
+        if let Some(shift) = options.shift_quirks {
+            quirks.shift = shift;
+        }
+        if let Some(logic) = options.logic_quirks {
+            quirks.vf_reset = logic;
+        }
+        if let Some(clip) = options.clip_quirks {
+            quirks.clipping = clip;
+        }
+        if let Some(vblank) = options.vblank_quirks {
+            quirks.display_wait = vblank;
+        }
+        if let Some(jump) = options.jump_quirks {
+            quirks.jump = jump;
+        }
+        if let Some(load_store) = options.load_store_quirks {
+            quirks.memory_increment = !load_store;
+        }
+        if let Some(vf_order) = options.vf_order_quirks {
+            quirks.vf_order = vf_order;
+        }
+        self.cpu.set_quirks(quirks);
+
+        self.metadata = Some(metadata);
+        Ok(())
+    }
+
+    // Load `rom`, applying the settings `db` has for it when it is a known
+    // dump. Returns whether the ROM was recognised.
+    #[cfg(feature = "rom-db")]
+    pub fn load_known_rom(&mut self, rom: &[u8], db: &RomDatabase) -> Result<bool> {
+        match db.lookup(rom) {
+            Some(metadata) => {
+                self.load_game(rom, metadata.clone())?;
+                Ok(true)
+            }
+            None => {
+                self.load_rom(rom)?;
+                Ok(false)
+            }
+        }
+    }
+
+    // Metadata of the game loaded with load_game() or load_known_rom().
+    pub fn metadata(&self) -> Option<&GameMetadata> {
+        self.metadata.as_ref()
+    }
+
+    // Load the ROM at `path` and keep watching it: whenever the file is
+    // rewritten, the machine resets and runs the new version, keeping its
+    // speed, quirk settings and metadata. Checked at the start of every
+    // frame.
+    pub fn watch_rom_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let watcher = RomWatcher::new(path);
+        self.load_rom_file(watcher.path())?;
+        self.watcher = Some(watcher);
+        Ok(())
+    }
+
+    pub fn stop_watching(&mut self) {
+        self.watcher = None;
+    }
+
+    // Reload the ROM if the watched file changed. A version that fails to
+    // load is skipped and the old one keeps running until the next save.
+    fn check_watched_rom(&mut self) {
+        let rom = match self.watcher.as_mut().and_then(RomWatcher::poll) {
+            Some(rom) => rom,
+            None => return,
+        };
+
+        // The ROM is checked before anything is reset, so on failure the old
+        // version is untouched.
+        let metadata = self.metadata.take();
+        let _ = self.load_file_contents(&rom);
+        self.metadata = metadata;
+    }
+
+    // Load `rom` with the IPS `patch` applied on top, e.g. a translation or
+    // bug fix distributed as a patch against the original dump.
+    pub fn load_patched_rom(&mut self, rom: &[u8], patch: &[u8]) -> Result<()> {
+        self.load_rom(&apply_ips(rom, patch)?)
+    }
+
+    // Restart the loaded ROM from scratch. Fails, leaving memory empty, if
+    // the ROM no longer fits because the memory size was changed through
+    // cpu_mut().
+    pub fn reset(&mut self) -> Result<()> {
+        self.cpu.reset();
+        self.frame_progress = 0;
+        self.frame_time = Duration::from_secs(0);
+        let loaded = match self.rom.is_empty() {
+            true => Ok(()),
+            false => self.cpu.load_program(&self.rom),
+        };
+        self.front.copy_from(self.cpu.framebuffer());
+        self.display.clear();
+        self.update_resolution();
+        self.update_audio();
+        loaded
+    }
+
+    // The CPU's state, see `Cpu::save_state`.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.cpu.save_state()
+    }
+
+    // Go back to a state from save_state() and show its screen. States
+    // with too little memory for the loaded ROM are refused.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<()> {
+        self.cpu.load_state_for(state, self.rom.len())?;
+        self.frame_progress = 0;
+        self.front.copy_from(self.cpu.framebuffer());
+        self.update_resolution();
+        self.display.draw(&self.front);
+        self.update_audio();
+        Ok(())
+    }
+
+    // Execute a single instruction. What it draws shows in framebuffer()
+    // once the frame it belongs to is finished.
+    pub fn step(&mut self) -> Result<State> {
+        self.cpu.step()
+    }
+
+    // Run one 60Hz frame worth of instructions and tick the timers. The
+    // frame is presented to the display if it changed, which is also
+    // returned.
+    pub fn run_frame(&mut self) -> Result<bool> {
+        self.check_watched_rom();
+        self.poll_input();
+
+        let changed = match self.timing {
+            Timing::Uniform => self.cpu.run_frame(self.cycles_per_frame)?,
+            Timing::CosmacVip => self.cpu.run_vip_frame()?,
+        };
+        self.finish_frame(changed)
+    }
+
+    // Like run_frame(), but `predicate` is checked before every instruction
+    // and the frame stops as soon as it holds, for breakpoints. Returns
+    // None if it stopped; the next call picks the frame up from there.
+    // Otherwise the frame is finished like run_frame() and Some tells
+    // whether the display changed.
+    //
+    // VIP timed frames only check `predicate` between frames.
+    pub fn run_frame_until<F>(&mut self, mut predicate: F) -> Result<Option<bool>>
+    where
+        F: FnMut(&Cpu) -> bool,
+    {
+        if self.frame_progress == 0 {
+            if predicate(&self.cpu) {
+                return Ok(None);
+            }
+            self.check_watched_rom();
+            self.poll_input();
+        }
+
+        if self.timing == Timing::CosmacVip {
+            let changed = self.cpu.run_vip_frame()?;
+            return self.finish_frame(changed).map(Some);
+        }
+
+        while self.frame_progress < self.cycles_per_frame {
+            if self.frame_progress > 0 && predicate(&self.cpu) {
+                return Ok(None);
+            }
+            self.frame_progress += 1;
+            if self.cpu.step()? != State::Running {
+                break;
+            }
+        }
+
+        self.frame_progress = 0;
+        let changed = self.cpu.run_frame(0)?;
+        self.finish_frame(changed).map(Some)
+    }
+
+    // Everything after the instructions of a frame: persisting flags,
+    // presenting the screen and the audio.
+    fn finish_frame(&mut self, changed: bool) -> Result<bool> {
+        self.save_rpl_flags()?;
+        self.apply_quirk_findings();
+        let back = self.cpu.framebuffer();
+        if changed || (back.width(), back.height()) != self.resolution {
+            self.front.copy_from(back);
+        }
+        self.update_resolution();
+        if changed {
+            self.display.draw(&self.front);
+            #[cfg(feature = "megachip")]
+            {
+                let mega = self.cpu.megachip();
+                if mega.is_enabled() {
+                    self.display.draw_megachip(mega.frame(), mega.alpha());
+                }
+            }
+        }
+        self.update_audio();
+        self.render_audio();
+        self.frames += 1;
+        Ok(changed)
+    }
+
+    // Wait until the next frame is due on the clock, then run it, keeping
+    // emulation at 60 frames per second. If the caller fell behind by more
+    // than a frame the schedule restarts from now instead of bursting to
+    // catch up.
+    pub fn run_paced(&mut self) -> Result<bool> {
+        let delay = self.schedule_frame();
+        if delay > Duration::from_secs(0) {
+            self.clock.sleep(delay);
+        }
+
+        self.run_frame()
+    }
+
+    // Run the frames `elapsed` is worth, for game loops that own the
+    // timing and hand over their frame time (e.g. macroquad's
+    // get_frame_time()) instead of letting the machine sleep. Time short
+    // of a frame carries over to the next call; after a stall more than
+    // MAX_UPDATE_FRAMES behind, the rest is dropped. Returns whether the
+    // display changed.
+    pub fn update(&mut self, elapsed: Duration) -> Result<bool> {
+        self.frame_time += elapsed;
+        let mut changed = false;
+        for _ in 0..MAX_UPDATE_FRAMES {
+            if self.frame_time < FRAME_DURATION {
+                return Ok(changed);
+            }
+            self.frame_time -= FRAME_DURATION;
+            changed |= self.run_frame()?;
+        }
+        if self.frame_time >= FRAME_DURATION {
+            self.frame_time = Duration::from_secs(0);
+        }
+        Ok(changed)
+    }
+
+    // Book the next paced frame and return how long to wait before
+    // running it.
+    pub(crate) fn schedule_frame(&mut self) -> Duration {
+        let now = self.clock.now();
+        let due = match self.next_frame {
+            Some(due) if due + FRAME_DURATION >= now => due,
+            _ => now,
+        };
+
+        self.next_frame = Some(due + FRAME_DURATION);
+        due.checked_sub(now).unwrap_or_default()
+    }
+
+    // Tell the display when the program switched between lores and hires.
+    fn update_resolution(&mut self) {
+        let fb = &self.front;
+        let resolution = (fb.width(), fb.height());
+        if resolution != self.resolution {
+            self.resolution = resolution;
+            self.display.set_resolution(resolution.0, resolution.1);
+        }
+    }
+
+    // Tell the audio backend about sound timer edges.
+    fn update_audio(&mut self) {
+        let beeping = self.cpu.is_beeping();
+        match (self.beeping, beeping) {
+            (false, true) => self.audio.start_beep(),
+            (true, false) => self.audio.stop_beep(),
+            _ => {}
+        }
+        self.beeping = beeping;
+    }
+
+    // Hand sample-based backends the audio of the frame that just ran.
+    fn render_audio(&mut self) {
+        if let Some(rate) = self.audio.sample_rate() {
+            self.samples.clear();
+            self.synth.render(
+                self.cpu.audio_pattern(),
+                self.cpu.pitch(),
+                self.beeping,
+                rate,
+                &mut self.samples,
+            );
+            #[cfg(feature = "megachip")]
+            self.cpu.megachip_mut().mix_sound(rate, &mut self.samples);
+            self.audio.queue_samples(&self.samples);
+        }
+    }
+
+    // Feed pending input events to the keypad, then bring any key whose
+    // level disagrees with the backend back in sync.
+    fn poll_input(&mut self) {
+        while let Some(event) = self.input.poll_event() {
+            match event {
+                KeyEvent::Pressed(key) => self.cpu.press_key(key),
+                KeyEvent::Released(key) => self.cpu.release_key(key),
+            }
+        }
+
+        for key in 0..CHIP8_NUM_KEYS as u8 {
+            match (self.input.is_pressed(key), self.cpu.is_key_pressed(key)) {
+                (true, false) => self.cpu.press_key(key),
+                (false, true) => self.cpu.release_key(key),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn cycles_per_frame(&self) -> usize {
+        self.cycles_per_frame
+    }
+
+    pub fn set_cycles_per_frame(&mut self, cycles: usize) {
+        self.cycles_per_frame = cycles;
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    // Choose between a fixed number of instructions per frame (the
+    // default) and the instruction costs of the COSMAC VIP, which games
+    // written for it were tuned against. cycles_per_frame() only applies
+    // to uniform timing.
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.cpu.press_key(key);
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.cpu.release_key(key);
+    }
+
+    // The screen as of the last finished frame. The one being drawn is
+    // `cpu().framebuffer()`.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.front
+    }
+
+    // Whether the buzzer should currently be sounding.
+    pub fn is_beeping(&self) -> bool {
+        self.cpu.is_beeping()
+    }
+
+    // How many frames have finished since the machine was made, for
+    // frontends that do something every frame however many an update()
+    // ran, like recording the screen.
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    // Whether the program ended itself with EXIT. Frontends can close or go
+    // back to a ROM menu; reset() or loading a ROM starts over.
+    pub fn has_exited(&self) -> bool {
+        self.cpu.has_exited()
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    // Headless copy of this machine for speculative execution: same CPU
+    // state, ROM and speed, but with null backends and a virtual clock so
+    // the branch can be run as fast as the caller likes without touching
+    // the original's screen, speakers or input.
+    pub fn fork(&self) -> Machine {
+        let mut fork = Machine::with_cpu(self.cpu.fork());
+        fork.rom = self.rom.clone();
+        fork.cycles_per_frame = self.cycles_per_frame;
+        fork.timing = self.timing;
+        fork.metadata = self.metadata.clone();
+        fork.clock = Box::new(VirtualClock::new());
+        fork
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::error::Chip8Error;
+    use crate::input::EventQueue;
+    use crate::memory::{CHIP8_RAM, XOCHIP_RAM};
+    use crate::quirks::Quirks;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Records the calls made by the machine.
+    #[derive(Default)]
+    struct RecordingDisplay {
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Display for RecordingDisplay {
+        fn draw(&mut self, framebuffer: &Framebuffer) {
+            let lit = framebuffer.get(0, 0);
+            self.calls.borrow_mut().push(format!("draw {}", lit));
+        }
+
+        fn clear(&mut self) {
+            self.calls.borrow_mut().push("clear".to_string());
+        }
+
+        fn set_resolution(&mut self, width: usize, height: usize) {
+            self.calls
+                .borrow_mut()
+                .push(format!("{}x{}", width, height));
+        }
+    }
+
+    // LD V0, 0; LD F, V0; DRW V0, V0, 5; LD ST, V0 (V0 = 3); JP 0x20a
+    const ROM: [u8; 12] = [
+        0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0x60, 0x03, 0xf0, 0x18, 0x12, 0x0a,
+    ];
+
+    #[test]
+    fn test_load_rom_and_run_frame() {
+        let mut machine = Machine::new();
+        machine.load_rom(&ROM).unwrap();
+
+        assert!(machine.run_frame().unwrap(), "the glyph was drawn");
+        assert!(machine.framebuffer().get(0, 0));
+        assert!(machine.is_beeping());
+        assert_eq!(machine.step(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_reset_restarts_rom() {
+        let mut machine = Machine::new();
+        machine.load_rom(&ROM).unwrap();
+        machine.run_frame().unwrap();
+        machine.reset().unwrap();
+
+        assert_eq!(machine.framebuffer(), &Framebuffer::new());
+        assert!(!machine.is_beeping());
+        assert_eq!(
+            machine.step(),
+            Ok(State::Running),
+            "the ROM is still loaded"
+        );
+    }
+
+    #[test]
+    fn test_reset_without_rom() {
+        let mut machine = Machine::new();
+        machine.reset().unwrap();
+        assert_eq!(machine.cpu().pc(), 0x200);
+    }
+
+    #[test]
+    fn test_load_state_keeps_room_for_rom() {
+        let small = Machine::new().save_state();
+
+        let mut machine = Machine::new();
+        machine.cpu_mut().set_memory_size(XOCHIP_RAM);
+        machine.load_rom(&[0; 8000]).unwrap();
+        assert_eq!(
+            machine.load_state(&small),
+            Err(Chip8Error::InvalidSaveState {
+                reason: "memory too small for the ROM"
+            })
+        );
+        machine.reset().unwrap();
+
+        machine.cpu_mut().set_memory_size(CHIP8_RAM);
+        assert!(matches!(
+            machine.reset(),
+            Err(Chip8Error::RomTooLarge { size: 8000, .. })
+        ));
+    }
+
+    #[test]
+    fn test_double_buffering() {
+        let mut machine = Machine::new();
+        machine.load_rom(&ROM).unwrap();
+
+        // Stopped right after the DRW, the glyph is only in the back
+        // buffer.
+        let stopped = machine.run_frame_until(|cpu| cpu.pc() == 0x206).unwrap();
+        assert_eq!(stopped, None);
+        assert!(machine.cpu().framebuffer().get(0, 0));
+        assert!(!machine.framebuffer().get(0, 0));
+
+        assert_eq!(machine.run_frame_until(|_| false).unwrap(), Some(true));
+        assert!(machine.framebuffer().get(0, 0));
+    }
+
+    #[test]
+    fn test_display_backend() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut machine = Machine::new();
+        machine.set_display(Box::new(RecordingDisplay {
+            calls: calls.clone(),
+        }));
+        machine.load_rom(&ROM).unwrap();
+        machine.run_frame().unwrap();
+        machine.run_frame().unwrap();
+
+        assert_eq!(
+            *calls.borrow(),
+            vec!["64x32", "draw false", "clear", "draw true"],
+            "only frames that changed are presented"
+        );
+    }
+
+    #[test]
+    fn test_input_backend() {
+        // LD V1, K; JP 0x202
+        let mut machine = Machine::new();
+        machine.load_rom(&[0xf1, 0x0a, 0x12, 0x02]).unwrap();
+        machine.run_frame().unwrap();
+        assert!(machine.cpu().is_waiting_for_key());
+
+        let mut queue = EventQueue::new();
+        queue.push(KeyEvent::Pressed(0xc));
+        queue.push(KeyEvent::Released(0xc));
+        machine.set_input(Box::new(queue));
+        machine.run_frame().unwrap();
+
+        assert!(!machine.cpu().is_waiting_for_key());
+        assert_eq!(machine.step(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_input_level_sync() {
+        struct HeldKey;
+
+        impl Input for HeldKey {
+            fn poll_event(&mut self) -> Option<KeyEvent> {
+                None
+            }
+
+            fn is_pressed(&self, key: u8) -> bool {
+                key == 0x3
+            }
+        }
+
+        let mut machine = Machine::new();
+        machine.load_rom(&[0x12, 0x00]).unwrap();
+        machine.press_key(0x7);
+        machine.set_input(Box::new(HeldKey));
+        machine.run_frame().unwrap();
+
+        assert!(machine.cpu().is_key_pressed(0x3));
+        assert!(!machine.cpu().is_key_pressed(0x7));
+    }
+
+    #[test]
+    fn test_audio_backend() {
+        struct RecordingAudio(Rc<RefCell<Vec<&'static str>>>);
+
+        impl Audio for RecordingAudio {
+            fn start_beep(&mut self) {
+                self.0.borrow_mut().push("start");
+            }
+
+            fn stop_beep(&mut self) {
+                self.0.borrow_mut().push("stop");
+            }
+        }
+
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut machine = Machine::new();
+        machine.set_audio(Box::new(RecordingAudio(calls.clone())));
+        machine.load_rom(&ROM).unwrap();
+
+        // ST is set to 3, then ticks down once per frame.
+        for _ in 0..4 {
+            machine.run_frame().unwrap();
+        }
+
+        assert_eq!(*calls.borrow(), vec!["start", "stop"]);
+    }
+
+    #[test]
+    fn test_audio_samples() {
+        struct SampleAudio(Rc<RefCell<Vec<f32>>>);
+
+        impl Audio for SampleAudio {
+            fn start_beep(&mut self) {}
+
+            fn stop_beep(&mut self) {}
+
+            fn sample_rate(&self) -> Option<u32> {
+                Some(6000)
+            }
+
+            fn queue_samples(&mut self, samples: &[f32]) {
+                self.0.borrow_mut().extend_from_slice(samples);
+            }
+        }
+
+        let samples = Rc::new(RefCell::new(Vec::new()));
+        let mut machine = Machine::new();
+        machine.set_audio(Box::new(SampleAudio(samples.clone())));
+        machine.load_rom(&ROM).unwrap();
+
+        machine.run_frame().unwrap();
+        assert_eq!(samples.borrow().len(), 100, "one frame at 6000Hz");
+        assert!(samples.borrow().iter().any(|&sample| sample > 0.0));
+
+        for _ in 0..3 {
+            machine.run_frame().unwrap();
+        }
+        assert!(samples.borrow()[300..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn test_vip_timing() {
+        // ADD V0, 1; JP 0x200
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut machine = Machine::new();
+        machine.load_rom(&rom).unwrap();
+        machine.set_timing(Timing::CosmacVip);
+        machine.run_frame().unwrap();
+
+        assert_eq!(
+            machine.cpu().registers()[0],
+            26,
+            "VIP timing, not 10 instructions"
+        );
+        assert_eq!(machine.fork().timing(), Timing::CosmacVip);
+    }
+
+    #[test]
+    fn test_run_frame_until() {
+        // ADD V0, 1; JP 0x200
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut machine = Machine::new();
+        machine.load_rom(&rom).unwrap();
+
+        // Stop at the third ADD, two into the frame.
+        let mut adds = 0;
+        let stop = machine
+            .run_frame_until(|cpu| {
+                adds += (cpu.pc() == 0x200) as u32;
+                adds == 3
+            })
+            .unwrap();
+        assert_eq!(stop, None);
+        assert_eq!(machine.cpu().registers()[0], 2);
+
+        // The rest of the frame.
+        assert!(machine.run_frame_until(|_| false).unwrap().is_some());
+        assert_eq!(machine.cpu().registers()[0], 5);
+    }
+
+    #[test]
+    fn test_update() {
+        // ADD V0, 1; JP 0x200: five ADDs a frame.
+        let mut machine = Machine::new();
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        machine.update(FRAME_DURATION / 2).unwrap();
+        assert_eq!(machine.cpu().registers()[0], 0);
+        machine.update(FRAME_DURATION / 2).unwrap();
+        assert_eq!(machine.cpu().registers()[0], 5);
+        assert_eq!(machine.frame_count(), 1);
+
+        // A stall only catches up MAX_UPDATE_FRAMES.
+        machine.update(Duration::from_secs(1)).unwrap();
+        assert_eq!(machine.cpu().registers()[0], 25);
+        assert_eq!(machine.frame_count(), 5);
+        machine.update(Duration::from_secs(0)).unwrap();
+        assert_eq!(machine.cpu().registers()[0], 25);
+    }
+
+    #[test]
+    fn test_run_paced() {
+        use crate::clock::VirtualClock;
+
+        struct SharedClock(Rc<RefCell<VirtualClock>>);
+
+        impl Clock for SharedClock {
+            fn now(&self) -> Duration {
+                self.0.borrow().now()
+            }
+
+            fn sleep(&mut self, duration: Duration) {
+                self.0.borrow_mut().sleep(duration);
+            }
+        }
+
+        let clock = Rc::new(RefCell::new(VirtualClock::new()));
+        let mut machine = Machine::new();
+        machine.set_clock(Box::new(SharedClock(clock.clone())));
+        machine.load_rom(&ROM).unwrap();
+
+        for _ in 0..60 {
+            machine.run_paced().unwrap();
+        }
+        assert_eq!(
+            clock.borrow().now(),
+            FRAME_DURATION * 59,
+            "60 frames take one second, the first runs immediately"
+        );
+
+        // A long stall does not cause a burst of catch-up frames.
+        clock.borrow_mut().advance(Duration::from_secs(1));
+        let before = clock.borrow().now();
+        machine.run_paced().unwrap();
+        machine.run_paced().unwrap();
+        assert_eq!(clock.borrow().now(), before + FRAME_DURATION);
+    }
+
+    #[test]
+    fn test_load_game() {
+        let metadata = GameMetadata::from_json(
+            r#"{"title": "Test", "options": {"tickrate": 20, "loadStoreQuirks": false}}"#,
+        )
+        .unwrap();
+
+        let mut machine = Machine::new();
+        machine.load_game(&ROM, metadata).unwrap();
+        assert_eq!(machine.cycles_per_frame(), 20);
+        assert_eq!(machine.metadata().map(|m| m.title.as_str()), Some("Test"));
+
+        // LD V0, 1; LD [I], V0 with I = 0x300
+        machine
+            .load_game(
+                &[0xa3, 0x00, 0x60, 0x01, 0xf0, 0x55],
+                machine.metadata().cloned().unwrap(),
+            )
+            .unwrap();
+        for _ in 0..3 {
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.cpu().i(), 0x301, "the VIP behaviour was selected");
+
+        machine.load_rom(&ROM).unwrap();
+        assert_eq!(machine.metadata(), None);
+    }
+
+    #[test]
+    fn test_load_game_platform() {
+        let xo = GameMetadata::from_json(
+            r#"{"title": "Big", "platform": "xochip", "options": {"vfOrderQuirks": true}}"#,
+        )
+        .unwrap();
+        let mut machine = Machine::new();
+        machine.load_game(&[0; 8000], xo).unwrap();
+        assert_eq!(machine.cpu().platform(), Platform::XoChip);
+        assert_eq!(machine.cpu().memory().size(), XOCHIP_RAM);
+        assert!(machine.cpu().quirks().vf_order);
+
+        let mut machine = Machine::new();
+        let unknown = GameMetadata::from_json(r#"{"title": "?", "platform": "vip"}"#).unwrap();
+        assert_eq!(
+            machine.load_game(&ROM, unknown),
+            Err(Chip8Error::InvalidMetadata {
+                reason: "unknown platform"
+            })
+        );
+        let schip = GameMetadata::from_json(r#"{"title": "S", "platform": "schip"}"#).unwrap();
+        assert!(machine.load_game(&[0; 8000], schip).is_err());
+        assert_eq!(machine.cpu().platform(), Platform::XoChip, "unchanged");
+        assert_eq!(machine.cpu().memory().size(), 4096);
+    }
+
+    #[cfg(feature = "rom-db")]
+    #[test]
+    fn test_load_known_rom() {
+        let mut db = RomDatabase::new();
+        db.insert(
+            &RomDatabase::hash(&ROM),
+            GameMetadata::from_json(r#"{"title": "Known", "options": {"tickrate": 30}}"#).unwrap(),
+        );
+
+        let mut machine = Machine::new();
+        assert_eq!(machine.load_known_rom(&ROM, &db), Ok(true));
+        assert_eq!(machine.cycles_per_frame(), 30);
+        assert_eq!(machine.load_known_rom(&[0x12, 0x00], &db), Ok(false));
+        assert_eq!(machine.metadata(), None);
+    }
+
+    #[cfg(feature = "rom-db")]
+    #[test]
+    fn test_load_known_rom_quirks() {
+        // The kind of entry a classic needs: VIP shifts, clipped sprites
+        // and a slower tickrate than the default.
+        let json = format!(
+            r#"{{"{}": {{"title": "Classic", "platform": "chip8", "options":
+                {{"tickrate": 15, "shiftQuirks": false, "clipQuirks": true}}}}}}"#,
+            RomDatabase::hash(&ROM)
+        );
+        let db = RomDatabase::from_json(&json).unwrap();
+
+        let mut machine = Machine::new();
+        assert_eq!(machine.load_known_rom(&ROM, &db), Ok(true));
+        let quirks = machine.cpu().quirks();
+        assert!(!quirks.shift && quirks.clipping);
+        assert_eq!(machine.cycles_per_frame(), 15);
+        assert_eq!(machine.cpu().platform(), Platform::Chip8);
+    }
+
+    #[test]
+    fn test_fork() {
+        // LD V0, K; LD F, V0; DRW V0, V0, 5; JP 0x206
+        let mut machine = Machine::new();
+        machine
+            .load_rom(&[0xf0, 0x0a, 0xf0, 0x29, 0xd0, 0x05, 0x12, 0x06])
+            .unwrap();
+        machine.run_frame().unwrap();
+
+        let mut fork = machine.fork();
+        fork.press_key(0x5);
+        fork.run_frame().unwrap();
+        fork.release_key(0x5);
+        fork.run_frame().unwrap();
+
+        assert!(fork.framebuffer().get(5, 5), "the fork drew the 5 glyph");
+        assert_eq!(machine.framebuffer(), &Framebuffer::new());
+        assert!(machine.cpu().is_waiting_for_key());
+
+        fork.reset().unwrap();
+        assert_eq!(fork.step(), Ok(State::WaitingForKey), "the ROM came along");
+    }
+
+    #[test]
+    fn test_load_rom_too_large() {
+        let mut machine = Machine::new();
+        assert!(machine.load_rom(&[0u8; 4096]).is_err());
+        assert_eq!(
+            machine.load_rom(&[0u8; 0xe01]),
+            Err(Chip8Error::RomTooLarge {
+                size: 0xe01,
+                max: 0xe00
+            })
+        );
+        assert_eq!(machine.load_rom(&[0u8; 0xe00]), Ok(()));
+    }
+
+    #[test]
+    fn test_load_rom_rejects_empty_without_reset() {
+        let mut machine = Machine::new();
+        machine.load_rom(&ROM).unwrap();
+        machine.run_frame().unwrap();
+
+        assert_eq!(machine.load_rom(&[]), Err(Chip8Error::EmptyRom));
+        assert!(machine.framebuffer().get(0, 0), "the old ROM kept running");
+    }
+
+    #[test]
+    fn test_load_rom_from_reader() {
+        let mut machine = Machine::new();
+        machine.load_rom_from_reader(&ROM[..]).unwrap();
+        assert!(machine.run_frame().unwrap());
+
+        // An endless stream is cut off just past the limit.
+        let endless = std::io::repeat(0x12);
+        assert_eq!(
+            machine.load_rom_from_reader(endless),
+            Err(Chip8Error::RomTooLarge {
+                size: 0xe01,
+                max: 0xe00
+            })
+        );
+    }
+
+    #[test]
+    fn test_load_patched_rom() {
+        // Patch LD V0, 0 into LD V0, 5 so the 5 glyph is drawn.
+        let patch = *b"PATCH\x00\x00\x01\x00\x01\x05EOF";
+        let mut machine = Machine::new();
+        machine.load_patched_rom(&ROM, &patch).unwrap();
+        machine.run_frame().unwrap();
+
+        assert_eq!(machine.cpu().registers()[0], 3);
+        assert_eq!(machine.framebuffer().row(5)[5..9], [1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_watch_rom_file() {
+        let path = std::env::temp_dir().join(format!("chip8-hot-{}.ch8", std::process::id()));
+        // LD V0, 1; JP 0x202
+        std::fs::write(&path, [0x60, 0x01, 0x12, 0x02]).unwrap();
+
+        let mut machine = Machine::new();
+        machine.watch_rom_file(&path).unwrap();
+        machine
+            .watcher
+            .as_mut()
+            .unwrap()
+            .set_interval(Duration::from_secs(0));
+        machine.set_cycles_per_frame(3);
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu().registers()[0], 1);
+
+        // LD V0, 2; JP 0x202
+        std::fs::write(&path, [0x60, 0x02, 0x12, 0x02, 0x00, 0x00]).unwrap();
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu().registers()[0], 2, "the new version runs");
+        assert_eq!(machine.cycles_per_frame(), 3, "settings are kept");
+
+        // Too big to load: the running version is left alone.
+        std::fs::write(&path, vec![0u8; 0x1000]).unwrap();
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu().registers()[0], 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_rom_file() {
+        let path = std::env::temp_dir().join(format!("chip8-test-{}.ch8", std::process::id()));
+        std::fs::write(&path, ROM).unwrap();
+
+        let mut machine = Machine::new();
+        machine.load_rom_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(machine.run_frame().unwrap());
+
+        match machine.load_rom_file(&path) {
+            Err(Chip8Error::Io { kind, .. }) => assert_eq!(kind, std::io::ErrorKind::NotFound),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_quirk_detection() {
+        // LD V1, 0x80; SHR V1, V4; JP 0x204
+        let rom = [0x61, 0x80, 0x81, 0x46, 0x12, 0x04];
+        let mut machine = Machine::with_cpu(
+            Cpu::builder()
+                .quirks(Quirks {
+                    shift: false,
+                    ..Quirks::default()
+                })
+                .build()
+                .unwrap(),
+        );
+
+        machine.set_quirk_detection(QuirkDetection::Suggest);
+        machine.load_rom(&rom).unwrap();
+        machine.run_frame().unwrap();
+        assert_eq!(machine.quirk_findings().len(), 1);
+        assert!(machine.quirk_findings()[0].enabled);
+        assert!(!machine.cpu().quirks().shift, "only a suggestion");
+        assert_eq!(machine.cpu().registers()[1], 0, "V4 was shifted into V1");
+
+        machine.set_quirk_detection(QuirkDetection::Auto);
+        machine.load_rom(&rom).unwrap();
+        machine.run_frame().unwrap();
+        assert!(
+            machine.cpu().quirks().shift,
+            "the shift quirk was switched on"
+        );
+
+        machine.load_rom(&rom).unwrap();
+        assert!(machine.quirk_findings().is_empty(), "loading starts over");
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu().registers()[1], 0x40);
+
+        machine.set_quirk_detection(QuirkDetection::Off);
+        assert!(machine.quirk_findings().is_empty());
+    }
+
+    #[test]
+    fn test_hires_resolution_is_announced() {
+        let display = RecordingDisplay::default();
+        let calls = display.calls.clone();
+        let mut machine = Machine::new();
+        machine.set_display(Box::new(display));
+        // HIGH; JP 0x202
+        machine.load_rom(&[0x00, 0xff, 0x12, 0x02]).unwrap();
+        calls.borrow_mut().clear();
+
+        machine.run_frame().unwrap();
+        assert_eq!(*calls.borrow(), ["128x64", "draw false"]);
+
+        calls.borrow_mut().clear();
+        machine.reset().unwrap();
+        assert_eq!(*calls.borrow(), ["clear", "64x32"]);
+    }
+
+    #[test]
+    fn test_rpl_file() {
+        let path = std::env::temp_dir().join(format!("chip8-rpl-{}.rpl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        // LD V0, 7; LD R, V0; JP 0x204
+        let rom = [0x60, 0x07, 0xf0, 0x75, 0x12, 0x04];
+
+        let mut machine = Machine::new();
+        machine.set_rpl_file(&path).unwrap();
+        machine.load_rom(&rom).unwrap();
+        machine.run_frame().unwrap();
+        assert_eq!(fs::read(&path).unwrap()[..2], [7, 0]);
+
+        let mut machine = Machine::new();
+        machine.set_rpl_file(&path).unwrap();
+        assert_eq!(machine.cpu().rpl_flags()[0], 7, "the flags were kept");
+        fs::remove_file(&path).unwrap();
+    }
+}
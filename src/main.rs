@@ -1,7 +1,585 @@
-mod processor;
-mod sprite;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-use sprite::FONT_SET;
+#[cfg(feature = "rom-db")]
+use hello_world::RomDatabase;
+use hello_world::{
+    recording_name, screenshot_name, screenshot_png, CombinedInput, Cpu, DebugCommand, Debugger,
+    GamepadInput, GamepadMap, Input, KeyMap, Machine, Palette, PipeAudio, QuirkPreset, Recorder,
+    RecordingFormat, RomInfo, StreamServer, TerminalBell, TerminalDisplay, TerminalInput,
+    TerminalRenderer, CHIP8_PROGRAM_START, CHIP8_RAM, DEFAULT_PIXEL_SCALE, SCHIP_HEIGHT,
+    SCHIP_WIDTH,
+};
+
+const USAGE: &str = "usage: chip8 info [--start ADDR] ROM
+       chip8 play [--speed CYCLES] [--preset NAME] [--start ADDR] [--debug]
+                  [--renderer auto|blocks|braille|sixel|kitty]
+                  [--palette mono|green|amber|octo|lcd|COLORS]
+                  [--scale PIXELS|fit] [--record gif|apng]
+                  [--sound bell|aplay|off] [--gamepad DEVICE]
+                  [--pad-map BUTTON=KEY,...] ROM
+       chip8 serve [--port PORT] [--speed CYCLES] [--preset NAME]
+                   [--start ADDR] ROM";
+
+// Where `chip8 serve` listens unless told otherwise.
+const DEFAULT_PORT: u16 = 8080;
+
+// Ctrl-C, which raw mode delivers as input and which ends `chip8 play`.
+const QUIT_KEY: u8 = 0x03;
+
+// Esc on its own also ends it. The same byte starts the sequences arrow
+// and function keys send, which arrive in the same read and are skipped.
+const ESC: u8 = 0x1b;
+
+// Ctrl-S, which saves a screenshot to the current directory.
+const SCREENSHOT_KEY: u8 = 0x13;
+
+// Ctrl-R, which starts recording the screen and, pressed again, saves the
+// recording to the current directory.
+const RECORD_KEY: u8 = 0x12;
+
+// How long the debugger waits between frames.
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("info") => info(&args[1..]),
+        Some("play") => play(&args[1..]),
+        Some("serve") => serve(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(2);
+    }
+}
+
+// Print what is known about a ROM without running it.
+fn info(args: &[String]) -> Result<(), String> {
+    let mut start = CHIP8_PROGRAM_START;
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--start" => {
+                let value = args.next().ok_or(USAGE)?;
+                start = parse_address(value)?;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let path = path.ok_or(USAGE)?;
+    let rom = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let info = RomInfo::new(&rom, start);
+    println!("{}", info);
+
+    #[cfg(feature = "rom-db")]
+    match RomDatabase::builtin().lookup(&rom) {
+        Some(game) => println!("title:    {}", game.title),
+        None => println!("title:    not in the database"),
+    }
+
+    Ok(())
+}
+
+// Run a ROM in the terminal, keys on the usual 1234/QWER/ASDF/ZXCV block.
+fn play(args: &[String]) -> Result<(), String> {
+    let mut speed = None;
+    let mut preset = None;
+    let mut start = CHIP8_PROGRAM_START;
+    let mut debug = false;
+    let mut sound = "bell".to_string();
+    let mut gamepad = None;
+    let mut pad_map = GamepadMap::standard();
+    let detected = TerminalRenderer::detect(|name| env::var(name).ok());
+    let mut renderer = detected;
+    let mut palette = None;
+    let mut scale = None;
+    let mut fit = false;
+    let mut format = RecordingFormat::Gif;
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => {
+                let value = args.next().ok_or(USAGE)?;
+                speed = Some(value.parse().map_err(|_| format!("bad speed: {}", value))?);
+            }
+            "--debug" => debug = true,
+            "--sound" => sound = args.next().ok_or(USAGE)?.clone(),
+            "--gamepad" => gamepad = Some(args.next().ok_or(USAGE)?),
+            "--pad-map" => {
+                let value = args.next().ok_or(USAGE)?;
+                pad_map = GamepadMap::parse(value)
+                    .ok_or_else(|| format!("bad gamepad mapping: {}", value))?;
+            }
+            "--palette" => {
+                let value = args.next().ok_or(USAGE)?;
+                palette =
+                    Some(Palette::parse(value).ok_or_else(|| format!("bad palette: {}", value))?);
+            }
+            "--scale" => {
+                let value = args.next().ok_or(USAGE)?;
+                match value.as_str() {
+                    "fit" => fit = true,
+                    _ => {
+                        let pixels = value.parse().ok().filter(|&pixels| pixels > 0);
+                        scale = Some(pixels.ok_or_else(|| format!("bad scale: {}", value))?);
+                    }
+                }
+            }
+            "--record" => {
+                let value = args.next().ok_or(USAGE)?;
+                format = RecordingFormat::from_name(value)
+                    .ok_or_else(|| format!("unknown recording format: {}", value))?;
+            }
+            "--renderer" => {
+                let value = args.next().ok_or(USAGE)?;
+                renderer = match value.as_str() {
+                    "auto" => detected,
+                    _ => TerminalRenderer::from_name(value)
+                        .ok_or_else(|| format!("unknown renderer: {}", value))?,
+                };
+            }
+            "--preset" => {
+                let value = args.next().ok_or(USAGE)?;
+                preset = Some(
+                    QuirkPreset::from_name(value)
+                        .ok_or_else(|| format!("unknown preset: {}", value))?,
+                );
+            }
+            "--start" => {
+                let value = args.next().ok_or(USAGE)?;
+                start = parse_address(value)?;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let path = path.ok_or(USAGE)?;
+    let mut machine = load_machine(path, preset, start, speed)?;
+    match sound.as_str() {
+        "bell" => machine.set_audio(Box::new(TerminalBell::new(io::stdout()))),
+        "aplay" => machine.set_audio(Box::new(
+            PipeAudio::aplay().map_err(|e| format!("can't start aplay: {}", e))?,
+        )),
+        "off" => {}
+        _ => return Err(format!("unknown sound: {}", sound)),
+    }
+    let gamepad = match gamepad {
+        Some(device) => Some(
+            GamepadInput::open(device, pad_map)
+                .map_err(|e| format!("can't open {}: {}", device, e))?,
+        ),
+        None => None,
+    };
+    // The keyboard, plus the gamepad if there is one.
+    let with_gamepad = |keyboard: TerminalInput| -> Box<dyn Input> {
+        match gamepad {
+            Some(gamepad) => Box::new(CombinedInput::new(vec![
+                Box::new(keyboard),
+                Box::new(gamepad),
+            ])),
+            None => Box::new(keyboard),
+        }
+    };
+
+    // The graphical renderers scaled as asked, fitting the terminal at
+    // the largest resolution programs switch to.
+    if let Some(scale) = scale {
+        renderer = renderer.with_scale(scale);
+    }
+    if fit {
+        let (columns, rows) = terminal_size().ok_or("can't tell the terminal size")?;
+        renderer = renderer.fit(SCHIP_WIDTH, SCHIP_HEIGHT, columns, rows);
+    }
+
+    let saved = enter_raw_mode()?;
+    let quit = Arc::new(AtomicBool::new(false));
+    let screenshot = Arc::new(AtomicBool::new(false));
+    let record = Arc::new(AtomicBool::new(false));
+    let (sender, typed) = mpsc::channel();
+    let stop = quit.clone();
+    let shoot = screenshot.clone();
+    let toggle = record.clone();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        while let Ok(len) = io::stdin().read(&mut buffer) {
+            // End of input quits as well.
+            if len == 0 {
+                stop.store(true, Ordering::Relaxed);
+                return;
+            }
+            let bytes = &buffer[..len];
+            let mut at = 0;
+            while at < bytes.len() {
+                let byte = bytes[at];
+                if byte == ESC && at + 1 < bytes.len() {
+                    at += escape_len(&bytes[at..]);
+                    continue;
+                }
+                at += 1;
+                if byte == QUIT_KEY || byte == ESC {
+                    stop.store(true, Ordering::Relaxed);
+                    return;
+                }
+                if byte == SCREENSHOT_KEY {
+                    shoot.store(true, Ordering::Relaxed);
+                    continue;
+                }
+                if byte == RECORD_KEY {
+                    toggle.store(true, Ordering::Relaxed);
+                    continue;
+                }
+                if sender.send(byte).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    // Screenshots and recordings at the scale on screen, or the usual one
+    // for the text renderers.
+    let mut captures = Captures {
+        screenshot,
+        record,
+        palette: palette.unwrap_or_default(),
+        scale: renderer.scale().or(scale).unwrap_or(DEFAULT_PIXEL_SCALE),
+        format,
+        recording: None,
+    };
+
+    print!("\x1b[?25l\x1b[2J");
+    let mut display = TerminalDisplay::with_renderer(io::stdout(), renderer);
+    if let Some(palette) = palette {
+        display.set_palette(palette);
+    }
+    machine.set_display(Box::new(display));
+    let result = if debug {
+        let (keys, receiver) = mpsc::channel();
+        machine.set_input(with_gamepad(TerminalInput::new(
+            receiver,
+            KeyMap::standard(),
+        )));
+        let palette = palette.unwrap_or_default();
+        debug_loop(
+            &mut machine,
+            renderer,
+            &palette,
+            &quit,
+            &mut captures,
+            &typed,
+            &keys,
+        )
+    } else {
+        machine.set_input(with_gamepad(TerminalInput::new(typed, KeyMap::standard())));
+        play_loop(&mut machine, &quit, &mut captures)
+    };
+    let recorded = captures.stop_recording();
+
+    print!("\x1b[?25h\r\n");
+    let _ = io::stdout().flush();
+    leave_raw_mode(&saved);
+    if let Some(message) = recorded {
+        eprintln!("{}", message);
+    }
+    result
+}
+
+// A machine for `chip8 play` and `chip8 serve` with the ROM at `path`
+// loaded at `start`, e.g. 0x600 for ETI-660 programs.
+fn load_machine(
+    path: &str,
+    preset: Option<QuirkPreset>,
+    start: u16,
+    speed: Option<usize>,
+) -> Result<Machine, String> {
+    let memory_size = preset.map_or(CHIP8_RAM, QuirkPreset::memory_size);
+    if start as usize >= memory_size {
+        return Err(format!("start {:#05x} is past the end of memory", start));
+    }
+    let mut builder = Cpu::builder().program_start(start);
+    if let Some(preset) = preset {
+        builder = builder.preset(preset);
+    }
+    let cpu = builder.build().map_err(|e| e.to_string())?;
+    let mut machine = Machine::with_cpu(cpu);
+    machine
+        .load_rom_file(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    if let Some(speed) = speed {
+        machine.set_cycles_per_frame(speed);
+    }
+    Ok(machine)
+}
+
+// Run headless, streaming the screen to browsers, see `StreamServer`.
+fn serve(args: &[String]) -> Result<(), String> {
+    let mut port = DEFAULT_PORT;
+    let mut speed = None;
+    let mut preset = None;
+    let mut start = CHIP8_PROGRAM_START;
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                let value = args.next().ok_or(USAGE)?;
+                port = value.parse().map_err(|_| format!("bad port: {}", value))?;
+            }
+            "--speed" => {
+                let value = args.next().ok_or(USAGE)?;
+                speed = Some(value.parse().map_err(|_| format!("bad speed: {}", value))?);
+            }
+            "--preset" => {
+                let value = args.next().ok_or(USAGE)?;
+                preset = Some(
+                    QuirkPreset::from_name(value)
+                        .ok_or_else(|| format!("unknown preset: {}", value))?,
+                );
+            }
+            "--start" => {
+                let value = args.next().ok_or(USAGE)?;
+                start = parse_address(value)?;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let path = path.ok_or(USAGE)?;
+    let mut machine = load_machine(path, preset, start, speed)?;
+    let server =
+        StreamServer::bind(("0.0.0.0", port)).map_err(|e| format!("port {}: {}", port, e))?;
+    machine.set_display(Box::new(server.display()));
+    machine.set_input(Box::new(server.input()));
+    machine.set_audio(Box::new(server.audio()));
+    println!("serving {} on http://localhost:{}/", path, port);
+
+    while !machine.has_exited() {
+        machine.run_paced().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Screenshots and recordings asked for with SCREENSHOT_KEY and
+// RECORD_KEY, and how to make them.
+struct Captures {
+    screenshot: Arc<AtomicBool>,
+    record: Arc<AtomicBool>,
+    palette: Palette,
+    scale: usize,
+    format: RecordingFormat,
+    // The recording going on and the file it goes to.
+    recording: Option<(String, Recorder)>,
+}
+
+impl Captures {
+    // Call after every frame: takes the screenshot asked for, starts or
+    // stops recording, and records the frame. Returns what happened, if
+    // anything, to show the user.
+    fn after_frame(&mut self, machine: &Machine) -> Option<String> {
+        let mut message = None;
+        if self.screenshot.swap(false, Ordering::Relaxed) {
+            let name = screenshot_name(SystemTime::now());
+            let png = screenshot_png(machine.framebuffer(), &self.palette, self.scale);
+            message = Some(match fs::write(&name, png) {
+                Ok(()) => format!("saved {}", name),
+                Err(e) => format!("{}: {}", name, e),
+            });
+        }
+        if self.record.swap(false, Ordering::Relaxed) {
+            message = match self.recording {
+                Some(_) => self.stop_recording(),
+                None => {
+                    let name = recording_name(SystemTime::now(), self.format);
+                    let recorder = Recorder::new(self.format, self.palette, self.scale);
+                    let message = format!("recording {}", name);
+                    self.recording = Some((name, recorder));
+                    Some(message)
+                }
+            };
+        }
+        if let Some((_, recorder)) = &mut self.recording {
+            recorder.capture(machine.framebuffer());
+        }
+        message
+    }
+
+    // Save the recording going on, if any.
+    fn stop_recording(&mut self) -> Option<String> {
+        let (name, recorder) = self.recording.take()?;
+        Some(match fs::write(&name, recorder.finish()) {
+            Ok(()) => format!("saved {}", name),
+            Err(e) => format!("{}: {}", name, e),
+        })
+    }
+}
+
+fn play_loop(
+    machine: &mut Machine,
+    quit: &AtomicBool,
+    captures: &mut Captures,
+) -> Result<(), String> {
+    while !quit.load(Ordering::Relaxed) && !machine.has_exited() {
+        machine.run_paced().map_err(|e| e.to_string())?;
+        // Nowhere to report them while the screen is drawn; the new files
+        // are all the feedback there is.
+        let _ = captures.after_frame(machine);
+    }
+    Ok(())
+}
+
+// Run with the debugger panels to the right of the screen. Typing ':'
+// opens the command line at the bottom, Enter runs the command; other keys
+// go to the keypad.
+fn debug_loop(
+    machine: &mut Machine,
+    renderer: TerminalRenderer,
+    palette: &Palette,
+    quit: &AtomicBool,
+    captures: &mut Captures,
+    typed: &Receiver<u8>,
+    keys: &Sender<u8>,
+) -> Result<(), String> {
+    let mut debugger = Debugger::new();
+    let mut command: Option<String> = None;
+    let mut message = String::from("type : for a command");
+
+    while !quit.load(Ordering::Relaxed) && !machine.has_exited() {
+        for byte in typed.try_iter() {
+            match (command.as_mut(), byte) {
+                (None, b':') => command = Some(String::new()),
+                (None, _) => {
+                    let _ = keys.send(byte);
+                }
+                (Some(line), b'\r') | (Some(line), b'\n') => {
+                    message = match DebugCommand::parse(line) {
+                        Some(parsed) => {
+                            debugger
+                                .execute(parsed, machine)
+                                .map_err(|e| e.to_string())?;
+                            // Stepping stops mid-frame, so show the screen
+                            // being drawn rather than the last finished one.
+                            let fb = machine.cpu().framebuffer();
+                            print!("\x1b[H{}", renderer.render(fb, palette));
+                            format!("ok: {}", line)
+                        }
+                        None => format!(
+                            "unknown command: {} (step [N], continue, pause, break/delete ADDR, \
+                             memory ADDR, quirk NAME on/off)",
+                            line
+                        ),
+                    };
+                    command = None;
+                }
+                (Some(line), 0x7f) | (Some(line), 0x08) => {
+                    line.pop();
+                }
+                (Some(line), _) => line.push(byte as char),
+            }
+        }
+
+        debugger.run_frame(machine).map_err(|e| e.to_string())?;
+        if let Some(update) = captures.after_frame(machine) {
+            message = update;
+        }
+
+        let fb = machine.cpu().framebuffer();
+        let (columns, rows) = renderer.cells(fb.width(), fb.height());
+        let column = columns + 3;
+        let mut out = String::new();
+        let mut panel = debugger.panel(machine.cpu());
+        panel.push(String::new());
+        panel.extend(debugger.memory_panel(machine.cpu()));
+        panel.push(String::new());
+        panel.extend(debugger.quirks_panel(machine.cpu()));
+        for (row, line) in panel.iter().enumerate() {
+            out.push_str(&format!("\x1b[{};{}H{}\x1b[K", row + 1, column, line));
+        }
+        let prompt = match &command {
+            Some(line) => format!(":{}", line),
+            None => message.clone(),
+        };
+        out.push_str(&format!("\x1b[{};1H{}\x1b[K", rows + 2, prompt));
+        print!("{}", out);
+        let _ = io::stdout().flush();
+
+        thread::sleep(FRAME_TIME);
+    }
+    Ok(())
+}
+
+// Columns and rows of the terminal, from stty.
+fn terminal_size() -> Option<(usize, usize)> {
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let (rows, columns) = text.trim().split_once(' ')?;
+    Some((columns.parse().ok()?, rows.parse().ok()?))
+}
+
+// Switch the terminal to raw mode with stty, returning the settings to
+// restore.
+fn enter_raw_mode() -> Result<String, String> {
+    let saved = Command::new("stty")
+        .arg("-g")
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("stty: {}", e))?;
+    if !saved.status.success() {
+        return Err("chip8 play needs a terminal".to_string());
+    }
+    Command::new("stty")
+        .args(["raw", "-echo"])
+        .stdin(Stdio::inherit())
+        .status()
+        .map_err(|e| format!("stty: {}", e))?;
+    Ok(String::from_utf8_lossy(&saved.stdout).trim().to_string())
+}
+
+fn leave_raw_mode(saved: &str) {
+    let _ = Command::new("stty")
+        .arg(saved)
+        .stdin(Stdio::inherit())
+        .status();
+}
+
+// Addresses are given in hex, with or without a 0x prefix.
+fn parse_address(value: &str) -> Result<u16, String> {
+    u16::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("bad address: {}", value))
+}
+
+// Length of the escape sequence at the start of `bytes`: a CSI sequence
+// (Esc [ ... final byte) for arrows and most other keys, an SS3 one
+// (Esc O x) for F1-F4 and some arrows, or Alt plus a key.
+fn escape_len(bytes: &[u8]) -> usize {
+    match bytes.get(1) {
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|byte| (0x40..=0x7e).contains(byte))
+            .map_or(bytes.len(), |end| end + 3),
+        Some(b'O') => bytes.len().min(3),
+        Some(_) => 2,
+        None => 1,
+    }
 }
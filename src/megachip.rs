@@ -0,0 +1,305 @@
+// MegaChip (Revival Studios, 2007) extends SUPER-CHIP with a 256x192 mode
+// whose sprites are bytes indexing a 255-colour palette, blended onto an
+// ARGB picture, plus sampled sound. The extra instructions only mean
+// anything after MEGAON; before that they are the SYS calls they look like.
+//
+// In the mega mode `Framebuffer` pixels hold palette indices, which is what
+// collisions are checked against, while the colours live in the ARGB
+// pictures here. DRW draws into a back buffer that CLS presents and clears,
+// so frontends show `frame()` rather than the framebuffer.
+
+// Size of the mega mode screen.
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+
+// MegaChip programs address 24 bits through LDHI.
+pub const MEGACHIP_RAM: usize = 1 << 24;
+
+// How BMODE combines a sprite pixel with the picture below it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    // The sprite replaces the picture.
+    Normal,
+    // The sprite is mixed in at 25%, 50% or 75% opacity.
+    Percent25,
+    Percent50,
+    Percent75,
+    // Colour channels are added, saturating at white.
+    Add,
+    // Colour channels are multiplied.
+    Multiply,
+}
+
+impl BlendMode {
+    // The mode selected by BMODE n. Unknown values draw normally.
+    pub fn from_nibble(n: u8) -> BlendMode {
+        match n {
+            1 => BlendMode::Percent25,
+            2 => BlendMode::Percent50,
+            3 => BlendMode::Percent75,
+            4 => BlendMode::Add,
+            5 => BlendMode::Multiply,
+            _ => BlendMode::Normal,
+        }
+    }
+
+    // Combine the ARGB colours `src` (the sprite) and `dst` (the picture).
+    // The result is opaque.
+    pub fn blend(self, src: u32, dst: u32) -> u32 {
+        let channel = |color: u32, shift: u32| (color >> shift) & 0xff;
+        let mix = |s: u32, d: u32| match self {
+            BlendMode::Normal => s,
+            BlendMode::Percent25 => (s + 3 * d) / 4,
+            BlendMode::Percent50 => (s + d) / 2,
+            BlendMode::Percent75 => (3 * s + d) / 4,
+            BlendMode::Add => (s + d).min(0xff),
+            BlendMode::Multiply => s * d / 0xff,
+        };
+
+        [16, 8, 0].iter().fold(0xff00_0000, |color, &shift| {
+            color | mix(channel(src, shift), channel(dst, shift)) << shift
+        })
+    }
+}
+
+// A sample started by DIGISND: unsigned 8-bit mono at `rate` Hz.
+#[derive(Clone, Debug, PartialEq)]
+struct DigiSound {
+    rate: u16,
+    data: Vec<u8>,
+    looping: bool,
+    // Position in `data`, in samples.
+    position: f64,
+}
+
+// The MegaChip state of a `Cpu`, see `Cpu::megachip`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MegaChip {
+    enabled: bool,
+    // ARGB colours; index 0 is transparent and never drawn.
+    palette: [u32; 256],
+    // Bits 16-23 of I, set by LDHI.
+    i_high: u8,
+    sprite_width: usize,
+    sprite_height: usize,
+    alpha: u8,
+    blend: BlendMode,
+    collision_color: u8,
+    // The picture DRW draws into, and the one CLS last presented.
+    screen: Vec<u32>,
+    frame: Vec<u32>,
+    sound: Option<DigiSound>,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        MegaChip::new()
+    }
+}
+
+impl MegaChip {
+    pub fn new() -> Self {
+        MegaChip {
+            enabled: false,
+            palette: [0; 256],
+            i_high: 0,
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xff,
+            blend: BlendMode::Normal,
+            collision_color: 0,
+            screen: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            frame: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            sound: None,
+        }
+    }
+
+    // Whether MEGAON is in effect.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // The picture presented by the last CLS, MEGA_WIDTH x MEGA_HEIGHT ARGB
+    // pixels in row-major order.
+    pub fn frame(&self) -> &[u32] {
+        &self.frame
+    }
+
+    pub fn palette(&self) -> &[u32; 256] {
+        &self.palette
+    }
+
+    // Opacity of the whole screen set by ALPHA, for fades.
+    pub fn alpha(&self) -> u8 {
+        self.alpha
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend
+    }
+
+    // Whether a DIGISND sample is playing.
+    pub fn is_playing(&self) -> bool {
+        self.sound.is_some()
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.screen.iter_mut().for_each(|pixel| *pixel = 0);
+    }
+
+    // The full 24-bit I given its low 16 bits.
+    pub(crate) fn address(&self, i: u16) -> usize {
+        (self.i_high as usize) << 16 | i as usize
+    }
+
+    pub(crate) fn set_i_high(&mut self, high: u8) {
+        self.i_high = high;
+    }
+
+    // Palette entries 1 onwards from big-endian ARGB words.
+    pub(crate) fn load_palette(&mut self, colors: &[u8]) {
+        for (entry, argb) in self.palette[1..].iter_mut().zip(colors.chunks(4)) {
+            *entry = argb.iter().fold(0, |acc, &byte| acc << 8 | byte as u32);
+        }
+    }
+
+    // SPRW/SPRH: 0 stands for 256.
+    pub(crate) fn set_sprite_size(&mut self, width: Option<u8>, height: Option<u8>) {
+        let size = |n: u8| if n == 0 { 256 } else { n as usize };
+        if let Some(width) = width {
+            self.sprite_width = size(width);
+        }
+        if let Some(height) = height {
+            self.sprite_height = size(height);
+        }
+    }
+
+    pub(crate) fn sprite_size(&self) -> (usize, usize) {
+        (self.sprite_width, self.sprite_height)
+    }
+
+    pub(crate) fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+    }
+
+    pub(crate) fn set_blend_mode(&mut self, blend: BlendMode) {
+        self.blend = blend;
+    }
+
+    pub(crate) fn set_collision_color(&mut self, index: u8) {
+        self.collision_color = index;
+    }
+
+    pub(crate) fn collision_color(&self) -> u8 {
+        self.collision_color
+    }
+
+    // Blend colour `index` onto the picture at (x, y).
+    pub(crate) fn plot(&mut self, x: usize, y: usize, index: u8) {
+        let pixel = &mut self.screen[y * MEGA_WIDTH + x];
+        *pixel = self.blend.blend(self.palette[index as usize], *pixel);
+    }
+
+    // CLS: show what was drawn and start over on a blank picture.
+    pub(crate) fn present(&mut self) {
+        self.frame.copy_from_slice(&self.screen);
+        self.screen.iter_mut().for_each(|pixel| *pixel = 0);
+    }
+
+    // Move the picture up by `n` rows.
+    pub(crate) fn scroll_up(&mut self, n: usize) {
+        let n = n.min(MEGA_HEIGHT) * MEGA_WIDTH;
+        self.screen.copy_within(n.., 0);
+        let len = self.screen.len();
+        self.screen[len - n..]
+            .iter_mut()
+            .for_each(|pixel| *pixel = 0);
+    }
+
+    // Bytes of sample data after a DIGISND header, which holds the rate as
+    // a big-endian word, a format byte and a 24-bit length.
+    pub(crate) fn sound_length(header: &[u8; 6]) -> usize {
+        (header[3] as usize) << 16 | (header[4] as usize) << 8 | header[5] as usize
+    }
+
+    // DIGISND: start playing `data`, described by `header`.
+    pub(crate) fn play(&mut self, header: &[u8; 6], data: &[u8], looping: bool) {
+        self.sound = Some(DigiSound {
+            rate: (header[0] as u16) << 8 | header[1] as u16,
+            data: data.to_vec(),
+            looping,
+            position: 0.0,
+        });
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.sound = None;
+    }
+
+    // Add the playing sample, resampled to `sample_rate`, to `out`.
+    pub(crate) fn mix_sound(&mut self, sample_rate: u32, out: &mut [f32]) {
+        let sound = match self.sound.as_mut() {
+            Some(sound) if !sound.data.is_empty() => sound,
+            _ => return,
+        };
+        let step = sound.rate as f64 / sample_rate as f64;
+
+        for sample in out.iter_mut() {
+            if sound.position as usize >= sound.data.len() {
+                if !sound.looping {
+                    self.sound = None;
+                    return;
+                }
+                sound.position = 0.0;
+            }
+            let value = sound.data[sound.position as usize] as f32 - 128.0;
+            *sample = (*sample + value / 256.0).clamp(-1.0, 1.0);
+            sound.position += step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_blend() {
+        let (src, dst) = (0xff80_4020, 0xff40_80ff);
+
+        assert_eq!(BlendMode::Normal.blend(src, dst), src);
+        assert_eq!(BlendMode::Percent50.blend(src, dst), 0xff60_608f);
+        assert_eq!(BlendMode::Add.blend(src, dst), 0xffc0_c0ff);
+        assert_eq!(BlendMode::Multiply.blend(src, dst), 0xff20_2020);
+        assert_eq!(BlendMode::from_nibble(9), BlendMode::Normal);
+    }
+
+    #[test]
+    fn test_present() {
+        let mut mega = MegaChip::new();
+        mega.load_palette(&[0x12, 0x34, 0x56, 0x78]);
+        mega.plot(3, 1, 1);
+        assert!(mega.frame().iter().all(|&pixel| pixel == 0));
+
+        mega.present();
+        assert_eq!(mega.frame()[MEGA_WIDTH + 3], 0xff34_5678);
+        mega.present();
+        assert!(mega.frame().iter().all(|&pixel| pixel == 0), "CLS cleared");
+    }
+
+    #[test]
+    fn test_sound() {
+        let mut mega = MegaChip::new();
+        let header = [0x00, 0x02, 0, 0, 0, 2];
+        assert_eq!(MegaChip::sound_length(&header), 2);
+        mega.play(&header, &[0xff, 0x00], false);
+
+        // A 2Hz sample at 4 samples/s: every sample twice, then silence.
+        let mut out = [0.0; 6];
+        mega.mix_sound(4, &mut out);
+        assert!(out[0] > 0.0 && out[1] > 0.0 && out[2] < 0.0 && out[3] < 0.0);
+        assert_eq!(out[4..], [0.0, 0.0]);
+        assert!(!mega.is_playing());
+    }
+}
@@ -0,0 +1,302 @@
+pub const CHIP8_RAM: usize = 4096;
+// XO-CHIP addresses the full 16-bit range.
+pub const XOCHIP_RAM: usize = 65536;
+
+// Largest memory there is room for. XO-CHIP's 64K is kept in a fixed array
+// so the core needs no heap; MegaChip's 16MB is too big for that and grows
+// on the heap, which the megachip feature has through std.
+#[cfg(not(feature = "megachip"))]
+pub(crate) const MAX_RAM: usize = XOCHIP_RAM;
+#[cfg(feature = "megachip")]
+pub(crate) const MAX_RAM: usize = crate::megachip::MEGACHIP_RAM;
+
+#[cfg(not(feature = "megachip"))]
+type Bytes = [u8; MAX_RAM];
+#[cfg(feature = "megachip")]
+type Bytes = Vec<u8>;
+
+// Why a memory access was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryError {
+    // The access reached `addr`, which is past the end of memory.
+    OutOfBounds(usize),
+    // The access tried to write `addr` inside the protected area.
+    WriteProtected(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+// A single logged access of `len` bytes starting at `addr`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub addr: usize,
+    pub len: usize,
+}
+
+// The memory bus. Every access made by the CPU goes through here, which is
+// where bounds checking, write protection and access logging live.
+//
+// Only the first `size` bytes are in use; the rest are kept zero, so
+// growing adds zeros.
+#[derive(Clone)]
+pub struct Memory {
+    bytes: Bytes,
+    size: usize,
+    // Writes below this address are refused (0 disables protection).
+    protected_end: usize,
+    // Accesses recorded while logging is enabled.
+    #[cfg(feature = "std")]
+    log: Option<Vec<MemoryAccess>>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory::with_size(CHIP8_RAM)
+    }
+
+    // Memory of `size` bytes, e.g. `XOCHIP_RAM`.
+    pub fn with_size(size: usize) -> Self {
+        check_size(size);
+        Memory {
+            #[cfg(not(feature = "megachip"))]
+            bytes: [0; MAX_RAM],
+            #[cfg(feature = "megachip")]
+            bytes: vec![0; size],
+            size,
+            protected_end: 0,
+            #[cfg(feature = "std")]
+            log: None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Zero all memory. Protection and logging settings are kept.
+    pub fn clear(&mut self) {
+        self.bytes[..self.size].fill(0);
+    }
+
+    // Grow or shrink to `size` bytes. Contents below the new size are kept
+    // and added bytes are zero.
+    pub fn resize(&mut self, size: usize) {
+        check_size(size);
+        #[cfg(not(feature = "megachip"))]
+        if size < self.size {
+            self.bytes[size..self.size].fill(0);
+        }
+        #[cfg(feature = "megachip")]
+        self.bytes.resize(size, 0);
+        self.size = size;
+    }
+
+    // Refuse writes to addresses below `end`, e.g. to guard the font area.
+    // Passing 0 turns protection off.
+    pub fn protect(&mut self, end: usize) {
+        self.protected_end = end;
+    }
+
+    // Start or stop recording accesses. Stopping discards the log.
+    #[cfg(feature = "std")]
+    pub fn set_logging(&mut self, enabled: bool) {
+        self.log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    // Hand over the accesses recorded since the last call.
+    #[cfg(feature = "std")]
+    pub fn take_log(&mut self) -> Vec<MemoryAccess> {
+        match self.log.as_mut() {
+            Some(log) => std::mem::take(log),
+            None => Vec::new(),
+        }
+    }
+
+    // Raw view of the whole memory. Not logged.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.size]
+    }
+
+    // Mutable raw view of the whole memory, for the CDP1802. Not logged
+    // and ignores write protection.
+    pub(crate) fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[..self.size]
+    }
+
+    pub fn read8(&mut self, addr: usize) -> Result<u8, MemoryError> {
+        Ok(self.slice(addr, 1)?[0])
+    }
+
+    // Read a big-endian word, the layout of CHIP-8 opcodes.
+    pub fn read16(&mut self, addr: usize) -> Result<u16, MemoryError> {
+        let bytes = self.slice(addr, 2)?;
+        Ok(((bytes[0] as u16) << 8) | (bytes[1] as u16))
+    }
+
+    pub fn write8(&mut self, addr: usize, value: u8) -> Result<(), MemoryError> {
+        self.slice_mut(addr, 1)?[0] = value;
+        Ok(())
+    }
+
+    // Borrow `len` bytes starting at `addr` for reading.
+    pub fn slice(&mut self, addr: usize, len: usize) -> Result<&[u8], MemoryError> {
+        self.check_range(addr, len)?;
+        self.record(AccessKind::Read, addr, len);
+        Ok(&self.bytes[addr..addr + len])
+    }
+
+    // Borrow `len` bytes starting at `addr` for writing.
+    pub fn slice_mut(&mut self, addr: usize, len: usize) -> Result<&mut [u8], MemoryError> {
+        self.check_range(addr, len)?;
+        if len > 0 && addr < self.protected_end {
+            return Err(MemoryError::WriteProtected(addr));
+        }
+        self.record(AccessKind::Write, addr, len);
+        Ok(&mut self.bytes[addr..addr + len])
+    }
+
+    // Copy `data` to `addr` ignoring write protection, for loading the font
+    // and programs. Not logged.
+    pub fn load(&mut self, addr: usize, data: &[u8]) -> Result<(), MemoryError> {
+        self.check_range(addr, data.len())?;
+        self.bytes[addr..addr + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn check_range(&self, addr: usize, len: usize) -> Result<(), MemoryError> {
+        if addr + len > self.size {
+            // Report the first address that falls outside memory.
+            return Err(MemoryError::OutOfBounds(addr.max(self.size)));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    fn record(&mut self, kind: AccessKind, addr: usize, len: usize) {
+        if let Some(log) = self.log.as_mut() {
+            log.push(MemoryAccess { kind, addr, len });
+        }
+    }
+
+    // Without std there is no log to keep.
+    #[cfg(not(feature = "std"))]
+    fn record(&mut self, _kind: AccessKind, _addr: usize, _len: usize) {}
+}
+
+fn check_size(size: usize) {
+    assert!(
+        size <= MAX_RAM,
+        "chip8.memory: unsupported memory size {}",
+        size
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_write() {
+        let mut memory = Memory::new();
+        memory.write8(0x200, 0xab).unwrap();
+        memory.write8(0x201, 0xcd).unwrap();
+
+        assert_eq!(memory.read8(0x200), Ok(0xab));
+        assert_eq!(memory.read16(0x200), Ok(0xabcd), "words are big-endian");
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let mut memory = Memory::new();
+
+        assert_eq!(memory.read8(0x1000), Err(MemoryError::OutOfBounds(0x1000)));
+        assert_eq!(memory.read16(0xfff), Err(MemoryError::OutOfBounds(0x1000)));
+        assert_eq!(
+            memory.load(0xffe, &[1, 2, 3]),
+            Err(MemoryError::OutOfBounds(0x1000))
+        );
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut memory = Memory::new();
+        memory.write8(0xfff, 0x12).unwrap();
+        memory.resize(XOCHIP_RAM);
+
+        assert_eq!(memory.size(), XOCHIP_RAM);
+        assert_eq!(memory.read8(0xfff), Ok(0x12));
+        assert_eq!(memory.read16(0xfffe), Ok(0));
+        assert_eq!(
+            memory.read8(0x10000),
+            Err(MemoryError::OutOfBounds(0x10000))
+        );
+    }
+
+    #[test]
+    fn test_write_protection() {
+        let mut memory = Memory::new();
+        memory.protect(0x50);
+
+        assert_eq!(
+            memory.write8(0x4f, 1),
+            Err(MemoryError::WriteProtected(0x4f))
+        );
+        assert_eq!(memory.write8(0x50, 1), Ok(()));
+        assert_eq!(
+            memory.load(0x0, &[0xf0]),
+            Ok(()),
+            "load bypasses protection"
+        );
+        assert_eq!(memory.read8(0x0), Ok(0xf0));
+    }
+
+    #[test]
+    fn test_shrink_and_grow() {
+        let mut memory = Memory::with_size(XOCHIP_RAM);
+        memory.write8(0x2000, 0x34).unwrap();
+        memory.resize(CHIP8_RAM);
+        memory.resize(XOCHIP_RAM);
+
+        assert_eq!(memory.read8(0x2000), Ok(0), "dropped bytes come back zero");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_logging() {
+        let mut memory = Memory::new();
+        memory.read8(0x10).unwrap();
+        assert!(memory.take_log().is_empty(), "logging is off by default");
+
+        memory.set_logging(true);
+        memory.read16(0x200).unwrap();
+        memory.write8(0x300, 7).unwrap();
+
+        assert_eq!(
+            memory.take_log(),
+            vec![
+                MemoryAccess {
+                    kind: AccessKind::Read,
+                    addr: 0x200,
+                    len: 2
+                },
+                MemoryAccess {
+                    kind: AccessKind::Write,
+                    addr: 0x300,
+                    len: 1
+                },
+            ]
+        );
+        assert!(memory.take_log().is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::error::{Chip8Error, Result};
+use crate::json::{self, Value};
+use crate::keypad::CHIP8_NUM_KEYS;
+use crate::palette::Palette;
+use crate::platform::Platform;
+
+// Octo's colours, for the one of fillColor and backgroundColor an entry
+// leaves out.
+const OCTO_FILL: &str = "#FFCC00";
+const OCTO_BACKGROUND: &str = "#996600";
+
+// Description of a game in the CHIP-8 Archive format
+// (https://github.com/JohnEarnest/chip8Archive): the `programs.json` entry
+// that ships next to each ROM.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GameMetadata {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub description: Option<String>,
+    // Target platform as named by the archive: "chip8", "schip", "xochip".
+    pub platform: Option<String>,
+    pub options: ArchiveOptions,
+    // Suggested bindings, from the archive's names ("up", "a", ...) to
+    // keypad keys, in file order.
+    pub keys: Vec<(String, u8)>,
+}
+
+// Emulator settings recommended by the archive, named after the Octo options
+// they come from. `None` means the entry doesn't say.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ArchiveOptions {
+    // Instructions per frame.
+    pub tickrate: Option<usize>,
+    // 8xy6/8xyE shift Vx in place instead of copying Vy.
+    pub shift_quirks: Option<bool>,
+    // Fx55/Fx65 leave I unchanged.
+    pub load_store_quirks: Option<bool>,
+    // 8xy1/8xy2/8xy3 clear VF.
+    pub logic_quirks: Option<bool>,
+    // Arithmetic writes VF before the result register.
+    pub vf_order_quirks: Option<bool>,
+    // Sprites are clipped at the screen edges instead of wrapping.
+    pub clip_quirks: Option<bool>,
+    // DRW waits for the next frame.
+    pub vblank_quirks: Option<bool>,
+    // Bnnn jumps to nnn + Vx.
+    pub jump_quirks: Option<bool>,
+    // Colours as "#rrggbb" strings.
+    pub fill_color: Option<String>,
+    pub background_color: Option<String>,
+}
+
+impl GameMetadata {
+    // Parse a single game's metadata object.
+    pub fn from_json(text: &str) -> Result<GameMetadata> {
+        let value = json::parse(text).map_err(invalid)?;
+        GameMetadata::from_value(&value)
+    }
+
+    // Look up the game `id` in a whole `programs.json` catalog, which maps
+    // ids to metadata objects.
+    pub fn from_catalog(text: &str, id: &str) -> Result<GameMetadata> {
+        let value = json::parse(text).map_err(invalid)?;
+        let entry = value.get(id).ok_or_else(|| invalid("no such game"))?;
+        GameMetadata::from_value(entry)
+    }
+
+    // The platform as a `Platform`, or None if the entry doesn't name one.
+    pub fn target_platform(&self) -> Result<Option<Platform>> {
+        match self.platform.as_deref() {
+            None => Ok(None),
+            Some("chip8") => Ok(Some(Platform::Chip8)),
+            Some("schip") | Some("superchip") => Ok(Some(Platform::SuperChip)),
+            Some("xochip") => Ok(Some(Platform::XoChip)),
+            Some(_) => Err(invalid("unknown platform")),
+        }
+    }
+
+    pub(crate) fn from_value(value: &Value) -> Result<GameMetadata> {
+        if value.as_object().is_none() {
+            return Err(invalid("expected an object"));
+        }
+
+        let string = |key| value.get(key).and_then(Value::as_str).map(String::from);
+        let authors = match value.get("authors") {
+            Some(Value::Array(names)) => names
+                .iter()
+                .map(|name| {
+                    name.as_str()
+                        .map(String::from)
+                        .ok_or_else(|| invalid("bad author"))
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => return Err(invalid("authors must be a list")),
+            None => Vec::new(),
+        };
+
+        Ok(GameMetadata {
+            title: string("title").ok_or_else(|| invalid("missing title"))?,
+            authors,
+            description: string("desc"),
+            platform: string("platform"),
+            options: value
+                .get("options")
+                .map(ArchiveOptions::from_value)
+                .unwrap_or_default(),
+            keys: parse_keys(value.get("keys"))?,
+        })
+    }
+}
+
+impl ArchiveOptions {
+    // The fill and background colours as a two-colour palette, or None if
+    // the entry names neither.
+    pub fn palette(&self) -> Result<Option<Palette>> {
+        if self.fill_color.is_none() && self.background_color.is_none() {
+            return Ok(None);
+        }
+        let background = self.background_color.as_deref().unwrap_or(OCTO_BACKGROUND);
+        let fill = self.fill_color.as_deref().unwrap_or(OCTO_FILL);
+        Palette::parse(&format!("{},{}", background, fill))
+            .map(Some)
+            .ok_or_else(|| invalid("bad colour"))
+    }
+
+    fn from_value(value: &Value) -> ArchiveOptions {
+        let flag = |key| value.get(key).and_then(Value::as_bool);
+        let string = |key| value.get(key).and_then(Value::as_str).map(String::from);
+
+        ArchiveOptions {
+            tickrate: value
+                .get("tickrate")
+                .and_then(Value::as_f64)
+                .filter(|&rate| rate >= 1.0)
+                .map(|rate| rate as usize),
+            shift_quirks: flag("shiftQuirks"),
+            load_store_quirks: flag("loadStoreQuirks"),
+            logic_quirks: flag("logicQuirks"),
+            vf_order_quirks: flag("vfOrderQuirks"),
+            clip_quirks: flag("clipQuirks"),
+            vblank_quirks: flag("vBlankQuirks"),
+            jump_quirks: flag("jumpQuirks"),
+            fill_color: string("fillColor"),
+            background_color: string("backgroundColor"),
+        }
+    }
+}
+
+// Key bindings are numbers or hex strings naming a keypad key.
+fn parse_keys(value: Option<&Value>) -> Result<Vec<(String, u8)>> {
+    let fields = match value {
+        Some(value) => value
+            .as_object()
+            .ok_or_else(|| invalid("keys must be an object"))?,
+        None => return Ok(Vec::new()),
+    };
+
+    fields
+        .iter()
+        .map(|(name, key)| {
+            let key = match key {
+                Value::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+                Value::String(s) => i64::from_str_radix(s.trim_start_matches("0x"), 16).ok(),
+                _ => None,
+            }
+            .filter(|&k| (0..CHIP8_NUM_KEYS as i64).contains(&k))
+            .ok_or_else(|| invalid("bad key binding"))?;
+
+            Ok((name.clone(), key as u8))
+        })
+        .collect()
+}
+
+fn invalid(reason: &'static str) -> Chip8Error {
+    Chip8Error::InvalidMetadata { reason }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CATALOG: &str = r##"{
+        "snake": {
+            "title": "Snake",
+            "authors": ["Someone", "Someone Else"],
+            "desc": "Eat the apples.",
+            "platform": "chip8",
+            "options": {
+                "tickrate": 15,
+                "loadStoreQuirks": true,
+                "shiftQuirks": false,
+                "logicQuirks": true,
+                "fillColor": "#FFCC00"
+            },
+            "keys": {"up": 5, "down": 8, "left": "7", "right": "0x9"}
+        },
+        "broken": {"title": "Broken", "keys": {"a": 16}}
+    }"##;
+
+    #[test]
+    fn test_from_catalog() {
+        let game = GameMetadata::from_catalog(CATALOG, "snake").unwrap();
+
+        assert_eq!(game.title, "Snake");
+        assert_eq!(game.authors, vec!["Someone", "Someone Else"]);
+        assert_eq!(game.description.as_deref(), Some("Eat the apples."));
+        assert_eq!(game.platform.as_deref(), Some("chip8"));
+        assert_eq!(game.options.tickrate, Some(15));
+        assert_eq!(game.options.load_store_quirks, Some(true));
+        assert_eq!(game.options.shift_quirks, Some(false));
+        assert_eq!(game.options.logic_quirks, Some(true));
+        assert_eq!(game.options.jump_quirks, None);
+        assert_eq!(game.options.fill_color.as_deref(), Some("#FFCC00"));
+        assert_eq!(game.target_platform(), Ok(Some(Platform::Chip8)));
+        let palette = game.options.palette().unwrap().unwrap();
+        assert_eq!(palette.foreground(), [0xff, 0xcc, 0x00]);
+        assert_eq!(palette.background(), [0x99, 0x66, 0x00]);
+        assert_eq!(
+            game.keys,
+            vec![
+                ("up".to_string(), 5),
+                ("down".to_string(), 8),
+                ("left".to_string(), 7),
+                ("right".to_string(), 9)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_metadata() {
+        assert_eq!(
+            GameMetadata::from_catalog(CATALOG, "broken"),
+            Err(Chip8Error::InvalidMetadata {
+                reason: "bad key binding"
+            })
+        );
+        assert!(GameMetadata::from_catalog(CATALOG, "tetris").is_err());
+        assert!(GameMetadata::from_json(r#"{"authors": []}"#).is_err());
+        assert!(GameMetadata::from_json("{").is_err());
+    }
+}
@@ -0,0 +1,46 @@
+#[cfg(feature = "std")]
+use crate::instruction::Instruction;
+
+// Copy of the CPU registers at a point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegisterSnapshot {
+    pub pc: u16,
+    pub i: u16,
+    pub sp: u8,
+    pub v: [u8; 16],
+    pub delay: u8,
+    pub sound: u8,
+}
+
+// Callbacks fired by the CPU around every instruction and at the end of
+// every frame. All methods do nothing by default, so tracers, debuggers and
+// achievement watchers only implement what they need.
+//
+// `pc` is always the address of the instruction, the snapshot holds the
+// registers as they are when the hook fires.
+#[cfg(feature = "std")]
+pub trait Observer {
+    fn before_instruction(
+        &mut self,
+        _pc: u16,
+        _instruction: Instruction,
+        _registers: &RegisterSnapshot,
+    ) {
+    }
+
+    fn after_instruction(
+        &mut self,
+        _pc: u16,
+        _instruction: Instruction,
+        _registers: &RegisterSnapshot,
+    ) {
+    }
+
+    // Fired by `Cpu::run_frame` after the timers have ticked.
+    fn frame_end(&mut self, _registers: &RegisterSnapshot) {}
+}
+
+// Handle returned when registering an observer, used to remove it again.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverId(pub(crate) usize);
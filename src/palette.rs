@@ -0,0 +1,184 @@
+// Colours of the four pixel values: off, plane 1, plane 2 and both, as
+// RGB. CHIP-8 and SUPER-CHIP only use the first two, background and
+// foreground; XO-CHIP programs draw with all four.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colors: [[u8; 3]; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::MONOCHROME
+    }
+}
+
+impl Palette {
+    // White on black, the planes in greys.
+    pub const MONOCHROME: Palette = Palette::new([
+        [0x00, 0x00, 0x00],
+        [0xff, 0xff, 0xff],
+        [0xaa, 0xaa, 0xaa],
+        [0x55, 0x55, 0x55],
+    ]);
+
+    // Green phosphor.
+    pub const GREEN: Palette = Palette::new([
+        [0x00, 0x14, 0x00],
+        [0x33, 0xff, 0x33],
+        [0x22, 0xaa, 0x22],
+        [0x11, 0x55, 0x11],
+    ]);
+
+    // Amber phosphor.
+    pub const AMBER: Palette = Palette::new([
+        [0x14, 0x0a, 0x00],
+        [0xff, 0xb0, 0x00],
+        [0xaa, 0x75, 0x00],
+        [0x55, 0x3a, 0x00],
+    ]);
+
+    // Octo's defaults: background, fill, fill 2 and blend.
+    pub const OCTO: Palette = Palette::new([
+        [0x99, 0x66, 0x00],
+        [0xff, 0xcc, 0x00],
+        [0xff, 0x66, 0x00],
+        [0x66, 0x22, 0x00],
+    ]);
+
+    // The original Game Boy's greenish LCD.
+    pub const LCD: Palette = Palette::new([
+        [0x9b, 0xbc, 0x0f],
+        [0x0f, 0x38, 0x0f],
+        [0x8b, 0xac, 0x0f],
+        [0x30, 0x62, 0x30],
+    ]);
+
+    // The built-in themes by name.
+    pub const THEMES: [(&'static str, Palette); 5] = [
+        ("mono", Palette::MONOCHROME),
+        ("green", Palette::GREEN),
+        ("amber", Palette::AMBER),
+        ("octo", Palette::OCTO),
+        ("lcd", Palette::LCD),
+    ];
+
+    pub const fn new(colors: [[u8; 3]; 4]) -> Self {
+        Palette { colors }
+    }
+
+    // A CHIP-8 palette of two colours. The XO-CHIP planes get the
+    // foreground at two thirds and one third of the way from the
+    // background.
+    pub fn two_color(background: [u8; 3], foreground: [u8; 3]) -> Self {
+        let mix = |weight: u16| {
+            let mut color = [0; 3];
+            for (n, channel) in color.iter_mut().enumerate() {
+                let (from, to) = (background[n] as u16, foreground[n] as u16);
+                *channel = ((from * (3 - weight) + to * weight) / 3) as u8;
+            }
+            color
+        };
+        Palette::new([background, foreground, mix(2), mix(1)])
+    }
+
+    // A built-in theme.
+    pub fn from_name(name: &str) -> Option<Palette> {
+        Palette::THEMES
+            .iter()
+            .find(|&&(theme, _)| theme == name)
+            .map(|&(_, palette)| palette)
+    }
+
+    // A theme name, or two or four comma separated colours like
+    // "#000000,#33ff33": background and foreground, or all four values.
+    pub fn parse(spec: &str) -> Option<Palette> {
+        if let Some(palette) = Palette::from_name(spec) {
+            return Some(palette);
+        }
+        let colors = spec
+            .split(',')
+            .map(|color| parse_color(color.trim()))
+            .collect::<Option<Vec<_>>>()?;
+        match colors[..] {
+            [background, foreground] => Some(Palette::two_color(background, foreground)),
+            [off, plane1, plane2, both] => Some(Palette::new([off, plane1, plane2, both])),
+            _ => None,
+        }
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]; 4] {
+        &self.colors
+    }
+
+    // The colour of pixel value `value`; only its two plane bits count.
+    pub fn color(&self, value: u8) -> [u8; 3] {
+        self.colors[value as usize & 3]
+    }
+
+    pub fn set_color(&mut self, value: u8, color: [u8; 3]) {
+        self.colors[value as usize & 3] = color;
+    }
+
+    pub fn background(&self) -> [u8; 3] {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> [u8; 3] {
+        self.colors[1]
+    }
+
+    // The colours as opaque RGBA, for `Framebuffer::write_rgba` and
+    // `RgbaTexture`.
+    pub fn rgba(&self) -> [[u8; 4]; 4] {
+        self.colors.map(|[r, g, b]| [r, g, b, 0xff])
+    }
+
+    // The colours as XRGB8888 words.
+    pub fn xrgb(&self) -> [u32; 4] {
+        self.colors
+            .map(|[r, g, b]| (r as u32) << 16 | (g as u32) << 8 | b as u32)
+    }
+}
+
+// "#rrggbb" or "rrggbb".
+fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |n: usize| u8::from_str_radix(&hex[n..n + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Palette::parse("octo"), Some(Palette::OCTO));
+        assert_eq!(
+            Palette::parse("#000000, 33ff33"),
+            Some(Palette::two_color([0, 0, 0], [0x33, 0xff, 0x33]))
+        );
+        assert_eq!(
+            Palette::parse("000000,ffffff,aaaaaa,555555"),
+            Some(Palette::MONOCHROME)
+        );
+        assert_eq!(Palette::parse("#000000"), None);
+        assert_eq!(Palette::parse("#00000g,#ffffff"), None);
+        assert_eq!(Palette::parse("sepia"), None);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let palette = Palette::two_color([0, 0, 0], [0x33, 0xff, 0x66]);
+        assert_eq!(palette.color(2), [0x22, 0xaa, 0x44]);
+        assert_eq!(palette.color(7), [0x11, 0x55, 0x22]);
+        assert_eq!(palette.rgba()[1], [0x33, 0xff, 0x66, 0xff]);
+        assert_eq!(
+            Palette::MONOCHROME.xrgb(),
+            [0x000000, 0xffffff, 0xaaaaaa, 0x555555]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use crate::framebuffer::Framebuffer;
+
+// Fades pixels out over a few frames instead of turning them off at once,
+// like the slow phosphor of old monitors. CHIP-8 programs erase and redraw
+// sprites with XOR to move them, so without this a moving sprite is blank
+// on every other frame and flickers.
+//
+// Feed it every frame, changed or not, since the fading happens one step a
+// frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhosphorFilter {
+    // Brightness lost per frame once a pixel is off, out of 255.
+    step: u8,
+    width: usize,
+    height: usize,
+    // Brightness of each pixel, 255 while lit.
+    levels: Vec<u8>,
+    // The value each pixel was last lit with, which it fades out in.
+    values: Vec<u8>,
+}
+
+impl PhosphorFilter {
+    // A filter fading pixels out over `frames` frames; 1 turns them off
+    // at once like the plain screen.
+    pub fn new(frames: u8) -> Self {
+        PhosphorFilter {
+            step: 255u16.div_ceil(frames.max(1) as u16) as u8,
+            width: 0,
+            height: 0,
+            levels: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    // Take in the next frame. A change of resolution starts over.
+    pub fn update(&mut self, fb: &Framebuffer) {
+        if (fb.width(), fb.height()) != (self.width, self.height) {
+            self.width = fb.width();
+            self.height = fb.height();
+            self.levels = vec![0; self.width * self.height];
+            self.values = vec![0; self.width * self.height];
+        }
+        let pixels = fb.pixels();
+        for (n, &pixel) in pixels.iter().enumerate() {
+            if pixel != 0 {
+                self.levels[n] = 255;
+                self.values[n] = pixel;
+            } else {
+                self.levels[n] = self.levels[n].saturating_sub(self.step);
+            }
+        }
+    }
+
+    // Whether some pixel is still fading, so the picture will change
+    // with the next update even if the screen doesn't.
+    pub fn is_fading(&self) -> bool {
+        self.levels.iter().any(|&level| level != 0 && level != 255)
+    }
+
+    // How bright the pixel at (x, y) is, 0 to 255.
+    pub fn level(&self, x: usize, y: usize) -> u8 {
+        self.levels[y * self.width + x]
+    }
+
+    // Fill `out` with RGBA8 pixels like `Framebuffer::write_rgba`, each
+    // pixel's colour mixed with the background by its brightness. `out`
+    // must hold width * height * 4 bytes.
+    pub fn write_rgba(&self, colors: &[[u8; 4]; 4], out: &mut [u8]) {
+        assert_eq!(
+            out.len(),
+            self.levels.len() * 4,
+            "chip8.phosphor: RGBA buffer of the wrong size"
+        );
+        let background = colors[0];
+        for (n, rgba) in out.chunks_exact_mut(4).enumerate() {
+            let level = self.levels[n] as u16;
+            let color = colors[self.values[n] as usize & 3];
+            for channel in 0..4 {
+                let (from, to) = (background[channel] as u16, color[channel] as u16);
+                rgba[channel] = ((from * (255 - level) + to * level) / 255) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fade() {
+        let mut fb = Framebuffer::with_size(2, 1);
+        let mut filter = PhosphorFilter::new(3);
+        fb.toggle(0, 0);
+        filter.update(&fb);
+        assert_eq!(filter.level(0, 0), 255);
+        assert!(!filter.is_fading());
+
+        fb.toggle(0, 0);
+        filter.update(&fb);
+        assert_eq!(filter.level(0, 0), 170);
+        assert!(filter.is_fading());
+
+        let colors = [[0, 0, 0, 255], [255, 255, 255, 255], [0; 4], [0; 4]];
+        let mut out = [0; 8];
+        filter.write_rgba(&colors, &mut out);
+        assert_eq!(out, [170, 170, 170, 255, 0, 0, 0, 255]);
+
+        filter.update(&fb);
+        filter.update(&fb);
+        assert_eq!(filter.level(0, 0), 0);
+        assert!(!filter.is_fading());
+    }
+}
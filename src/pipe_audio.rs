@@ -0,0 +1,99 @@
+use crate::audio::Audio;
+
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+// Sample rate `PipeAudio::aplay` asks for.
+pub const PIPE_SAMPLE_RATE: u32 = 44100;
+
+// Plays the machine's samples through a separate program reading raw
+// signed 16-bit little-endian mono PCM on its stdin, such as `aplay` or
+// `pacat`. The machine's `PatternSynth` renders the buzzer, fading it in
+// and out, so the edges need no handling here.
+//
+// This keeps the terminal frontend free of an audio library; the winit
+// frontend plays through cpal instead.
+pub struct PipeAudio {
+    child: Child,
+    // None once the player has gone away.
+    stdin: Option<ChildStdin>,
+    sample_rate: u32,
+    buffer: Vec<u8>,
+}
+
+impl PipeAudio {
+    // Start `command` with its stdin connected to the samples.
+    pub fn spawn(command: &mut Command, sample_rate: u32) -> io::Result<Self> {
+        let mut child = command.stdin(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take();
+        Ok(PipeAudio {
+            child,
+            stdin,
+            sample_rate,
+            buffer: Vec::new(),
+        })
+    }
+
+    // ALSA's `aplay` at PIPE_SAMPLE_RATE.
+    pub fn aplay() -> io::Result<Self> {
+        let rate = PIPE_SAMPLE_RATE.to_string();
+        let mut command = Command::new("aplay");
+        command.args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r", &rate]);
+        PipeAudio::spawn(&mut command, PIPE_SAMPLE_RATE)
+    }
+}
+
+impl Audio for PipeAudio {
+    fn start_beep(&mut self) {}
+
+    fn stop_beep(&mut self) {}
+
+    fn sample_rate(&self) -> Option<u32> {
+        Some(self.sample_rate)
+    }
+
+    fn queue_samples(&mut self, samples: &[f32]) {
+        let stdin = match &mut self.stdin {
+            Some(stdin) => stdin,
+            None => return,
+        };
+        self.buffer.clear();
+        for &sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        if stdin.write_all(&self.buffer).is_err() {
+            self.stdin = None;
+        }
+    }
+}
+
+impl Drop for PipeAudio {
+    // Closing stdin lets the player finish what it was given.
+    fn drop(&mut self) {
+        self.stdin = None;
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn test_pipe_audio() {
+        let path = std::env::temp_dir().join(format!("chip8-pipe-{}", std::process::id()));
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("cat > \"$0\"").arg(&path);
+        let mut audio = PipeAudio::spawn(&mut command, 8000).unwrap();
+        assert_eq!(audio.sample_rate(), Some(8000));
+        audio.queue_samples(&[0.0, 1.0, -1.0, 2.0]);
+        drop(audio);
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(bytes, [0x00, 0x00, 0xff, 0x7f, 0x01, 0x80, 0xff, 0x7f]);
+    }
+}
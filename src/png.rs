@@ -0,0 +1,205 @@
+use crate::framebuffer::Framebuffer;
+use crate::hash::{adler32, crc32};
+use crate::palette::Palette;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// Most bytes a stored deflate block holds.
+const STORED_BLOCK: usize = 65535;
+
+// A PNG of `width` x `height` RGBA8 pixels. The pixels go in uncompressed
+// (deflate's stored blocks), which keeps the encoder small; CHIP-8
+// screens compress well, but a screenshot of one is a few hundred
+// kilobytes at most anyway.
+pub fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(
+        rgba.len(),
+        width * height * 4,
+        "chip8.png: RGBA buffer of the wrong size"
+    );
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace.
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Each row starts with its filter type, 0 for none.
+    let mut raw = Vec::with_capacity((width * 4 + 1) * height);
+    for row in rgba.chunks_exact(width * 4) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// The screen as a PNG, coloured with `palette`, every pixel `scale`
+// pixels square.
+pub fn screenshot_png(fb: &Framebuffer, palette: &Palette, scale: usize) -> Vec<u8> {
+    let mut rgba = vec![0; fb.width() * fb.height() * 4];
+    fb.write_rgba(&palette.rgba(), &mut rgba);
+    let scale = scale.max(1);
+    let rgba = scale_rgba(fb.width(), fb.height(), &rgba, scale);
+    encode_png(fb.width() * scale, fb.height() * scale, &rgba)
+}
+
+// RGBA8 pixels with every pixel repeated `scale` times across and down.
+pub fn scale_rgba(width: usize, height: usize, rgba: &[u8], scale: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(rgba.len() * scale * scale);
+    for row in rgba.chunks_exact(width * 4).take(height) {
+        let start = out.len();
+        for pixel in row.chunks_exact(4) {
+            for _ in 0..scale {
+                out.extend_from_slice(pixel);
+            }
+        }
+        for _ in 1..scale {
+            out.extend_from_within(start..start + width * scale * 4);
+        }
+    }
+    out
+}
+
+// A file name for a screenshot taken at `time`, like
+// "chip8-20240131-235959.png", in UTC.
+pub fn screenshot_name(time: SystemTime) -> String {
+    format!("chip8-{}.png", timestamp(time))
+}
+
+// `time` as "20240131-235959", in UTC.
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_date((seconds / 86400) as i64);
+    let time = seconds % 86400;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+// The year, month and day `days` days after 1970-01-01, in the proleptic
+// Gregorian calendar (Howard Hinnant's civil_from_days).
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+// Append a chunk: length, type, data and the CRC of type and data.
+pub(crate) fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// `data` in a zlib stream of stored deflate blocks.
+pub(crate) fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len().div_ceil(STORED_BLOCK).max(1);
+    let mut out = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    // Deflate with a 32K window, no preset dictionary; the check bits make
+    // the header a multiple of 31.
+    out.extend_from_slice(&[0x78, 0x01]);
+    for n in 0..blocks {
+        let block =
+            &data[(n * STORED_BLOCK).min(data.len())..((n + 1) * STORED_BLOCK).min(data.len())];
+        // BFINAL on the last block, BTYPE 00 for stored.
+        out.push((n + 1 == blocks) as u8);
+        out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_encode_png() {
+        let png = encode_png(1, 1, &[0xff, 0x00, 0x00, 0xff]);
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        // IHDR: 13 bytes, 1x1, 8-bit RGBA.
+        assert_eq!(png[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(png[16..29], [0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        assert_eq!(png[29..33], crc32(&png[12..29]).to_be_bytes());
+        // IDAT: the filter byte and the pixel in one stored block.
+        assert_eq!(png[33..41], [0, 0, 0, 16, b'I', b'D', b'A', b'T']);
+        assert_eq!(
+            png[41..57],
+            [
+                0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, 0, 0xff, 0, 0, 0xff, 0x05, 0x00, 0x01,
+                0xff
+            ]
+        );
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+
+    #[test]
+    fn test_zlib_blocks() {
+        let data = vec![7; STORED_BLOCK + 1];
+        let stream = zlib_stored(&data);
+        assert_eq!(stream.len(), 2 + 5 + STORED_BLOCK + 5 + 1 + 4);
+        assert_eq!(stream[2..7], [0x00, 0xff, 0xff, 0x00, 0x00]);
+        assert_eq!(
+            stream[7 + STORED_BLOCK..12 + STORED_BLOCK],
+            [0x01, 0x01, 0x00, 0xfe, 0xff]
+        );
+        assert_eq!(
+            zlib_stored(&[]),
+            [0x78, 0x01, 0x01, 0, 0, 0xff, 0xff, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn test_screenshot() {
+        let mut fb = Framebuffer::with_size(2, 1);
+        fb.toggle(1, 0);
+        let rgba = scale_rgba(2, 1, &[1, 1, 1, 1, 2, 2, 2, 2], 2);
+        assert_eq!(
+            rgba,
+            [
+                1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2,
+                2, 2, 2, 2
+            ]
+        );
+        let png = screenshot_png(&fb, &Palette::MONOCHROME, 3);
+        assert_eq!(png[16..24], [0, 0, 0, 6, 0, 0, 0, 3]);
+
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+        assert_eq!(screenshot_name(time), "chip8-20240229-235959.png");
+        assert_eq!(screenshot_name(UNIX_EPOCH), "chip8-19700101-000000.png");
+    }
+}
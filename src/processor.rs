@@ -1,18 +1,68 @@
 #![allow(dead_code)]
 
-use crate::FONT_SET;
+use crate::audio::{AUDIO_PATTERN_SIZE, DEFAULT_AUDIO_PATTERN, DEFAULT_PITCH};
+use crate::cdp1802::{Cdp1802, Cdp1802Exit, VIP_DISPLAY, VIP_REGISTERS, VIP_STACK};
+use crate::error::{Chip8Error, Result};
+use crate::framebuffer::{
+    Framebuffer, ALL_PLANES, CHIP8_HEIGHT, CHIP8_WIDTH, HIRES_CHIP8_HEIGHT, SCHIP_HEIGHT,
+    SCHIP_WIDTH,
+};
+use crate::instruction::Instruction;
+use crate::keypad::Keypad;
+#[cfg(feature = "megachip")]
+use crate::megachip::{BlendMode, MegaChip, MEGA_HEIGHT, MEGA_WIDTH};
+use crate::memory::{Memory, MAX_RAM};
+use crate::observer::RegisterSnapshot;
+#[cfg(feature = "std")]
+use crate::observer::{Observer, ObserverId};
+use crate::platform::Platform;
+use crate::quirks::{QuirkPreset, Quirks};
+use crate::rng::{Rng, XorShiftRng};
+use crate::timers::Timers;
+use crate::timing::{vip_cycles, VIP_CYCLES_PER_FRAME, VIP_DMA_CYCLES};
+use crate::{BIG_FONT_SET, FONT_SET};
+
+use core::time::Duration;
 
 const CHIP8_OPCODE_SIZE: u16 = 2;
+// Start of every save_state(), and the layout's version.
+const SAVE_STATE_MAGIC: &[u8; 4] = b"C8ST";
+const SAVE_STATE_VERSION: u8 = 1;
+// 1802 instructions a SYS subroutine may run before it counts as stuck.
+const MACHINE_CODE_LIMIT: usize = 1_000_000;
 const CHIP8_FONT_SET_SIZE: usize = 80;
-const CHIP8_RAM: usize = 4096;
-const CHIP8_HEIGHT: usize = 32;
-const CHIP8_WIDTH: usize = 64;
+const CHIP8_FONT_GLYPH_SIZE: u16 = 5;
+// The big font follows the small one.
+const BIG_FONT_START: u16 = CHIP8_FONT_SET_SIZE as u16;
+const BIG_FONT_GLYPH_SIZE: u16 = 10;
+// Where programs are loaded and start executing by default...
+pub const CHIP8_PROGRAM_START: u16 = 0x200;
+// ...and on the ETI-660, which reserves more memory for its interpreter.
+pub const ETI660_PROGRAM_START: u16 = 0x600;
 const CHIP8_NUM_REGS: usize = 16;
+const CHIP8_STACK_SIZE: usize = 16;
+
+// Every screen size a program can switch to, smallest first.
+#[cfg(not(feature = "megachip"))]
+pub(crate) const RESOLUTIONS: [(usize, usize); 3] = [
+    (CHIP8_WIDTH, CHIP8_HEIGHT),
+    (CHIP8_WIDTH, HIRES_CHIP8_HEIGHT),
+    (SCHIP_WIDTH, SCHIP_HEIGHT),
+];
+#[cfg(feature = "megachip")]
+pub(crate) const RESOLUTIONS: [(usize, usize); 4] = [
+    (CHIP8_WIDTH, CHIP8_HEIGHT),
+    (CHIP8_WIDTH, HIRES_CHIP8_HEIGHT),
+    (SCHIP_WIDTH, SCHIP_HEIGHT),
+    (MEGA_WIDTH, MEGA_HEIGHT),
+];
 
 enum ProgramCounterAction {
     Skip,
     Next,
     Jump(u16),
+    // Stay on the current instruction.
+    Wait,
 }
 
 impl ProgramCounterAction {
@@ -25,52 +75,831 @@ impl ProgramCounterAction {
     }
 }
 
+// What to do with SYS addr (0nnn), a jump into native COSMAC VIP machine
+// code.
+#[derive(Clone, Copy)]
+pub enum SysPolicy {
+    // Skip the instruction silently.
+    Ignore,
+    // Skip the instruction and log it to stderr, or just skip it without
+    // std.
+    Warn,
+    // Treat the instruction as fatal.
+    Error,
+    // Hand the address to a user routine, then continue with the next
+    // instruction.
+    Callback(fn(&mut Cpu, u16)),
+    // Run the machine code on the emulated CDP1802 until it returns with
+    // SEP R4, the way hybrid VIP programs expect.
+    Cdp1802,
+}
+
+// Execution state reported by `Cpu::step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    // The instruction executed normally.
+    Running,
+    // LD Vx, K is blocked until a key is released.
+    WaitingForKey,
+    // DRW is blocked until the next 60Hz interrupt (display wait quirk).
+    WaitingForVblank,
+    // The program ran EXIT (00FD) and won't run any further until reset.
+    Exited,
+    // The program reached a jump to itself and will never make progress.
+    Halted,
+}
+
+// Why `Cpu::run_until` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    // The predicate returned true.
+    Condition,
+    // The cycle cap was reached first.
+    CycleLimit,
+    // The program halted.
+    Halted,
+    // The program is blocked waiting for a key.
+    WaitingForKey,
+    // The program is blocked until the timers tick.
+    WaitingForVblank,
+    // The program exited.
+    Exited,
+}
+
+// Random source for RND: any `Rng` with std, the default PRNG without.
+#[cfg(feature = "std")]
+type CpuRng = Box<dyn Rng>;
+#[cfg(not(feature = "std"))]
+type CpuRng = XorShiftRng;
+
 pub struct Cpu {
-    // RAM memory.
-    ram: [u8; CHIP8_RAM],
+    // RAM, accessed through the memory bus.
+    memory: Memory,
     // Stack memory.
-    stack: [u16; 16],
+    stack: [u16; CHIP8_STACK_SIZE],
     // Program Counter.
     pc: u16,
     // Stack pointer.
     sp: u8,
 
-    dt: u8,
-    st: u8,
+    // Delay and sound timers.
+    timers: Timers,
 
     // Index register.
     i: u16,
     // Registers array.
     v: [u8; CHIP8_NUM_REGS],
     // Graphics memory.
-    vram: [[u8; CHIP8_WIDTH]; CHIP8_HEIGHT],
+    vram: Framebuffer,
+    // Hex keypad state.
+    keypad: Keypad,
+    // Set when CLS or DRW touched vram since the last frame.
+    display_changed: bool,
+    // Set while LD Vx, K is blocked on a key release.
+    waiting_for_key: bool,
+    // Set while DRW waits for the 60Hz interrupt, and whether it has come.
+    waiting_for_vblank: bool,
+    vblank: bool,
+    // Set once EXIT ran.
+    exited: bool,
+    // XO-CHIP planes that CLS, DRW and the scrolls act on, one bit each.
+    planes: u8,
+    // XO-CHIP audio pattern buffer and pitch register.
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    // Random source for RND.
+    rng: CpuRng,
+    // HP 48 RPL user flags saved by Fx75. They survive reset, like the
+    // calculator's did between programs.
+    rpl_flags: [u8; CHIP8_NUM_REGS],
+    // Interpreter-specific opcode behaviors.
+    quirks: Quirks,
+    // Instruction set accepted; anything newer is an unknown instruction.
+    platform: Platform,
+    // Whether the two-page hires CHIP-8 interpreter is emulated.
+    two_page_hires: bool,
+    // MegaChip mode, palette and pictures.
+    #[cfg(feature = "megachip")]
+    mega: MegaChip,
+    // How SYS addr is handled.
+    sys_policy: SysPolicy,
+    // Where programs are loaded and where PC starts after a reset.
+    program_start: u16,
+    // Machine cycles run_vip_frame() has left; negative after running
+    // over, which is paid back in the next frame.
+    cycle_balance: i32,
+    // Registered instruction/frame hooks.
+    #[cfg(feature = "std")]
+    observers: Vec<(ObserverId, Box<dyn Observer>)>,
+    #[cfg(feature = "std")]
+    next_observer_id: usize,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new()
+    }
+}
+
+// Cloning copies the whole emulation state, including the random source's
+// position. Observers belong to whoever attached them and are not copied.
+impl Clone for Cpu {
+    fn clone(&self) -> Self {
+        Cpu {
+            memory: self.memory.clone(),
+            stack: self.stack,
+            pc: self.pc,
+            sp: self.sp,
+            timers: self.timers.clone(),
+            i: self.i,
+            v: self.v,
+            vram: self.vram.clone(),
+            keypad: self.keypad.clone(),
+            display_changed: self.display_changed,
+            waiting_for_key: self.waiting_for_key,
+            waiting_for_vblank: self.waiting_for_vblank,
+            vblank: self.vblank,
+            exited: self.exited,
+            planes: self.planes,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            rng: self.rng.clone(),
+            rpl_flags: self.rpl_flags,
+            quirks: self.quirks,
+            platform: self.platform,
+            two_page_hires: self.two_page_hires,
+            #[cfg(feature = "megachip")]
+            mega: self.mega.clone(),
+            sys_policy: self.sys_policy,
+            program_start: self.program_start,
+            cycle_balance: self.cycle_balance,
+            #[cfg(feature = "std")]
+            observers: Vec::new(),
+            #[cfg(feature = "std")]
+            next_observer_id: self.next_observer_id,
+        }
+    }
 }
 
 impl Cpu {
+    // A CPU with the default PRNG seeded from the clock. Without std there
+    // is no clock and the seed is always the same; see `with_seed`.
     pub fn new() -> Self {
-        let mut ram = [0u8; CHIP8_RAM];
+        #[cfg(feature = "std")]
+        let seed = crate::rng::time_seed();
+        #[cfg(not(feature = "std"))]
+        let seed = 0;
+        Cpu::with_seed(seed)
+    }
 
-        // Load the font set into ram.
-        for i in 0..CHIP8_FONT_SET_SIZE {
-            ram[i] = FONT_SET[i];
-        }
+    // A CPU whose RND draws from the default PRNG seeded with `seed`, e.g.
+    // from a hardware random number generator.
+    pub fn with_seed(seed: u32) -> Self {
+        let rng = XorShiftRng::new(seed);
+        #[cfg(feature = "std")]
+        let rng = Box::new(rng);
+        Cpu::with_cpu_rng(rng)
+    }
+
+    // Create a CPU that draws its random numbers from `rng`.
+    #[cfg(feature = "std")]
+    pub fn with_rng(rng: Box<dyn Rng>) -> Self {
+        Cpu::with_cpu_rng(rng)
+    }
+
+    fn with_cpu_rng(rng: CpuRng) -> Self {
+        let mut memory = Memory::new();
+
+        // Load the font sets into ram.
+        load_fonts(&mut memory);
 
         Cpu {
-            ram,
-            pc: 0x200,
-            vram: [[0; CHIP8_WIDTH]; CHIP8_HEIGHT],
+            memory,
+            pc: CHIP8_PROGRAM_START,
+            vram: Framebuffer::new(),
             sp: 0,
-            dt: 0,
-            st: 0,
+            timers: Timers::new(),
             i: 0,
             v: [0; CHIP8_NUM_REGS],
-            stack: [0; 16],
+            stack: [0; CHIP8_STACK_SIZE],
+            keypad: Keypad::new(),
+            display_changed: false,
+            waiting_for_key: false,
+            waiting_for_vblank: false,
+            vblank: false,
+            exited: false,
+            planes: 1,
+            audio_pattern: DEFAULT_AUDIO_PATTERN,
+            pitch: DEFAULT_PITCH,
+            rng,
+            rpl_flags: [0; CHIP8_NUM_REGS],
+            quirks: Quirks::default(),
+            platform: Platform::XoChip,
+            two_page_hires: false,
+            #[cfg(feature = "megachip")]
+            mega: MegaChip::new(),
+            sys_policy: SysPolicy::Error,
+            program_start: CHIP8_PROGRAM_START,
+            cycle_balance: 0,
+            #[cfg(feature = "std")]
+            observers: Vec::new(),
+            #[cfg(feature = "std")]
+            next_observer_id: 0,
+        }
+    }
+
+    // Return to the power-on state: registers, memory, display, keys and
+    // timers are cleared and the font is reloaded. Configuration (quirks,
+    // SYS policy, start address, random source) and the RPL flags are
+    // kept.
+    pub fn reset(&mut self) {
+        self.memory.clear();
+        load_fonts(&mut self.memory);
+        self.stack = [0; CHIP8_STACK_SIZE];
+        self.pc = self.program_start;
+        self.sp = 0;
+        self.i = 0;
+        self.v = [0; CHIP8_NUM_REGS];
+        self.vram = Framebuffer::new();
+        self.timers = Timers::new();
+        self.keypad = Keypad::new();
+        self.waiting_for_key = false;
+        self.waiting_for_vblank = false;
+        self.vblank = false;
+        self.exited = false;
+        self.planes = 1;
+        self.cycle_balance = 0;
+        self.audio_pattern = DEFAULT_AUDIO_PATTERN;
+        self.pitch = DEFAULT_PITCH;
+        #[cfg(feature = "megachip")]
+        {
+            self.mega = MegaChip::new();
+        }
+        self.display_changed = true;
+    }
+
+    // Independent copy of this CPU to explore a branch of execution (e.g.
+    // "what if key 5 is held for 10 frames") without disturbing the
+    // original. Same as clone(): no observers are carried over.
+    pub fn fork(&self) -> Cpu {
+        self.clone()
+    }
+
+    // The emulation state as bytes, for saving to a file or browser
+    // storage: registers, stack, timers, memory, the screen and the XO-CHIP
+    // audio. Configuration (quirks, platform, policies) isn't included,
+    // nor are the random source, the keys held and the MegaChip state.
+    #[cfg(feature = "std")]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = SAVE_STATE_MAGIC.to_vec();
+        state.push(SAVE_STATE_VERSION);
+        state.extend_from_slice(&self.pc.to_be_bytes());
+        state.extend_from_slice(&self.i.to_be_bytes());
+        state.push(self.sp);
+        for addr in &self.stack {
+            state.extend_from_slice(&addr.to_be_bytes());
+        }
+        state.extend_from_slice(&self.v);
+        state.push(self.timers.delay());
+        state.push(self.timers.sound());
+        let flags = [
+            self.waiting_for_key,
+            self.waiting_for_vblank,
+            self.vblank,
+            self.exited,
+        ];
+        state.push(
+            flags
+                .iter()
+                .rev()
+                .fold(0, |bits, &flag| bits << 1 | flag as u8),
+        );
+        state.push(self.planes);
+        state.push(self.pitch);
+        state.extend_from_slice(&self.audio_pattern);
+        state.extend_from_slice(&self.rpl_flags);
+        state.extend_from_slice(&self.cycle_balance.to_be_bytes());
+
+        state.extend_from_slice(&(self.vram.width() as u16).to_be_bytes());
+        state.extend_from_slice(&(self.vram.height() as u16).to_be_bytes());
+        for y in 0..self.vram.height() {
+            state.extend_from_slice(self.vram.row(y));
+        }
+        state.extend_from_slice(&(self.memory.size() as u32).to_be_bytes());
+        state.extend_from_slice(self.memory.bytes());
+        state
+    }
+
+    // Restore a state from save_state(). Nothing changes if it is refused.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<()> {
+        self.load_state_for(state, 0)
+    }
+
+    // load_state(), also refusing a state whose memory is too small for a
+    // program of `program_len` bytes, so the program can be reloaded on a
+    // reset.
+    pub(crate) fn load_state_for(&mut self, state: &[u8], program_len: usize) -> Result<()> {
+        let invalid = |reason| Chip8Error::InvalidSaveState { reason };
+        let mut rest = state;
+        let mut take = |len: usize| -> Result<&[u8]> {
+            if rest.len() < len {
+                return Err(invalid("truncated"));
+            }
+            let (bytes, after) = rest.split_at(len);
+            rest = after;
+            Ok(bytes)
+        };
+        let word = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+
+        if take(SAVE_STATE_MAGIC.len())? != SAVE_STATE_MAGIC {
+            return Err(invalid("not a save state"));
+        }
+        if take(1)?[0] != SAVE_STATE_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let pc = word(take(2)?);
+        let i = word(take(2)?);
+        let sp = take(1)?[0];
+        let mut stack = [0; CHIP8_STACK_SIZE];
+        for addr in stack.iter_mut() {
+            *addr = word(take(2)?);
+        }
+        let mut v = [0; CHIP8_NUM_REGS];
+        v.copy_from_slice(take(CHIP8_NUM_REGS)?);
+        let timers = take(2)?;
+        let (delay, sound) = (timers[0], timers[1]);
+        let flags = take(1)?[0];
+        let planes = take(1)?[0];
+        let pitch = take(1)?[0];
+        let mut audio_pattern = [0; AUDIO_PATTERN_SIZE];
+        audio_pattern.copy_from_slice(take(AUDIO_PATTERN_SIZE)?);
+        let mut rpl_flags = [0; CHIP8_NUM_REGS];
+        rpl_flags.copy_from_slice(take(CHIP8_NUM_REGS)?);
+        let balance = take(4)?;
+        let cycle_balance = i32::from_be_bytes([balance[0], balance[1], balance[2], balance[3]]);
+
+        let width = word(take(2)?) as usize;
+        let height = word(take(2)?) as usize;
+        if !RESOLUTIONS.contains(&(width, height)) {
+            return Err(invalid("unsupported resolution"));
+        }
+        let pixels = take(width * height)?;
+        let mut vram = Framebuffer::with_size(width, height);
+        for (index, &pixel) in pixels.iter().enumerate() {
+            vram.set_pixel(index % width, index / width, pixel);
+        }
+        let size = take(4)?;
+        let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        if sp as usize > CHIP8_STACK_SIZE || size < self.program_start as usize || size > MAX_RAM {
+            return Err(invalid("out of range"));
+        }
+        if size - (self.program_start as usize) < program_len {
+            return Err(invalid("memory too small for the ROM"));
+        }
+        let memory = take(size)?;
+        if !rest.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+
+        self.memory.resize(size);
+        self.memory
+            .load(0, memory)
+            .expect("chip8.cpu: memory was just resized");
+        self.pc = pc;
+        self.i = i;
+        self.sp = sp;
+        self.stack = stack;
+        self.v = v;
+        self.timers.set_delay(delay);
+        self.timers.set_sound(sound);
+        self.waiting_for_key = flags & 0b0001 != 0;
+        self.waiting_for_vblank = flags & 0b0010 != 0;
+        self.vblank = flags & 0b0100 != 0;
+        self.exited = flags & 0b1000 != 0;
+        self.planes = planes;
+        self.pitch = pitch;
+        self.audio_pattern = audio_pattern;
+        self.rpl_flags = rpl_flags;
+        self.cycle_balance = cycle_balance;
+        self.vram = vram;
+        self.display_changed = true;
+        Ok(())
+    }
+
+    // Give the CPU `size` bytes of RAM, e.g. `XOCHIP_RAM` for XO-CHIP
+    // programs. Like the quirks this is configuration and survives reset.
+    pub fn set_memory_size(&mut self, size: usize) {
+        assert!(
+            size >= self.program_start as usize && size <= MAX_RAM,
+            "chip8.processor: unsupported memory size {}",
+            size
+        );
+        self.memory.resize(size);
+    }
+
+    // Number of bytes available to a program, from the start address to the
+    // end of RAM.
+    pub fn max_program_size(&self) -> usize {
+        self.memory.size() - self.program_start as usize
+    }
+
+    // Refuse empty programs and programs that don't fit in memory.
+    pub(crate) fn check_program(&self, program: &[u8]) -> Result<()> {
+        let max = self.max_program_size();
+        if program.is_empty() {
+            Err(Chip8Error::EmptyRom)
+        } else if program.len() > max {
+            Err(Chip8Error::RomTooLarge {
+                size: program.len(),
+                max,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    // Copy a program into memory at the program start address.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<()> {
+        self.check_program(program)?;
+        let pc = self.pc;
+        self.memory
+            .load(self.program_start as usize, program)
+            .map_err(|e| Chip8Error::from_memory(e, pc))
+    }
+
+    // The memory bus, e.g. to enable write protection or access logging.
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    // The display memory.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.vram
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    // The index register.
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    // The general purpose registers V0..=VF.
+    pub fn registers(&self) -> &[u8; CHIP8_NUM_REGS] {
+        &self.v
+    }
+
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
+    }
+
+    // Return addresses currently on the stack, oldest first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    // Copy of the current register state.
+    pub fn snapshot(&self) -> RegisterSnapshot {
+        RegisterSnapshot {
+            pc: self.pc,
+            i: self.i,
+            sp: self.sp,
+            v: self.v,
+            delay: self.timers.delay(),
+            sound: self.timers.sound(),
+        }
+    }
+
+    // Register hooks fired around every instruction and at frame ends.
+    #[cfg(feature = "std")]
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) -> ObserverId {
+        let id = ObserverId(self.next_observer_id);
+        self.next_observer_id += 1;
+        self.observers.push((id, observer));
+        id
+    }
+
+    // Unregister an observer, handing it back.
+    #[cfg(feature = "std")]
+    pub fn remove_observer(&mut self, id: ObserverId) -> Option<Box<dyn Observer>> {
+        let index = self.observers.iter().position(|(other, _)| *other == id)?;
+        Some(self.observers.remove(index).1)
+    }
+
+    // Mark `key` (0x0-0xF) as held down.
+    pub fn press_key(&mut self, key: u8) {
+        self.keypad.press(key);
+    }
+
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        self.keypad.is_pressed(key)
+    }
+
+    // Mark `key` (0x0-0xF) as released.
+    pub fn release_key(&mut self, key: u8) {
+        self.keypad.release(key);
+    }
+
+    // The RPL user flags, as last saved by Fx75.
+    pub fn rpl_flags(&self) -> &[u8; CHIP8_NUM_REGS] {
+        &self.rpl_flags
+    }
+
+    // Restore flags saved by an earlier run, e.g. from a file.
+    pub fn set_rpl_flags(&mut self, flags: [u8; CHIP8_NUM_REGS]) {
+        self.rpl_flags = flags;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Select the interpreter behaviors to emulate, see `Quirks`. Takes
+    // effect from the next instruction.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    // Only accept the instructions of `platform`, e.g. `Platform::Chip8`
+    // for CHIP-48, which has no SUPER-CHIP graphics. All instruction sets
+    // are accepted by default.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+    }
+
+    // Emulate the two-page hires CHIP-8 interpreter: a program starting
+    // with JP 0x260 at 0x200 switches to a 64x64 screen and continues at
+    // 0x2C0, past the interpreter patch the ROM carries, and SYS 0x230
+    // clears that screen.
+    pub fn set_two_page_hires(&mut self, enabled: bool) {
+        self.two_page_hires = enabled;
+    }
+
+    // Emulate a well-known interpreter: its quirks, instruction set and
+    // memory size. Load the program afterwards, since a smaller memory
+    // may cut it off.
+    pub fn set_preset(&mut self, preset: QuirkPreset) {
+        self.set_quirks(preset.quirks());
+        self.set_platform(preset.platform());
+        self.set_two_page_hires(preset.two_page_hires());
+        self.set_memory_size(preset.memory_size());
+    }
+
+    // MegaChip state: whether the mega mode is on and the picture to show
+    // while it is.
+    #[cfg(feature = "megachip")]
+    pub fn megachip(&self) -> &MegaChip {
+        &self.mega
+    }
+
+    #[cfg(feature = "megachip")]
+    pub(crate) fn megachip_mut(&mut self) -> &mut MegaChip {
+        &mut self.mega
+    }
+
+    // Choose how SYS addr (0nnn) instructions are handled. Defaults to
+    // `SysPolicy::Error`.
+    pub fn set_sys_policy(&mut self, policy: SysPolicy) {
+        self.sys_policy = policy;
+    }
+
+    pub fn program_start(&self) -> u16 {
+        self.program_start
+    }
+
+    // Load programs at `addr` instead of 0x200, e.g. `ETI660_PROGRAM_START`
+    // for ETI-660 ROMs. PC moves there too, so set this before loading.
+    pub fn set_program_start(&mut self, addr: u16) {
+        assert!(
+            (addr as usize) < self.memory.size(),
+            "chip8.processor: program start {:#05x} is past the end of memory",
+            addr
+        );
+        self.program_start = addr;
+        self.pc = addr;
+    }
+
+    // Whether execution is blocked on LD Vx, K. Drivers can stop issuing
+    // cycles until the next key event instead of spinning.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
+
+    // Current value of the sound timer.
+    pub fn sound_timer(&self) -> u8 {
+        self.timers.sound()
+    }
+
+    // Whether the buzzer should currently be sounding.
+    pub fn is_beeping(&self) -> bool {
+        self.timers.sound() > 0
+    }
+
+    // The XO-CHIP audio pattern played while the sound timer runs.
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        &self.audio_pattern
+    }
+
+    // The XO-CHIP pitch register, see `audio::pattern_rate`.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // Whether the program stopped itself with EXIT.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    // Fetch, decode and execute the instruction at PC.
+    pub fn step(&mut self) -> Result<State> {
+        if self.exited {
+            return Ok(State::Exited);
+        }
+
+        let opcode = self.read_opcode()?;
+        let instruction = self.decode(opcode)?;
+
+        // JP to its own address is the usual way for a ROM to stop.
+        if instruction == Instruction::Jump(self.pc) {
+            return Ok(State::Halted);
+        }
+
+        self.execute(instruction)?;
+
+        if self.exited {
+            Ok(State::Exited)
+        } else if self.waiting_for_key {
+            Ok(State::WaitingForKey)
+        } else if self.waiting_for_vblank {
+            Ok(State::WaitingForVblank)
+        } else {
+            Ok(State::Running)
+        }
+    }
+
+    // Step until `predicate` holds, checking it before every instruction,
+    // executing at most `max_cycles` instructions. Timers are not ticked.
+    //
+    //     cpu.run_until(10_000, |cpu| cpu.pc() == 0x2f0)?;
+    pub fn run_until<F>(&mut self, max_cycles: usize, mut predicate: F) -> Result<StopReason>
+    where
+        F: FnMut(&Cpu) -> bool,
+    {
+        for _ in 0..max_cycles {
+            if predicate(self) {
+                return Ok(StopReason::Condition);
+            }
+
+            match self.step()? {
+                State::Running => {}
+                State::Halted => return Ok(StopReason::Halted),
+                State::WaitingForKey => return Ok(StopReason::WaitingForKey),
+                State::WaitingForVblank => return Ok(StopReason::WaitingForVblank),
+                State::Exited => return Ok(StopReason::Exited),
+            }
+        }
+
+        match predicate(self) {
+            true => Ok(StopReason::Condition),
+            false => Ok(StopReason::CycleLimit),
+        }
+    }
+
+    // Run up to `cycles` instructions followed by one 60Hz timer tick.
+    // Returns whether the display changed during the frame; the
+    // framebuffer's `dirty_rows` tells which rows.
+    //
+    // The frame ends early once the CPU is waiting for a key, a vblank or
+    // is halted, since further cycles would only repeat the same
+    // instruction.
+    pub fn run_frame(&mut self, cycles: usize) -> Result<bool> {
+        for _ in 0..cycles {
+            if self.step()? != State::Running {
+                break;
+            }
+        }
+
+        Ok(self.end_frame())
+    }
+
+    // Run one frame at the speed of the COSMAC VIP: instructions are
+    // executed until their cost (see `timing::vip_cycles`) uses up the
+    // machine cycles the interpreter got per frame, then the timers tick.
+    // A frame that runs over takes the excess out of the next one.
+    pub fn run_vip_frame(&mut self) -> Result<bool> {
+        self.cycle_balance += (VIP_CYCLES_PER_FRAME - VIP_DMA_CYCLES) as i32;
+
+        while self.cycle_balance > 0 {
+            let pc = self.pc;
+            let registers = self.v;
+            let instruction = self.peek_opcode().and_then(|op| self.decode(op).ok());
+
+            let state = self.step()?;
+            if let Some(instruction) = instruction {
+                let skipped = self.pc != pc.wrapping_add(CHIP8_OPCODE_SIZE);
+                self.cycle_balance -= vip_cycles(instruction, &registers, skipped) as i32;
+            }
+
+            // Waiting or halted, the interpreter idles out the frame.
+            if state != State::Running {
+                self.cycle_balance = 0;
+                break;
+            }
+        }
+
+        Ok(self.end_frame())
+    }
+
+    // Tick the timers and tell observers, once the frame's instructions
+    // have run. Returns whether the display changed.
+    fn end_frame(&mut self) -> bool {
+        self.tick_timers();
+
+        #[cfg(feature = "std")]
+        if !self.observers.is_empty() {
+            let registers = self.snapshot();
+            for (_, observer) in self.observers.iter_mut() {
+                observer.frame_end(&registers);
+            }
+        }
+
+        self.vram.end_frame();
+        let changed = self.display_changed;
+        self.display_changed = false;
+        changed
+    }
+
+    // Count the delay and sound timers down by one 60Hz tick.
+    pub fn tick_timers(&mut self) {
+        self.timers.tick();
+        self.interrupt();
+    }
+
+    // Advance the timers by `elapsed` time. Frontends that drive the CPU
+    // with step() at their own rate call this instead of run_frame() so
+    // the timers still run at 60Hz.
+    pub fn update_timers(&mut self, elapsed: Duration) -> u32 {
+        let ticks = self.timers.update(elapsed);
+        if ticks > 0 {
+            self.interrupt();
+        }
+        ticks
+    }
+
+    // Where a taken skip lands: past the next instruction, which is four
+    // bytes long if it is XO-CHIP's F000 nnnn.
+    fn skip_target(&self) -> u16 {
+        let next = self.pc.wrapping_add(CHIP8_OPCODE_SIZE);
+        let bytes = self.memory.bytes();
+        let opcode = bytes.get(next as usize..next as usize + 2);
+        let long = opcode == Some(&[0xf0, 0x00][..]);
+        #[cfg(feature = "megachip")]
+        let long = long || self.mega.is_enabled() && opcode.map(|op| op[0]) == Some(0x01);
+        let size = if long { 2 } else { 1 } * CHIP8_OPCODE_SIZE;
+        next.wrapping_add(size)
+    }
+
+    // The 60Hz interrupt releases a DRW waiting for vblank.
+    fn interrupt(&mut self) {
+        if self.waiting_for_vblank {
+            self.vblank = true;
         }
     }
 
-    fn read_opcode(&self) -> u16 {
-        let index = self.pc as usize;
-        return ((self.ram[index] as u16) << 8) | (self.ram[index + 1] as u16);
+    pub fn timers(&self) -> &Timers {
+        &self.timers
+    }
+
+    // The opcode at PC without going through the memory bus.
+    fn peek_opcode(&self) -> Option<u16> {
+        let pc = self.pc as usize;
+        let bytes = self.memory.bytes().get(pc..pc + 2)?;
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn read_opcode(&mut self) -> Result<u16> {
+        let pc = self.pc;
+        self.memory
+            .read16(pc as usize)
+            .map_err(|e| Chip8Error::from_memory(e, pc))
     }
 
     fn op_3xkk(&mut self, x: usize, kk: u8) -> ProgramCounterAction {
@@ -85,13 +914,47 @@ impl Cpu {
         ProgramCounterAction::skip_if(self.v[x] == self.v[y])
     }
 
+    // LD [I], Vx-Vy: store Vx..=Vy at I. With x > y the registers are
+    // stored in descending order. I is left alone.
+    fn op_5xy2(&mut self, x: usize, y: usize) -> Result<ProgramCounterAction> {
+        let v = self.v;
+        let pc = self.pc;
+        let count = x.max(y) - x.min(y) + 1;
+        let bytes = self
+            .memory
+            .slice_mut(self.i as usize, count)
+            .map_err(|e| Chip8Error::from_memory(e, pc))?;
+        for (byte, r) in bytes.iter_mut().zip(register_range(x, y)) {
+            *byte = v[r];
+        }
+        Ok(ProgramCounterAction::Next)
+    }
+
+    // LD Vx-Vy, [I]: load Vx..=Vy from I, in the same order as 5xy2.
+    fn op_5xy3(&mut self, x: usize, y: usize) -> Result<ProgramCounterAction> {
+        let pc = self.pc;
+        let count = x.max(y) - x.min(y) + 1;
+        let bytes = self
+            .memory
+            .slice(self.i as usize, count)
+            .map_err(|e| Chip8Error::from_memory(e, pc))?;
+        for (&byte, r) in bytes.iter().zip(register_range(x, y)) {
+            self.v[r] = byte;
+        }
+        Ok(ProgramCounterAction::Next)
+    }
+
+    fn op_9xy0(&mut self, x: usize, y: usize) -> ProgramCounterAction {
+        ProgramCounterAction::skip_if(self.v[x] != self.v[y])
+    }
+
     fn op_6xkk(&mut self, x: usize, kk: u8) -> ProgramCounterAction {
         self.v[x] = kk;
         ProgramCounterAction::Next
     }
 
     fn op_7xkk(&mut self, x: usize, kk: u8) -> ProgramCounterAction {
-        self.v[x] += kk;
+        self.v[x] = self.v[x].wrapping_add(kk);
         ProgramCounterAction::Next
     }
 
@@ -101,218 +964,2426 @@ impl Cpu {
     }
 
     fn op_8xy1(&mut self, x: usize, y: usize) -> ProgramCounterAction {
-        self.v[x] = self.v[x] | self.v[y];
+        self.v[x] |= self.v[y];
+        self.logic_vf_reset();
         ProgramCounterAction::Next
     }
 
     fn op_8xy2(&mut self, x: usize, y: usize) -> ProgramCounterAction {
-        self.v[x] = self.v[x] & self.v[y];
+        self.v[x] &= self.v[y];
+        self.logic_vf_reset();
         ProgramCounterAction::Next
     }
 
     fn op_8xy3(&mut self, x: usize, y: usize) -> ProgramCounterAction {
-        self.v[x] = self.v[x] ^ self.v[y];
+        self.v[x] ^= self.v[y];
+        self.logic_vf_reset();
         ProgramCounterAction::Next
     }
 
+    // The COSMAC VIP ran OR/AND/XOR through the ALU, which left VF cleared.
+    fn logic_vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xf] = 0;
+        }
+    }
+
     fn op_8xy4(&mut self, x: usize, y: usize) -> ProgramCounterAction {
         let (result, overflow) = self.v[x].overflowing_add(self.v[y]);
-
-        self.v[x] = result;
-        self.v[0xf] = match overflow {
-            true => 1,
-            false => 0,
-        };
+        self.set_with_flag(x, result, overflow as u8);
 
         ProgramCounterAction::Next
     }
 
     fn op_8xy5(&mut self, x: usize, y: usize) -> ProgramCounterAction {
-        let (result, overflow) = self.v[x].overflowing_sub(self.v[y]);
+        let (result, borrow) = self.v[x].overflowing_sub(self.v[y]);
 
-        self.v[x] = result;
-        self.v[0xf] = match overflow {
-            true => 1,
-            false => 0,
-        };
+        // VF is NOT borrow.
+        self.set_with_flag(x, result, !borrow as u8);
 
         ProgramCounterAction::Next
     }
 
     fn op_8xy6(&mut self, x: usize, y: usize) -> ProgramCounterAction {
-        self.v[0xf] = self.v[x] & 0x1;
-        self.v[x] = self.v[x] >> 1;
+        let value = self.shift_operand(x, y);
+        self.set_with_flag(x, value >> 1, value & 0x1);
 
-        ProgramCounterAction::Next 
+        ProgramCounterAction::Next
     }
 
-    fn op_8xy7(&mut self, x:usize, y:usize) -> ProgramCounterAction {
-        let (result, overflow) = self.v[y].overflowing_sub(self.v[x]);
+    fn op_8xy7(&mut self, x: usize, y: usize) -> ProgramCounterAction {
+        let (result, borrow) = self.v[y].overflowing_sub(self.v[x]);
 
-        self.v[x] = result;
-        self.v[0xf] = match overflow {
-            true => 1,
-            false => 0,
-        };
+        // VF is NOT borrow.
+        self.set_with_flag(x, result, !borrow as u8);
 
         ProgramCounterAction::Next
     }
 
-    fn op_8xye(&mut self, x:usize, y:usize) -> ProgramCounterAction {
-        let tmp = self.v[x] & 0b10000000;
-        if tmp > 0 {
-            self.v[0xf] = 1;
-        }
-        else {
-            self.v[0xf] = 0;
-        }
-
-        self.v[x] = self.v[x] << 1;
+    fn op_8xye(&mut self, x: usize, y: usize) -> ProgramCounterAction {
+        let value = self.shift_operand(x, y);
+        self.set_with_flag(x, value << 1, value >> 7);
 
-        print!("v[x]={}, v[f]={}", self.v[x], self.v[0xf]);
         ProgramCounterAction::Next
     }
 
-    // RET
-    fn op_00ee(&mut self) -> ProgramCounterAction {
-        let addr = self.stack[self.sp as usize];
+    // Store an arithmetic result in Vx and its flag in VF. Which write comes
+    // last, and so wins when x is F, is the vf_order quirk.
+    fn set_with_flag(&mut self, x: usize, result: u8, flag: u8) {
+        match self.quirks.vf_order {
+            true => {
+                self.v[0xf] = flag;
+                self.v[x] = result;
+            }
+            false => {
+                self.v[x] = result;
+                self.v[0xf] = flag;
+            }
+        }
+    }
+
+    // The register 8xy6/8xyE shift: Vx itself with the shift quirk, Vy on
+    // the COSMAC VIP.
+    fn shift_operand(&self, x: usize, y: usize) -> u8 {
+        match self.quirks.shift {
+            true => self.v[x],
+            false => self.v[y],
+        }
+    }
+
+    // RET: pop the return address off the stack.
+    fn op_00ee(&mut self) -> Result<ProgramCounterAction> {
+        if self.sp == 0 {
+            return Err(Chip8Error::StackUnderflow { pc: self.pc });
+        }
+
         self.sp -= 1;
-        ProgramCounterAction::Jump(addr)
+        Ok(ProgramCounterAction::Jump(self.stack[self.sp as usize]))
     }
 
     // JMP insutrction.
     fn op_1nnn(&mut self, nnn: u16) -> ProgramCounterAction {
+        // The entry point of a two-page hires program: the jump goes into
+        // native code that sets up the 64x64 display, done here instead.
+        if self.two_page_hires && self.pc == CHIP8_PROGRAM_START && nnn == 0x260 {
+            self.set_resolution(CHIP8_WIDTH, HIRES_CHIP8_HEIGHT);
+            return ProgramCounterAction::Jump(0x2c0);
+        }
+
         ProgramCounterAction::Jump(nnn)
     }
 
     // CALL addr.
-    // increment sp, then put pc on top of the stack
-    fn op_2nnn(&mut self, nnn: u16) -> ProgramCounterAction {
+    // push the address of the next instruction, then jump to nnn.
+    fn op_2nnn(&mut self, nnn: u16) -> Result<ProgramCounterAction> {
+        if self.sp as usize == CHIP8_STACK_SIZE {
+            return Err(Chip8Error::StackOverflow { pc: self.pc });
+        }
+
+        // With 64K of RAM a CALL can sit at 0xfffe; its return address wraps
+        // like the PC does.
+        self.stack[self.sp as usize] = self.pc.wrapping_add(CHIP8_OPCODE_SIZE);
         self.sp += 1;
-        self.stack[self.sp as usize] = self.pc;
 
-        ProgramCounterAction::Jump(nnn)
+        Ok(ProgramCounterAction::Jump(nnn))
     }
 
-    #[inline]
-    // CLS: clear the screen.
-    fn op_00e0(&mut self) -> ProgramCounterAction {
-        for y in 0..CHIP8_HEIGHT {
-            for x in 0..CHIP8_WIDTH {
-                self.vram[y][x] = 0;
-            }
-        }
-
-        ProgramCounterAction::Next
-    }
-
-    fn run(&mut self, opcode: u16) {
-        let nibbles = (
-            (opcode & 0xF000) >> 12,
-            (opcode & 0x0F00) >> 8,
-            (opcode & 0x00F0) >> 4,
-            (opcode & 0x000F),
-        );
-
-        let nnn = opcode & 0x0FFF;
-        let kk = (opcode & 0x00FF) as u8;
-        let x = nibbles.1 as usize;
-        let y = nibbles.2 as usize;
-        let n = nibbles.3 as usize;
-
-        let action = match nibbles {
-            (0x0, 0x0, 0xe, 0x0) => self.op_00e0(),
-            (0x0, 0x0, 0xe, 0xe) => self.op_00ee(),
-            (0x1, _, _, _) => self.op_1nnn(nnn),
-            (0x2, _, _, _) => self.op_2nnn(nnn),
-            (0x3, _, _, _) => self.op_3xkk(x, kk),
-            (0x4, _, _, _) => self.op_4xkk(x, kk),
-            (0x5, _, _, 0x0) => self.op_5xy0(x, y),
-            (0x6, _, _, _) => self.op_6xkk(x, kk),
-            (0x7, _, _, _) => self.op_7xkk(x, kk),
-            (0x8, _, _, 0x0) => self.op_8xy0(x, y),
-            (0x8, _, _, 0x1) => self.op_8xy1(x, y),
-            (0x8, _, _, 0x2) => self.op_8xy2(x, y),
-            (0x8, _, _, 0x3) => self.op_8xy3(x, y),
-            (0x8, _, _, 0x4) => self.op_8xy4(x, y),
-            (0x8, _, _, 0x5) => self.op_8xy5(x, y),
-            (0x8, _, _, 0x6) => self.op_8xy6(x, y),
-            (0x8, _, _, 0x7) => self.op_8xy7(x, y),
-            (0x8, _, _, 0xe) => self.op_8xye(x, y),
-            _ => panic!("chip8.cpu: unimplemented instruction {:?}", nibbles),
-        };
+    // SYS addr: jump to a machine code routine at nnn.
+    fn op_0nnn(&mut self, nnn: u16) -> Result<ProgramCounterAction> {
+        // The hires interpreter's own clear screen routine.
+        if self.two_page_hires && nnn == 0x230 {
+            return Ok(self.op_00e0());
+        }
 
-        match action {
-            ProgramCounterAction::Next => self.pc += CHIP8_OPCODE_SIZE,
-            ProgramCounterAction::Skip => self.pc += 2 * CHIP8_OPCODE_SIZE,
-            ProgramCounterAction::Jump(addr) => self.pc = addr,
+        match self.sys_policy {
+            SysPolicy::Ignore => {}
+            SysPolicy::Warn => {
+                #[cfg(feature = "std")]
+                eprintln!("chip8.cpu: ignoring SYS {:#05x} at {:#05x}", nnn, self.pc)
+            }
+            SysPolicy::Error => {
+                return Err(Chip8Error::UnsupportedSys {
+                    addr: nnn,
+                    pc: self.pc,
+                })
+            }
+            SysPolicy::Callback(callback) => callback(self, nnn),
+            SysPolicy::Cdp1802 => self.run_machine_code(nnn)?,
         }
+
+        Ok(ProgramCounterAction::Next)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    // Call the 1802 subroutine at `nnn` with V0-VF and the screen where the
+    // VIP interpreter keeps them, and I in RA.
+    fn run_machine_code(&mut self, nnn: u16) -> Result<()> {
+        let lores = self.vram.width() == CHIP8_WIDTH && self.vram.height() == CHIP8_HEIGHT;
+        let display = VIP_DISPLAY..VIP_DISPLAY + CHIP8_WIDTH * CHIP8_HEIGHT / 8;
+        if self.memory.size() < display.end {
+            return Err(Chip8Error::UnsupportedSys {
+                addr: nnn,
+                pc: self.pc,
+            });
+        }
 
-    #[test]
-    fn test_initial_state() {
-        let cpu = Cpu::new();
+        let vram = &self.vram;
+        let bytes = self.memory.bytes_mut();
+        bytes[VIP_REGISTERS..VIP_REGISTERS + CHIP8_NUM_REGS].copy_from_slice(&self.v);
+        if lores {
+            for (index, byte) in bytes[display.clone()].iter_mut().enumerate() {
+                let (x, y) = (index % 8 * 8, index / 8);
+                *byte = (0..8).fold(0, |acc, bit| acc << 1 | vram.pixel(x + bit, y) & 1);
+            }
+        }
 
-        for i in 0..CHIP8_FONT_SET_SIZE {
-            assert_eq!(cpu.ram[i], FONT_SET[i]);
+        let mut cdp = Cdp1802::new();
+        cdp.p = 3;
+        cdp.r[3] = nnn;
+        cdp.x = 2;
+        cdp.r[2] = VIP_STACK;
+        cdp.r[5] = self.pc.wrapping_add(CHIP8_OPCODE_SIZE);
+        cdp.r[0xa] = self.i;
+        cdp.r[0xb] = VIP_DISPLAY as u16;
+
+        let keypad = &self.keypad;
+        let exit = cdp.run(self.memory.bytes_mut(), MACHINE_CODE_LIMIT, |key| {
+            keypad.is_pressed(key)
+        });
+        if exit == Cdp1802Exit::Stuck {
+            return Err(Chip8Error::MachineCodeStuck {
+                addr: nnn,
+                pc: self.pc,
+            });
         }
 
-        assert_eq!(cpu.pc, 0x200);
-        assert_eq!(cpu.sp, 0x0);
+        let bytes = self.memory.bytes();
+        self.v
+            .copy_from_slice(&bytes[VIP_REGISTERS..VIP_REGISTERS + CHIP8_NUM_REGS]);
+        self.i = cdp.r[0xa];
+        if lores {
+            for (index, &byte) in bytes[display].iter().enumerate() {
+                let (x, y) = (index % 8 * 8, index / 8);
+                for bit in 0..8 {
+                    let pixel = self.vram.pixel(x + bit, y);
+                    let value = pixel & !1 | byte >> (7 - bit) & 1;
+                    if value != pixel {
+                        self.vram.set_pixel(x + bit, y, value);
+                        self.display_changed = true;
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_cls_opcode() {
-        let mut cpu = Cpu::new();
-        cpu.run(0x00e0);
-
+    #[inline]
+    // CLS: clear the screen, or just the selected planes on XO-CHIP.
+    fn op_00e0(&mut self) -> ProgramCounterAction {
+        #[cfg(feature = "megachip")]
+        {
+            if self.mega.is_enabled() {
+                self.mega.present();
+            }
+        }
+        self.vram.clear_planes(self.planes);
+        self.display_changed = true;
+
+        ProgramCounterAction::Next
+    }
+
+    // SCD nibble: scroll the screen down by n pixels.
+    fn op_00cn(&mut self, n: usize) -> ProgramCounterAction {
+        let n = self.scroll_amount(n);
+        self.vram.scroll(0, n as isize, self.planes);
+        self.display_changed = true;
+        ProgramCounterAction::Next
+    }
+
+    // SCU nibble: scroll the screen up by n pixels.
+    fn op_00dn(&mut self, n: usize) -> ProgramCounterAction {
+        let n = self.scroll_amount(n);
+        self.vram.scroll(0, -(n as isize), self.planes);
+        self.display_changed = true;
+        ProgramCounterAction::Next
+    }
+
+    // SCR: scroll the screen right by 4 pixels.
+    fn op_00fb(&mut self) -> ProgramCounterAction {
+        let n = self.scroll_amount(4);
+        self.vram.scroll(n as isize, 0, self.planes);
+        self.display_changed = true;
+        ProgramCounterAction::Next
+    }
+
+    // SCL: scroll the screen left by 4 pixels.
+    fn op_00fc(&mut self) -> ProgramCounterAction {
+        let n = self.scroll_amount(4);
+        self.vram.scroll(-(n as isize), 0, self.planes);
+        self.display_changed = true;
+        ProgramCounterAction::Next
+    }
+
+    // Scroll distances are in hires pixels on SUPER-CHIP 1.1.
+    fn scroll_amount(&self, n: usize) -> usize {
+        match self.quirks.half_scroll && !self.vram.is_hires() {
+            true => n / 2,
+            false => n,
+        }
+    }
+
+    // EXIT: stop the program. PC stays on the instruction.
+    fn op_00fd(&mut self) -> ProgramCounterAction {
+        self.exited = true;
+        ProgramCounterAction::Wait
+    }
+
+    // LOW: switch to the 64x32 mode. Like on the HP 48, the screen is
+    // cleared whenever the resolution changes.
+    fn op_00fe(&mut self) -> ProgramCounterAction {
+        self.set_resolution(CHIP8_WIDTH, CHIP8_HEIGHT);
+        ProgramCounterAction::Next
+    }
+
+    // HIGH: switch to the SUPER-CHIP 128x64 mode.
+    fn op_00ff(&mut self) -> ProgramCounterAction {
+        self.set_resolution(SCHIP_WIDTH, SCHIP_HEIGHT);
+        ProgramCounterAction::Next
+    }
+
+    fn set_resolution(&mut self, width: usize, height: usize) {
+        if (self.vram.width(), self.vram.height()) != (width, height) {
+            self.vram.resize(width, height);
+            self.display_changed = true;
+        }
+    }
+
+    // MEGAON/MEGAOFF: enter or leave the 256x192 MegaChip mode.
+    #[cfg(feature = "megachip")]
+    fn op_001n(&mut self, on: bool) -> ProgramCounterAction {
+        self.mega.set_enabled(on);
+        match on {
+            true => self.set_resolution(MEGA_WIDTH, MEGA_HEIGHT),
+            false => self.set_resolution(CHIP8_WIDTH, CHIP8_HEIGHT),
+        }
+        ProgramCounterAction::Next
+    }
+
+    // SCRU nibble: scroll the MegaChip screen up by n pixels.
+    #[cfg(feature = "megachip")]
+    fn op_00bn(&mut self, n: usize) -> ProgramCounterAction {
+        self.vram.scroll(0, -(n as isize), ALL_PLANES);
+        self.mega.scroll_up(n);
+        self.display_changed = true;
+        ProgramCounterAction::Next
+    }
+
+    // LDHI I, nnnnnn: load nn and the word after the opcode into I.
+    #[cfg(feature = "megachip")]
+    fn op_01nn(&mut self, nn: u8) -> Result<ProgramCounterAction> {
+        let pc = self.pc;
+        self.i = self
+            .memory
+            .read16(pc as usize + 2)
+            .map_err(|e| Chip8Error::from_memory(e, pc))?;
+        self.mega.set_i_high(nn);
+        Ok(ProgramCounterAction::Jump(pc.wrapping_add(4)))
+    }
+
+    // LDPAL nn: load palette entries 1 to nn from ARGB words at I.
+    #[cfg(feature = "megachip")]
+    fn op_02nn(&mut self, nn: usize) -> Result<ProgramCounterAction> {
+        let pc = self.pc;
+        let colors = self
+            .memory
+            .slice(self.mega.address(self.i), nn * 4)
+            .map_err(|e| Chip8Error::from_memory(e, pc))?;
+        self.mega.load_palette(colors);
+        Ok(ProgramCounterAction::Next)
+    }
+
+    // DIGISND n: play the sample at I, looping when n is 0.
+    #[cfg(feature = "megachip")]
+    fn op_060n(&mut self, n: u8) -> Result<ProgramCounterAction> {
+        let pc = self.pc;
+        let addr = self.mega.address(self.i);
+        let mut header = [0; 6];
+        header.copy_from_slice(
+            self.memory
+                .slice(addr, 6)
+                .map_err(|e| Chip8Error::from_memory(e, pc))?,
+        );
+        let data = self
+            .memory
+            .slice(addr + 6, MegaChip::sound_length(&header))
+            .map_err(|e| Chip8Error::from_memory(e, pc))?;
+        self.mega.play(&header, data, n == 0);
+        Ok(ProgramCounterAction::Next)
+    }
+
+    // DRW Vx, Vy in the mega mode: copy a SPRW x SPRH sprite of palette
+    // indices from I to (Vx, Vy), blending it onto the picture. Index 0 is
+    // transparent. Sprites are clipped at the edges, and VF is set if a
+    // pixel of the collision colour (CCOL) is drawn over.
+    #[cfg(feature = "megachip")]
+    fn op_dxyn_mega(&mut self, x: usize, y: usize) -> Result<ProgramCounterAction> {
+        let (width, height) = self.mega.sprite_size();
+        self.v[0xf] = 0;
+        if width == 0 || height == 0 {
+            return Ok(ProgramCounterAction::Next);
+        }
+
+        let pc = self.pc;
+        let sprite = self
+            .memory
+            .slice(self.mega.address(self.i), width * height)
+            .map_err(|e| Chip8Error::from_memory(e, pc))?;
+        let (x0, y0) = (self.v[x] as usize, self.v[y] as usize);
+        let collision = self.mega.collision_color();
+
+        for (row, line) in sprite.chunks(width).enumerate() {
+            let py = y0 + row;
+            if py >= MEGA_HEIGHT {
+                break;
+            }
+            for (col, &index) in line.iter().enumerate() {
+                let px = x0 + col;
+                if px >= MEGA_WIDTH {
+                    break;
+                }
+                if index == 0 {
+                    continue;
+                }
+                let below = self.vram.pixel(px, py);
+                if below != 0 && below == collision {
+                    self.v[0xf] = 1;
+                }
+                self.vram.set_pixel(px, py, index);
+                self.mega.plot(px, py, index);
+            }
+        }
+
+        self.display_changed = true;
+        Ok(ProgramCounterAction::Next)
+    }
+
+    // LD I, addr: set the index register to nnn.
+    fn op_annn(&mut self, nnn: u16) -> ProgramCounterAction {
+        self.i = nnn;
+        #[cfg(feature = "megachip")]
+        self.mega.set_i_high(0);
+        ProgramCounterAction::Next
+    }
+
+    // JP V0, addr: jump to nnn plus the value of V0.
+    fn op_bnnn(&mut self, nnn: u16) -> ProgramCounterAction {
+        // With the jump quirk this is BXnn: the offset register is the high
+        // nibble of the address.
+        let x = match self.quirks.jump {
+            true => (nnn >> 8) as usize,
+            false => 0,
+        };
+        ProgramCounterAction::Jump(nnn + self.v[x] as u16)
+    }
+
+    // RND Vx, byte: set Vx to a random byte ANDed with kk.
+    fn op_cxkk(&mut self, x: usize, kk: u8) -> ProgramCounterAction {
+        self.v[x] = self.rng.next_u8() & kk;
+        ProgramCounterAction::Next
+    }
+
+    // DRW Vx, Vy, nibble: XOR an n-byte sprite from memory at I onto the
+    // screen at (Vx, Vy). VF is set to 1 if any lit pixel gets erased.
+    //
+    // In the SUPER-CHIP hires mode DRW Vx, Vy, 0 draws a 16x16 sprite
+    // stored as 16 big-endian rows of two bytes.
+    //
+    // The sprite goes to each plane selected by PLANE. With both selected,
+    // the data for the first plane is followed by the data for the second.
+    fn op_dxyn(&mut self, x: usize, y: usize, n: usize) -> Result<ProgramCounterAction> {
+        #[cfg(feature = "megachip")]
+        {
+            if self.mega.is_enabled() {
+                return self.op_dxyn_mega(x, y);
+            }
+        }
+
+        let (rows, row_bytes) = match n == 0 && self.vram.is_hires() {
+            true => (16, 2),
+            false => (n, 1),
+        };
+
+        let selected = self.planes;
+        let planes = [1u8, 2]
+            .iter()
+            .copied()
+            .filter(|plane| selected & plane != 0);
+        let mut sprites = [(0u8, [0u16; 16]); 2];
+        let mut count = 0;
+        let pc = self.pc;
+        for plane in planes {
+            let start = self.i as usize + count * rows * row_bytes;
+            let bytes = self
+                .memory
+                .slice(start, rows * row_bytes)
+                .map_err(|e| Chip8Error::from_memory(e, pc))?;
+            let (mask, sprite) = &mut sprites[count];
+            *mask = plane;
+            for (line, row) in sprite.iter_mut().zip(bytes.chunks(row_bytes)) {
+                *line = row.iter().fold(0, |acc, &byte| acc << 8 | byte as u16);
+            }
+            count += 1;
+        }
+        let sprite_width = row_bytes * 8;
+
+        // With display wait the sprite is drawn once the next interrupt has
+        // come, so at most one DRW completes per frame.
+        if self.quirks.display_wait {
+            if !self.vblank {
+                self.waiting_for_vblank = true;
+                return Ok(ProgramCounterAction::Wait);
+            }
+            self.waiting_for_vblank = false;
+            self.vblank = false;
+        }
+
+        self.v[0xf] = 0;
+
+        // The starting position always wraps; the rest of the sprite wraps
+        // too unless the clipping quirk cuts it off at the edges.
+        let (width, height) = (self.vram.width(), self.vram.height());
+        let x0 = self.v[x] as usize % width;
+        let y0 = self.v[y] as usize % height;
+        let clipping = self.quirks.clipping;
+
+        for &(plane, ref sprite) in &sprites[..count] {
+            for (row, &line) in sprite[..rows].iter().enumerate() {
+                if clipping && y0 + row >= height {
+                    break;
+                }
+                let py = (y0 + row) % height;
+
+                for bit in 0..sprite_width {
+                    if clipping && x0 + bit >= width {
+                        break;
+                    }
+                    let px = (x0 + bit) % width;
+                    let pixel = (line >> (sprite_width - 1 - bit)) & 0x1;
+
+                    if pixel == 1 && self.vram.toggle_planes(px, py, plane) {
+                        self.v[0xf] = 1;
+                    }
+                }
+            }
+        }
+
+        self.display_changed = true;
+
+        Ok(ProgramCounterAction::Next)
+    }
+
+    // SKP Vx: skip the next instruction if the key in Vx is down.
+    fn op_ex9e(&mut self, x: usize) -> ProgramCounterAction {
+        ProgramCounterAction::skip_if(self.keypad.is_pressed(self.v[x]))
+    }
+
+    // SKNP Vx: skip the next instruction if the key in Vx is up.
+    fn op_exa1(&mut self, x: usize) -> ProgramCounterAction {
+        ProgramCounterAction::skip_if(!self.keypad.is_pressed(self.v[x]))
+    }
+
+    // LD I, LONG nnnn: load the 16-bit address in the next word into I and
+    // continue after it.
+    fn op_f000(&mut self) -> Result<ProgramCounterAction> {
+        let pc = self.pc;
+        self.i = self
+            .memory
+            .read16(pc as usize + 2)
+            .map_err(|e| Chip8Error::from_memory(e, pc))?;
+        Ok(ProgramCounterAction::Jump(pc.wrapping_add(4)))
+    }
+
+    // AUDIO: load the 16-byte audio pattern buffer from memory at I.
+    fn op_f002(&mut self) -> Result<ProgramCounterAction> {
+        let pc = self.pc;
+        let pattern = self
+            .memory
+            .slice(self.i as usize, AUDIO_PATTERN_SIZE)
+            .map_err(|e| Chip8Error::from_memory(e, pc))?;
+        self.audio_pattern.copy_from_slice(pattern);
+        Ok(ProgramCounterAction::Next)
+    }
+
+    // PLANE n: select the XO-CHIP planes later drawing acts on, bit 0 for
+    // the first plane and bit 1 for the second. PLANE 0 draws nothing.
+    fn op_fn01(&mut self, n: u8) -> ProgramCounterAction {
+        self.planes = n & ALL_PLANES;
+        ProgramCounterAction::Next
+    }
+
+    // LD Vx, DT: set Vx to the delay timer value.
+    fn op_fx07(&mut self, x: usize) -> ProgramCounterAction {
+        self.v[x] = self.timers.delay();
+        ProgramCounterAction::Next
+    }
+
+    // LD Vx, K: wait for a key and store it in Vx. Like the COSMAC VIP, the
+    // key is registered when it is released, not when it is pressed.
+    fn op_fx0a(&mut self, x: usize) -> ProgramCounterAction {
+        if !self.waiting_for_key {
+            // Ignore releases that happened before the instruction started.
+            self.keypad.take_released();
+            self.waiting_for_key = true;
+        }
+
+        match self.keypad.take_released() {
+            Some(key) => {
+                self.v[x] = key;
+                self.waiting_for_key = false;
+                ProgramCounterAction::Next
+            }
+            None => ProgramCounterAction::Wait,
+        }
+    }
+
+    // LD DT, Vx: set the delay timer to Vx.
+    fn op_fx15(&mut self, x: usize) -> ProgramCounterAction {
+        self.timers.set_delay(self.v[x]);
+        ProgramCounterAction::Next
+    }
+
+    // LD ST, Vx: set the sound timer to Vx.
+    fn op_fx18(&mut self, x: usize) -> ProgramCounterAction {
+        self.timers.set_sound(self.v[x]);
+        ProgramCounterAction::Next
+    }
+
+    // ADD I, Vx: add Vx to the index register.
+    fn op_fx1e(&mut self, x: usize) -> ProgramCounterAction {
+        self.i = self.i.wrapping_add(self.v[x] as u16);
+
+        if self.quirks.index_overflow {
+            self.v[0xf] = match self.i > 0xfff {
+                true => 1,
+                false => 0,
+            };
+        }
+
+        ProgramCounterAction::Next
+    }
+
+    // LD F, Vx: point I at the font glyph for the hex digit in Vx.
+    fn op_fx29(&mut self, x: usize) -> ProgramCounterAction {
+        self.i = (self.v[x] & 0xf) as u16 * CHIP8_FONT_GLYPH_SIZE;
+        ProgramCounterAction::Next
+    }
+
+    // LD HF, Vx: point I at the SUPER-CHIP big glyph for the digit in Vx.
+    fn op_fx30(&mut self, x: usize) -> ProgramCounterAction {
+        self.i = BIG_FONT_START + (self.v[x] & 0xf) as u16 * BIG_FONT_GLYPH_SIZE;
+        ProgramCounterAction::Next
+    }
+
+    // PITCH Vx: set the playback rate of the audio pattern.
+    fn op_fx3a(&mut self, x: usize) -> ProgramCounterAction {
+        self.pitch = self.v[x];
+        ProgramCounterAction::Next
+    }
+
+    // LD B, Vx: store the BCD digits of Vx at I, I+1 and I+2.
+    fn op_fx33(&mut self, x: usize) -> Result<ProgramCounterAction> {
+        let value = self.v[x];
+        let pc = self.pc;
+        let digits = self
+            .memory
+            .slice_mut(self.i as usize, 3)
+            .map_err(|e| Chip8Error::from_memory(e, pc))?;
+
+        digits[0] = value / 100;
+        digits[1] = (value / 10) % 10;
+        digits[2] = value % 10;
+
+        Ok(ProgramCounterAction::Next)
+    }
+
+    // LD [I], Vx: store V0..=Vx in memory starting at I.
+    fn op_fx55(&mut self, x: usize) -> Result<ProgramCounterAction> {
+        let v = self.v;
+        let pc = self.pc;
+        self.memory
+            .slice_mut(self.i as usize, x + 1)
+            .map_err(|e| Chip8Error::from_memory(e, pc))?
+            .copy_from_slice(&v[..=x]);
+
+        if self.quirks.memory_increment {
+            self.i = self.i.wrapping_add(self.memory_increment(x));
+        }
+
+        Ok(ProgramCounterAction::Next)
+    }
+
+    // How far Fx55/Fx65 move I with the memory increment quirk.
+    fn memory_increment(&self, x: usize) -> u16 {
+        match self.quirks.memory_increment_by_x {
+            true => x as u16,
+            false => x as u16 + 1,
+        }
+    }
+
+    // LD Vx, [I]: read V0..=Vx from memory starting at I.
+    fn op_fx65(&mut self, x: usize) -> Result<ProgramCounterAction> {
+        let pc = self.pc;
+        let bytes = self.memory.slice(self.i as usize, x + 1);
+        self.v[..=x].copy_from_slice(bytes.map_err(|e| Chip8Error::from_memory(e, pc))?);
+
+        if self.quirks.memory_increment {
+            self.i = self.i.wrapping_add(self.memory_increment(x));
+        }
+
+        Ok(ProgramCounterAction::Next)
+    }
+
+    // LD R, Vx: save V0..=Vx to the RPL flags. SUPER-CHIP had 8 flags,
+    // XO-CHIP extends them to all 16 registers.
+    fn op_fx75(&mut self, x: usize) -> ProgramCounterAction {
+        self.rpl_flags[..=x].copy_from_slice(&self.v[..=x]);
+        ProgramCounterAction::Next
+    }
+
+    // LD Vx, R: restore V0..=Vx from the RPL flags.
+    fn op_fx85(&mut self, x: usize) -> ProgramCounterAction {
+        self.v[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+        ProgramCounterAction::Next
+    }
+
+    // Decode and execute a single opcode.
+    pub fn run(&mut self, opcode: u16) -> Result<()> {
+        let instruction = self.decode(opcode)?;
+        self.execute(instruction)
+    }
+
+    // Decode `opcode`, refusing instructions of a later platform.
+    fn decode(&self, opcode: u16) -> Result<Instruction> {
+        #[cfg(feature = "megachip")]
+        {
+            if self.mega.is_enabled() || opcode == 0x0011 {
+                if let Some(instruction) = Instruction::decode_megachip(opcode) {
+                    return Ok(instruction);
+                }
+            }
+        }
+
+        let pc = self.pc;
+        match Instruction::decode(opcode) {
+            Some(instruction) if instruction.platform() <= self.platform => Ok(instruction),
+            _ => Err(Chip8Error::UnknownInstruction { opcode, pc }),
+        }
+    }
+
+    // Execute a decoded instruction and advance the program counter.
+    pub fn execute(&mut self, instruction: Instruction) -> Result<()> {
+        #[cfg(feature = "std")]
+        if !self.observers.is_empty() {
+            return self.execute_observed(instruction);
+        }
+
+        self.dispatch(instruction)
+    }
+
+    // execute(), telling the observers before and after.
+    #[cfg(feature = "std")]
+    fn execute_observed(&mut self, instruction: Instruction) -> Result<()> {
+        let pc = self.pc;
+        let registers = self.snapshot();
+        for (_, observer) in self.observers.iter_mut() {
+            observer.before_instruction(pc, instruction, &registers);
+        }
+
+        self.dispatch(instruction)?;
+
+        let registers = self.snapshot();
+        for (_, observer) in self.observers.iter_mut() {
+            observer.after_instruction(pc, instruction, &registers);
+        }
+
+        Ok(())
+    }
+
+    fn dispatch(&mut self, instruction: Instruction) -> Result<()> {
+        let action = match instruction {
+            Instruction::ScrollDown(n) => self.op_00cn(n as usize),
+            Instruction::ScrollUp(n) => self.op_00dn(n as usize),
+            Instruction::Cls => self.op_00e0(),
+            Instruction::Ret => self.op_00ee()?,
+            Instruction::ScrollRight => self.op_00fb(),
+            Instruction::ScrollLeft => self.op_00fc(),
+            Instruction::Exit => self.op_00fd(),
+            Instruction::LowRes => self.op_00fe(),
+            Instruction::HighRes => self.op_00ff(),
+            Instruction::Sys(nnn) => self.op_0nnn(nnn)?,
+            #[cfg(feature = "megachip")]
+            Instruction::MegaOff => self.op_001n(false),
+            #[cfg(feature = "megachip")]
+            Instruction::MegaOn => self.op_001n(true),
+            #[cfg(feature = "megachip")]
+            Instruction::MegaScrollUp(n) => self.op_00bn(n as usize),
+            #[cfg(feature = "megachip")]
+            Instruction::LoadHighI(nn) => self.op_01nn(nn)?,
+            #[cfg(feature = "megachip")]
+            Instruction::LoadPalette(nn) => self.op_02nn(nn as usize)?,
+            #[cfg(feature = "megachip")]
+            Instruction::SpriteWidth(nn) => {
+                self.mega.set_sprite_size(Some(nn), None);
+                ProgramCounterAction::Next
+            }
+            #[cfg(feature = "megachip")]
+            Instruction::SpriteHeight(nn) => {
+                self.mega.set_sprite_size(None, Some(nn));
+                ProgramCounterAction::Next
+            }
+            #[cfg(feature = "megachip")]
+            Instruction::ScreenAlpha(nn) => {
+                self.mega.set_alpha(nn);
+                ProgramCounterAction::Next
+            }
+            #[cfg(feature = "megachip")]
+            Instruction::PlaySound(n) => self.op_060n(n)?,
+            #[cfg(feature = "megachip")]
+            Instruction::StopSound => {
+                self.mega.stop();
+                ProgramCounterAction::Next
+            }
+            #[cfg(feature = "megachip")]
+            Instruction::BlendMode(n) => {
+                self.mega.set_blend_mode(BlendMode::from_nibble(n));
+                ProgramCounterAction::Next
+            }
+            #[cfg(feature = "megachip")]
+            Instruction::CollisionColor(nn) => {
+                self.mega.set_collision_color(nn);
+                ProgramCounterAction::Next
+            }
+            Instruction::Jump(nnn) => self.op_1nnn(nnn),
+            Instruction::Call(nnn) => self.op_2nnn(nnn)?,
+            Instruction::SkipEqByte(x, kk) => self.op_3xkk(x as usize, kk),
+            Instruction::SkipNeByte(x, kk) => self.op_4xkk(x as usize, kk),
+            Instruction::SkipEqReg(x, y) => self.op_5xy0(x as usize, y as usize),
+            Instruction::StoreRange(x, y) => self.op_5xy2(x as usize, y as usize)?,
+            Instruction::LoadRange(x, y) => self.op_5xy3(x as usize, y as usize)?,
+            Instruction::LoadByte(x, kk) => self.op_6xkk(x as usize, kk),
+            Instruction::AddByte(x, kk) => self.op_7xkk(x as usize, kk),
+            Instruction::LoadReg(x, y) => self.op_8xy0(x as usize, y as usize),
+            Instruction::Or(x, y) => self.op_8xy1(x as usize, y as usize),
+            Instruction::And(x, y) => self.op_8xy2(x as usize, y as usize),
+            Instruction::Xor(x, y) => self.op_8xy3(x as usize, y as usize),
+            Instruction::AddReg(x, y) => self.op_8xy4(x as usize, y as usize),
+            Instruction::Sub(x, y) => self.op_8xy5(x as usize, y as usize),
+            Instruction::ShiftRight(x, y) => self.op_8xy6(x as usize, y as usize),
+            Instruction::SubN(x, y) => self.op_8xy7(x as usize, y as usize),
+            Instruction::ShiftLeft(x, y) => self.op_8xye(x as usize, y as usize),
+            Instruction::SkipNeReg(x, y) => self.op_9xy0(x as usize, y as usize),
+            Instruction::LoadI(nnn) => self.op_annn(nnn),
+            Instruction::JumpV0(nnn) => self.op_bnnn(nnn),
+            Instruction::Random(x, kk) => self.op_cxkk(x as usize, kk),
+            Instruction::Draw(x, y, n) => self.op_dxyn(x as usize, y as usize, n as usize)?,
+            Instruction::SkipKeyPressed(x) => self.op_ex9e(x as usize),
+            Instruction::SkipKeyReleased(x) => self.op_exa1(x as usize),
+            Instruction::LoadDelay(x) => self.op_fx07(x as usize),
+            Instruction::LoadLongI => self.op_f000()?,
+            Instruction::LoadPattern => self.op_f002()?,
+            Instruction::SelectPlane(n) => self.op_fn01(n),
+            Instruction::WaitKey(x) => self.op_fx0a(x as usize),
+            Instruction::SetDelay(x) => self.op_fx15(x as usize),
+            Instruction::SetSound(x) => self.op_fx18(x as usize),
+            Instruction::AddI(x) => self.op_fx1e(x as usize),
+            Instruction::LoadFont(x) => self.op_fx29(x as usize),
+            Instruction::LoadBigFont(x) => self.op_fx30(x as usize),
+            Instruction::Bcd(x) => self.op_fx33(x as usize)?,
+            Instruction::SetPitch(x) => self.op_fx3a(x as usize),
+            Instruction::StoreRegs(x) => self.op_fx55(x as usize)?,
+            Instruction::LoadRegs(x) => self.op_fx65(x as usize)?,
+            Instruction::StoreFlags(x) => self.op_fx75(x as usize),
+            Instruction::LoadFlags(x) => self.op_fx85(x as usize),
+        };
+
+        match action {
+            ProgramCounterAction::Next => self.pc = self.pc.wrapping_add(CHIP8_OPCODE_SIZE),
+            ProgramCounterAction::Skip => self.pc = self.skip_target(),
+            ProgramCounterAction::Jump(addr) => self.pc = addr,
+            ProgramCounterAction::Wait => {}
+        }
+
+        Ok(())
+    }
+}
+
+// Registers x to y inclusive, counting down if x > y.
+fn register_range(x: usize, y: usize) -> impl Iterator<Item = usize> {
+    let count = x.max(y) - x.min(y) + 1;
+    (0..count).map(move |n| if x <= y { x + n } else { x - n })
+}
+
+fn load_fonts(memory: &mut Memory) {
+    memory.load(0, &FONT_SET).unwrap();
+    memory.load(BIG_FONT_START as usize, &BIG_FONT_SET).unwrap();
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::memory::{CHIP8_RAM, XOCHIP_RAM};
+
+    // Replays a fixed sequence of bytes.
+    #[derive(Clone)]
+    struct SequenceRng(Vec<u8>, usize);
+
+    impl Rng for SequenceRng {
+        fn next_u8(&mut self) -> u8 {
+            let value = self.0[self.1 % self.0.len()];
+            self.1 += 1;
+            value
+        }
+    }
+
+    #[test]
+    fn test_initial_state() {
+        let cpu = Cpu::new();
+
+        assert_eq!(cpu.memory.bytes()[..CHIP8_FONT_SET_SIZE], FONT_SET);
+
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.sp, 0x0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(Quirks {
+            memory_increment: true,
+            ..Quirks::default()
+        });
+        cpu.load_program(&[0x60, 0x01, 0xd0, 0x05]).unwrap();
+        cpu.run_frame(2).unwrap();
+        cpu.press_key(0x4);
+        cpu.reset();
+
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.v, [0; CHIP8_NUM_REGS]);
+        assert_eq!(
+            cpu.memory.bytes()[0x200..0x204],
+            [0; 4],
+            "the program is unloaded"
+        );
+        assert_eq!(cpu.memory.bytes()[..CHIP8_FONT_SET_SIZE], FONT_SET);
+        assert_eq!(cpu.vram, Framebuffer::new());
+        assert!(!cpu.keypad.is_pressed(0x4));
+        assert!(cpu.quirks.memory_increment, "configuration is kept");
+    }
+
+    #[test]
+    fn test_load_program_too_large() {
+        let mut cpu = Cpu::new();
+        let program = [0u8; CHIP8_RAM - 0x200 + 1];
+
+        assert_eq!(
+            cpu.load_program(&program),
+            Err(Chip8Error::RomTooLarge {
+                size: CHIP8_RAM - 0x200 + 1,
+                max: CHIP8_RAM - 0x200
+            })
+        );
+        assert_eq!(cpu.load_program(&[]), Err(Chip8Error::EmptyRom));
+    }
+
+    #[test]
+    fn test_cls_opcode() {
+        let mut cpu = Cpu::new();
+        cpu.run(0x00e0).unwrap();
+
         // Check that PC is now pointing to next instruction.
         assert_eq!(cpu.pc, 0x200 + 2);
 
-        // Check that the vram is actually cleared.
-        for row in 0..CHIP8_HEIGHT {
-            for col in 0..CHIP8_WIDTH {
-                assert_eq!(cpu.vram[row][col], 0);
+        // Check that the vram is actually cleared.
+        for row in 0..CHIP8_HEIGHT {
+            for col in 0..CHIP8_WIDTH {
+                assert!(!cpu.vram.get(col, row));
+            }
+        }
+    }
+
+    #[test]
+    fn test_op_00ff_00fe() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        cpu.memory.write8(0x300, 0x80).unwrap();
+        cpu.run(0x00ff).unwrap();
+
+        assert_eq!((cpu.vram.width(), cpu.vram.height()), (128, 64));
+        assert_eq!(cpu.pc, 0x200 + 2);
+
+        cpu.v[0] = 100;
+        cpu.v[1] = 40;
+        cpu.run(0xd011).unwrap();
+        assert!(cpu.vram.get(100, 40), "DRW uses the whole hires screen");
+
+        cpu.v[0] = 130;
+        cpu.run(0xd011).unwrap();
+        assert!(cpu.vram.get(2, 40), "and wraps at its edges");
+
+        cpu.run(0x00fe).unwrap();
+        assert_eq!(cpu.vram, Framebuffer::new(), "back to a blank 64x32 screen");
+
+        cpu.run(0x00ff).unwrap();
+        cpu.reset();
+        assert!(!cpu.vram.is_hires(), "reset returns to lores");
+    }
+
+    #[test]
+    fn test_scroll_opcodes() {
+        let mut cpu = Cpu::new();
+        cpu.run(0x00ff).unwrap();
+        cpu.vram.toggle(10, 10);
+
+        cpu.run(0x00c3).unwrap();
+        assert!(cpu.vram.get(10, 13), "00CN scrolls down n rows");
+        cpu.run(0x00fb).unwrap();
+        assert!(cpu.vram.get(14, 13), "00FB scrolls right 4");
+        cpu.run(0x00fc).unwrap();
+        cpu.run(0x00fc).unwrap();
+        assert!(cpu.vram.get(6, 13), "00FC scrolls left 4");
+        assert_eq!(cpu.pc, 0x200 + 10);
+    }
+
+    #[test]
+    fn test_op_00dn_scroll_up() {
+        for &hires in &[false, true] {
+            let mut cpu = Cpu::new();
+            if hires {
+                cpu.run(0x00ff).unwrap();
+            }
+            cpu.vram.toggle_planes(10, 10, ALL_PLANES);
+            cpu.vram.toggle(20, 1);
+
+            cpu.run(0xf201).unwrap();
+            cpu.run(0x00d3).unwrap();
+            assert_eq!(cpu.vram.pixel(10, 7), 2, "only plane 2 moved up");
+            assert_eq!(cpu.vram.pixel(10, 10), 1);
+
+            cpu.run(0xf301).unwrap();
+            cpu.run(0x00d2).unwrap();
+            assert_eq!((cpu.vram.pixel(10, 5), cpu.vram.pixel(10, 8)), (2, 1));
+            assert!(
+                (0..cpu.vram.width()).all(|x| !cpu.vram.get(x, cpu.vram.height() - 1)),
+                "rows uncovered at the bottom are blank"
+            );
+            assert!(!cpu.vram.get(20, 0), "pixels scrolled off the top are lost");
+        }
+    }
+
+    #[test]
+    fn test_scroll_half_scroll_quirk() {
+        let run = |half_scroll| {
+            let mut cpu = Cpu::new();
+            cpu.set_quirks(Quirks {
+                half_scroll,
+                ..Quirks::default()
+            });
+            cpu.vram.toggle(10, 10);
+            cpu.run(0x00c4).unwrap();
+            cpu.run(0x00fb).unwrap();
+            cpu
+        };
+
+        assert!(run(false).vram.get(14, 14), "modern: lores pixels");
+        assert!(run(true).vram.get(12, 12), "legacy: half as far in lores");
+    }
+
+    #[test]
+    fn test_op_00fd_exit() {
+        let mut cpu = Cpu::builder()
+            .rom(&[0x60, 0x01, 0x00, 0xfd])
+            .build()
+            .unwrap();
+
+        assert_eq!(cpu.run_until(100, |_| false), Ok(StopReason::Exited));
+        assert!(cpu.has_exited());
+        assert_eq!(cpu.pc, 0x202);
+        assert_eq!(cpu.step(), Ok(State::Exited), "nothing runs after EXIT");
+        assert_eq!(cpu.pc, 0x202);
+
+        cpu.reset();
+        assert!(!cpu.has_exited());
+    }
+
+    #[test]
+    fn test_unimplemented_instruction_errors() {
+        let mut cpu = Cpu::new();
+        let result = cpu.run(0x5121);
+
+        assert_eq!(
+            result,
+            Err(Chip8Error::UnknownInstruction {
+                opcode: 0x5121,
+                pc: 0x200
+            })
+        );
+        assert_eq!(cpu.pc, 0x200, "PC does not advance on error");
+    }
+
+    #[test]
+    fn test_sys_error() {
+        let mut cpu = Cpu::new();
+        let result = cpu.run(0x0022);
+
+        assert_eq!(
+            result,
+            Err(Chip8Error::UnsupportedSys {
+                addr: 0x022,
+                pc: 0x200
+            })
+        );
+    }
+
+    #[test]
+    fn test_call_and_ret() {
+        let mut cpu = Cpu::new();
+        cpu.run(0x2400).unwrap();
+        assert_eq!(cpu.pc, 0x400, "CALL jumps to nnn");
+        assert_eq!(cpu.sp, 1);
+
+        cpu.run(0x00ee).unwrap();
+        assert_eq!(cpu.pc, 0x202, "RET returns past the CALL");
+        assert_eq!(cpu.sp, 0);
+    }
+
+    #[test]
+    fn test_call_at_end_of_memory() {
+        let mut cpu = Cpu::new();
+        cpu.set_memory_size(0x10000);
+        cpu.pc = 0xfffe;
+        cpu.run(0x2400).unwrap();
+        assert_eq!(cpu.pc, 0x400);
+        assert_eq!(cpu.stack[0], 0x0000, "the return address wraps");
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let mut cpu = Cpu::new();
+        for _ in 0..16 {
+            cpu.run(0x2200).unwrap();
+        }
+
+        assert_eq!(
+            cpu.run(0x2200),
+            Err(Chip8Error::StackOverflow { pc: 0x200 }),
+            "the 17th nested CALL overflows"
+        );
+        assert_eq!(cpu.sp, 16, "the stack is left intact");
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        let mut cpu = Cpu::new();
+
+        assert_eq!(
+            cpu.run(0x00ee),
+            Err(Chip8Error::StackUnderflow { pc: 0x200 })
+        );
+        assert_eq!(cpu.sp, 0);
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn test_sys_ignore() {
+        let mut cpu = Cpu::new();
+        cpu.set_sys_policy(SysPolicy::Ignore);
+        cpu.run(0x0123).unwrap();
+
+        assert_eq!(cpu.pc, 0x200 + 2, "SYS is skipped");
+    }
+
+    #[test]
+    fn test_sys_callback() {
+        fn handler(cpu: &mut Cpu, addr: u16) {
+            cpu.press_key((addr & 0xf) as u8);
+        }
+
+        let mut cpu = Cpu::new();
+        cpu.set_sys_policy(SysPolicy::Callback(handler));
+        cpu.run(0x0125).unwrap();
+
+        assert!(cpu.keypad.is_pressed(0x5), "the callback received nnn");
+        assert_eq!(cpu.pc, 0x200 + 2);
+    }
+
+    #[test]
+    fn test_op_0nnn_cdp1802() {
+        let mut cpu = Cpu::new();
+        cpu.set_sys_policy(SysPolicy::Cdp1802);
+        cpu.i = 0x345;
+        cpu.v[1] = 0x10;
+        #[rustfmt::skip]
+        cpu.memory.load(0x300, &[
+            0xf8, 0x0e, 0xbf, 0xf8, 0xf1, 0xaf, // RF = 0xef1, V1
+            0x0f, 0xfc, 0x05, 0x5f, // V1 += 5
+            0x1a, // INC RA, I
+            0xf8, 0x80, 0x5b, // M(RB = 0xf00) = 0x80, top left pixel
+            0xd4, // SEP R4
+        ]).unwrap();
+
+        cpu.run(0x0300).unwrap();
+        assert_eq!(cpu.v[1], 0x15);
+        assert_eq!(cpu.i, 0x346);
+        assert!(cpu.vram.get(0, 0) && !cpu.vram.get(1, 0));
+        assert!(cpu.display_changed);
+        assert_eq!(cpu.pc, 0x202);
+
+        // BR to itself never gets back.
+        cpu.memory.load(0x300, &[0x30, 0x00]).unwrap();
+        assert_eq!(
+            cpu.run(0x0300),
+            Err(Chip8Error::MachineCodeStuck {
+                addr: 0x300,
+                pc: 0x202
+            })
+        );
+    }
+
+    #[test]
+    fn test_step() {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0x200, &[0x61, 0x2a, 0x12, 0x00]).unwrap();
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.v[1], 0x2a, "LD V1, 0x2a was executed");
+        assert_eq!(cpu.pc, 0x202);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x200, "JP 0x200 was executed");
+    }
+
+    #[test]
+    fn test_step_reports_unknown_pc() {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0x200, &[0x00, 0xe0, 0x51, 0x21]).unwrap();
+
+        cpu.step().unwrap();
+        let error = cpu.step().unwrap_err();
+        assert_eq!(
+            error,
+            Chip8Error::UnknownInstruction {
+                opcode: 0x5121,
+                pc: 0x202
+            }
+        );
+        assert_eq!(error.to_string(), "unknown instruction 0x5121 at 0x202");
+    }
+
+    #[test]
+    fn test_step_detects_halt() {
+        let mut cpu = Cpu::new();
+        // LD V0, 1; JP 0x202
+        cpu.memory.load(0x200, &[0x60, 0x01, 0x12, 0x02]).unwrap();
+
+        assert_eq!(cpu.step(), Ok(State::Running));
+        assert_eq!(cpu.step(), Ok(State::Halted));
+        assert_eq!(cpu.step(), Ok(State::Halted), "halt is sticky");
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let mut cpu = Cpu::new();
+        // ADD V0, 1; JP 0x200
+        cpu.memory.load(0x200, &[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        let reason = cpu.run_until(1000, |cpu| cpu.registers()[0] == 5).unwrap();
+        assert_eq!(reason, StopReason::Condition);
+        assert_eq!(cpu.registers()[0], 5);
+        assert_eq!(cpu.pc(), 0x202, "stopped right after the ADD");
+
+        let reason = cpu.run_until(10, |cpu| cpu.pc() == 0x2f0).unwrap();
+        assert_eq!(reason, StopReason::CycleLimit);
+    }
+
+    #[test]
+    fn test_run_until_halt() {
+        let mut cpu = Cpu::new();
+        // LD VF, 1; JP 0x202
+        cpu.memory.load(0x200, &[0x6f, 0x01, 0x12, 0x02]).unwrap();
+
+        let vf = cpu.registers()[0xf];
+        let reason = cpu.run_until(10, |cpu| cpu.registers()[0xf] != vf).unwrap();
+        assert_eq!(reason, StopReason::Condition, "VF changed");
+
+        let reason = cpu.run_until(10, |_| false).unwrap();
+        assert_eq!(reason, StopReason::Halted);
+    }
+
+    #[test]
+    fn test_step_reports_waiting_for_key() {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0x200, &[0xf0, 0x0a]).unwrap();
+
+        assert_eq!(cpu.step(), Ok(State::WaitingForKey));
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut cpu = Cpu::new();
+        // LD V0, 5; LD DT, V0; JP 0x204
+        cpu.memory
+            .load(0x200, &[0x60, 0x05, 0xf0, 0x15, 0x12, 0x04])
+            .unwrap();
+
+        let changed = cpu.run_frame(10).unwrap();
+        assert!(!changed, "nothing was drawn");
+        assert_eq!(cpu.timers.delay(), 4, "the delay timer ticked once");
+
+        cpu.memory.load(0x204, &[0x00, 0xe0]).unwrap();
+        let changed = cpu.run_frame(1).unwrap();
+        assert!(changed, "CLS changes the display");
+        assert_eq!(cpu.timers.delay(), 3);
+
+        assert!(!cpu.run_frame(0).unwrap(), "the flag is cleared per frame");
+    }
+
+    #[test]
+    fn test_update_timers_independent_of_cycles() {
+        let mut cpu = Cpu::new();
+        cpu.timers.set_delay(60);
+
+        // 1000 instructions in a quarter of a second is still 15 ticks.
+        cpu.memory.load(0x200, &[0x70, 0x01, 0x12, 0x00]).unwrap();
+        for _ in 0..1000 {
+            cpu.step().unwrap();
+        }
+        cpu.update_timers(Duration::from_millis(250));
+
+        assert_eq!(cpu.timers().delay(), 45);
+    }
+
+    #[test]
+    fn test_run_frame_stops_when_waiting_for_key() {
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0x200, &[0xf0, 0x0a]).unwrap();
+
+        cpu.run_frame(100).unwrap();
+        assert!(cpu.is_waiting_for_key());
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn test_run_frame_display_wait() {
+        // DRW V0, V0, 1; DRW V0, V0, 1; JP 0x204
+        let rom = [0xd0, 0x01, 0xd0, 0x01, 0x12, 0x04];
+        let mut cpu = Cpu::builder()
+            .rom(&rom)
+            .quirks(Quirks {
+                display_wait: true,
+                ..Quirks::default()
+            })
+            .build()
+            .unwrap();
+
+        assert!(!cpu.run_frame(100).unwrap(), "nothing was drawn yet");
+        assert_eq!(cpu.pc, 0x200, "the first DRW waits for the interrupt");
+        assert!(!cpu.vram.get(0, 0));
+
+        assert!(cpu.run_frame(100).unwrap());
+        assert!(
+            cpu.vram.get(0, 0),
+            "the sprite is drawn after the interrupt"
+        );
+        assert_eq!(cpu.pc, 0x202, "the second DRW waits for the next one");
+
+        cpu.run_frame(100).unwrap();
+        assert!(!cpu.vram.get(0, 0), "the second DRW erased the sprite");
+        assert_eq!(cpu.step(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_observer() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Tracer(Rc<RefCell<Vec<String>>>);
+
+        impl Observer for Tracer {
+            fn before_instruction(&mut self, pc: u16, ins: Instruction, r: &RegisterSnapshot) {
+                let line = format!("before {:#05x} {} v1={}", pc, ins, r.v[1]);
+                self.0.borrow_mut().push(line);
+            }
+
+            fn after_instruction(&mut self, pc: u16, ins: Instruction, r: &RegisterSnapshot) {
+                let line = format!("after {:#05x} {} v1={} pc={:#05x}", pc, ins, r.v[1], r.pc);
+                self.0.borrow_mut().push(line);
+            }
+
+            fn frame_end(&mut self, r: &RegisterSnapshot) {
+                self.0.borrow_mut().push(format!("frame pc={:#05x}", r.pc));
+            }
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = Cpu::new();
+        cpu.memory.load(0x200, &[0x61, 0x2a, 0x12, 0x02]).unwrap();
+        let id = cpu.add_observer(Box::new(Tracer(log.clone())));
+        cpu.run_frame(10).unwrap();
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "before 0x200 LD V1, 0x2a v1=0",
+                "after 0x200 LD V1, 0x2a v1=42 pc=0x202",
+                "frame pc=0x202",
+            ]
+        );
+
+        assert!(cpu.remove_observer(id).is_some());
+        assert!(cpu.remove_observer(id).is_none());
+        cpu.run_frame(1).unwrap();
+        assert_eq!(log.borrow().len(), 3, "removed observers are not called");
+    }
+
+    #[test]
+    fn test_read_opcode() {
+        let mut cpu = Cpu::new();
+
+        cpu.memory.write8(0x200, 0xB1).unwrap();
+        cpu.memory.write8(0x201, 0x5A).unwrap();
+
+        let opcode: u16 = cpu.read_opcode().unwrap();
+        assert_eq!(
+            opcode, 0xB15A,
+            "the opcode was correctly read using two bytes starting at PC."
+        );
+    }
+
+    #[test]
+    fn test_op_5xy0() {
+        let mut cpu = Cpu::new();
+        cpu.v[1] = 0x42;
+        cpu.v[2] = 0x42;
+        cpu.run(0x5120).unwrap();
+        assert_eq!(cpu.pc, 0x200 + 4, "Vx == Vy, skip");
+
+        cpu.v[2] = 0x43;
+        cpu.run(0x5120).unwrap();
+        assert_eq!(cpu.pc, 0x204 + 2, "Vx != Vy, no skip");
+    }
+
+    #[test]
+    fn test_op_9xy0() {
+        let mut cpu = Cpu::new();
+        cpu.v[1] = 0x42;
+        cpu.v[2] = 0x42;
+        cpu.run(0x9120).unwrap();
+        assert_eq!(cpu.pc, 0x200 + 2, "Vx == Vy, no skip");
+
+        cpu.v[2] = 0x43;
+        cpu.run(0x9120).unwrap();
+        assert_eq!(cpu.pc, 0x202 + 4, "Vx != Vy, skip");
+    }
+
+    #[test]
+    fn test_op_7xkk_wraps() {
+        let mut cpu = Cpu::new();
+        cpu.v[3] = 0xff;
+        cpu.v[0xf] = 0x5;
+        cpu.run(0x7302).unwrap();
+
+        assert_eq!(cpu.v[3], 0x01, "Vx wraps around");
+        assert_eq!(cpu.v[0xf], 0x5, "Vf is not affected");
+    }
+
+    #[test]
+    fn test_read_opcode_out_of_bounds() {
+        let mut cpu = Cpu::new();
+        cpu.pc = 0xfff;
+
+        assert_eq!(
+            cpu.step(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0x1000,
+                pc: 0xfff
+            })
+        );
+    }
+
+    #[test]
+    fn test_memory_ops_out_of_bounds() {
+        for &opcode in [0xd00f, 0xf033, 0xf355, 0xf365].iter() {
+            let mut cpu = Cpu::new();
+            cpu.i = 0xffe;
+
+            assert_eq!(
+                cpu.run(opcode),
+                Err(Chip8Error::MemoryOutOfBounds {
+                    addr: 0x1000,
+                    pc: 0x200
+                }),
+                "{:#06x} reaches past the end of RAM",
+                opcode
+            );
+            assert_eq!(cpu.pc, 0x200);
+        }
+    }
+
+    #[test]
+    fn test_write_protected_font() {
+        let mut cpu = Cpu::new();
+        cpu.memory_mut().protect(CHIP8_FONT_SET_SIZE);
+        cpu.i = 0x10;
+
+        assert_eq!(
+            cpu.run(0xf055),
+            Err(Chip8Error::WriteProtected {
+                addr: 0x10,
+                pc: 0x200
+            })
+        );
+        assert_eq!(cpu.memory.bytes()[..CHIP8_FONT_SET_SIZE], FONT_SET);
+    }
+
+    #[test]
+    fn test_program_start() {
+        let mut cpu = Cpu::new();
+        cpu.set_program_start(ETI660_PROGRAM_START);
+        cpu.load_program(&[0x16, 0x00]).unwrap();
+
+        assert_eq!(cpu.memory.bytes()[0x600..0x602], [0x16, 0x00]);
+        assert_eq!(cpu.max_program_size(), CHIP8_RAM - 0x600);
+        assert_eq!(cpu.step(), Ok(State::Halted), "JP 0x600 at 0x600");
+
+        cpu.reset();
+        assert_eq!(cpu.pc, 0x600, "the start address is kept");
+    }
+
+    #[test]
+    fn test_op_8xy1_8xy2_8xy3() {
+        let cases = [(0x8121, 0b1110), (0x8122, 0b1000), (0x8123, 0b0110)];
+
+        for &(opcode, result) in cases.iter() {
+            let mut cpu = Cpu::new();
+            cpu.v[1] = 0b1100;
+            cpu.v[2] = 0b1010;
+            cpu.v[0xf] = 0x7;
+            cpu.run(opcode).unwrap();
+
+            assert_eq!(cpu.v[1], result, "{:04x}", opcode);
+            assert_eq!(cpu.v[0xf], 0x7, "Vf is untouched without the quirk");
+        }
+    }
+
+    #[test]
+    fn test_op_8xy1_8xy2_8xy3_vf_reset_quirk() {
+        for &opcode in [0x8121, 0x8122, 0x8123].iter() {
+            let mut cpu = Cpu::new();
+            cpu.set_quirks(Quirks {
+                vf_reset: true,
+                ..Quirks::default()
+            });
+            cpu.v[1] = 0b1100;
+            cpu.v[2] = 0b1010;
+            cpu.v[0xf] = 0x7;
+            cpu.run(opcode).unwrap();
+
+            assert_eq!(cpu.v[0xf], 0, "{:04x} clears Vf", opcode);
+        }
+    }
+
+    #[test]
+    fn test_vf_order_quirk() {
+        // 0x10 - 0x20 borrows: VF ends up 0 as the flag, 0xf0 as the result.
+        for &(vf_order, expected) in [(false, 0), (true, 0xf0)].iter() {
+            let mut cpu = Cpu::new();
+            cpu.set_quirks(Quirks {
+                vf_order,
+                ..Quirks::default()
+            });
+            cpu.v[0xf] = 0x10;
+            cpu.v[1] = 0x20;
+            cpu.run(0x8f15).unwrap();
+            assert_eq!(cpu.v[0xf], expected, "vf_order {}", vf_order);
+        }
+    }
+
+    #[test]
+    fn test_op_8xy5() {
+        let mut cpu = Cpu::new();
+        cpu.v[1] = 5;
+        cpu.v[2] = 3;
+        cpu.run(0x8125).unwrap();
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (2, 1), "no borrow sets Vf");
+
+        cpu.run(0x8125).unwrap();
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0xff, 0), "a borrow clears Vf");
+    }
+
+    #[test]
+    fn test_op_8xy7() {
+        let mut cpu = Cpu::new();
+        cpu.v[1] = 3;
+        cpu.v[2] = 5;
+        cpu.run(0x8127).unwrap();
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (2, 1), "Vx is set to Vy - Vx");
+
+        cpu.v[1] = 6;
+        cpu.run(0x8127).unwrap();
+        assert_eq!((cpu.v[1], cpu.v[0xf]), (0xff, 0), "a borrow clears Vf");
+    }
+
+    #[test]
+    fn test_op_8xy6() {
+        let mut cpu = Cpu::new();
+        cpu.v[1] = 0b10000001;
+        cpu.v[2] = 0b00000110;
+        cpu.run(0x8126).unwrap();
+
+        assert_eq!(cpu.v[0xf], 1, "Vf is set to the bit shifted out");
+        assert_eq!(cpu.v[1], 0b01000000, "Vx is set to Vx >> 1");
+        assert_eq!(cpu.v[2], 0b00000110, "Vy is untouched");
+    }
+
+    #[test]
+    fn test_op_8xy6_8xye_vip_shift() {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(Quirks {
+            shift: false,
+            ..Quirks::default()
+        });
+        cpu.v[1] = 0xff;
+        cpu.v[2] = 0b10000110;
+        cpu.run(0x8126).unwrap();
+
+        assert_eq!(cpu.v[1], 0b01000011, "Vx is set to Vy >> 1");
+        assert_eq!(cpu.v[0xf], 0);
+
+        cpu.run(0x812e).unwrap();
+        assert_eq!(cpu.v[1], 0b00001100, "Vx is set to Vy << 1");
+        assert_eq!(cpu.v[0xf], 1);
+        assert_eq!(cpu.v[2], 0b10000110, "Vy is untouched");
+    }
+
+    #[test]
+    fn test_op_8xye() {
+        let mut cpu = Cpu::new();
+        cpu.v[1] = 0b10000001;
+        cpu.run(0x812e).unwrap();
+
+        assert_eq!(cpu.v[0xf], 1, "Vf is set to carry");
+        assert_eq!(cpu.v[1], 0b00000010, "Vx is set to Vx << 1");
+    }
+
+    #[test]
+    fn test_op_annn() {
+        let mut cpu = Cpu::new();
+        cpu.run(0xa2f0).unwrap();
+
+        assert_eq!(cpu.i, 0x2f0, "I is set to nnn");
+        assert_eq!(cpu.pc, 0x200 + 2);
+    }
+
+    #[test]
+    fn test_op_bnnn() {
+        let mut cpu = Cpu::new();
+        cpu.v[0] = 0x12;
+        cpu.run(0xb300).unwrap();
+
+        assert_eq!(cpu.pc, 0x312, "PC jumps to nnn + V0");
+    }
+
+    #[test]
+    fn test_op_bxnn_jump_quirk() {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(Quirks {
+            jump: true,
+            ..Quirks::default()
+        });
+        cpu.v[0] = 0x12;
+        cpu.v[3] = 0x04;
+        cpu.run(0xb320).unwrap();
+
+        assert_eq!(cpu.pc, 0x324, "PC jumps to xnn + Vx");
+    }
+
+    #[test]
+    fn test_op_bnnn_zero_offset() {
+        let mut cpu = Cpu::new();
+        cpu.run(0xb456).unwrap();
+
+        assert_eq!(cpu.pc, 0x456, "PC jumps to nnn when V0 is zero");
+    }
+
+    #[test]
+    fn test_op_cxkk() {
+        let mut cpu = Cpu::with_rng(Box::new(SequenceRng(vec![0xab, 0xff], 0)));
+        cpu.run(0xc30f).unwrap();
+        assert_eq!(cpu.v[3], 0x0b, "Vx is set to rnd & kk");
+
+        cpu.run(0xc3f0).unwrap();
+        assert_eq!(cpu.v[3], 0xf0, "the next byte of the sequence is used");
+        assert_eq!(cpu.pc, 0x200 + 4);
+    }
+
+    #[test]
+    fn test_op_ex9e() {
+        let mut cpu = Cpu::new();
+        cpu.v[2] = 0x7;
+        cpu.run(0xe29e).unwrap();
+        assert_eq!(cpu.pc, 0x200 + 2, "key is up, no skip");
+
+        cpu.press_key(0x7);
+        cpu.run(0xe29e).unwrap();
+        assert_eq!(cpu.pc, 0x202 + 4, "key is down, skip");
+    }
+
+    #[test]
+    fn test_op_exa1() {
+        let mut cpu = Cpu::new();
+        cpu.v[2] = 0x7;
+        cpu.press_key(0x7);
+        cpu.run(0xe2a1).unwrap();
+        assert_eq!(cpu.pc, 0x200 + 2, "key is down, no skip");
+
+        cpu.release_key(0x7);
+        cpu.run(0xe2a1).unwrap();
+        assert_eq!(cpu.pc, 0x202 + 4, "key is up, skip");
+    }
+
+    #[test]
+    fn test_op_fx07() {
+        let mut cpu = Cpu::new();
+        cpu.timers.set_delay(0x3c);
+        cpu.run(0xf407).unwrap();
+
+        assert_eq!(cpu.v[4], 0x3c, "Vx is set to DT");
+        assert_eq!(cpu.pc, 0x200 + 2);
+    }
+
+    #[test]
+    fn test_op_fx0a() {
+        let mut cpu = Cpu::new();
+        cpu.run(0xf50a).unwrap();
+        assert!(cpu.is_waiting_for_key());
+        assert_eq!(cpu.pc, 0x200, "PC stays put while waiting");
+
+        cpu.press_key(0x9);
+        cpu.run(0xf50a).unwrap();
+        assert!(
+            cpu.is_waiting_for_key(),
+            "a press alone does not finish the wait"
+        );
+        assert_eq!(cpu.pc, 0x200);
+
+        cpu.release_key(0x9);
+        cpu.run(0xf50a).unwrap();
+        assert!(!cpu.is_waiting_for_key());
+        assert_eq!(cpu.v[5], 0x9, "Vx is set to the released key");
+        assert_eq!(cpu.pc, 0x200 + 2);
+    }
+
+    #[test]
+    fn test_op_fx0a_ignores_earlier_release() {
+        let mut cpu = Cpu::new();
+        cpu.press_key(0x1);
+        cpu.release_key(0x1);
+        cpu.run(0xf50a).unwrap();
+
+        assert!(
+            cpu.is_waiting_for_key(),
+            "release before Fx0A is not counted"
+        );
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn test_op_fx15() {
+        let mut cpu = Cpu::new();
+        cpu.v[4] = 0x3c;
+        cpu.run(0xf415).unwrap();
+
+        assert_eq!(cpu.timers.delay(), 0x3c, "DT is set to Vx");
+    }
+
+    #[test]
+    fn test_op_fx18() {
+        let mut cpu = Cpu::new();
+        assert!(!cpu.is_beeping());
+
+        cpu.v[4] = 0x3c;
+        cpu.run(0xf418).unwrap();
+
+        assert_eq!(cpu.sound_timer(), 0x3c, "ST is set to Vx");
+        assert!(cpu.is_beeping());
+    }
+
+    #[test]
+    fn test_op_fx1e() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0xffe;
+        cpu.v[1] = 0x4;
+        cpu.v[0xf] = 0x7;
+        cpu.run(0xf11e).unwrap();
+
+        assert_eq!(cpu.i, 0x1002, "I is set to I + Vx");
+        assert_eq!(cpu.v[0xf], 0x7, "Vf is untouched without the quirk");
+        assert_eq!(cpu.pc, 0x200 + 2);
+    }
+
+    #[test]
+    fn test_op_fx1e_overflow_quirk() {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(Quirks {
+            index_overflow: true,
+            ..Quirks::default()
+        });
+        cpu.i = 0xffe;
+        cpu.v[1] = 0x1;
+        cpu.run(0xf11e).unwrap();
+        assert_eq!(cpu.v[0xf], 0, "I is still within 0xFFF");
+
+        cpu.run(0xf11e).unwrap();
+        assert_eq!(cpu.i, 0x1000);
+        assert_eq!(cpu.v[0xf], 1, "Vf is set once I passes 0xFFF");
+    }
+
+    #[test]
+    fn test_op_fx30() {
+        let mut cpu = Cpu::new();
+
+        for digit in 0..16u8 {
+            cpu.v[2] = digit;
+            cpu.run(0xf230).unwrap();
+
+            let addr = cpu.i as usize;
+            assert_eq!(addr, 80 + digit as usize * 10);
+            let glyph = digit as usize * 10;
+            assert_eq!(
+                cpu.memory.bytes()[addr..addr + 10],
+                BIG_FONT_SET[glyph..glyph + 10],
+                "I points at the big glyph for {:x}",
+                digit
+            );
+        }
+    }
+
+    #[test]
+    fn test_op_5xy2_5xy3() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        cpu.v[2..5].copy_from_slice(&[0xa, 0xb, 0xc]);
+
+        cpu.run(0x5242).unwrap();
+        assert_eq!(cpu.memory.bytes()[0x300..0x303], [0xa, 0xb, 0xc]);
+        cpu.run(0x5422).unwrap();
+        assert_eq!(
+            cpu.memory.bytes()[0x300..0x303],
+            [0xc, 0xb, 0xa],
+            "x > y stores in reverse"
+        );
+        assert_eq!(cpu.i, 0x300, "I is unchanged");
+
+        cpu.run(0x5893).unwrap();
+        assert_eq!(cpu.v[8..10], [0xc, 0xb]);
+        cpu.run(0x5f73).unwrap();
+        assert_eq!(cpu.v[0xd..=0xf], [0xa, 0xb, 0xc]);
+    }
+
+    #[test]
+    fn test_set_preset() {
+        let mut cpu = Cpu::new();
+        cpu.set_preset(QuirkPreset::XoChip);
+        assert_eq!(cpu.quirks(), QuirkPreset::XoChip.quirks());
+        assert_eq!(cpu.platform(), Platform::XoChip);
+        assert_eq!(cpu.memory().size(), XOCHIP_RAM);
+
+        cpu.set_preset(QuirkPreset::Chip48);
+        assert_eq!(cpu.platform(), Platform::Chip8);
+        assert_eq!(cpu.memory().size(), CHIP8_RAM);
+    }
+
+    #[test]
+    fn test_op_f000_long_i() {
+        let rom = [
+            0xf0, 0x00, 0xff, 0xf0, // LD I, LONG 0xfff0
+            0x60, 0x01, // LD V0, 1
+            0x30, 0x01, // SE V0, 1
+            0xf0, 0x00, 0x12, 0x34, // skipped as a whole
+            0xf0, 0x55, // LD [I], V0
+        ];
+        let mut cpu = Cpu::builder()
+            .rom(&rom)
+            .preset(QuirkPreset::XoChip)
+            .build()
+            .unwrap();
+        assert_eq!(cpu.max_program_size(), XOCHIP_RAM - 0x200);
+
+        cpu.step().unwrap();
+        assert_eq!((cpu.i, cpu.pc), (0xfff0, 0x204));
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x20c, "the skip steps over all four bytes");
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory.bytes()[0xfff0], 1);
+
+        // With 4K of RAM the same address is out of reach.
+        let mut small = Cpu::builder().rom(&rom).build().unwrap();
+        small.run_until(3, |_| false).unwrap();
+        assert_eq!(
+            small.step().unwrap_err(),
+            Chip8Error::MemoryOutOfBounds {
+                addr: 0xfff0,
+                pc: 0x20c
             }
+        );
+    }
+
+    #[test]
+    fn test_op_f002_fx3a() {
+        let mut cpu = Cpu::new();
+        assert_eq!(*cpu.audio_pattern(), DEFAULT_AUDIO_PATTERN);
+        assert_eq!(cpu.pitch(), DEFAULT_PITCH);
+
+        let pattern: Vec<u8> = (0..16).collect();
+        cpu.memory.load(0x300, &pattern).unwrap();
+        cpu.i = 0x300;
+        cpu.v[5] = 112;
+        cpu.run(0xf002).unwrap();
+        cpu.run(0xf53a).unwrap();
+
+        assert_eq!(cpu.audio_pattern()[..], pattern[..]);
+        assert_eq!(cpu.pitch(), 112);
+
+        cpu.reset();
+        assert_eq!(*cpu.audio_pattern(), DEFAULT_AUDIO_PATTERN);
+        assert_eq!(cpu.pitch(), DEFAULT_PITCH);
+    }
+
+    #[test]
+    fn test_op_fn01_planes() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        cpu.memory.load(0x300, &[0x80, 0x40]).unwrap();
+
+        // Both planes: the first byte goes to plane 1, the second to plane 2.
+        cpu.run(0xf301).unwrap();
+        cpu.run(0xd011).unwrap();
+        assert_eq!((cpu.vram.pixel(0, 0), cpu.vram.pixel(1, 0)), (1, 2));
+        assert_eq!(cpu.v[0xf], 0);
+
+        // Plane 2 alone reads the sprite from I again and can collide.
+        cpu.run(0xf201).unwrap();
+        cpu.run(0xd011).unwrap();
+        assert_eq!((cpu.vram.pixel(0, 0), cpu.vram.pixel(1, 0)), (3, 2));
+        cpu.run(0xd011).unwrap();
+        assert_eq!(cpu.v[0xf], 1, "erasing a plane 2 pixel collides");
+
+        // CLS and the scrolls only touch the selected planes.
+        cpu.run(0xf101).unwrap();
+        cpu.run(0x00fb).unwrap();
+        assert_eq!((cpu.vram.pixel(0, 0), cpu.vram.pixel(4, 0)), (0, 1));
+        cpu.run(0xf201).unwrap();
+        cpu.run(0xd011).unwrap();
+        cpu.run(0x00e0).unwrap();
+        assert_eq!((cpu.vram.pixel(0, 0), cpu.vram.pixel(4, 0)), (0, 1));
+
+        cpu.reset();
+        assert_eq!(cpu.planes, 1);
+    }
+
+    #[test]
+    fn test_op_fx29() {
+        let mut cpu = Cpu::new();
+
+        for digit in 0..16u8 {
+            cpu.v[2] = digit;
+            cpu.run(0xf229).unwrap();
+
+            let addr = cpu.i as usize;
+            assert_eq!(
+                addr,
+                digit as usize * 5,
+                "I points at the glyph for {:x}",
+                digit
+            );
+            assert_eq!(cpu.memory.bytes()[addr..addr + 5], FONT_SET[addr..addr + 5]);
         }
     }
 
     #[test]
-    fn test_unimplemented_instruction_panics() {
-        let result = std::panic::catch_unwind(|| {
+    fn test_op_fx33() {
+        let cases = [(0, [0, 0, 0]), (9, [0, 0, 9]), (255, [2, 5, 5])];
+
+        for &(value, digits) in cases.iter() {
             let mut cpu = Cpu::new();
-            cpu.run(0x0022);
+            cpu.i = 0x300;
+            cpu.v[6] = value;
+            cpu.run(0xf633).unwrap();
+
+            assert_eq!(cpu.memory.bytes()[0x300..0x303], digits, "BCD of {}", value);
+            assert_eq!(cpu.i, 0x300, "I is unchanged");
+        }
+    }
+
+    #[test]
+    fn test_op_fx55() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        cpu.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+        cpu.run(0xf255).unwrap();
+
+        assert_eq!(
+            cpu.memory.bytes()[0x300..0x304],
+            [1, 2, 3, 0],
+            "V0..=V2 are stored"
+        );
+        assert_eq!(cpu.i, 0x300, "I is unchanged");
+    }
+
+    #[test]
+    fn test_op_fx65() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        cpu.memory.load(0x300, &[1, 2, 3, 4]).unwrap();
+        cpu.run(0xf265).unwrap();
+
+        assert_eq!(cpu.v[..4], [1, 2, 3, 0], "V0..=V2 are loaded");
+        assert_eq!(cpu.i, 0x300, "I is unchanged");
+    }
+
+    #[test]
+    fn test_op_fx55_fx65_memory_increment_quirk() {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(Quirks {
+            memory_increment: true,
+            ..Quirks::default()
         });
+        cpu.i = 0x300;
+        cpu.run(0xf255).unwrap();
+        assert_eq!(cpu.i, 0x303, "I is incremented by x + 1");
 
-        assert!(result.is_err());
+        cpu.run(0xf065).unwrap();
+        assert_eq!(cpu.i, 0x304);
     }
 
     #[test]
-    fn test_read_opcode() {
+    fn test_chip48_profile() {
+        let mut cpu = Cpu::builder()
+            .preset(QuirkPreset::Chip48)
+            .i(0x300)
+            .build()
+            .unwrap();
+
+        cpu.run(0xf255).unwrap();
+        assert_eq!(cpu.i, 0x302, "CHIP-48 increments I by x");
+
+        for &opcode in [0x00ff, 0x00c2, 0xf130, 0xf002].iter() {
+            assert_eq!(
+                cpu.run(opcode),
+                Err(Chip8Error::UnknownInstruction { opcode, pc: 0x202 }),
+                "{:04x} is not a CHIP-48 instruction",
+                opcode
+            );
+        }
+        assert_eq!(cpu.framebuffer().width(), CHIP8_WIDTH);
+    }
+
+    #[test]
+    fn test_two_page_hires() {
+        let mut rom = vec![0; 0xd0];
+        rom[..2].copy_from_slice(&[0x12, 0x60]); // JP 0x260
+        rom[0xc0..0xc8].copy_from_slice(&[
+            0x60, 0x3f, // LD V0, 63
+            0xd0, 0x01, // DRW V0, V0, 1
+            0x02, 0x30, // SYS 0x230
+            0x12, 0xc6, // JP 0x2c6
+        ]);
+        let mut cpu = Cpu::builder()
+            .rom(&rom)
+            .preset(QuirkPreset::HiresChip8)
+            .build()
+            .unwrap();
+        cpu.set_quirks(Quirks {
+            display_wait: false,
+            ..cpu.quirks()
+        });
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x2c0);
+        assert_eq!((cpu.vram.width(), cpu.vram.height()), (64, 64));
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.v[0xf], 0);
+        assert!(
+            cpu.vram.get(63, 63),
+            "row 63 is on screen, the sprite byte at I is 0xf0"
+        );
+        assert_eq!(cpu.run_until(10, |_| false), Ok(StopReason::Halted));
+        assert_eq!(cpu.vram, Framebuffer::with_size(64, 64), "SYS 0x230 clears");
+
+        let mut plain = Cpu::builder().rom(&rom).build().unwrap();
+        plain.step().unwrap();
+        assert_eq!(plain.pc, 0x260, "an ordinary interpreter just jumps");
+    }
+
+    #[cfg(feature = "megachip")]
+    #[test]
+    fn test_megachip() {
         let mut cpu = Cpu::new();
+        cpu.set_sys_policy(SysPolicy::Ignore);
+        cpu.run(0x0302).unwrap();
+        assert_eq!(cpu.megachip().sprite_size(), (0, 0), "SYS before MEGAON");
+
+        cpu.memory.load(0x300, &[0xff, 0xff, 0x00, 0x00]).unwrap();
+        cpu.memory.load(0x310, &[1, 0, 1, 1]).unwrap();
+        cpu.run(0x0011).unwrap();
+        cpu.i = 0x300;
+        cpu.run(0x0201).unwrap();
+        cpu.run(0x0302).unwrap();
+        cpu.run(0x0402).unwrap();
+        cpu.run(0x0901).unwrap();
+        assert_eq!(cpu.vram.width(), MEGA_WIDTH);
+
+        cpu.i = 0x310;
+        cpu.v[0] = 10;
+        cpu.run(0xd001).unwrap();
+        assert_eq!(cpu.v[0xf], 0);
+        assert_eq!((cpu.vram.pixel(10, 10), cpu.vram.pixel(11, 10)), (1, 0));
+        cpu.run(0xd001).unwrap();
+        assert_eq!(cpu.v[0xf], 1, "drawn over the collision colour");
+
+        assert!(cpu.megachip().frame().iter().all(|&pixel| pixel == 0));
+        cpu.run(0x00e0).unwrap();
+        let frame = cpu.megachip().frame();
+        assert_eq!(frame[10 * MEGA_WIDTH + 10], 0xffff_0000, "CLS presents");
+        assert_eq!(frame[11 * MEGA_WIDTH + 11], 0xffff_0000);
+        assert_eq!(frame[10 * MEGA_WIDTH + 11], 0, "index 0 is transparent");
+
+        // LDHI is four bytes long, also when skipped.
+        cpu.pc = 0x400;
+        cpu.memory.load(0x400, &[0x01, 0x01, 0x23, 0x45]).unwrap();
+        cpu.step().unwrap();
+        assert_eq!((cpu.megachip().address(cpu.i), cpu.pc), (0x012345, 0x404));
+        cpu.memory.load(0x404, &[0x30, 0x0a, 0x01, 0x00]).unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x40a);
+
+        cpu.run(0x0010).unwrap();
+        assert_eq!(cpu.vram.width(), CHIP8_WIDTH);
+    }
+
+    #[test]
+    fn test_run_vip_frame() {
+        // ADD V0, 1; JP 0x200: 50 + 52 machine cycles a round.
+        let mut cpu = Cpu::builder()
+            .rom(&[0x70, 0x01, 0x12, 0x00])
+            .build()
+            .unwrap();
+
+        cpu.run_vip_frame().unwrap();
+        assert_eq!(cpu.v[0], 26);
+        assert_eq!(cpu.cycle_balance, -8, "the last jump ran over");
+        cpu.run_vip_frame().unwrap();
+        assert_eq!(cpu.v[0], 52);
+
+        // A frame spent waiting for a key doesn't bank cycles.
+        let mut waiting = Cpu::builder().rom(&[0xf0, 0x0a]).build().unwrap();
+        waiting.run_vip_frame().unwrap();
+        assert_eq!(waiting.cycle_balance, 0);
+    }
 
-        cpu.ram[0x200] = 0xB1;
-        cpu.ram[0x201] = 0x5A;
+    #[test]
+    fn test_memory_increment_quirk_programs() {
+        // LD I, 0x300; LD V0, 0xaa; LD [I], V0; LD [I], V0; LD V1, [I]
+        let rom = [0xa3, 0x00, 0x60, 0xaa, 0xf0, 0x55, 0xf0, 0x55, 0xf1, 0x65];
+        let run = |memory_increment| {
+            let mut cpu = Cpu::builder()
+                .rom(&rom)
+                .quirks(Quirks {
+                    memory_increment,
+                    ..Quirks::default()
+                })
+                .build()
+                .unwrap();
+            for _ in 0..5 {
+                cpu.step().unwrap();
+            }
+            cpu
+        };
 
-        let opcode: u16 = cpu.read_opcode();
+        let vip = run(true);
         assert_eq!(
-            opcode, 0xB15A,
-            "the opcode was correctly read using two bytes starting at PC."
+            vip.memory.bytes()[0x300..0x302],
+            [0xaa, 0xaa],
+            "stores advance"
+        );
+        assert_eq!(vip.i, 0x304);
+        assert_eq!(vip.v[..2], [0, 0], "the load reads past the stored bytes");
+
+        let modern = run(false);
+        assert_eq!(
+            modern.memory.bytes()[0x300..0x302],
+            [0xaa, 0x00],
+            "stores overwrite"
+        );
+        assert_eq!(modern.i, 0x300);
+        assert_eq!(
+            modern.v[..2],
+            [0xaa, 0x00],
+            "the load reads the stored byte back"
         );
     }
 
     #[test]
-    fn test_op_8xye() {
+    fn test_op_fx75_fx85() {
         let mut cpu = Cpu::new();
-        cpu.v[1] = 0b10000001;
-        cpu.run(0x812e);
+        cpu.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+        cpu.run(0xf275).unwrap();
+        assert_eq!(cpu.rpl_flags()[..4], [1, 2, 3, 0], "V0..=V2 are saved");
 
-        assert_eq!(cpu.v[0xf], 1, "Vf is set to carry");
-        assert_eq!(cpu.v[1], 0b00000010, "Vx is set to Vx << 1");
+        cpu.reset();
+        assert_eq!(cpu.rpl_flags()[..3], [1, 2, 3], "flags survive a reset");
+        cpu.run(0xf185).unwrap();
+        assert_eq!(cpu.v[..3], [1, 2, 0], "V0..=V1 are restored");
+        assert_eq!(cpu.pc, 0x200 + 2);
+    }
+
+    #[test]
+    fn test_op_dxyn() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        cpu.memory.write8(0x300, 0b11000011).unwrap();
+        cpu.memory.write8(0x301, 0b00111100).unwrap();
+        cpu.v[0] = 4;
+        cpu.v[1] = 2;
+        cpu.run(0xd012).unwrap();
+
+        assert_eq!(cpu.pc, 0x200 + 2);
+        assert_eq!(cpu.v[0xf], 0, "no pixels were erased");
+        assert_eq!(cpu.vram.row(2)[4..12], [1, 1, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(cpu.vram.row(3)[4..12], [0, 0, 1, 1, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_op_dxyn_collision() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        cpu.memory.write8(0x300, 0b11110000).unwrap();
+        cpu.run(0xd001).unwrap();
+        cpu.run(0xd001).unwrap();
+
+        assert_eq!(cpu.v[0xf], 1, "Vf is set when a lit pixel is erased");
+        assert_eq!(
+            cpu.vram.row(0)[0..8],
+            [0; 8],
+            "drawing twice erases the sprite"
+        );
+    }
+
+    #[test]
+    fn test_op_dxyn_wraps() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        cpu.memory.write8(0x300, 0b11111111).unwrap();
+        cpu.memory.write8(0x301, 0b11111111).unwrap();
+        cpu.v[0] = 60;
+        cpu.v[1] = 31;
+        cpu.run(0xd012).unwrap();
+
+        assert_eq!(cpu.vram.row(31)[60..64], [1; 4]);
+        assert_eq!(cpu.vram.row(31)[0..4], [1; 4], "x wraps to the left edge");
+        assert_eq!(cpu.vram.row(0)[60..64], [1; 4], "y wraps to the top edge");
+        assert_eq!(cpu.vram.row(0)[0..4], [1; 4]);
+    }
+
+    #[test]
+    fn test_op_dxy0_hires() {
+        let mut cpu = Cpu::new();
+        cpu.i = 0x300;
+        let mut sprite = [0u8; 32];
+        sprite[0] = 0b10000000;
+        sprite[1] = 0b00000001;
+        sprite[31] = 0b00000001;
+        cpu.memory.load(0x300, &sprite).unwrap();
+
+        cpu.run(0xd000).unwrap();
+        assert!(!cpu.vram.get(0, 0), "DXY0 draws nothing in lores");
+
+        cpu.run(0x00ff).unwrap();
+        cpu.v[0] = 8;
+        cpu.v[1] = 4;
+        cpu.run(0xd010).unwrap();
+        assert!(cpu.vram.get(8, 4));
+        assert!(cpu.vram.get(23, 4), "rows are 16 pixels wide");
+        assert!(cpu.vram.get(23, 19), "there are 16 rows");
+        assert_eq!(cpu.v[0xf], 0);
+
+        cpu.run(0xd010).unwrap();
+        assert_eq!(cpu.v[0xf], 1, "erasing a 16x16 sprite collides");
+        assert!(!cpu.vram.get(23, 19));
+    }
+
+    // Draw a solid 8x4 sprite at (124, 62), off the bottom right
+    // corner, and render the screen as text: '#' lit, '.' dark.
+    fn render_corner_sprite(quirks: Quirks) -> String {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(quirks);
+        cpu.i = 0x300;
+        cpu.memory.load(0x300, &[0xff; 4]).unwrap();
+        cpu.v[0] = 124;
+        cpu.v[1] = 62;
+        cpu.run(0xd014).unwrap();
+
+        let mut text = String::new();
+        for y in 0..CHIP8_HEIGHT {
+            for &pixel in cpu.vram.row(y) {
+                text.push(if pixel == 1 { '#' } else { '.' });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    #[test]
+    fn test_op_dxyn_wrap_golden() {
+        assert_eq!(
+            render_corner_sprite(Quirks::default()),
+            include_str!("../testdata/dxyn_wrap.txt")
+        );
+    }
+
+    #[test]
+    fn test_op_dxyn_clip_golden() {
+        let quirks = Quirks {
+            clipping: true,
+            ..Quirks::default()
+        };
+        assert_eq!(
+            render_corner_sprite(quirks),
+            include_str!("../testdata/dxyn_clip.txt")
+        );
+    }
+
+    #[test]
+    fn test_save_state() {
+        let mut cpu = Cpu::builder()
+            .rom(&[0x61, 0x2a, 0x22, 0x08, 0x00, 0xe0, 0x12, 0x06, 0xd0, 0x05])
+            .build()
+            .unwrap();
+        cpu.run_frame(3).unwrap();
+        cpu.timers.set_delay(9);
+        let state = cpu.save_state();
+
+        let mut restored = Cpu::new();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.pc, cpu.pc);
+        assert_eq!(restored.stack(), &[0x204]);
+        assert_eq!(restored.v, cpu.v);
+        assert_eq!(restored.timers.delay(), 9);
+        assert_eq!(restored.vram, cpu.vram);
+        assert_eq!(restored.memory.bytes(), cpu.memory.bytes());
+        assert_eq!(restored.save_state(), state);
+
+        assert_eq!(
+            restored.load_state(&state[..state.len() - 1]),
+            Err(Chip8Error::InvalidSaveState {
+                reason: "truncated"
+            })
+        );
+        assert_eq!(
+            restored.load_state(b"C8ST\x09"),
+            Err(Chip8Error::InvalidSaveState {
+                reason: "unsupported version"
+            })
+        );
+        assert_eq!(restored.pc, cpu.pc, "a refused state changes nothing");
+    }
+
+    #[test]
+    fn test_load_state_rejects_malformed() {
+        let state = Cpu::new().save_state();
+        // The screen size follows the registers; the memory size follows
+        // the 64x32 pixels.
+        let screen = 99;
+        let memory = screen + 4 + CHIP8_WIDTH * CHIP8_HEIGHT;
+        let invalid = |reason| Err(Chip8Error::InvalidSaveState { reason });
+
+        for &(width, height) in
+            [(0u16, 0u16), (0, 32), (64, 0), (100, 100), (0xffff, 0xffff)].iter()
+        {
+            let mut bad = state[..screen].to_vec();
+            bad.extend_from_slice(&width.to_be_bytes());
+            bad.extend_from_slice(&height.to_be_bytes());
+            bad.extend_from_slice(&state[screen + 4..]);
+            let mut cpu = Cpu::new();
+            assert_eq!(
+                cpu.load_state(&bad),
+                invalid("unsupported resolution"),
+                "{}x{}",
+                width,
+                height
+            );
+            assert_eq!(cpu.framebuffer().width(), CHIP8_WIDTH);
+        }
+
+        let mut small = state[..memory].to_vec();
+        small.extend_from_slice(&0x100u32.to_be_bytes());
+        small.extend_from_slice(&[0; 0x100]);
+        assert_eq!(Cpu::new().load_state(&small), invalid("out of range"));
+    }
+
+    #[test]
+    fn test_op_dxyn_clipped_collision() {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(Quirks {
+            clipping: true,
+            ..Quirks::default()
+        });
+        cpu.i = 0x300;
+        cpu.memory.write8(0x300, 0b11110000).unwrap();
+        cpu.vram.toggle(0, 0);
+        cpu.v[0] = 62;
+        cpu.run(0xd001).unwrap();
+
+        assert_eq!(cpu.v[0xf], 0, "clipped pixels don't collide");
+        assert!(cpu.vram.get(0, 0));
+    }
+}
+
+// Runs in the no_std build too, which the other tests need std for.
+#[cfg(test)]
+mod core_test {
+    use super::*;
+
+    #[test]
+    fn test_run_rom() {
+        // LD V0, 5; LD F, V0; DRW V0, V1, 5; CALL 0x20c; RND V2, 0xff;
+        // JP 0x20a; RET
+        let rom = [
+            0x60, 0x05, 0xf0, 0x29, 0xd0, 0x15, 0x22, 0x0c, 0xc2, 0xff, 0x12, 0x0a, 0x00, 0xee,
+        ];
+        let mut cpu = Cpu::with_seed(7);
+        cpu.set_preset(QuirkPreset::XoChip);
+        cpu.load_program(&rom).unwrap();
+
+        let mut steps = 0;
+        while cpu.step().unwrap() != State::Halted {
+            steps += 1;
+        }
+        assert_eq!(steps, 6);
+        assert_eq!(cpu.pc(), 0x20a);
+        assert_eq!(cpu.memory().size(), crate::memory::XOCHIP_RAM);
+        assert!(cpu.framebuffer().get(5, 0) && !cpu.framebuffer().get(9, 0));
+
+        let mut same = Cpu::with_seed(7);
+        same.load_program(&rom).unwrap();
+        same.run_until(10, |cpu| cpu.pc() == 0x20a).unwrap();
+        assert_eq!(
+            same.registers()[2],
+            cpu.registers()[2],
+            "same seed, same RND"
+        );
     }
 }
@@ -0,0 +1,286 @@
+// Behaviors that differ between CHIP-8 interpreters. Each flag picks one of
+// the two historical behaviors of the opcodes it names; the default matches
+// what this emulator has always done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Quirks {
+    // 8xy6/8xyE shift Vx in place and ignore y (CHIP-48 and later). Off, Vy
+    // is shifted into Vx (COSMAC VIP).
+    pub shift: bool,
+    // Fx55/Fx65 leave I at I + x + 1 (COSMAC VIP). Off, I is unchanged.
+    pub memory_increment: bool,
+    // With memory_increment, I is left at I + x, one short (CHIP-48).
+    pub memory_increment_by_x: bool,
+    // Bnnn jumps to nnn + Vx, with x the high nibble of nnn (CHIP-48 BXNN).
+    // Off, it jumps to nnn + V0.
+    pub jump: bool,
+    // 8xy1/8xy2/8xy3 clear VF (COSMAC VIP).
+    pub vf_reset: bool,
+    // Sprites are clipped at the screen edges. Off, they wrap around.
+    pub clipping: bool,
+    // Dxyn waits for the start of the next frame (COSMAC VIP vblank).
+    pub display_wait: bool,
+    // Fx1E sets VF when I goes past 0xFFF (Amiga interpreter).
+    pub index_overflow: bool,
+    // In the 64x32 mode, 00CN/00FB/00FC scroll by half as many pixels, as
+    // SUPER-CHIP 1.1 did by scrolling its 128x64 screen. Odd amounts round
+    // down.
+    pub half_scroll: bool,
+    // 8xy4/8xy5/8xy6/8xy7/8xyE write VF before the result, so with x = F
+    // the result is kept rather than the flag (Octo's vF order quirk).
+    pub vf_order: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift: true,
+            memory_increment: false,
+            memory_increment_by_x: false,
+            jump: false,
+            vf_reset: false,
+            clipping: false,
+            display_wait: false,
+            index_overflow: false,
+            half_scroll: false,
+            vf_order: false,
+        }
+    }
+}
+
+impl Quirks {
+    // Names of the flags, for settings a frontend edits at runtime.
+    pub const NAMES: [&'static str; 10] = [
+        "shift",
+        "memory_increment",
+        "memory_increment_by_x",
+        "jump",
+        "vf_reset",
+        "clipping",
+        "display_wait",
+        "index_overflow",
+        "half_scroll",
+        "vf_order",
+    ];
+
+    pub fn new() -> Self {
+        Quirks::default()
+    }
+
+    // The flag called `name`, one of `NAMES`.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        let mut quirks = *self;
+        quirks.flag(name).map(|flag| *flag)
+    }
+
+    // Set the flag called `name`. Returns false if there is none.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        match self.flag(name) {
+            Some(flag) => {
+                *flag = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        Some(match name {
+            "shift" => &mut self.shift,
+            "memory_increment" => &mut self.memory_increment,
+            "memory_increment_by_x" => &mut self.memory_increment_by_x,
+            "jump" => &mut self.jump,
+            "vf_reset" => &mut self.vf_reset,
+            "clipping" => &mut self.clipping,
+            "display_wait" => &mut self.display_wait,
+            "index_overflow" => &mut self.index_overflow,
+            "half_scroll" => &mut self.half_scroll,
+            "vf_order" => &mut self.vf_order,
+            _ => return None,
+        })
+    }
+}
+
+use crate::memory::{CHIP8_RAM, XOCHIP_RAM};
+use crate::platform::Platform;
+
+// Quirk settings of well-known interpreters, to configure the whole
+// `Quirks` struct for the platform a ROM targets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QuirkPreset {
+    // The COSMAC VIP interpreter (1977).
+    OriginalChip8,
+    // The COSMAC VIP two-page hires interpreter with its 64x64 screen.
+    HiresChip8,
+    // CHIP-48 on the HP 48 calculators.
+    Chip48,
+    // SUPER-CHIP 1.1 as it behaved on the HP 48.
+    SuperChipLegacy,
+    // SUPER-CHIP as implemented by modern interpreters, without the
+    // display wait of the calculator version.
+    SuperChipModern,
+    // XO-CHIP as defined by Octo.
+    XoChip,
+}
+
+impl QuirkPreset {
+    pub const ALL: [QuirkPreset; 6] = [
+        QuirkPreset::OriginalChip8,
+        QuirkPreset::HiresChip8,
+        QuirkPreset::Chip48,
+        QuirkPreset::SuperChipLegacy,
+        QuirkPreset::SuperChipModern,
+        QuirkPreset::XoChip,
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        let none = Quirks {
+            shift: false,
+            memory_increment: false,
+            memory_increment_by_x: false,
+            jump: false,
+            vf_reset: false,
+            clipping: false,
+            display_wait: false,
+            index_overflow: false,
+            half_scroll: false,
+            vf_order: false,
+        };
+
+        match self {
+            QuirkPreset::OriginalChip8 | QuirkPreset::HiresChip8 => Quirks {
+                memory_increment: true,
+                vf_reset: true,
+                clipping: true,
+                display_wait: true,
+                ..none
+            },
+            QuirkPreset::Chip48 => Quirks {
+                shift: true,
+                memory_increment: true,
+                memory_increment_by_x: true,
+                jump: true,
+                clipping: true,
+                ..none
+            },
+            QuirkPreset::SuperChipLegacy => Quirks {
+                shift: true,
+                jump: true,
+                clipping: true,
+                display_wait: true,
+                half_scroll: true,
+                ..none
+            },
+            QuirkPreset::SuperChipModern => Quirks {
+                shift: true,
+                jump: true,
+                clipping: true,
+                ..none
+            },
+            QuirkPreset::XoChip => Quirks {
+                memory_increment: true,
+                ..none
+            },
+        }
+    }
+
+    // Instruction set the interpreter understands. CHIP-48 predates the
+    // SUPER-CHIP additions, so it has none of the hires graphics.
+    pub fn platform(self) -> Platform {
+        match self {
+            QuirkPreset::OriginalChip8 | QuirkPreset::HiresChip8 | QuirkPreset::Chip48 => {
+                Platform::Chip8
+            }
+            QuirkPreset::SuperChipLegacy | QuirkPreset::SuperChipModern => Platform::SuperChip,
+            QuirkPreset::XoChip => Platform::XoChip,
+        }
+    }
+
+    // Whether the interpreter is the two-page hires CHIP-8, see
+    // `Cpu::set_two_page_hires`.
+    pub fn two_page_hires(self) -> bool {
+        self == QuirkPreset::HiresChip8
+    }
+
+    // Bytes of memory the platform gives programs.
+    pub fn memory_size(self) -> usize {
+        match self {
+            QuirkPreset::XoChip => XOCHIP_RAM,
+            _ => CHIP8_RAM,
+        }
+    }
+
+    // Short name, as accepted by `from_name` (e.g. for a --platform flag).
+    pub fn name(self) -> &'static str {
+        match self {
+            QuirkPreset::OriginalChip8 => "chip8",
+            QuirkPreset::HiresChip8 => "chip8-hires",
+            QuirkPreset::Chip48 => "chip48",
+            QuirkPreset::SuperChipLegacy => "schip-legacy",
+            QuirkPreset::SuperChipModern => "schip",
+            QuirkPreset::XoChip => "xochip",
+        }
+    }
+
+    // The preset called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<QuirkPreset> {
+        QuirkPreset::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl From<QuirkPreset> for Quirks {
+    fn from(preset: QuirkPreset) -> Quirks {
+        preset.quirks()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        let vip = QuirkPreset::OriginalChip8.quirks();
+        assert!(!vip.shift && vip.memory_increment && vip.vf_reset && vip.display_wait);
+
+        let schip = Quirks::from(QuirkPreset::SuperChipModern);
+        assert!(schip.shift && schip.jump && schip.clipping && !schip.display_wait);
+        assert!(QuirkPreset::SuperChipLegacy.quirks().display_wait);
+
+        let xo = QuirkPreset::XoChip.quirks();
+        assert!(!xo.shift && !xo.jump && !xo.clipping && xo.memory_increment);
+
+        let chip48 = QuirkPreset::Chip48.quirks();
+        assert!(chip48.shift && chip48.jump && chip48.memory_increment_by_x);
+    }
+
+    #[test]
+    fn test_preset_machines() {
+        assert_eq!(QuirkPreset::XoChip.memory_size(), XOCHIP_RAM);
+        assert_eq!(QuirkPreset::Chip48.memory_size(), CHIP8_RAM);
+        assert_eq!(QuirkPreset::Chip48.platform(), Platform::Chip8);
+    }
+
+    #[test]
+    fn test_names() {
+        let mut quirks = Quirks::new();
+        for name in Quirks::NAMES.iter() {
+            assert!(quirks.set(name, true));
+            assert_eq!(quirks.get(name), Some(true));
+        }
+        assert!(quirks.memory_increment_by_x && quirks.half_scroll && quirks.vf_order);
+        assert!(!quirks.set("wrap", true));
+        assert_eq!(quirks.get("wrap"), None);
+    }
+
+    #[test]
+    fn test_from_name() {
+        for &preset in QuirkPreset::ALL.iter() {
+            assert_eq!(QuirkPreset::from_name(preset.name()), Some(preset));
+        }
+        assert_eq!(QuirkPreset::from_name("XOCHIP"), Some(QuirkPreset::XoChip));
+        assert_eq!(QuirkPreset::from_name("megachip"), None);
+    }
+}
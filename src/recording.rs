@@ -0,0 +1,581 @@
+use crate::framebuffer::Framebuffer;
+use crate::palette::Palette;
+use crate::png::{timestamp, write_chunk, zlib_stored, PNG_SIGNATURE};
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+// Frames a second the machine runs at, and so the recordings.
+const RECORDING_FPS: u32 = 60;
+
+// Shortest frame time in a GIF, in hundredths of a second. Browsers show
+// frames with a delay below 2 for a tenth of a second instead, so faster
+// frames are merged.
+const GIF_MIN_DELAY: u32 = 2;
+
+// Largest code in a GIF's LZW stream.
+const LZW_MAX_CODE: u16 = 4095;
+
+// The formats a `Recorder` writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+    // Plays everywhere, but only shows frames at up to 50 a second.
+    Gif,
+    // Animated PNG: every frame at its exact time, in current browsers.
+    Apng,
+}
+
+impl RecordingFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            RecordingFormat::Gif => "gif",
+            RecordingFormat::Apng => "apng",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RecordingFormat> {
+        [RecordingFormat::Gif, RecordingFormat::Apng]
+            .iter()
+            .copied()
+            .find(|format| format.name() == name)
+    }
+
+    // The usual file extension; APNGs are PNG files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordingFormat::Gif => "gif",
+            RecordingFormat::Apng => "png",
+        }
+    }
+}
+
+// A file name for a recording started at `time`, like
+// "chip8-20240131-235959.gif", in UTC.
+pub fn recording_name(time: SystemTime, format: RecordingFormat) -> String {
+    format!("chip8-{}.{}", timestamp(time), format.extension())
+}
+
+// One screen of a recording and how many frames it was shown for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    frames: u32,
+}
+
+// Records the screen into an animated GIF or APNG, for sharing clips and
+// bug reports. Call capture() once every 60Hz frame, changed or not, then
+// finish(). A frame repeating the one before only makes it last longer,
+// and each frame stores just the rectangle that changed.
+//
+// The animation takes the largest resolution the program used; frames at
+// a lower one are scaled up to it. Each pixel is `scale` pixels square and
+// coloured with `palette`.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    format: RecordingFormat,
+    palette: Palette,
+    scale: usize,
+    frames: Vec<Frame>,
+}
+
+impl Recorder {
+    pub fn new(format: RecordingFormat, palette: Palette, scale: usize) -> Self {
+        Recorder {
+            format,
+            palette,
+            scale: scale.max(1),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn format(&self) -> RecordingFormat {
+        self.format
+    }
+
+    // Take in the screen as it is this frame.
+    pub fn capture(&mut self, fb: &Framebuffer) {
+        if let Some(last) = self.frames.last_mut() {
+            if (last.width, last.height) == (fb.width(), fb.height()) && last.pixels == fb.pixels()
+            {
+                last.frames += 1;
+                return;
+            }
+        }
+        self.frames.push(Frame {
+            width: fb.width(),
+            height: fb.height(),
+            pixels: fb.pixels().to_vec(),
+            frames: 1,
+        });
+    }
+
+    // How many 60Hz frames have been captured.
+    pub fn frame_count(&self) -> u32 {
+        self.frames.iter().map(|frame| frame.frames).sum()
+    }
+
+    // The recording as a GIF or APNG file. Empty if nothing was captured.
+    pub fn finish(&self) -> Vec<u8> {
+        let width = self.frames.iter().map(|frame| frame.width).max();
+        let height = self.frames.iter().map(|frame| frame.height).max();
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Vec::new(),
+        };
+
+        // Every frame at the full resolution, identical ones merged.
+        let mut frames: Vec<Frame> = Vec::new();
+        for frame in &self.frames {
+            let pixels = resample(frame, width, height);
+            match frames.last_mut() {
+                Some(last) if last.pixels == pixels => last.frames += frame.frames,
+                _ => frames.push(Frame {
+                    width,
+                    height,
+                    pixels,
+                    frames: frame.frames,
+                }),
+            }
+        }
+
+        let canvas = Canvas {
+            width,
+            height,
+            scale: self.scale,
+            colors: *self.palette.colors(),
+        };
+        match self.format {
+            RecordingFormat::Gif => encode_gif(&canvas, &frames),
+            RecordingFormat::Apng => encode_apng(&canvas, &frames),
+        }
+    }
+}
+
+// The size and colours of the animation.
+struct Canvas {
+    width: usize,
+    height: usize,
+    scale: usize,
+    colors: [[u8; 3]; 4],
+}
+
+// A part of the canvas, in emulated pixels: x, y, width and height.
+type Rect = (usize, usize, usize, usize);
+
+impl Canvas {
+    // The smallest rectangle holding every pixel that differs between
+    // `before` and `after`, or a single pixel if none does.
+    fn changed(&self, before: &[u8], after: &[u8]) -> Rect {
+        let (mut left, mut top, mut right, mut bottom) = (self.width, self.height, 0, 0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if before[y * self.width + x] != after[y * self.width + x] {
+                    left = left.min(x);
+                    right = right.max(x + 1);
+                    top = top.min(y);
+                    bottom = bottom.max(y + 1);
+                }
+            }
+        }
+        match left < right {
+            true => (left, top, right - left, bottom - top),
+            false => (0, 0, 1, 1),
+        }
+    }
+
+    // The pixel values of `rect`, scaled, row by row.
+    fn region(&self, pixels: &[u8], (x, y, width, height): Rect) -> Vec<u8> {
+        let scale = self.scale;
+        let mut out = Vec::with_capacity(width * height * scale * scale);
+        for row in y..y + height {
+            let start = out.len();
+            for &pixel in &pixels[row * self.width + x..row * self.width + x + width] {
+                for _ in 0..scale {
+                    out.push(pixel & 3);
+                }
+            }
+            for _ in 1..scale {
+                out.extend_from_within(start..start + width * scale);
+            }
+        }
+        out
+    }
+}
+
+// A frame's pixel values at `width` x `height`, by nearest neighbour.
+fn resample(frame: &Frame, width: usize, height: usize) -> Vec<u8> {
+    if (frame.width, frame.height) == (width, height) {
+        return frame.pixels.clone();
+    }
+    let mut out = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = y * frame.height / height * frame.width;
+        for x in 0..width {
+            out.push(frame.pixels[row + x * frame.width / width]);
+        }
+    }
+    out
+}
+
+// GIF89a, looping forever. Frame times are in hundredths of a second, so
+// the 60ths are rounded, and frames shorter than GIF_MIN_DELAY dropped.
+fn encode_gif(canvas: &Canvas, frames: &[Frame]) -> Vec<u8> {
+    let hundredths = |frame: u32| (frame * 100 + RECORDING_FPS / 2) / RECORDING_FPS;
+    let total: u32 = frames.iter().map(|frame| frame.frames).sum();
+
+    // The frames kept and the 60ths they start at.
+    let mut kept: Vec<(&Frame, u32)> = Vec::new();
+    let mut start = 0;
+    for frame in frames {
+        let long_enough = kept
+            .last()
+            .is_none_or(|&(_, at)| hundredths(start) - hundredths(at) >= GIF_MIN_DELAY);
+        if long_enough {
+            kept.push((frame, start));
+        }
+        start += frame.frames;
+    }
+
+    let (width, height) = (canvas.width * canvas.scale, canvas.height * canvas.scale);
+    let mut gif = b"GIF89a".to_vec();
+    gif.extend_from_slice(&(width as u16).to_le_bytes());
+    gif.extend_from_slice(&(height as u16).to_le_bytes());
+    // A global colour table of 4 colours, background colour 0.
+    gif.extend_from_slice(&[0xf1, 0, 0]);
+    for color in &canvas.colors {
+        gif.extend_from_slice(color);
+    }
+    // The NETSCAPE2.0 extension: loop forever.
+    gif.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+    for (n, &(frame, at)) in kept.iter().enumerate() {
+        let end = kept.get(n + 1).map_or(total, |&(_, next)| next);
+        let delay = (hundredths(end) - hundredths(at)).max(GIF_MIN_DELAY);
+        let rect = match n {
+            0 => (0, 0, canvas.width, canvas.height),
+            _ => canvas.changed(&kept[n - 1].0.pixels, &frame.pixels),
+        };
+        // Graphic control: leave the frame in place for the next one.
+        gif.extend_from_slice(&[0x21, 0xf9, 0x04, 0x04]);
+        gif.extend_from_slice(&(delay as u16).to_le_bytes());
+        gif.extend_from_slice(&[0, 0]);
+
+        let (x, y, w, h) = rect;
+        gif.push(0x2c);
+        for value in [x, y, w, h] {
+            gif.extend_from_slice(&((value * canvas.scale) as u16).to_le_bytes());
+        }
+        gif.push(0);
+        // Codes start at 3 bits for the 4 colours.
+        gif.push(2);
+        for block in lzw_encode(2, &canvas.region(&frame.pixels, rect)).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.push(0);
+    }
+    gif.push(0x3b);
+    gif
+}
+
+// GIF's variable width LZW of `indices`, with codes packed LSB first.
+fn lzw_encode(min_code_size: u8, indices: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut bits = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut width = min_code_size as u32 + 1;
+
+    bits.write(clear, width);
+    let mut rest = indices.iter();
+    let mut prefix = match rest.next() {
+        Some(&index) => index as u16,
+        None => {
+            bits.write(end, width);
+            return bits.finish();
+        }
+    };
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        bits.write(prefix, width);
+        if next <= LZW_MAX_CODE {
+            table.insert((prefix, index), next);
+            next += 1;
+            // The decoder adds each code one step later, so it only
+            // widens once `next` is past what the width holds.
+            if next > 1 << width && width < 12 {
+                width += 1;
+            }
+        } else {
+            bits.write(clear, width);
+            table.clear();
+            next = end + 1;
+            width = min_code_size as u32 + 1;
+        }
+        prefix = index as u16;
+    }
+    bits.write(prefix, width);
+    bits.write(end, width);
+    bits.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u32) {
+        self.buffer |= (code as u32) << self.count;
+        self.count += width;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// An APNG with 2-bit palette pixels, looping forever. The first frame is
+// also the still image shown by viewers without APNG support.
+fn encode_apng(canvas: &Canvas, frames: &[Frame]) -> Vec<u8> {
+    let (width, height) = (canvas.width * canvas.scale, canvas.height * canvas.scale);
+    let mut png = PNG_SIGNATURE.to_vec();
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 2 bits per pixel, palette, deflate, adaptive filtering, no interlace.
+    ihdr.extend_from_slice(&[2, 3, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &ihdr);
+
+    let mut actl = (frames.len() as u32).to_be_bytes().to_vec();
+    actl.extend_from_slice(&0u32.to_be_bytes());
+    write_chunk(&mut png, b"acTL", &actl);
+    write_chunk(&mut png, b"PLTE", &canvas.colors.concat());
+
+    // fcTL and fdAT chunks share one sequence.
+    let mut sequence = 0u32;
+    for (n, frame) in frames.iter().enumerate() {
+        let rect = match n {
+            0 => (0, 0, canvas.width, canvas.height),
+            _ => canvas.changed(&frames[n - 1].pixels, &frame.pixels),
+        };
+        let (x, y, w, h) = rect;
+        let mut fctl = sequence.to_be_bytes().to_vec();
+        for value in [w, h, x, y] {
+            fctl.extend_from_slice(&((value * canvas.scale) as u32).to_be_bytes());
+        }
+        fctl.extend_from_slice(&(frame.frames.min(u16::MAX as u32) as u16).to_be_bytes());
+        fctl.extend_from_slice(&(RECORDING_FPS as u16).to_be_bytes());
+        // Leave the frame in place for the next one, replacing what was
+        // under it.
+        fctl.extend_from_slice(&[0, 0]);
+        write_chunk(&mut png, b"fcTL", &fctl);
+        sequence += 1;
+
+        let data = zlib_stored(&pack_rows(
+            &canvas.region(&frame.pixels, rect),
+            w * canvas.scale,
+        ));
+        if n == 0 {
+            write_chunk(&mut png, b"IDAT", &data);
+        } else {
+            let mut fdat = sequence.to_be_bytes().to_vec();
+            fdat.extend_from_slice(&data);
+            write_chunk(&mut png, b"fdAT", &fdat);
+            sequence += 1;
+        }
+    }
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// 2-bit pixel values packed four to a byte, leftmost in the top bits, each
+// row after a filter byte of 0.
+fn pack_rows(pixels: &[u8], width: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity((width.div_ceil(4) + 1) * pixels.len() / width.max(1));
+    for row in pixels.chunks_exact(width) {
+        out.push(0);
+        for quad in row.chunks(4) {
+            let mut byte = 0;
+            for (n, &pixel) in quad.iter().enumerate() {
+                byte |= (pixel & 3) << (6 - 2 * n);
+            }
+            out.push(byte);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::hash::crc32;
+
+    // A plain GIF LZW decoder to check the encoder against.
+    fn lzw_decode(min_code_size: u8, data: &[u8]) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut width = min_code_size as u32 + 1;
+        let (mut buffer, mut count, mut bytes) = (0u32, 0u32, data.iter());
+        let mut previous: Option<usize> = None;
+        let mut out = Vec::new();
+        loop {
+            while count < width {
+                buffer |= (*bytes.next().unwrap() as u32) << count;
+                count += 8;
+            }
+            let code = (buffer & ((1 << width) - 1)) as usize;
+            buffer >>= width;
+            count -= width;
+
+            if code == clear {
+                table = (0..clear).map(|n| vec![n as u8]).collect();
+                table.push(Vec::new());
+                table.push(Vec::new());
+                width = min_code_size as u32 + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return out;
+            }
+            let entry = match (table.get(code), previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => {
+                    let mut entry = table[previous].clone();
+                    entry.push(table[previous][0]);
+                    entry
+                }
+                (None, None) => panic!("bad first code"),
+            };
+            if let Some(previous) = previous {
+                if table.len() < 4096 {
+                    let mut added = table[previous].clone();
+                    added.push(entry[0]);
+                    table.push(added);
+                    if table.len() == 1 << width && width < 12 {
+                        width += 1;
+                    }
+                }
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    // The chunk types in a PNG, in order.
+    fn chunks(png: &[u8]) -> Vec<String> {
+        let mut kinds = Vec::new();
+        let mut at = PNG_SIGNATURE.len();
+        while at < png.len() {
+            let len = u32::from_be_bytes([png[at], png[at + 1], png[at + 2], png[at + 3]]) as usize;
+            let crc = &png[at + 8 + len..at + 12 + len];
+            assert_eq!(crc, crc32(&png[at + 4..at + 8 + len]).to_be_bytes());
+            kinds.push(String::from_utf8_lossy(&png[at + 4..at + 8]).to_string());
+            at += 12 + len;
+        }
+        kinds
+    }
+
+    #[test]
+    fn test_lzw() {
+        // Enough varied input to fill the table and start over.
+        let mut state = 1u32;
+        let indices: Vec<u8> = (0..40000)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 29) as u8 & 3
+            })
+            .collect();
+        assert_eq!(lzw_decode(2, &lzw_encode(2, &indices)), indices);
+        assert_eq!(lzw_decode(2, &lzw_encode(2, &[0; 1000])), [0; 1000]);
+        assert_eq!(lzw_decode(2, &lzw_encode(2, &[])), []);
+    }
+
+    #[test]
+    fn test_gif() {
+        let mut fb = Framebuffer::with_size(4, 2);
+        let mut recorder = Recorder::new(RecordingFormat::Gif, Palette::MONOCHROME, 2);
+        recorder.capture(&fb);
+        fb.toggle(2, 1);
+        for _ in 0..3 {
+            recorder.capture(&fb);
+        }
+        assert_eq!(recorder.frame_count(), 4);
+
+        let gif = recorder.finish();
+        assert_eq!(gif[..13], *b"GIF89a\x08\x00\x04\x00\xf1\x00\x00");
+        assert_eq!(
+            gif[13..25],
+            [0, 0, 0, 255, 255, 255, 170, 170, 170, 85, 85, 85]
+        );
+        assert_eq!(gif.iter().filter(|&&byte| byte == 0x2c).count(), 2);
+        assert_eq!(gif.last(), Some(&0x3b));
+        // The second frame only covers the pixel that changed, for the
+        // remaining 3/60 of a second.
+        let second = gif.iter().rposition(|&byte| byte == 0x2c).unwrap();
+        assert_eq!(
+            gif[second - 8..second],
+            [0x21, 0xf9, 0x04, 0x04, 5, 0, 0, 0]
+        );
+        assert_eq!(gif[second + 1..second + 10], [4, 0, 2, 0, 2, 0, 2, 0, 0]);
+        let data = &gif[second + 12..second + 12 + gif[second + 11] as usize];
+        assert_eq!(lzw_decode(2, data), [1; 4]);
+    }
+
+    #[test]
+    fn test_apng() {
+        let mut recorder = Recorder::new(RecordingFormat::Apng, Palette::MONOCHROME, 1);
+        assert_eq!(recorder.finish(), Vec::<u8>::new());
+        let mut fb = Framebuffer::with_size(64, 32);
+        recorder.capture(&fb);
+        fb.toggle(0, 0);
+        recorder.capture(&fb);
+        // A switch to hires comes out at the higher resolution.
+        recorder.capture(&Framebuffer::with_size(128, 64));
+
+        let png = recorder.finish();
+        assert_eq!(
+            chunks(&png),
+            ["IHDR", "acTL", "PLTE", "fcTL", "IDAT", "fcTL", "fdAT", "fcTL", "fdAT", "IEND"]
+        );
+        assert_eq!(png[16..24], [0, 0, 0, 128, 0, 0, 0, 64]);
+        // acTL: 3 frames, looping forever.
+        assert_eq!(png[41..49], [0, 0, 0, 3, 0, 0, 0, 0]);
+        assert_eq!(
+            pack_rows(&[1, 2, 3, 0, 1], 5),
+            [0, 0b01_10_11_00, 0b01_00_00_00]
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            RecordingFormat::from_name("apng"),
+            Some(RecordingFormat::Apng)
+        );
+        assert_eq!(RecordingFormat::from_name("mp4"), None);
+        assert_eq!(
+            recording_name(std::time::UNIX_EPOCH, RecordingFormat::Gif),
+            "chip8-19700101-000000.gif"
+        );
+    }
+}
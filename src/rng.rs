@@ -0,0 +1,108 @@
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Source of random bytes for the RND (Cxkk) instruction.
+//
+// With std the CPU owns a boxed `Rng` so tests and replays can plug in a
+// deterministic sequence instead of the default PRNG; without it there is
+// no heap to box one on and it always runs `XorShiftRng`. Implementors must
+// be `Clone` so a forked CPU carries on with the same sequence.
+pub trait Rng: RngClone {
+    fn next_u8(&mut self) -> u8;
+}
+
+// Object-safe cloning for boxed `Rng`s, implemented for every `Clone` rng.
+pub trait RngClone {
+    #[cfg(feature = "std")]
+    fn clone_box(&self) -> Box<dyn Rng>;
+}
+
+impl<T: Rng + Clone + 'static> RngClone for T {
+    #[cfg(feature = "std")]
+    fn clone_box(&self) -> Box<dyn Rng> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clone for Box<dyn Rng> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+// Small xorshift PRNG used by default. Not suitable for anything but games.
+#[derive(Clone)]
+pub struct XorShiftRng {
+    state: u32,
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u32) -> Self {
+        // Xorshift gets stuck on a zero state.
+        XorShiftRng {
+            state: if seed == 0 { 0x2545_f491 } else { seed },
+        }
+    }
+
+    // Seed from the system clock.
+    #[cfg(feature = "std")]
+    pub fn from_time() -> Self {
+        XorShiftRng::new(time_seed())
+    }
+}
+
+impl Rng for XorShiftRng {
+    fn next_u8(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+
+        (x >> 24) as u8
+    }
+}
+
+// A seed from the system clock.
+#[cfg(feature = "std")]
+pub(crate) fn time_seed() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() ^ d.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = XorShiftRng::new(42);
+        let mut b = XorShiftRng::new(42);
+
+        for _ in 0..32 {
+            assert_eq!(a.next_u8(), b.next_u8());
+        }
+    }
+
+    #[test]
+    fn test_zero_seed_does_not_stick() {
+        let mut rng = XorShiftRng::new(0);
+        let values: Vec<u8> = (0..8).map(|_| rng.next_u8()).collect();
+
+        assert!(values.iter().any(|&v| v != 0));
+    }
+
+    #[test]
+    fn test_boxed_clone_continues_sequence() {
+        let mut a: Box<dyn Rng> = Box::new(XorShiftRng::new(7));
+        a.next_u8();
+        let mut b = a.clone();
+
+        for _ in 0..8 {
+            assert_eq!(a.next_u8(), b.next_u8());
+        }
+    }
+}
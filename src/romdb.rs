@@ -0,0 +1,146 @@
+use crate::error::{Chip8Error, Result};
+use crate::hash::sha1_hex;
+use crate::json;
+use crate::metadata::GameMetadata;
+
+// Known ROMs keyed by the SHA-1 of their contents, with the title and the
+// settings each one needs to run correctly. The values are the same records
+// as CHIP-8 Archive metadata, so the recommended options are applied the
+// same way (see `Machine::load_known_rom`).
+#[derive(Clone, Debug, Default)]
+pub struct RomDatabase {
+    // (lowercase hex SHA-1, metadata), in insertion order.
+    entries: Vec<(String, GameMetadata)>,
+}
+
+// Built-in entries: (SHA-1, metadata JSON). A dump is only listed
+// once its hash has been checked against a known-good copy; anything else
+// belongs in a database loaded with `RomDatabase::from_json`. So far that
+// is the self-tests under roms/, which the tests below hash. Entries for
+// the classics (Blitz, Space Invaders, ...) are to be added the same way,
+// hashed from checked dumps, with the quirks and tickrate they need.
+const BUILTIN: &[(&str, &str)] = &[
+    (
+        "df76a5b1a854428d50fec810cf5347b3c6b13e40",
+        r#"{"title": "Opcode test", "platform": "chip8"}"#,
+    ),
+    (
+        "965ff4e44b4987565a088b533209daec81df756f",
+        r#"{"title": "Flags test", "platform": "chip8"}"#,
+    ),
+];
+
+impl RomDatabase {
+    pub fn new() -> Self {
+        RomDatabase::default()
+    }
+
+    // The database shipped with the crate.
+    pub fn builtin() -> Self {
+        let mut db = RomDatabase::new();
+        for &(sha1, metadata) in BUILTIN {
+            let metadata =
+                GameMetadata::from_json(metadata).expect("chip8.romdb: bad built-in entry");
+            db.insert(sha1, metadata);
+        }
+        db
+    }
+
+    // Load a database from a JSON object mapping hex SHA-1s to metadata
+    // objects in the CHIP-8 Archive format:
+    //
+    //     {"0123...": {"title": "Blitz", "options": {"tickrate": 15}}}
+    pub fn from_json(text: &str) -> Result<RomDatabase> {
+        let value = json::parse(text).map_err(|reason| Chip8Error::InvalidMetadata { reason })?;
+        let fields = value.as_object().ok_or(Chip8Error::InvalidMetadata {
+            reason: "expected an object",
+        })?;
+
+        let mut db = RomDatabase::new();
+        for (sha1, entry) in fields {
+            if sha1.len() != 40 || !sha1.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(Chip8Error::InvalidMetadata {
+                    reason: "bad SHA-1",
+                });
+            }
+            db.insert(sha1, GameMetadata::from_value(entry)?);
+        }
+        Ok(db)
+    }
+
+    // Add or replace the entry for the ROM with hash `sha1`.
+    pub fn insert(&mut self, sha1: &str, metadata: GameMetadata) {
+        let sha1 = sha1.to_ascii_lowercase();
+        match self.entries.iter_mut().find(|(hash, _)| *hash == sha1) {
+            Some(entry) => entry.1 = metadata,
+            None => self.entries.push((sha1, metadata)),
+        }
+    }
+
+    // Metadata for `rom`, if it is a known dump.
+    pub fn lookup(&self, rom: &[u8]) -> Option<&GameMetadata> {
+        let sha1 = RomDatabase::hash(rom);
+        self.entries
+            .iter()
+            .find(|(hash, _)| *hash == sha1)
+            .map(|(_, metadata)| metadata)
+    }
+
+    // Key under which `rom` is stored: its SHA-1 as lowercase hex.
+    pub fn hash(rom: &[u8]) -> String {
+        sha1_hex(rom)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let rom = [0x12, 0x00];
+        let json = format!(
+            r#"{{"{}": {{"title": "Halt", "options": {{"tickrate": 7}}}}}}"#,
+            RomDatabase::hash(&rom).to_uppercase()
+        );
+        let db = RomDatabase::from_json(&json).unwrap();
+
+        assert_eq!(db.len(), 1);
+        let entry = db.lookup(&rom).unwrap();
+        assert_eq!(entry.title, "Halt");
+        assert_eq!(entry.options.tickrate, Some(7));
+        assert!(db.lookup(&[0x12, 0x02]).is_none());
+    }
+
+    #[test]
+    fn test_from_json_rejects_bad_hash() {
+        assert_eq!(
+            RomDatabase::from_json(r#"{"abc": {"title": "Nope"}}"#).unwrap_err(),
+            Chip8Error::InvalidMetadata {
+                reason: "bad SHA-1"
+            }
+        );
+    }
+
+    #[test]
+    fn test_builtin_entries_parse() {
+        assert_eq!(RomDatabase::builtin().len(), BUILTIN.len());
+    }
+
+    #[test]
+    fn test_builtin_hashes() {
+        let db = RomDatabase::builtin();
+        let opcode_test = db.lookup(include_bytes!("../roms/opcode_test.ch8"));
+        assert_eq!(opcode_test.unwrap().title, "Opcode test");
+        let flags_test = db.lookup(include_bytes!("../roms/flags_test.ch8"));
+        assert_eq!(flags_test.unwrap().title, "Flags test");
+    }
+}
@@ -0,0 +1,370 @@
+use crate::audio::Audio;
+use crate::display::Display;
+use crate::framebuffer::Framebuffer;
+use crate::input::{Input, KeyEvent};
+use crate::json;
+use crate::keypad::CHIP8_NUM_KEYS;
+use crate::websocket::{write_handshake, write_message, Message, MessageReader, Request};
+
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// The page served to plain HTTP requests: a canvas and the keyboard,
+// connected back over WebSocket.
+const STREAM_PAGE: &str = include_str!("../web/stream.html");
+
+// A client that takes longer than this to accept a frame is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// What the listener, client threads and backends share.
+#[derive(Default)]
+struct Shared {
+    // Write halves of the connected clients, by id.
+    clients: Mutex<Vec<(u64, TcpStream)>>,
+    next_id: AtomicU64,
+    // The last frame message, for clients as they join.
+    frame: Mutex<Vec<u8>>,
+    // Key events from all clients, oldest first.
+    events: Mutex<VecDeque<KeyEvent>>,
+}
+
+impl Shared {
+    // Send `message` to every client, dropping those that fail.
+    fn broadcast(&self, message: &Message) {
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|(_, client)| write_message(client, message, None).is_ok());
+    }
+}
+
+// Streams the screen to WebSocket clients and takes key presses from
+// them, so a session running on a server can be watched and played from
+// browsers, any number at once. Plain HTTP requests get web/stream.html,
+// a page that does just that. `chip8 serve` runs one:
+//
+//     let server = StreamServer::bind("0.0.0.0:8080")?;
+//     machine.set_display(Box::new(server.display()));
+//     machine.set_input(Box::new(server.input()));
+//     machine.set_audio(Box::new(server.audio()));
+//
+// The protocol is a binary message per frame that changed the screen, and
+// small JSON texts for the rest:
+// - frames: width and height as big endian u16s, then one byte per pixel
+//   holding its 2-bit value, row by row;
+// - from the server, {"beep":true} and {"beep":false} at buzzer edges;
+// - from clients, {"key":5,"pressed":true} for key 5 going down. Keys a
+//   client holds when it disconnects are released.
+pub struct StreamServer {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+}
+
+impl StreamServer {
+    // Listen on `addr`, accepting clients on a thread of their own.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<StreamServer> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let shared = Arc::new(Shared::default());
+        let accepting = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = accepting.clone();
+                thread::spawn(move || serve_client(stream, &shared));
+            }
+        });
+        Ok(StreamServer { addr, shared })
+    }
+
+    // The address listened on, with the port picked if 0 was asked for.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn clients(&self) -> usize {
+        self.shared.clients.lock().unwrap().len()
+    }
+
+    pub fn display(&self) -> StreamDisplay {
+        StreamDisplay {
+            shared: self.shared.clone(),
+        }
+    }
+
+    pub fn input(&self) -> StreamInput {
+        StreamInput {
+            shared: self.shared.clone(),
+            keys: [false; CHIP8_NUM_KEYS],
+        }
+    }
+
+    pub fn audio(&self) -> StreamAudio {
+        StreamAudio {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+// Answer one connection: the page, or a WebSocket session until the
+// client goes away.
+fn serve_client(stream: TcpStream, shared: &Shared) {
+    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    let request = match Request::read(&mut reader) {
+        Ok(request) => request,
+        Err(_) => return,
+    };
+
+    let key = match request.websocket_key() {
+        Some(key) => key,
+        None => {
+            let _ = serve_page(&mut writer, &request.path);
+            return;
+        }
+    };
+    if write_handshake(&mut writer, key).is_err() {
+        return;
+    }
+    let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+    {
+        let mut clients = shared.clients.lock().unwrap();
+        let frame = shared.frame.lock().unwrap();
+        if !frame.is_empty()
+            && write_message(&mut writer, &Message::Binary(frame.clone()), None).is_err()
+        {
+            return;
+        }
+        match writer.try_clone() {
+            Ok(client) => clients.push((id, client)),
+            Err(_) => return,
+        }
+    }
+
+    let mut held = [false; CHIP8_NUM_KEYS];
+    let mut messages = MessageReader::new(reader);
+    while let Ok(message) = messages.read() {
+        match message {
+            Message::Text(text) => {
+                if let Some(event) = parse_key_event(&text) {
+                    match event {
+                        KeyEvent::Pressed(key) => held[key as usize] = true,
+                        KeyEvent::Released(key) => held[key as usize] = false,
+                    }
+                    shared.events.lock().unwrap().push_back(event);
+                }
+            }
+            Message::Ping(payload) => {
+                // Frames go out on the other handle too; the clients lock
+                // keeps them from interleaving.
+                let _clients = shared.clients.lock().unwrap();
+                let _ = write_message(&mut writer, &Message::Pong(payload), None);
+            }
+            Message::Close => break,
+            Message::Binary(_) | Message::Pong(_) => {}
+        }
+    }
+
+    shared
+        .clients
+        .lock()
+        .unwrap()
+        .retain(|&(client, _)| client != id);
+    let mut events = shared.events.lock().unwrap();
+    for key in (0..CHIP8_NUM_KEYS as u8).filter(|&key| held[key as usize]) {
+        events.push_back(KeyEvent::Released(key));
+    }
+    drop(events);
+    let _ = writer.shutdown(std::net::Shutdown::Both);
+}
+
+fn serve_page<W: Write>(out: &mut W, path: &str) -> io::Result<()> {
+    match path {
+        "/" | "/index.html" => write!(
+            out,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            STREAM_PAGE.len(),
+            STREAM_PAGE
+        ),
+        _ => write!(
+            out,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        ),
+    }?;
+    out.flush()
+}
+
+// A client's {"key":N,"pressed":BOOL} message.
+fn parse_key_event(text: &str) -> Option<KeyEvent> {
+    let message = json::parse(text).ok()?;
+    let key = message.get("key")?.as_f64()?;
+    if !(0.0..CHIP8_NUM_KEYS as f64).contains(&key) || key.fract() != 0.0 {
+        return None;
+    }
+    match message.get("pressed")?.as_bool()? {
+        true => Some(KeyEvent::Pressed(key as u8)),
+        false => Some(KeyEvent::Released(key as u8)),
+    }
+}
+
+// The frame message for `framebuffer`.
+fn frame_message(framebuffer: &Framebuffer) -> Vec<u8> {
+    let mut message = Vec::with_capacity(4 + framebuffer.width() * framebuffer.height());
+    message.extend_from_slice(&(framebuffer.width() as u16).to_be_bytes());
+    message.extend_from_slice(&(framebuffer.height() as u16).to_be_bytes());
+    for y in 0..framebuffer.height() {
+        message.extend_from_slice(framebuffer.row(y));
+    }
+    message
+}
+
+// Sends every frame to the server's clients.
+pub struct StreamDisplay {
+    shared: Arc<Shared>,
+}
+
+impl Display for StreamDisplay {
+    fn draw(&mut self, framebuffer: &Framebuffer) {
+        let frame = frame_message(framebuffer);
+        *self.shared.frame.lock().unwrap() = frame.clone();
+        self.shared.broadcast(&Message::Binary(frame));
+    }
+}
+
+// Key events from the server's clients.
+pub struct StreamInput {
+    shared: Arc<Shared>,
+    keys: [bool; CHIP8_NUM_KEYS],
+}
+
+impl Input for StreamInput {
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        let event = self.shared.events.lock().unwrap().pop_front()?;
+        match event {
+            KeyEvent::Pressed(key) => self.keys[key as usize] = true,
+            KeyEvent::Released(key) => self.keys[key as usize] = false,
+        }
+        Some(event)
+    }
+
+    fn is_pressed(&self, key: u8) -> bool {
+        self.keys[key as usize & 0xf]
+    }
+}
+
+// Tells the server's clients when the buzzer starts and stops.
+pub struct StreamAudio {
+    shared: Arc<Shared>,
+}
+
+impl Audio for StreamAudio {
+    fn start_beep(&mut self) {
+        self.shared
+            .broadcast(&Message::Text("{\"beep\":true}".to_string()));
+    }
+
+    fn stop_beep(&mut self) {
+        self.shared
+            .broadcast(&Message::Text("{\"beep\":false}".to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::{BufRead, Read};
+    use std::time::Instant;
+
+    // Wait up to a second for `done`.
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !done() {
+            assert!(start.elapsed() < Duration::from_secs(1), "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_parse_key_event() {
+        assert_eq!(
+            parse_key_event("{\"key\":5,\"pressed\":true}"),
+            Some(KeyEvent::Pressed(5))
+        );
+        assert_eq!(
+            parse_key_event("{\"pressed\":false,\"key\":15}"),
+            Some(KeyEvent::Released(15))
+        );
+        assert_eq!(parse_key_event("{\"key\":16,\"pressed\":true}"), None);
+        assert_eq!(parse_key_event("{\"key\":1.5,\"pressed\":true}"), None);
+        assert_eq!(parse_key_event("key 5"), None);
+    }
+
+    #[test]
+    fn test_session() {
+        let server = StreamServer::bind("127.0.0.1:0").unwrap();
+        let mut display = server.display();
+        let mut input = server.input();
+        let mut fb = Framebuffer::new();
+        fb.toggle(2, 0);
+        display.draw(&fb);
+
+        let client = TcpStream::connect(server.local_addr()).unwrap();
+        let mut writer = client.try_clone().unwrap();
+        write!(
+            writer,
+            "GET / HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"
+        )
+        .unwrap();
+        let mut reader = BufReader::new(client);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("HTTP/1.1 101"));
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+
+        // The frame drawn before joining comes first.
+        let mut messages = MessageReader::new(reader);
+        let frame = match messages.read().unwrap() {
+            Message::Binary(frame) => frame,
+            message => panic!("expected a frame, got {:?}", message),
+        };
+        assert_eq!(frame[..4], [0, 64, 0, 32]);
+        assert_eq!(frame[4..8], [0, 0, 1, 0]);
+        assert_eq!(frame.len(), 4 + 64 * 32);
+
+        let press = Message::Text("{\"key\":5,\"pressed\":true}".to_string());
+        write_message(&mut writer, &press, Some([9, 8, 7, 6])).unwrap();
+        wait_for(|| input.poll_event().is_some());
+        assert!(input.is_pressed(5));
+
+        // Leaving releases the key.
+        drop(writer);
+        drop(messages);
+        wait_for(|| input.poll_event().is_some());
+        assert!(!input.is_pressed(5));
+        display.draw(&fb);
+        assert_eq!(server.clients(), 0);
+    }
+
+    #[test]
+    fn test_page() {
+        let server = StreamServer::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(server.local_addr()).unwrap();
+        write!(client, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("new WebSocket"));
+    }
+}
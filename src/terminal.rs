@@ -0,0 +1,588 @@
+// Backends for playing in a text terminal, over SSH or wherever there is
+// no window system. They only rely on ANSI escape sequences; putting the
+// terminal into raw mode and reading stdin is up to the frontend, see
+// `chip8 play`, which does it with stty so nothing beyond std is needed.
+// frontends/tui does the same on crossterm.
+
+use crate::audio::Audio;
+use crate::display::Display;
+use crate::framebuffer::Framebuffer;
+use crate::hash::base64;
+use crate::input::{Input, KeyEvent};
+use crate::keymap::KeyMap;
+use crate::keypad::CHIP8_NUM_KEYS;
+use crate::palette::Palette;
+
+use std::io::Write;
+use std::sync::mpsc::Receiver;
+
+// Terminals only report keys going down, repeating while they are held, so
+// a key counts as released once it hasn't come in for this many frames.
+// That spans the pause before autorepeat starts.
+pub const KEY_HOLD_FRAMES: u8 = 30;
+
+// Screen pixels per CHIP-8 pixel in the graphical renderers.
+pub const DEFAULT_PIXEL_SCALE: usize = 8;
+
+// The size in pixels of a terminal cell, which graphics can't be measured
+// in. Most fonts are somewhere close.
+const CELL_PIXELS: (usize, usize) = (10, 20);
+
+// How the screen is turned into terminal output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminalRenderer {
+    // Half block characters, two pixels to a cell: 64x32 takes 64x16
+    // cells.
+    HalfBlocks,
+    // Braille patterns, 2x4 pixels to a cell: 64x32 takes 32x8 cells and
+    // 128x64 fits in 64x16.
+    Braille,
+    // Sixel graphics, each pixel `scale` screen pixels square, for the
+    // terminals that support them (xterm -ti vt340, mlterm, WezTerm).
+    Sixel { scale: usize },
+    // The kitty graphics protocol (kitty, Ghostty, WezTerm): the image is
+    // replaced in place every frame, which flickers less than sixels.
+    Kitty { scale: usize },
+}
+
+impl TerminalRenderer {
+    pub fn name(self) -> &'static str {
+        match self {
+            TerminalRenderer::HalfBlocks => "blocks",
+            TerminalRenderer::Braille => "braille",
+            TerminalRenderer::Sixel { .. } => "sixel",
+            TerminalRenderer::Kitty { .. } => "kitty",
+        }
+    }
+
+    // The renderer called `name`, graphical ones at DEFAULT_PIXEL_SCALE.
+    pub fn from_name(name: &str) -> Option<TerminalRenderer> {
+        let scale = DEFAULT_PIXEL_SCALE;
+        [
+            TerminalRenderer::HalfBlocks,
+            TerminalRenderer::Braille,
+            TerminalRenderer::Sixel { scale },
+            TerminalRenderer::Kitty { scale },
+        ]
+        .iter()
+        .copied()
+        .find(|renderer| renderer.name() == name)
+    }
+
+    // The best renderer the terminal described by the environment
+    // variables `var` returns is known to support. Sixel support can't be
+    // told from the environment, so it is never picked.
+    pub fn detect<F>(var: F) -> TerminalRenderer
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let kitty = var("KITTY_WINDOW_ID").is_some()
+            || var("TERM").is_some_and(|term| term.contains("kitty"))
+            || var("TERM_PROGRAM")
+                .is_some_and(|program| program == "ghostty" || program == "WezTerm");
+        match kitty {
+            true => TerminalRenderer::Kitty {
+                scale: DEFAULT_PIXEL_SCALE,
+            },
+            false => TerminalRenderer::HalfBlocks,
+        }
+    }
+
+    // Screen pixels per emulated pixel, for the graphical renderers.
+    pub fn scale(self) -> Option<usize> {
+        match self {
+            TerminalRenderer::Sixel { scale } | TerminalRenderer::Kitty { scale } => Some(scale),
+            _ => None,
+        }
+    }
+
+    // The same renderer drawing each pixel `scale` screen pixels square.
+    // The text renderers have a fixed size and stay as they are.
+    pub fn with_scale(self, scale: usize) -> TerminalRenderer {
+        let scale = scale.max(1);
+        match self {
+            TerminalRenderer::Sixel { .. } => TerminalRenderer::Sixel { scale },
+            TerminalRenderer::Kitty { .. } => TerminalRenderer::Kitty { scale },
+            _ => self,
+        }
+    }
+
+    // The same renderer at the largest scale at which a width x height
+    // screen fits in `columns` x `rows` cells, and at least 1.
+    pub fn fit(self, width: usize, height: usize, columns: usize, rows: usize) -> TerminalRenderer {
+        let fits = |scale: usize| {
+            let (needed_columns, needed_rows) = self.with_scale(scale).cells(width, height);
+            needed_columns <= columns && needed_rows <= rows
+        };
+        let mut scale = 1;
+        while self.scale().is_some() && fits(scale + 1) {
+            scale += 1;
+        }
+        self.with_scale(scale)
+    }
+
+    // Columns and rows of cells a screen of this size takes up, a guess
+    // for the graphical renderers.
+    pub fn cells(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            TerminalRenderer::HalfBlocks => (width, height.div_ceil(2)),
+            TerminalRenderer::Braille => (width.div_ceil(2), height.div_ceil(4)),
+            TerminalRenderer::Sixel { scale } | TerminalRenderer::Kitty { scale } => (
+                (width * scale).div_ceil(CELL_PIXELS.0),
+                (height * scale).div_ceil(CELL_PIXELS.1),
+            ),
+        }
+    }
+
+    // The screen, the graphical renderers colouring it with `palette`.
+    // The text renderers leave colours to the terminal.
+    pub fn render(self, framebuffer: &Framebuffer, palette: &Palette) -> String {
+        match self {
+            TerminalRenderer::HalfBlocks => half_blocks(framebuffer),
+            TerminalRenderer::Braille => braille(framebuffer),
+            TerminalRenderer::Sixel { scale } => sixel(framebuffer, scale, palette.colors()),
+            TerminalRenderer::Kitty { scale } => kitty(framebuffer, scale, palette.colors()),
+        }
+    }
+}
+
+// Draws the screen on a terminal, see `TerminalRenderer`. With the text
+// renderers only the lines covering the framebuffer's dirty rows are
+// rewritten, after a first full frame.
+//
+// The graphical renderers use the default palette unless given another.
+// The text ones draw in the terminal's colours, or in the palette's
+// foreground and background once one is set.
+pub struct TerminalDisplay<W: Write> {
+    out: W,
+    renderer: TerminalRenderer,
+    palette: Option<Palette>,
+    // Whether the next frame must be drawn whole.
+    full: bool,
+}
+
+impl<W: Write> TerminalDisplay<W> {
+    pub fn new(out: W) -> Self {
+        TerminalDisplay::with_renderer(out, TerminalRenderer::HalfBlocks)
+    }
+
+    pub fn with_renderer(out: W, renderer: TerminalRenderer) -> Self {
+        TerminalDisplay {
+            out,
+            renderer,
+            palette: None,
+            full: true,
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = Some(palette);
+        self.full = true;
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Display for TerminalDisplay<W> {
+    fn draw(&mut self, framebuffer: &Framebuffer) {
+        // Home the cursor and overwrite the previous frame in place, which
+        // flickers less than clearing first, or move to each changed line
+        // and overwrite just that. A terminal that went away isn't worth
+        // stopping the emulation for.
+        let rows = match self.renderer {
+            TerminalRenderer::HalfBlocks => 2,
+            TerminalRenderer::Braille => 4,
+            _ => 0,
+        };
+        let text = self
+            .renderer
+            .render(framebuffer, &self.palette.unwrap_or_default());
+        let mut frame = match rows {
+            0 => format!("\x1b[H{}", text),
+            _ if self.full => format!("\x1b[H{}", text),
+            _ => {
+                let height = framebuffer.height();
+                let mut frame = String::new();
+                for (line, cells) in text.split("\r\n").enumerate() {
+                    let covered = line * rows..((line + 1) * rows).min(height);
+                    if covered.into_iter().any(|y| framebuffer.is_row_dirty(y)) {
+                        frame.push_str(&format!("\x1b[{};1H{}", line + 1, cells));
+                    }
+                }
+                frame
+            }
+        };
+        if let (Some(palette), true) = (self.palette, rows > 0) {
+            let ([fr, fg, fb], [br, bg, bb]) = (palette.foreground(), palette.background());
+            frame = format!(
+                "\x1b[38;2;{};{};{};48;2;{};{};{}m{}\x1b[0m",
+                fr, fg, fb, br, bg, bb, frame
+            );
+        }
+        self.full = false;
+        let _ = self
+            .out
+            .write_all(frame.as_bytes())
+            .and_then(|_| self.out.flush());
+    }
+
+    fn clear(&mut self) {
+        self.full = true;
+        let _ = self
+            .out
+            .write_all(b"\x1b[2J")
+            .and_then(|_| self.out.flush());
+    }
+
+    fn set_resolution(&mut self, _width: usize, _height: usize) {
+        // Whatever the old resolution left behind would stay on screen.
+        self.clear();
+    }
+}
+
+// The screen as lines of half blocks, separated by "\r\n" so it also comes
+// out right in raw mode.
+pub fn half_blocks(framebuffer: &Framebuffer) -> String {
+    let mut out = String::new();
+    for y in (0..framebuffer.height()).step_by(2) {
+        if y > 0 {
+            out.push_str("\r\n");
+        }
+        for x in 0..framebuffer.width() {
+            let bottom = y + 1 < framebuffer.height() && framebuffer.get(x, y + 1);
+            out.push(match (framebuffer.get(x, y), bottom) {
+                (false, false) => ' ',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (true, true) => '█',
+            });
+        }
+    }
+    out
+}
+
+// Bit of each of the 2x4 dots in a braille pattern, by row.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+// The screen as lines of braille patterns, separated like `half_blocks`.
+// Blank cells are spaces, since some fonts draw the empty pattern.
+pub fn braille(framebuffer: &Framebuffer) -> String {
+    let mut out = String::new();
+    for y in (0..framebuffer.height()).step_by(4) {
+        if y > 0 {
+            out.push_str("\r\n");
+        }
+        for x in (0..framebuffer.width()).step_by(2) {
+            let mut dots = 0;
+            for (dy, row) in BRAILLE_DOTS.iter().enumerate() {
+                for (dx, bit) in row.iter().enumerate() {
+                    let (px, py) = (x + dx, y + dy);
+                    if px < framebuffer.width()
+                        && py < framebuffer.height()
+                        && framebuffer.get(px, py)
+                    {
+                        dots |= bit;
+                    }
+                }
+            }
+            out.push(match dots {
+                0 => ' ',
+                _ => std::char::from_u32(0x2800 + dots).unwrap(),
+            });
+        }
+    }
+    out
+}
+
+// The screen as a sixel image, every pixel `scale` times as big, in
+// `colors`.
+pub fn sixel(framebuffer: &Framebuffer, scale: usize, colors: &[[u8; 3]; 4]) -> String {
+    let (width, height) = (framebuffer.width() * scale, framebuffer.height() * scale);
+    // Start with 1:1 pixels, then the raster size and the colour registers
+    // in RGB percentages.
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    for (n, rgb) in colors.iter().enumerate() {
+        let [r, g, b] = rgb.map(|c| c as usize * 100 / 0xff);
+        out.push_str(&format!("#{};2;{};{};{}", n, r, g, b));
+    }
+
+    let value = |x: usize, y: usize| match y < height {
+        true => framebuffer.pixel(x / scale, y / scale) as usize & 0b11,
+        false => usize::MAX,
+    };
+    // Each band is six rows high and is painted once per colour, the
+    // columns of each pass run length encoded.
+    for band in (0..height).step_by(6) {
+        for color in 0..colors.len() {
+            out.push_str(&format!("#{}", color));
+            let mut run: Option<(u8, usize)> = None;
+            for x in 0..width {
+                let bits = (0..6).fold(0, |bits, row| match value(x, band + row) == color {
+                    true => bits | 1 << row,
+                    false => bits,
+                });
+                run = match run {
+                    Some((last, count)) if last == bits => Some((last, count + 1)),
+                    Some(previous) => {
+                        push_sixels(&mut out, previous);
+                        Some((bits, 1))
+                    }
+                    None => Some((bits, 1)),
+                };
+            }
+            if let Some(run) = run {
+                push_sixels(&mut out, run);
+            }
+            // Back to the start of the band for the next colour.
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+// One run of sixel `bits` repeated `count` times.
+fn push_sixels(out: &mut String, (bits, count): (u8, usize)) {
+    let sixel = (b'?' + bits) as char;
+    match count {
+        1..=3 => (0..count).for_each(|_| out.push(sixel)),
+        _ => out.push_str(&format!("!{}{}", count, sixel)),
+    }
+}
+
+// Bytes of base64 per kitty escape sequence; longer images go in chunks.
+const KITTY_CHUNK: usize = 4096;
+
+// The screen as a kitty graphics command that puts it at the cursor, as
+// image 1 so every frame replaces the one before.
+pub fn kitty(framebuffer: &Framebuffer, scale: usize, colors: &[[u8; 3]; 4]) -> String {
+    let (width, height) = (framebuffer.width() * scale, framebuffer.height() * scale);
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let value = framebuffer.pixel(x / scale, y / scale) & 0b11;
+            rgb.extend_from_slice(&colors[value as usize]);
+        }
+    }
+
+    let data = base64(&rgb);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::new();
+    for (n, chunk) in chunks.iter().enumerate() {
+        let more = (n + 1 < chunks.len()) as u8;
+        // The first chunk carries the keys: transmit and display 24-bit
+        // RGB, without moving the cursor or answering.
+        if n == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=24,s={},v={},i=1,C=1,q=2,m={};",
+                width, height, more
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};", more));
+        }
+        out.push_str(std::str::from_utf8(chunk).unwrap());
+        out.push_str("\x1b\\");
+    }
+    out
+}
+
+// Rings the terminal bell when the buzzer starts.
+pub struct TerminalBell<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TerminalBell<W> {
+    pub fn new(out: W) -> Self {
+        TerminalBell { out }
+    }
+}
+
+impl<W: Write> Audio for TerminalBell<W> {
+    fn start_beep(&mut self) {
+        let _ = self.out.write_all(b"\x07").and_then(|_| self.out.flush());
+    }
+
+    fn stop_beep(&mut self) {}
+}
+
+// Keys typed into a terminal, received byte by byte from whatever reads
+// stdin. See `KEY_HOLD_FRAMES` for how releases are made up.
+pub struct TerminalInput {
+    bytes: Receiver<u8>,
+    keymap: KeyMap,
+    // Frames left until each key counts as released.
+    held: [u8; CHIP8_NUM_KEYS],
+}
+
+impl TerminalInput {
+    pub fn new(bytes: Receiver<u8>, keymap: KeyMap) -> Self {
+        TerminalInput {
+            bytes,
+            keymap,
+            held: [0; CHIP8_NUM_KEYS],
+        }
+    }
+}
+
+impl Input for TerminalInput {
+    // Called at the start of every frame: counts down the held keys and
+    // takes in what was typed since. The keypad follows `is_pressed`, so
+    // no events are needed.
+    fn poll_event(&mut self) -> Option<KeyEvent> {
+        self.held
+            .iter_mut()
+            .for_each(|frames| *frames = frames.saturating_sub(1));
+        for byte in self.bytes.try_iter() {
+            if let Some(key) = self.keymap.key(byte as char) {
+                self.held[key as usize] = KEY_HOLD_FRAMES;
+            }
+        }
+        None
+    }
+
+    fn is_pressed(&self, key: u8) -> bool {
+        self.held[key as usize & 0xf] > 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_half_blocks() {
+        let mut fb = Framebuffer::with_size(3, 3);
+        fb.toggle(0, 0);
+        fb.toggle(1, 1);
+        fb.toggle(2, 0);
+        fb.toggle(2, 1);
+        fb.toggle(0, 2);
+
+        assert_eq!(half_blocks(&fb), "▀▄█\r\n▀  ");
+    }
+
+    #[test]
+    fn test_braille() {
+        let mut fb = Framebuffer::with_size(4, 5);
+        fb.toggle(0, 0);
+        fb.toggle(1, 3);
+        fb.toggle(3, 4);
+
+        assert_eq!(braille(&fb), "\u{2881} \r\n \u{2808}");
+        assert_eq!(TerminalRenderer::Braille.cells(128, 64), (64, 16));
+        assert_eq!(
+            TerminalRenderer::from_name("braille"),
+            Some(TerminalRenderer::Braille)
+        );
+    }
+
+    #[test]
+    fn test_sixel() {
+        let mut fb = Framebuffer::with_size(2, 1);
+        fb.toggle(1, 0);
+        let image = sixel(&fb, 3, Palette::MONOCHROME.colors());
+
+        assert!(image.starts_with("\x1bP0;1;0q\"1;1;6;3#0;2;0;0;0#1;2;100;100;100"));
+        // Rows 0-2 are bits 0-2: colour 0 on the left, colour 1 on the
+        // right, colours 2 and 3 nowhere.
+        assert!(image.ends_with("#0FFF???$#1???FFF$#2!6?$#3!6?$-\x1b\\"));
+    }
+
+    #[test]
+    fn test_kitty() {
+        let mut fb = Framebuffer::with_size(1, 1);
+        fb.toggle(0, 0);
+        assert_eq!(
+            kitty(&fb, 1, Palette::MONOCHROME.colors()),
+            "\x1b_Ga=T,f=24,s=1,v=1,i=1,C=1,q=2,m=0;////\x1b\\"
+        );
+
+        // 64x32 at scale 8 is 512K of base64, sent in 4K chunks.
+        let image = kitty(&Framebuffer::new(), 8, Palette::MONOCHROME.colors());
+        assert_eq!(image.matches("\x1b_G").count(), 128);
+        assert_eq!(image.matches("m=1;").count(), 127);
+    }
+
+    #[test]
+    fn test_scale() {
+        let kitty = TerminalRenderer::Kitty { scale: 8 };
+        assert_eq!(kitty.with_scale(3), TerminalRenderer::Kitty { scale: 3 });
+        assert_eq!(kitty.with_scale(0).scale(), Some(1));
+        assert_eq!(TerminalRenderer::Braille.with_scale(3).scale(), None);
+
+        // 128x64 at scale 3 is 39x10 cells, at scale 4 52x13.
+        assert_eq!(kitty.fit(128, 64, 80, 12).scale(), Some(3));
+        assert_eq!(kitty.fit(128, 64, 10, 2).scale(), Some(1));
+        assert_eq!(
+            TerminalRenderer::HalfBlocks.fit(64, 32, 80, 24),
+            TerminalRenderer::HalfBlocks
+        );
+    }
+
+    #[test]
+    fn test_detect() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        assert_eq!(
+            TerminalRenderer::detect(env(&[("TERM", "xterm-kitty")])),
+            TerminalRenderer::Kitty { scale: 8 }
+        );
+        assert_eq!(
+            TerminalRenderer::detect(env(&[("TERM", "xterm-256color")])),
+            TerminalRenderer::HalfBlocks
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let mut display = TerminalDisplay::new(Vec::new());
+        let mut fb = Framebuffer::with_size(2, 6);
+        display.draw(&fb);
+        assert_eq!(display.out, b"\x1b[H  \r\n  \r\n  ");
+
+        // Only the line with the changed row is redrawn.
+        display.out.clear();
+        fb.end_frame();
+        fb.toggle(1, 3);
+        fb.end_frame();
+        display.draw(&fb);
+        assert_eq!(display.out, "\x1b[2;1H ▄".as_bytes());
+
+        // A palette colours the text, and the next frame is drawn whole.
+        display.out.clear();
+        display.set_palette(Palette::GREEN);
+        display.draw(&fb);
+        let frame = String::from_utf8(display.into_inner()).unwrap();
+        assert!(frame.starts_with("\x1b[38;2;51;255;51;48;2;0;20;0m\x1b[H"));
+        assert!(frame.ends_with("\x1b[0m"));
+    }
+
+    #[test]
+    fn test_input() {
+        let (sender, receiver) = mpsc::channel();
+        let mut input = TerminalInput::new(receiver, KeyMap::standard());
+
+        sender.send(b'w').unwrap();
+        sender.send(b'p').unwrap();
+        assert_eq!(input.poll_event(), None);
+        assert!(input.is_pressed(0x5));
+
+        for _ in 1..KEY_HOLD_FRAMES {
+            input.poll_event();
+        }
+        assert!(input.is_pressed(0x5));
+        input.poll_event();
+        assert!(!input.is_pressed(0x5), "released after the hold ran out");
+    }
+}
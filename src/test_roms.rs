@@ -0,0 +1,99 @@
+// Test ROMs bundled for integration tests and demos, so neither needs
+// anything downloaded. They were written for this crate and share its
+// licence; the assembly sources sit next to the binaries in `roms/`.
+//
+// They stand in for the community ROMs usually used for this: the IBM
+// logo (no licence at all), corax89's opcode test and Timendus' flags test
+// (GPL-3.0, from chip8-test-suite), none of which can be redistributed
+// here. They cover the same ground, but not instruction for instruction,
+// so passing them isn't a claim of passing those.
+//
+// The opcode and flags tests run their checks and halt on a jump to
+// themselves, leaving the number of the failed check in VE (0 when
+// everything passed) and showing 600D or BAD plus that number on screen.
+
+// Draws CHIP8 with nothing but the six instructions the IBM logo uses.
+pub const DISPLAY_TEST: &[u8] = include_bytes!("../roms/display_test.ch8");
+
+// Every CHIP-8 instruction, avoiding behaviours that differ between
+// interpreters.
+pub const OPCODE_TEST: &[u8] = include_bytes!("../roms/opcode_test.ch8");
+
+// VF results of 8xy4, 8xy5, 8xy7, 8xy6 and 8xyE, including VF as the
+// destination register.
+pub const FLAGS_TEST: &[u8] = include_bytes!("../roms/flags_test.ch8");
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::{Cpu, StopReason};
+    use crate::quirks::Quirks;
+
+    // Run `rom` to its final jump and return VE.
+    fn run(rom: &[u8]) -> u8 {
+        run_with(rom, Quirks::default())
+    }
+
+    fn run_with(rom: &[u8], quirks: Quirks) -> u8 {
+        let mut cpu = Cpu::builder().rom(rom).quirks(quirks).build().unwrap();
+        assert_eq!(cpu.run_until(10_000, |_| false), Ok(StopReason::Halted));
+        cpu.registers()[0xe]
+    }
+
+    #[test]
+    fn test_display_test_draws() {
+        let mut cpu = Cpu::builder().rom(DISPLAY_TEST).build().unwrap();
+        assert_eq!(cpu.run_until(100, |_| false), Ok(StopReason::Halted));
+
+        let rows: Vec<String> = (12..17)
+            .map(|y| {
+                (8..33)
+                    .map(|x| {
+                        if cpu.framebuffer().get(x, y) {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "####.#..#.###..####.####.",
+                "#....#..#..#...#..#.#..#.",
+                "#....####..#...####.####.",
+                "#....#..#..#...#....#..#.",
+                "####.#..#.###..#....####.",
+            ]
+        );
+        let lit = cpu
+            .framebuffer()
+            .pixels()
+            .iter()
+            .filter(|&&p| p != 0)
+            .count();
+        assert_eq!(lit, 60, "nothing is drawn outside the word");
+    }
+
+    #[test]
+    fn test_opcode_test_passes() {
+        assert_eq!(run(OPCODE_TEST), 0);
+    }
+
+    #[test]
+    fn test_flags_test_passes() {
+        assert_eq!(run(FLAGS_TEST), 0);
+    }
+
+    #[test]
+    fn test_flags_test_passes_with_vip_quirks() {
+        let quirks = Quirks {
+            shift: false,
+            vf_reset: true,
+            ..Quirks::default()
+        };
+        assert_eq!(run_with(FLAGS_TEST, quirks), 0);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::framebuffer::Framebuffer;
+use crate::phosphor::PhosphorFilter;
+
+// An RGBA8 copy of the screen for engines that draw it as a texture
+// (Bevy's Image, macroquad's Texture2D, an egui TextureHandle). The
+// texture has to be recreated when the program switches resolution, which
+// update() reports.
+//
+// With a `PhosphorFilter` set, pixels fade out instead of going off at
+// once, and update() has to be called every frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RgbaTexture {
+    colors: [[u8; 4]; 4],
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    phosphor: Option<PhosphorFilter>,
+}
+
+impl RgbaTexture {
+    // An empty texture colouring pixel values with `colors`.
+    pub fn new(colors: [[u8; 4]; 4]) -> Self {
+        RgbaTexture {
+            colors,
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+            phosphor: None,
+        }
+    }
+
+    // Copy the screen in. Returns true if its size changed.
+    pub fn update(&mut self, fb: &Framebuffer) -> bool {
+        let resized = (fb.width(), fb.height()) != (self.width, self.height);
+        self.width = fb.width();
+        self.height = fb.height();
+        self.pixels.resize(self.width * self.height * 4, 0);
+        match &mut self.phosphor {
+            Some(phosphor) => {
+                phosphor.update(fb);
+                phosphor.write_rgba(&self.colors, &mut self.pixels);
+            }
+            None => fb.write_rgba(&self.colors, &mut self.pixels),
+        }
+        resized
+    }
+
+    pub fn colors(&self) -> &[[u8; 4]; 4] {
+        &self.colors
+    }
+
+    pub fn set_colors(&mut self, colors: [[u8; 4]; 4]) {
+        self.colors = colors;
+    }
+
+    // Fade pixels out with `phosphor`, or not with None.
+    pub fn set_phosphor(&mut self, phosphor: Option<PhosphorFilter>) {
+        self.phosphor = phosphor;
+    }
+
+    // Whether the picture is still changing as pixels fade, so it needs
+    // updating and redrawing even if the screen stayed the same.
+    pub fn is_fading(&self) -> bool {
+        self.phosphor
+            .as_ref()
+            .is_some_and(|phosphor| phosphor.is_fading())
+    }
+
+    pub fn has_phosphor(&self) -> bool {
+        self.phosphor.is_some()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Rows top to bottom, four bytes a pixel.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let colors = [[0, 0, 0, 255], [255; 4], [1; 4], [2; 4]];
+        let mut texture = RgbaTexture::new(colors);
+        let mut fb = Framebuffer::new();
+        fb.toggle(1, 0);
+
+        assert!(texture.update(&fb));
+        assert_eq!((texture.width(), texture.height()), (64, 32));
+        assert_eq!(texture.pixels()[..8], [0, 0, 0, 255, 255, 255, 255, 255]);
+        assert!(!texture.update(&fb));
+
+        fb.resize(128, 64);
+        assert!(texture.update(&fb));
+        assert_eq!(texture.pixels().len(), 128 * 64 * 4);
+
+        texture.set_phosphor(Some(PhosphorFilter::new(2)));
+        fb.toggle(0, 0);
+        texture.update(&fb);
+        fb.toggle(0, 0);
+        texture.update(&fb);
+        assert!(texture.is_fading());
+        assert_eq!(texture.pixels()[..4], [127, 127, 127, 255]);
+    }
+}
@@ -0,0 +1,100 @@
+use core::time::Duration;
+
+// Rate at which the delay and sound timers count down.
+pub const TIMER_HZ: u32 = 60;
+
+// The delay and sound timers. They count down at 60Hz independently of the
+// CPU clock, so they are driven by elapsed wall (or virtual) time rather
+// than by instruction count.
+#[derive(Clone, Debug, Default)]
+pub struct Timers {
+    delay: u8,
+    sound: u8,
+    // Time accumulated towards the next tick.
+    pending: Duration,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Timers::default()
+    }
+
+    pub fn delay(&self) -> u8 {
+        self.delay
+    }
+
+    pub fn sound(&self) -> u8 {
+        self.sound
+    }
+
+    pub fn set_delay(&mut self, value: u8) {
+        self.delay = value;
+    }
+
+    pub fn set_sound(&mut self, value: u8) {
+        self.sound = value;
+    }
+
+    // Count both timers down by a single 60Hz tick.
+    pub fn tick(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    // Advance the timers by `elapsed` time, ticking once for every full
+    // 1/60th of a second. Leftover time carries over to the next call.
+    // Returns the number of ticks performed.
+    pub fn update(&mut self, elapsed: Duration) -> u32 {
+        let period = Duration::from_secs(1) / TIMER_HZ;
+        let mut ticks = 0;
+
+        self.pending += elapsed;
+        while self.pending >= period {
+            self.pending -= period;
+            self.tick();
+            ticks += 1;
+        }
+
+        ticks
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tick_saturates() {
+        let mut timers = Timers::new();
+        timers.set_delay(1);
+        timers.set_sound(2);
+
+        timers.tick();
+        timers.tick();
+        assert_eq!(timers.delay(), 0);
+        assert_eq!(timers.sound(), 0);
+    }
+
+    #[test]
+    fn test_update_ticks_at_60hz() {
+        let mut timers = Timers::new();
+        timers.set_delay(100);
+
+        assert_eq!(timers.update(Duration::from_secs(1)), 60);
+        assert_eq!(timers.delay(), 40);
+    }
+
+    #[test]
+    fn test_update_carries_remainder() {
+        let mut timers = Timers::new();
+        timers.set_delay(10);
+
+        // Many small updates add up to the same number of ticks as one
+        // large update, however fast the caller runs.
+        for _ in 0..1000 {
+            timers.update(Duration::from_micros(50));
+        }
+
+        assert_eq!(timers.delay(), 7, "50ms is three full ticks");
+    }
+}
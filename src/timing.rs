@@ -0,0 +1,85 @@
+use crate::instruction::Instruction;
+
+// How many instructions make up a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timing {
+    // A fixed number of instructions per frame, `Machine::cycles_per_frame`.
+    Uniform,
+    // Each instruction costs what it took the COSMAC VIP interpreter, see
+    // `vip_cycles`, out of the machine cycles the VIP had per frame.
+    CosmacVip,
+}
+
+// CDP1802 machine cycles (8 clocks at 1.7609MHz) in one 60Hz frame...
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+// ...of which the CDP1861 video DMA takes 8 bytes on each of 128 lines,
+// leaving the rest to the interpreter.
+pub const VIP_DMA_CYCLES: u32 = 1024;
+
+// Fetching and decoding an instruction, paid by every instruction.
+const FETCH_CYCLES: u32 = 40;
+
+// Machine cycles the VIP interpreter spent on `instruction` given the
+// registers `v` it runs with, and whether it skipped. The figures follow
+// the lengths of the interpreter's routines; instructions the VIP never
+// had are charged like a register operation.
+pub fn vip_cycles(instruction: Instruction, v: &[u8; 16], skipped: bool) -> u32 {
+    use Instruction::*;
+
+    let skip = if skipped { 4 } else { 0 };
+    let cycles = match instruction {
+        Cls => 3000,
+        Ret => 10,
+        Sys(_) => 10,
+        Jump(_) => 12,
+        Call(_) => 26,
+        SkipEqByte(..) | SkipNeByte(..) => 10 + skip,
+        SkipEqReg(..) | SkipNeReg(..) => 14 + skip,
+        LoadByte(..) => 6,
+        AddByte(..) => 10,
+        LoadReg(..) | Or(..) | And(..) | Xor(..) | AddReg(..) | Sub(..) | ShiftRight(..)
+        | SubN(..) | ShiftLeft(..) => 44,
+        LoadI(_) => 12,
+        JumpV0(_) => 22,
+        Random(..) => 36,
+        // Every sprite row is shifted into place one bit at a time, so
+        // sprites further from a byte boundary take longer.
+        Draw(x, _, n) => 26 + n as u32 * (46 + 8 * (v[x as usize] % 8) as u32),
+        SkipKeyPressed(_) | SkipKeyReleased(_) => 14 + skip,
+        LoadDelay(_) | SetDelay(_) | SetSound(_) => 10,
+        WaitKey(_) => 20,
+        AddI(_) | LoadFont(_) => 16,
+        // One subtraction loop pass per unit of each digit.
+        Bcd(x) => {
+            let value = v[x as usize];
+            84 + 16 * (value / 100 + value / 10 % 10 + value % 10) as u32
+        }
+        StoreRegs(x) | LoadRegs(x) => 14 + 14 * (x as u32 + 1),
+        _ => 44,
+    };
+
+    FETCH_CYCLES + cycles
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vip_cycles() {
+        let mut v = [0; 16];
+        assert_eq!(vip_cycles(Instruction::LoadByte(0, 1), &v, false), 46);
+        assert_eq!(vip_cycles(Instruction::SkipEqByte(0, 0), &v, true), 54);
+
+        let aligned = vip_cycles(Instruction::Draw(1, 2, 5), &v, false);
+        v[1] = 3;
+        let shifted = vip_cycles(Instruction::Draw(1, 2, 5), &v, false);
+        assert_eq!(shifted - aligned, 5 * 3 * 8, "three more shifts per row");
+
+        v[2] = 199;
+        assert_eq!(
+            vip_cycles(Instruction::Bcd(2), &v, false),
+            FETCH_CYCLES + 84 + 16 * 19
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the file is checked by default. Stat'ing every frame would be
+// wasteful and nobody saves faster than this.
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(250);
+
+// Polls a ROM file for changes, for a live edit-assemble-run loop. Uses the
+// modification time and size as a cheap first check, then compares the
+// contents so touching the file without changing it doesn't reload.
+pub struct RomWatcher {
+    path: PathBuf,
+    interval: Duration,
+    last_check: Option<Instant>,
+    // Modification time and size at the last check.
+    stamp: Option<(SystemTime, u64)>,
+    // Contents last handed out, or read when watching started.
+    contents: Vec<u8>,
+}
+
+impl RomWatcher {
+    // Start watching `path`, treating its current contents as already seen.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let stamp = stamp(&path);
+        let contents = fs::read(&path).unwrap_or_default();
+
+        RomWatcher {
+            path,
+            interval: DEFAULT_INTERVAL,
+            last_check: None,
+            stamp,
+            contents,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Minimum time between two looks at the file.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    // The new contents if the file was rewritten since the last call. A
+    // missing or empty file (e.g. halfway through a save) is skipped until
+    // it has contents again.
+    pub fn poll(&mut self) -> Option<Vec<u8>> {
+        let now = Instant::now();
+        if let Some(last) = self.last_check {
+            if now.duration_since(last) < self.interval {
+                return None;
+            }
+        }
+        self.last_check = Some(now);
+
+        let stamp = stamp(&self.path);
+        if stamp.is_none() || stamp == self.stamp {
+            return None;
+        }
+        self.stamp = stamp;
+
+        let contents = fs::read(&self.path).ok()?;
+        if contents.is_empty() || contents == self.contents {
+            return None;
+        }
+        self.contents = contents.clone();
+        Some(contents)
+    }
+}
+
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_poll() {
+        let path = std::env::temp_dir().join(format!("chip8-watch-{}.ch8", std::process::id()));
+        fs::write(&path, [0x12, 0x00]).unwrap();
+
+        let mut watcher = RomWatcher::new(&path);
+        watcher.set_interval(Duration::from_secs(0));
+        assert_eq!(watcher.poll(), None, "the initial contents were seen");
+
+        fs::write(&path, [0x12, 0x00, 0x00]).unwrap();
+        assert_eq!(watcher.poll(), Some(vec![0x12, 0x00, 0x00]));
+        assert_eq!(watcher.poll(), None);
+
+        fs::write(&path, []).unwrap();
+        assert_eq!(watcher.poll(), None, "empty files are skipped");
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(watcher.poll(), None);
+    }
+}
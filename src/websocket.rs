@@ -0,0 +1,296 @@
+// Just enough of HTTP/1.1 and WebSocket (RFC 6455) for the server side of
+// `StreamServer`: reading a request, the upgrade handshake, and messages
+// in frames.
+use std::io::{self, BufRead, Read, Write};
+
+use crate::hash::{base64, sha1};
+
+// Appended to the client's key before hashing it for the handshake.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// Longest message taken from a client; key events are a few bytes.
+const MAX_MESSAGE: usize = 1 << 16;
+
+// Longest request or header line, and most header lines, in a request.
+const MAX_LINE: u64 = 8192;
+const MAX_HEADERS: usize = 100;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close,
+}
+
+// The request line's path and the headers of an HTTP request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Request {
+    pub(crate) path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    // Read a request up to the blank line ending its headers.
+    pub(crate) fn read<R: BufRead>(input: &mut R) -> io::Result<Request> {
+        let mut line = String::new();
+        read_line(input, &mut line)?;
+        let path = line
+            .split_whitespace()
+            .nth(1)
+            .ok_or_else(|| invalid("malformed request line"))?
+            .to_string();
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            if read_line(input, &mut line)? == 0 {
+                return Err(invalid("request ended inside the headers"));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                return Ok(Request { path, headers });
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(invalid("too many headers"));
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+    }
+
+    // The value of header `name`, ignoring case.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The client's handshake key, if this asks to upgrade to WebSocket.
+    pub(crate) fn websocket_key(&self) -> Option<&str> {
+        let upgrade = self.header("Upgrade")?;
+        match upgrade.eq_ignore_ascii_case("websocket") {
+            true => self.header("Sec-WebSocket-Key"),
+            false => None,
+        }
+    }
+}
+
+// The Sec-WebSocket-Accept value answering a client's key.
+pub(crate) fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()))
+}
+
+// Answer a request carrying `key`, switching the connection to frames.
+pub(crate) fn write_handshake<W: Write>(out: &mut W, key: &str) -> io::Result<()> {
+    write!(
+        out,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    out.flush()
+}
+
+// Send a message in one frame. Servers don't mask their frames; the test
+// client passes a mask, as clients must.
+pub(crate) fn write_message<W: Write>(
+    out: &mut W,
+    message: &Message,
+    mask: Option<[u8; 4]>,
+) -> io::Result<()> {
+    let (opcode, payload) = match message {
+        Message::Text(text) => (OP_TEXT, text.as_bytes()),
+        Message::Binary(bytes) => (OP_BINARY, &bytes[..]),
+        Message::Ping(bytes) => (OP_PING, &bytes[..]),
+        Message::Pong(bytes) => (OP_PONG, &bytes[..]),
+        Message::Close => (OP_CLOSE, &[][..]),
+    };
+
+    let mut frame = vec![0x80 | opcode];
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    match payload.len() {
+        len if len < 126 => frame.push(mask_bit | len as u8),
+        len if len <= 0xffff => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    match mask {
+        Some(mask) => {
+            frame.extend_from_slice(&mask);
+            frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+        }
+        None => frame.extend_from_slice(payload),
+    }
+    out.write_all(&frame)?;
+    out.flush()
+}
+
+// Reads messages off a connection, joining fragmented ones.
+pub(crate) struct MessageReader<R: Read> {
+    input: R,
+    // Opcode and payload so far of a fragmented message, which control
+    // frames may interrupt.
+    partial: Option<(u8, Vec<u8>)>,
+}
+
+impl<R: Read> MessageReader<R> {
+    pub(crate) fn new(input: R) -> Self {
+        MessageReader {
+            input,
+            partial: None,
+        }
+    }
+
+    pub(crate) fn read(&mut self) -> io::Result<Message> {
+        loop {
+            if let Some(message) = self.read_frame()? {
+                return Ok(message);
+            }
+        }
+    }
+
+    // Read one frame, returning the message it completes, if any.
+    fn read_frame(&mut self) -> io::Result<Option<Message>> {
+        let input = &mut self.input;
+        let mut head = [0; 2];
+        input.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0f;
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut len = [0; 2];
+                input.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as u64
+            }
+            127 => {
+                let mut len = [0; 8];
+                input.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => len as u64,
+        };
+        let pending = self
+            .partial
+            .as_ref()
+            .map_or(0, |(_, payload)| payload.len());
+        if len > (MAX_MESSAGE - pending) as u64 {
+            return Err(invalid("message too long"));
+        }
+        let mut mask = [0; 4];
+        if head[1] & 0x80 != 0 {
+            input.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; len as usize];
+        input.read_exact(&mut payload)?;
+        payload
+            .iter_mut()
+            .zip(mask.iter().cycle())
+            .for_each(|(byte, m)| *byte ^= m);
+
+        match opcode {
+            OP_CLOSE => return Ok(Some(Message::Close)),
+            OP_PING => return Ok(Some(Message::Ping(payload))),
+            OP_PONG => return Ok(Some(Message::Pong(payload))),
+            OP_CONTINUATION => match self.partial.as_mut() {
+                Some((_, data)) => data.extend_from_slice(&payload),
+                None => return Err(invalid("continuation without a message")),
+            },
+            OP_TEXT | OP_BINARY if self.partial.is_none() => self.partial = Some((opcode, payload)),
+            _ => return Err(invalid("unexpected opcode")),
+        }
+        if !fin {
+            return Ok(None);
+        }
+
+        let (opcode, data) = self
+            .partial
+            .take()
+            .expect("chip8.websocket: message started");
+        match opcode {
+            OP_TEXT => String::from_utf8(data)
+                .map(|text| Some(Message::Text(text)))
+                .map_err(|_| invalid("text message is not UTF-8")),
+            _ => Ok(Some(Message::Binary(data))),
+        }
+    }
+}
+
+// `BufRead::read_line`, refusing lines longer than MAX_LINE rather than
+// buffering whatever a client sends before its first newline.
+fn read_line<R: BufRead>(input: &mut R, line: &mut String) -> io::Result<usize> {
+    let read = input.by_ref().take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(invalid("request line too long"));
+    }
+    Ok(read)
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_handshake() {
+        // The example from RFC 6455, section 1.3.
+        let request = "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\n\
+                       Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+        let request = Request::read(&mut Cursor::new(request)).unwrap();
+        assert_eq!(request.path, "/chat");
+        assert_eq!(request.header("host"), Some("server.example.com"));
+        let key = request.websocket_key().unwrap();
+        assert_eq!(accept_key(key), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_request_limits() {
+        let long = "GET /".to_string() + &"a".repeat(1 << 16);
+        assert!(Request::read(&mut Cursor::new(long)).is_err());
+        let many = "GET / HTTP/1.1\r\n".to_string() + &"X: y\r\n".repeat(1000) + "\r\n";
+        assert!(Request::read(&mut Cursor::new(many)).is_err());
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut wire = Vec::new();
+        let text = Message::Text("{\"key\":5}".to_string());
+        write_message(&mut wire, &text, Some([1, 2, 3, 4])).unwrap();
+        let frame = Message::Binary(vec![7; 300]);
+        write_message(&mut wire, &frame, None).unwrap();
+        assert_eq!(wire[2 + 4 + 9..2 + 4 + 9 + 4], [0x82, 126, 0x01, 0x2c]);
+
+        let mut reader = MessageReader::new(Cursor::new(wire));
+        assert_eq!(reader.read().unwrap(), text);
+        assert_eq!(reader.read().unwrap(), frame);
+        assert!(reader.read().is_err());
+
+        // "Hel" and "lo" in two frames, a ping between them.
+        let fragmented = [
+            0x01, 0x03, b'H', b'e', b'l', 0x89, 0x00, 0x80, 0x02, b'l', b'o',
+        ];
+        let mut reader = MessageReader::new(&fragmented[..]);
+        assert_eq!(reader.read().unwrap(), Message::Ping(Vec::new()));
+        assert_eq!(reader.read().unwrap(), Message::Text("Hello".to_string()));
+    }
+}
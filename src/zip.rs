@@ -0,0 +1,568 @@
+use std::io::Read;
+
+use crate::error::{Chip8Error, Result};
+use crate::hash::crc32;
+use crate::memory::MAX_RAM;
+
+// Just enough of the ZIP format to pull ROMs out of collections: stored and
+// deflated entries, no encryption, no ZIP64, no multi-disk archives.
+
+const LOCAL_HEADER_SIG: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY_SIG: u32 = 0x0605_4b50;
+// Fixed part of the end of central directory record.
+const END_OF_DIRECTORY_SIZE: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+// Whether `data` starts like a ZIP archive. No CHIP-8 program starts with
+// these bytes: 0x504b is not a valid 5xy0.
+pub(crate) fn is_zip(data: &[u8]) -> bool {
+    data.len() >= 4 && read_u32(data, 0) == Some(LOCAL_HEADER_SIG)
+}
+
+// Read a whole archive from `reader` and extract a ROM from it: the entry
+// called `name` (matched against the full path or just the file name), or
+// the first `.ch8` entry when no name is given.
+pub(crate) fn extract_rom<R: Read>(mut reader: R, name: Option<&str>) -> Result<Vec<u8>> {
+    let mut archive = Vec::new();
+    reader.read_to_end(&mut archive)?;
+    extract_rom_from_bytes(&archive, name)
+}
+
+pub(crate) fn extract_rom_from_bytes(archive: &[u8], name: Option<&str>) -> Result<Vec<u8>> {
+    let entry = entries(archive)?
+        .into_iter()
+        .find(|entry| entry.matches(name))
+        .ok_or_else(|| Chip8Error::RomNotInArchive {
+            name: name.map(String::from),
+        })?;
+
+    entry.extract(archive)
+}
+
+// A file listed in the central directory.
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+impl Entry {
+    fn matches(&self, name: Option<&str>) -> bool {
+        if self.name.ends_with('/') {
+            return false;
+        }
+
+        match name {
+            Some(name) => self.name == name || self.name.rsplit('/').next() == Some(name),
+            None => self.name.to_ascii_lowercase().ends_with(".ch8"),
+        }
+    }
+
+    fn extract(&self, archive: &[u8]) -> Result<Vec<u8>> {
+        // The sizes come from the archive; don't allocate for anything
+        // bigger than any machine's memory.
+        if self.size > MAX_RAM {
+            return Err(invalid("entry too large for a ROM"));
+        }
+
+        let header = self.header_offset;
+        if read_u32(archive, header) != Some(LOCAL_HEADER_SIG) {
+            return Err(invalid("bad local file header"));
+        }
+
+        let name_len = read_u16(archive, header + 26).ok_or_else(|| invalid("truncated"))?;
+        let extra_len = read_u16(archive, header + 28).ok_or_else(|| invalid("truncated"))?;
+        let start = header + 30 + name_len as usize + extra_len as usize;
+        let data = archive
+            .get(start..start + self.compressed_size)
+            .ok_or_else(|| invalid("truncated entry"))?;
+
+        let bytes = match self.method {
+            METHOD_STORED => data.to_vec(),
+            METHOD_DEFLATED => inflate(data, self.size)?,
+            _ => return Err(invalid("unsupported compression method")),
+        };
+
+        if bytes.len() != self.size || crc32(&bytes) != self.crc {
+            return Err(invalid("entry checksum mismatch"));
+        }
+
+        Ok(bytes)
+    }
+}
+
+// List the archive's files from its central directory.
+fn entries(archive: &[u8]) -> Result<Vec<Entry>> {
+    // The end record sits at the very end, followed only by a comment of at
+    // most 64K.
+    let last = archive
+        .len()
+        .checked_sub(END_OF_DIRECTORY_SIZE)
+        .ok_or_else(|| invalid("too short"))?;
+    let first = last.saturating_sub(0xffff);
+    let end = (first..=last)
+        .rev()
+        .find(|&at| read_u32(archive, at) == Some(END_OF_DIRECTORY_SIG))
+        .ok_or_else(|| invalid("no end of central directory"))?;
+
+    let count = read_u16(archive, end + 10).unwrap();
+    let mut at = read_u32(archive, end + 16).unwrap() as usize;
+    let mut entries = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        if read_u32(archive, at) != Some(CENTRAL_HEADER_SIG) {
+            return Err(invalid("bad central directory"));
+        }
+
+        let field16 = |offset| read_u16(archive, at + offset).ok_or_else(|| invalid("truncated"));
+        let field32 = |offset| read_u32(archive, at + offset).ok_or_else(|| invalid("truncated"));
+        let name_len = field16(28)? as usize;
+        let extra_len = field16(30)? as usize;
+        let comment_len = field16(32)? as usize;
+        let name = archive
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| invalid("truncated"))?;
+
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: field16(10)?,
+            crc: field32(16)?,
+            compressed_size: field32(20)? as usize,
+            size: field32(24)? as usize,
+            header_offset: field32(42)? as usize,
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+
+    Ok(entries)
+}
+
+fn invalid(reason: &'static str) -> Chip8Error {
+    Chip8Error::InvalidArchive { reason }
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    let bytes = data.get(at..at + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    let bytes = data.get(at..at + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Base lengths and extra bits for length codes 257..285.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// Base distances and extra bits for distance codes 0..29.
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order in which code length code lengths are stored in dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const MAX_BITS: usize = 15;
+
+// LSB-first bit reader over a deflate stream.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bits {
+            data,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn take(&mut self, n: u32) -> Result<u32> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| invalid("truncated deflate stream"))?;
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+
+        let value = self.buffer & ((1u64 << n) - 1) as u32;
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    // Drop the rest of the current byte (stored blocks are byte aligned).
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// Canonical Huffman code, decoded one bit at a time.
+struct Huffman {
+    // Number of codes of each length.
+    counts: [u16; MAX_BITS + 1],
+    // Symbols ordered by code.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+
+        for len in 1..=MAX_BITS {
+            code |= bits.take(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(invalid("bad huffman code"))
+    }
+}
+
+// Decompress a raw deflate stream expected to produce `size` bytes. Output
+// past `size` is refused so a corrupt entry can't balloon.
+fn inflate(data: &[u8], size: usize) -> Result<Vec<u8>> {
+    let mut bits = Bits::new(data);
+    let mut out = Vec::with_capacity(size);
+
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => {
+                bits.align();
+                let len = read_u16(data, bits.pos).ok_or_else(|| invalid("truncated"))?;
+                let nlen = read_u16(data, bits.pos + 2).ok_or_else(|| invalid("truncated"))?;
+                if len != !nlen {
+                    return Err(invalid("bad stored block length"));
+                }
+                let start = bits.pos + 4;
+                let block = data
+                    .get(start..start + len as usize)
+                    .ok_or_else(|| invalid("truncated"))?;
+                out.extend_from_slice(block);
+                bits.pos = start + len as usize;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].iter_mut().for_each(|l| *l = 8);
+                lengths[144..256].iter_mut().for_each(|l| *l = 9);
+                lengths[256..280].iter_mut().for_each(|l| *l = 7);
+                lengths[280..].iter_mut().for_each(|l| *l = 8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut bits, &literals, &distances, &mut out, size)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &literals, &distances, &mut out, size)?;
+            }
+            _ => return Err(invalid("bad deflate block type")),
+        }
+
+        if out.len() > size {
+            return Err(invalid("entry larger than declared"));
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+// Read the literal/length and distance codes of a dynamic block.
+fn read_dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman)> {
+    let literal_count = bits.take(5)? as usize + 257;
+    let distance_count = bits.take(5)? as usize + 1;
+    let code_length_count = bits.take(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &at in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[at] = bits.take(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut at = 0;
+    while at < lengths.len() {
+        let symbol = code_length_code.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..at]
+                    .last()
+                    .ok_or_else(|| invalid("repeat with no previous length"))?;
+                (previous, 3 + bits.take(2)? as usize)
+            }
+            17 => (0, 3 + bits.take(3)? as usize),
+            _ => (0, 11 + bits.take(7)? as usize),
+        };
+
+        let run = lengths
+            .get_mut(at..at + repeat)
+            .ok_or_else(|| invalid("too many code lengths"))?;
+        run.iter_mut().for_each(|l| *l = value);
+        at += repeat;
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+// Decode symbols of a Huffman block up to its end-of-block code.
+fn inflate_block(
+    bits: &mut Bits,
+    literals: &Huffman,
+    distances: &Huffman,
+    out: &mut Vec<u8>,
+    size: usize,
+) -> Result<()> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let code = symbol - 257;
+            if code >= LENGTH_BASE.len() {
+                return Err(invalid("bad length code"));
+            }
+            let len = LENGTH_BASE[code] as usize + bits.take(LENGTH_EXTRA[code] as u32)? as usize;
+
+            let code = distances.decode(bits)? as usize;
+            if code >= DIST_BASE.len() {
+                return Err(invalid("bad distance code"));
+            }
+            let dist = DIST_BASE[code] as usize + bits.take(DIST_EXTRA[code] as u32)? as usize;
+            if dist > out.len() {
+                return Err(invalid("distance before start of output"));
+            }
+
+            // Byte by byte: the copy may overlap what it produces.
+            let start = out.len() - dist;
+            for k in 0..len {
+                let byte = out[start + k];
+                out.push(byte);
+            }
+        }
+
+        if out.len() > size {
+            return Err(invalid("entry larger than declared"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // LD V0, 0; LD F, V0; DRW V0, V0, 5; LD ST, V0 (V0 = 3); JP 0x20a
+    const ROM: [u8; 12] = [
+        0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0x60, 0x03, 0xf0, 0x18, 0x12, 0x0a,
+    ];
+
+    // ROM repeated four times, compressed with a fixed Huffman block.
+    const ROM_X4_DEFLATED: [u8; 17] = [
+        0x4b, 0x60, 0xf8, 0xa0, 0x79, 0x81, 0x35, 0x81, 0xf9, 0x83, 0x84, 0x10, 0x57, 0x02, 0x11,
+        0x6c, 0x00,
+    ];
+
+    // Same bytes as `ROM`, deflated.
+    const ROM_DEFLATED: [u8; 14] = [
+        0x4b, 0x60, 0xf8, 0xa0, 0x79, 0x81, 0x35, 0x81, 0xf9, 0x83, 0x84, 0x10, 0x17, 0x00,
+    ];
+
+    // 64 pseudo random bytes from `lcg_bytes()`, compressed with a dynamic
+    // Huffman block.
+    const LCG_DEFLATED: [u8; 35] = [
+        0x3d, 0x8a, 0xb1, 0x11, 0x00, 0x00, 0x10, 0xc1, 0xcc, 0x60, 0xff, 0x2a, 0x93, 0x3e, 0x8a,
+        0x57, 0xb8, 0x5c, 0x40, 0x89, 0x81, 0x94, 0xa0, 0x84, 0xc5, 0xec, 0x47, 0x5b, 0x7b, 0x0b,
+        0xd7, 0xd3, 0xf3, 0xe0, 0x00,
+    ];
+
+    fn lcg_bytes() -> Vec<u8> {
+        let mut x = 1u32;
+        (0..64)
+            .map(|_| {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                [0x00, 0x12, 0xa2][((x >> 16) % 3) as usize]
+            })
+            .collect()
+    }
+
+    // Build an archive from (name, method, stored data, uncompressed data).
+    fn archive(files: &[(&str, u16, &[u8], &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut directory = Vec::new();
+
+        for &(name, method, data, plain) in files {
+            let offset = out.len() as u32;
+            let fields = |out: &mut Vec<u8>| {
+                out.extend_from_slice(&[20, 0, 0, 0]);
+                out.extend_from_slice(&method.to_le_bytes());
+                out.extend_from_slice(&[0; 4]);
+                out.extend_from_slice(&crc32(plain).to_le_bytes());
+                out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                out.extend_from_slice(&(plain.len() as u32).to_le_bytes());
+                out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+                out.extend_from_slice(&[0; 2]);
+            };
+
+            out.extend_from_slice(&LOCAL_HEADER_SIG.to_le_bytes());
+            fields(&mut out);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(data);
+
+            directory.extend_from_slice(&CENTRAL_HEADER_SIG.to_le_bytes());
+            directory.extend_from_slice(&[20, 0]);
+            fields(&mut directory);
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let directory_offset = out.len() as u32;
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(&END_OF_DIRECTORY_SIG.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&directory_offset.to_le_bytes());
+        out.extend_from_slice(&[0; 2]);
+        out
+    }
+
+    #[test]
+    fn test_inflate() {
+        // Stored block.
+        assert_eq!(
+            inflate(&[0x01, 0x02, 0x00, 0xfd, 0xff, 0x12, 0x00], 2),
+            Ok(vec![0x12, 0x00])
+        );
+        assert_eq!(inflate(&ROM_X4_DEFLATED, 48), Ok(ROM.repeat(4)));
+        assert_eq!(inflate(&LCG_DEFLATED, 64), Ok(lcg_bytes()));
+        assert!(inflate(&ROM_X4_DEFLATED, 47).is_err(), "output is capped");
+        assert!(inflate(&ROM_X4_DEFLATED[..8], 48).is_err(), "truncated");
+    }
+
+    #[test]
+    fn test_extract_first_ch8() {
+        let zip = archive(&[
+            ("readme.txt", METHOD_STORED, b"hi", b"hi"),
+            ("games/pong.ch8", METHOD_DEFLATED, &ROM_DEFLATED, &ROM),
+            (
+                "games/halt.CH8",
+                METHOD_STORED,
+                &[0x12, 0x00],
+                &[0x12, 0x00],
+            ),
+        ]);
+
+        assert!(is_zip(&zip));
+        assert_eq!(extract_rom(&zip[..], None), Ok(ROM.to_vec()));
+        assert_eq!(
+            extract_rom(&zip[..], Some("halt.CH8")),
+            Ok(vec![0x12, 0x00])
+        );
+        assert_eq!(
+            extract_rom(&zip[..], Some("games/halt.CH8")),
+            Ok(vec![0x12, 0x00])
+        );
+        assert_eq!(
+            extract_rom(&zip[..], Some("tetris.ch8")),
+            Err(Chip8Error::RomNotInArchive {
+                name: Some("tetris.ch8".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn test_extract_checks_crc() {
+        let zip = archive(&[("halt.ch8", METHOD_STORED, &[0x12, 0x02], &[0x12, 0x00])]);
+
+        assert_eq!(
+            extract_rom(&zip[..], None),
+            Err(Chip8Error::InvalidArchive {
+                reason: "entry checksum mismatch"
+            })
+        );
+    }
+
+    #[test]
+    fn test_extract_refuses_huge_entries() {
+        let mut zip = archive(&[("halt.ch8", METHOD_STORED, &[0x12, 0x00], &[0x12, 0x00])]);
+        // Claim a 4GB uncompressed size in the central directory.
+        let directory = read_u32(&zip, zip.len() - END_OF_DIRECTORY_SIZE + 16).unwrap() as usize;
+        zip[directory + 24..directory + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert_eq!(
+            extract_rom(&zip[..], None),
+            Err(Chip8Error::InvalidArchive {
+                reason: "entry too large for a ROM"
+            })
+        );
+    }
+
+    #[test]
+    fn test_not_an_archive() {
+        assert!(!is_zip(&ROM));
+        assert!(extract_rom(&ROM[..], None).is_err());
+    }
+}
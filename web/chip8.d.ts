@@ -0,0 +1,75 @@
+export type Chip8State = "running" | "waitingForKey" | "waitingForVblank" | "exited" | "halted";
+
+export class Chip8 {
+  static load(url: string | URL): Promise<Chip8>;
+  constructor(instance: WebAssembly.Instance, seed?: number);
+  onBeep: ((on: boolean) => void) | null;
+  readonly width: number;
+  readonly height: number;
+  readonly isBeeping: boolean;
+  speed: number;
+  free(): void;
+  loadRom(rom: ArrayBuffer | ArrayBufferView): void;
+  step(): Chip8State;
+  runFrame(): boolean;
+  frameBuffer(): ImageData;
+  setPhosphor(frames: number): void;
+  setPalette(colors: number[][]): void;
+  keyDown(key: number): void;
+  keyUp(key: number): void;
+  keyFor(event: KeyboardEvent): number;
+  saveState(): Uint8Array;
+  screenshot(scale?: number): Uint8Array;
+  startRecording(format?: "gif" | "apng", scale?: number): void;
+  readonly recording: boolean;
+  stopRecording(): Uint8Array;
+  loadState(state: Uint8Array): void;
+  rplFlags: Uint8Array;
+}
+
+export class Beeper {
+  constructor(frequency?: number, volume?: number);
+  resume(): Promise<void>;
+  set(on: boolean): void;
+}
+
+export class CrtScreen {
+  constructor(canvas: HTMLCanvasElement);
+  crt: boolean;
+  draw(image?: ImageData): void;
+}
+
+export function screenshotName(date?: Date): string;
+export function recordingName(format: "gif" | "apng", date?: Date): string;
+export function download(bytes: Uint8Array, name: string, type?: string): void;
+
+export class ScreenSize {
+  constructor(canvas: HTMLCanvasElement, container: HTMLElement, storage?: Storage);
+  readonly scale: number;
+  readonly current: number;
+  readonly fullscreen: boolean;
+  onResize: () => void;
+  setScale(scale: number): void;
+  setResolution(width: number, height: number): void;
+  toggleFullscreen(): Promise<void>;
+  fit(width: number, height: number): number;
+  update(): void;
+}
+
+export const KEYPAD_LAYOUT: readonly number[];
+
+export class VirtualKeypad {
+  constructor(container: HTMLElement, onKey: (key: number, pressed: boolean) => void);
+  readonly element: HTMLDivElement;
+  remove(): void;
+}
+
+export function romKey(rom: ArrayBuffer | ArrayBufferView): Promise<string>;
+
+export class RomStorage {
+  constructor(key: string, storage?: Storage);
+  saveState(state: Uint8Array): void;
+  loadState(): Uint8Array | null;
+  saveRplFlags(flags: Uint8Array): void;
+  loadRplFlags(): Uint8Array | null;
+}
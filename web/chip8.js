@@ -0,0 +1,558 @@
+// The emulator as a JavaScript class, around the exports of a wasm32 build
+// with the `wasm` feature (see src/ffi.rs).
+//
+//   import { Chip8 } from "chip8-wasm";
+//   const chip8 = await Chip8.load(new URL("chip8.wasm", import.meta.url));
+//   chip8.loadRom(await (await fetch("pong.ch8")).arrayBuffer());
+//   chip8.onBeep = (on) => ...;
+//   requestAnimationFrame(function frame() {
+//     if (chip8.runFrame()) context.putImageData(chip8.frameBuffer(), 0, 0);
+//     requestAnimationFrame(frame);
+//   });
+
+// What step() returns, by the numbers chip8_step() uses.
+const STATES = ["running", "waitingForKey", "waitingForVblank", "exited", "halted"];
+
+export class Chip8 {
+  // Fetch and instantiate the module at `url`, e.g. chip8.wasm next to
+  // this file.
+  static async load(url) {
+    const { instance } = await WebAssembly.instantiateStreaming(fetch(url));
+    return new Chip8(instance);
+  }
+
+  // Wrap an already instantiated module.
+  constructor(instance, seed = Math.random() * 2 ** 32) {
+    this.exports = instance.exports;
+    this.machine = this.exports.chip8_new(seed >>> 0);
+    // Called with true when the buzzer starts and false when it stops.
+    this.onBeep = null;
+    this.beeping = false;
+  }
+
+  // Release the machine; the object can't be used afterwards.
+  free() {
+    this.exports.chip8_free(this.machine);
+    this.machine = 0;
+  }
+
+  // Load and start a ROM from an ArrayBuffer or typed array. Throws if the
+  // emulator refuses it, e.g. when it's empty or too big.
+  loadRom(rom) {
+    const bytes = rom instanceof Uint8Array ? rom : new Uint8Array(rom.buffer || rom);
+    const buffer = this.exports.chip8_alloc(bytes.length);
+    new Uint8Array(this.exports.memory.buffer, buffer, bytes.length).set(bytes);
+    const loaded = this.exports.chip8_load_rom(this.machine, buffer, bytes.length);
+    this.exports.chip8_dealloc(buffer, bytes.length);
+    if (!loaded) throw new Error("chip8: ROM refused");
+    this.updateBeep();
+  }
+
+  // Execute one instruction, returning the state the machine is left in.
+  step() {
+    const state = this.exports.chip8_step(this.machine);
+    if (state < 0) throw new Error("chip8: the program crashed");
+    return STATES[state];
+  }
+
+  // Run one 60Hz frame, returning whether the screen changed.
+  runFrame() {
+    const changed = this.exports.chip8_run_frame(this.machine);
+    if (changed < 0) throw new Error("chip8: the program crashed");
+    this.updateBeep();
+    return changed > 0;
+  }
+
+  // The screen as an ImageData, ready for putImageData on a canvas as big
+  // as width x height.
+  frameBuffer() {
+    const width = this.width;
+    const height = this.height;
+    const pixels = new Uint8ClampedArray(
+      this.exports.memory.buffer,
+      this.exports.chip8_frame(this.machine),
+      width * height * 4,
+    );
+    // Copied, since the module's memory can move when it grows.
+    return new ImageData(new Uint8ClampedArray(pixels), width, height);
+  }
+
+  // Fade pixels out over this many frames, hiding the flicker of XOR
+  // drawing; 0 turns it off. While pixels fade runFrame() keeps returning
+  // true.
+  setPhosphor(frames) {
+    this.exports.chip8_set_phosphor(this.machine, frames);
+  }
+
+  // Colour frameBuffer() with four [r, g, b] colours: off, plane 1, plane
+  // 2 and both.
+  setPalette(colors) {
+    const buffer = this.exports.chip8_alloc(12);
+    new Uint8Array(this.exports.memory.buffer, buffer, 12).set(colors.flat());
+    this.exports.chip8_set_palette(this.machine, buffer);
+    this.exports.chip8_dealloc(buffer, 12);
+  }
+
+  get width() {
+    return this.exports.chip8_width(this.machine);
+  }
+
+  get height() {
+    return this.exports.chip8_height(this.machine);
+  }
+
+  // Instructions run per frame.
+  get speed() {
+    return this.exports.chip8_speed(this.machine);
+  }
+
+  set speed(cycles) {
+    this.exports.chip8_set_speed(this.machine, cycles);
+  }
+
+  // Press and release hex keys 0x0-0xF.
+  keyDown(key) {
+    this.exports.chip8_key_down(this.machine, key);
+  }
+
+  keyUp(key) {
+    this.exports.chip8_key_up(this.machine, key);
+  }
+
+  // The hex key for a KeyboardEvent on the 1234/QWER/ASDF/ZXCV block, or
+  // -1.
+  keyFor(event) {
+    if (event.key.length !== 1) return -1;
+    return this.exports.chip8_key_for_char(this.machine, event.key.codePointAt(0));
+  }
+
+  // The emulation state as bytes, see Cpu::save_state.
+  saveState() {
+    const state = this.exports.chip8_save_state(this.machine);
+    const len = this.exports.chip8_state_len(this.machine);
+    return new Uint8Array(this.exports.memory.buffer, state, len).slice();
+  }
+
+  // The screen as shown, as PNG bytes with every pixel `scale` pixels
+  // square.
+  screenshot(scale = 1) {
+    const png = this.exports.chip8_screenshot(this.machine, scale);
+    const len = this.exports.chip8_screenshot_len(this.machine);
+    return new Uint8Array(this.exports.memory.buffer, png, len).slice();
+  }
+
+  // Record every runFrame() from now on into an animation, "gif" or
+  // "apng", at the current palette and `scale`.
+  startRecording(format = "gif", scale = 1) {
+    const code = { gif: 0, apng: 1 }[format];
+    if (code === undefined || !this.exports.chip8_start_recording(this.machine, code, scale)) {
+      throw new Error(`chip8: unknown recording format ${format}`);
+    }
+  }
+
+  get recording() {
+    return Boolean(this.exports.chip8_is_recording(this.machine));
+  }
+
+  // Stop recording, returning the GIF or APNG file's bytes.
+  stopRecording() {
+    const file = this.exports.chip8_stop_recording(this.machine);
+    const len = this.exports.chip8_recording_len(this.machine);
+    return new Uint8Array(this.exports.memory.buffer, file, len).slice();
+  }
+
+  // Go back to a state from saveState(). Throws if it was refused.
+  loadState(state) {
+    const buffer = this.exports.chip8_alloc(state.length);
+    new Uint8Array(this.exports.memory.buffer, buffer, state.length).set(state);
+    const loaded = this.exports.chip8_load_state(this.machine, buffer, state.length);
+    this.exports.chip8_dealloc(buffer, state.length);
+    if (!loaded) throw new Error("chip8: save state refused");
+    this.updateBeep();
+  }
+
+  // The 16 RPL user flags (Fx75/Fx85) games keep high scores in.
+  get rplFlags() {
+    const flags = this.exports.chip8_rpl_flags(this.machine);
+    return new Uint8Array(this.exports.memory.buffer, flags, 16).slice();
+  }
+
+  set rplFlags(flags) {
+    const buffer = this.exports.chip8_alloc(16);
+    new Uint8Array(this.exports.memory.buffer, buffer, 16).set(flags.subarray(0, 16));
+    this.exports.chip8_set_rpl_flags(this.machine, buffer);
+    this.exports.chip8_dealloc(buffer, 16);
+  }
+
+  get isBeeping() {
+    return this.beeping;
+  }
+
+  updateBeep() {
+    const beeping = this.exports.chip8_is_beeping(this.machine) !== 0;
+    if (beeping !== this.beeping) {
+      this.beeping = beeping;
+      if (this.onBeep) this.onBeep(beeping);
+    }
+  }
+}
+
+// A square wave buzzer on WebAudio, for Chip8.onBeep:
+//
+//   const beeper = new Beeper();
+//   chip8.onBeep = (on) => beeper.set(on);
+//
+// Browsers only start audio after a user gesture, so call resume() from
+// e.g. the first keydown.
+export class Beeper {
+  constructor(frequency = 440, volume = 0.1) {
+    this.context = new AudioContext();
+    this.volume = volume;
+    this.gain = this.context.createGain();
+    this.gain.gain.value = 0;
+    this.gain.connect(this.context.destination);
+    const oscillator = this.context.createOscillator();
+    oscillator.type = "square";
+    oscillator.frequency.value = frequency;
+    oscillator.connect(this.gain);
+    oscillator.start();
+  }
+
+  resume() {
+    return this.context.resume();
+  }
+
+  // Start or stop the tone. The level ramps over a few milliseconds, which
+  // keeps the edges from clicking.
+  set(on) {
+    const now = this.context.currentTime;
+    this.gain.gain.cancelScheduledValues(now);
+    this.gain.gain.setTargetAtTime(on ? this.volume : 0, now, 0.005);
+  }
+}
+
+// Draws frames from Chip8.frameBuffer() on a canvas with WebGL, through a
+// CRT look unless `crt` is turned off: scanlines between the rows, a
+// slight barrel curvature and some bloom around lit pixels.
+//
+//   const screen = new CrtScreen(canvas);
+//   if (chip8.runFrame()) screen.draw(chip8.frameBuffer());
+//   screen.crt = false;
+//
+// The canvas is rendered at its displayed size, so size it with CSS.
+export class CrtScreen {
+  constructor(canvas) {
+    const gl = canvas.getContext("webgl");
+    if (!gl) throw new Error("chip8: WebGL is not available");
+    this.canvas = canvas;
+    this.gl = gl;
+    this.crt = true;
+    this.image = null;
+
+    const program = gl.createProgram();
+    gl.attachShader(program, compile(gl, gl.VERTEX_SHADER, CRT_VERTEX));
+    gl.attachShader(program, compile(gl, gl.FRAGMENT_SHADER, CRT_FRAGMENT));
+    gl.linkProgram(program);
+    if (!gl.getProgramParameter(program, gl.LINK_STATUS)) {
+      throw new Error(`chip8: ${gl.getProgramInfoLog(program)}`);
+    }
+    gl.useProgram(program);
+    this.uniforms = {
+      crt: gl.getUniformLocation(program, "u_crt"),
+      size: gl.getUniformLocation(program, "u_size"),
+    };
+
+    // A quad covering the canvas.
+    gl.bindBuffer(gl.ARRAY_BUFFER, gl.createBuffer());
+    gl.bufferData(gl.ARRAY_BUFFER, new Float32Array([-1, -1, 1, -1, -1, 1, 1, 1]), gl.STATIC_DRAW);
+    const position = gl.getAttribLocation(program, "a_position");
+    gl.enableVertexAttribArray(position);
+    gl.vertexAttribPointer(position, 2, gl.FLOAT, false, 0, 0);
+
+    gl.bindTexture(gl.TEXTURE_2D, gl.createTexture());
+    gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_MIN_FILTER, gl.NEAREST);
+    gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_MAG_FILTER, gl.NEAREST);
+    gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_WRAP_S, gl.CLAMP_TO_EDGE);
+    gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_WRAP_T, gl.CLAMP_TO_EDGE);
+  }
+
+  // Show an ImageData, or the last one again, e.g. after toggling `crt`.
+  draw(image = this.image) {
+    if (!image) return;
+    const gl = this.gl;
+    const width = Math.round(this.canvas.clientWidth * devicePixelRatio);
+    const height = Math.round(this.canvas.clientHeight * devicePixelRatio);
+    if (this.canvas.width !== width || this.canvas.height !== height) {
+      this.canvas.width = width;
+      this.canvas.height = height;
+    }
+    if (image !== this.image) {
+      gl.texImage2D(gl.TEXTURE_2D, 0, gl.RGBA, gl.RGBA, gl.UNSIGNED_BYTE, image);
+      this.image = image;
+    }
+    gl.viewport(0, 0, width, height);
+    gl.uniform1i(this.uniforms.crt, this.crt ? 1 : 0);
+    gl.uniform2f(this.uniforms.size, image.width, image.height);
+    gl.drawArrays(gl.TRIANGLE_STRIP, 0, 4);
+  }
+}
+
+const CRT_VERTEX = `
+attribute vec2 a_position;
+varying vec2 v_uv;
+void main() {
+  // Texture rows run top to bottom.
+  v_uv = vec2(a_position.x, -a_position.y) * 0.5 + 0.5;
+  gl_Position = vec4(a_position, 0.0, 1.0);
+}`;
+
+const CRT_FRAGMENT = `
+precision mediump float;
+uniform sampler2D u_screen;
+uniform bool u_crt;
+uniform vec2 u_size;
+varying vec2 v_uv;
+
+vec2 curve(vec2 uv) {
+  uv = uv * 2.0 - 1.0;
+  uv *= 1.0 + 0.03 * dot(uv, uv);
+  return uv * 0.5 + 0.5;
+}
+
+void main() {
+  if (!u_crt) {
+    gl_FragColor = texture2D(u_screen, v_uv);
+    return;
+  }
+  vec2 uv = curve(v_uv);
+  if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+    gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+    return;
+  }
+  vec2 texel = 1.0 / u_size;
+  vec3 color = texture2D(u_screen, uv).rgb;
+  vec3 glow = texture2D(u_screen, uv + vec2(texel.x, 0.0)).rgb
+    + texture2D(u_screen, uv - vec2(texel.x, 0.0)).rgb
+    + texture2D(u_screen, uv + vec2(0.0, texel.y)).rgb
+    + texture2D(u_screen, uv - vec2(0.0, texel.y)).rgb;
+  color += glow * 0.08;
+  // Darkest between two rows, full brightness in the middle of one.
+  float row = fract(uv.y * u_size.y);
+  color *= 0.7 + 0.3 * sin(row * 3.14159265);
+  // Darker towards the corners.
+  vec2 edge = uv * (1.0 - uv);
+  color *= pow(edge.x * edge.y * 16.0, 0.15);
+  gl_FragColor = vec4(color, 1.0);
+}`;
+
+function compile(gl, type, source) {
+  const shader = gl.createShader(type);
+  gl.shaderSource(shader, source);
+  gl.compileShader(shader);
+  if (!gl.getShaderParameter(shader, gl.COMPILE_STATUS)) {
+    throw new Error(`chip8: ${gl.getShaderInfoLog(shader)}`);
+  }
+  return shader;
+}
+
+// A file name for a screenshot taken at `date`, like
+// "chip8-20240131-235959.png", in local time.
+export function screenshotName(date = new Date()) {
+  return `chip8-${stamp(date)}.png`;
+}
+
+// A file name for a recording started at `date` in `format`, "gif" or
+// "apng", like "chip8-20240131-235959.gif".
+export function recordingName(format, date = new Date()) {
+  return `chip8-${stamp(date)}.${format === "apng" ? "png" : "gif"}`;
+}
+
+function stamp(date) {
+  const two = (n) => String(n).padStart(2, "0");
+  const day = `${date.getFullYear()}${two(date.getMonth() + 1)}${two(date.getDate())}`;
+  const time = `${two(date.getHours())}${two(date.getMinutes())}${two(date.getSeconds())}`;
+  return `${day}-${time}`;
+}
+
+// Offer bytes to the user as a download called `name`.
+export function download(bytes, name, type = "application/octet-stream") {
+  const url = URL.createObjectURL(new Blob([bytes], { type }));
+  const link = document.createElement("a");
+  link.href = url;
+  link.download = name;
+  link.click();
+  setTimeout(() => URL.revokeObjectURL(url), 0);
+}
+
+// Where ScreenSize keeps the scale picked last.
+const SCALE_KEY = "chip8:scale";
+// 640x320 for the 64x32 screen.
+const DEFAULT_SCALE = 10;
+
+// Sizes the screen canvas at a whole multiple of the CHIP-8 resolution, so
+// every emulated pixel is the same size, and switches fullscreen.
+// `container` holds the canvas; it is what goes fullscreen, with the canvas
+// centred on black at the largest multiple the display takes.
+//
+// A scale of 0 fits the largest multiple in the window below the top of
+// the canvas. The scale picked last is kept in localStorage.
+export class ScreenSize {
+  constructor(canvas, container, storage = localStorage) {
+    this.canvas = canvas;
+    this.container = container;
+    this.storage = storage;
+    const saved = storage.getItem(SCALE_KEY);
+    this.scale = saved === null ? DEFAULT_SCALE : Math.max(0, parseInt(saved, 10) || 0);
+    this.width = 64;
+    this.height = 32;
+    // The scale the screen is drawn at, a whole number even when fitting.
+    this.current = this.scale || 1;
+    // Called after the canvas changed size, to draw it again.
+    this.onResize = () => {};
+    addEventListener("resize", () => this.update());
+    document.addEventListener("fullscreenchange", () => this.update());
+    this.update();
+  }
+
+  setScale(scale) {
+    this.scale = scale;
+    this.storage.setItem(SCALE_KEY, String(scale));
+    this.update();
+  }
+
+  // Follow the machine's resolution.
+  setResolution(width, height) {
+    if (width === this.width && height === this.height) return;
+    this.width = width;
+    this.height = height;
+    this.update();
+  }
+
+  get fullscreen() {
+    return document.fullscreenElement === this.container;
+  }
+
+  toggleFullscreen() {
+    if (this.fullscreen) return document.exitFullscreen();
+    return this.container.requestFullscreen();
+  }
+
+  // The largest whole multiple of the resolution fitting in an area, and
+  // at least 1.
+  fit(width, height) {
+    return Math.max(1, Math.floor(Math.min(width / this.width, height / this.height)));
+  }
+
+  update() {
+    let scale;
+    if (this.fullscreen) {
+      const fit = this.fit(this.container.clientWidth, this.container.clientHeight);
+      scale = this.scale === 0 ? fit : Math.min(this.scale, fit);
+    } else if (this.scale === 0) {
+      const top = this.canvas.getBoundingClientRect().top + scrollY;
+      scale = this.fit(document.documentElement.clientWidth, innerHeight - top);
+    } else {
+      scale = this.scale;
+    }
+    this.current = scale;
+    this.canvas.style.width = `${this.width * scale}px`;
+    this.canvas.style.height = `${this.height * scale}px`;
+    this.onResize();
+  }
+}
+
+// The COSMAC VIP keypad, row by row.
+export const KEYPAD_LAYOUT = [0x1, 0x2, 0x3, 0xc, 0x4, 0x5, 0x6, 0xd, 0x7, 0x8, 0x9, 0xe, 0xa, 0x0, 0xb, 0xf];
+
+// The hex keypad as a 4x4 grid of buttons for mouse and touch, filling
+// `container`:
+//
+//   new VirtualKeypad(element, (key, pressed) =>
+//     pressed ? chip8.keyDown(key) : chip8.keyUp(key));
+//
+// Each button gets the class "pressed" while held. Fingers can hold
+// several keys at once.
+export class VirtualKeypad {
+  constructor(container, onKey) {
+    this.onKey = onKey;
+    this.element = document.createElement("div");
+    this.element.className = "chip8-keypad";
+    this.element.style.display = "grid";
+    this.element.style.gridTemplateColumns = "repeat(4, 1fr)";
+    this.element.style.touchAction = "none";
+    this.element.style.userSelect = "none";
+    // Pointers down on each key.
+    this.pointers = new Array(16).fill(0);
+    for (const key of KEYPAD_LAYOUT) {
+      const button = document.createElement("button");
+      button.textContent = key.toString(16).toUpperCase();
+      button.addEventListener("pointerdown", (event) => {
+        button.setPointerCapture(event.pointerId);
+        this.press(button, key, true);
+        event.preventDefault();
+      });
+      const release = () => this.press(button, key, false);
+      button.addEventListener("pointerup", release);
+      button.addEventListener("pointercancel", release);
+      button.addEventListener("contextmenu", (event) => event.preventDefault());
+      this.element.appendChild(button);
+    }
+    container.appendChild(this.element);
+  }
+
+  remove() {
+    this.element.remove();
+  }
+
+  press(button, key, pressed) {
+    const before = this.pointers[key];
+    this.pointers[key] = Math.max(0, before + (pressed ? 1 : -1));
+    if ((before === 0) !== (this.pointers[key] === 0)) {
+      button.classList.toggle("pressed", pressed);
+      this.onKey(key, pressed);
+    }
+  }
+}
+
+// Name to keep a ROM's data under in storage: its SHA-1 in hex.
+export async function romKey(rom) {
+  const digest = await crypto.subtle.digest("SHA-1", rom);
+  return Array.from(new Uint8Array(digest), (byte) => byte.toString(16).padStart(2, "0")).join("");
+}
+
+// Save states and RPL flags kept in localStorage, per ROM.
+export class RomStorage {
+  constructor(key, storage = localStorage) {
+    this.key = key;
+    this.storage = storage;
+  }
+
+  saveState(state) {
+    this.storage.setItem(`chip8:state:${this.key}`, encode(state));
+  }
+
+  // The saved state, or null.
+  loadState() {
+    return decode(this.storage.getItem(`chip8:state:${this.key}`));
+  }
+
+  saveRplFlags(flags) {
+    this.storage.setItem(`chip8:rpl:${this.key}`, encode(flags));
+  }
+
+  loadRplFlags() {
+    return decode(this.storage.getItem(`chip8:rpl:${this.key}`));
+  }
+}
+
+// localStorage only holds strings, so bytes go in as base64.
+function encode(bytes) {
+  let binary = "";
+  bytes.forEach((byte) => (binary += String.fromCharCode(byte)));
+  return btoa(binary);
+}
+
+function decode(text) {
+  if (text === null) return null;
+  return Uint8Array.from(atob(text), (c) => c.charCodeAt(0));
+}